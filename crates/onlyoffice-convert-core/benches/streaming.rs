@@ -0,0 +1,107 @@
+//! Benchmarks for the source streaming paths, run with `cargo bench -p onlyoffice-convert-core`
+
+use std::{env::temp_dir, hint::black_box};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use onlyoffice_convert_core::{
+    encrypted::get_file_condition,
+    source::{SOURCE_HEADER_SIZE, SourceFileWriter},
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Representative source file sizes
+const FILE_SIZES: &[usize] = &[64 * 1024, 1024 * 1024, 16 * 1024 * 1024];
+
+/// Chunk size of the S3 body stream
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Create a source file of `size` bytes with a ZIP end record so it
+/// passes through every file condition check
+fn source_data(size: usize) -> Vec<u8> {
+    let mut data: Vec<u8> = (0..size).map(|index| (index % 251) as u8).collect();
+    data[..2].copy_from_slice(b"PK");
+    data[size - 22..size - 18].copy_from_slice(&[0x50, 0x4b, 0x05, 0x06]);
+    data
+}
+
+fn bench_write_source(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("write_source");
+
+    for &size in FILE_SIZES {
+        let data = source_data(size);
+        let path = temp_dir().join(format!(
+            "onlyoffice-convert-bench-{}",
+            Uuid::new_v4().simple()
+        ));
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.to_async(&runtime).iter(|| async {
+                let mut writer = SourceFileWriter::create(&path, None).await.unwrap();
+                for chunk in data.chunks(CHUNK_SIZE) {
+                    writer.write_chunk(chunk).await.unwrap();
+                }
+                black_box(writer.finish().await.unwrap())
+            });
+        });
+
+        _ = std::fs::remove_file(&path);
+    }
+
+    group.finish();
+}
+
+fn bench_hash_source(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash_source");
+
+    for &size in FILE_SIZES {
+        let data = source_data(size);
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| {
+                let mut hasher = Sha256::new();
+                for chunk in data.chunks(CHUNK_SIZE) {
+                    hasher.update(chunk);
+                }
+                black_box(hasher.finalize())
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_file_condition(c: &mut Criterion) {
+    let mut group = c.benchmark_group("file_condition");
+
+    // The checks only scan the header, a full header without any signature
+    // is the worst case as every signature is searched for
+    let header = source_data(SOURCE_HEADER_SIZE);
+    group.throughput(Throughput::Bytes(header.len() as u64));
+    group.bench_function("zip_header", |b| {
+        b.iter(|| black_box(get_file_condition(black_box(&header))))
+    });
+
+    let mut encrypted = source_data(SOURCE_HEADER_SIZE);
+    encrypted[512..528].copy_from_slice(b"EncryptedPackage");
+    group.bench_function("encrypted_header", |b| {
+        b.iter(|| black_box(get_file_condition(black_box(&encrypted))))
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_write_source,
+    bench_hash_source,
+    bench_file_condition
+);
+criterion_main!(benches);
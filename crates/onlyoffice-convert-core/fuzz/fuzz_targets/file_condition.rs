@@ -0,0 +1,26 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use onlyoffice_convert_core::encrypted::{FileCondition, get_file_condition};
+
+/// Size of the header checked for encryption signatures
+const HEADER_SIZE: usize = 1024 * 32;
+
+fuzz_target!(|data: &[u8]| {
+    let condition = get_file_condition(data);
+
+    // Files too small to identify are always treated as corrupted
+    if data.len() < 4 {
+        assert!(matches!(condition, FileCondition::LikelyCorrupted));
+        return;
+    }
+
+    // Encryption signatures within the header take priority over corruption checks
+    let header = &data[..data.len().min(HEADER_SIZE)];
+    if header
+        .windows(b"EncryptedPackage".len())
+        .any(|window| window == b"EncryptedPackage")
+    {
+        assert!(matches!(condition, FileCondition::LikelyEncrypted));
+    }
+});
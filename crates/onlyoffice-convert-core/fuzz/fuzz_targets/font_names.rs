@@ -0,0 +1,17 @@
+#![no_main]
+
+use std::collections::BTreeSet;
+
+use libfuzzer_sys::fuzz_target;
+use onlyoffice_convert_core::fuzz::collect_font_names;
+
+fuzz_target!(|contents: &str| {
+    let mut fonts = BTreeSet::new();
+    collect_font_names(contents, &mut fonts);
+
+    for font in fonts {
+        // Empty names and theme font references are skipped
+        assert!(!font.is_empty());
+        assert!(!font.starts_with('+'));
+    }
+});
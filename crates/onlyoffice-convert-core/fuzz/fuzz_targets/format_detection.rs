@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use onlyoffice_convert_core::{
+    format::InputFormat,
+    fuzz::{detect_input_format, detect_unsupported_format},
+};
+
+/// Signature of a ZIP local file header
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+fuzz_target!(|header: &[u8]| {
+    if let Some(format) = detect_unsupported_format(header) {
+        assert_eq!(format.error().reason, Some("UNSUPPORTED_FORMAT"));
+    }
+
+    let format = detect_input_format(header);
+
+    // ZIP archives are only detected as Visio drawings, other packages are left to x2t
+    if header.starts_with(ZIP_SIGNATURE) {
+        assert!(matches!(format, None | Some(InputFormat::Vsdx)));
+    } else {
+        assert_ne!(format, Some(InputFormat::Vsdx));
+    }
+});
@@ -0,0 +1,83 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{config::app_config, error::ConvertError};
+
+/// Total bytes reserved by conversions that are currently in progress
+static RESERVED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Disk space reserved for a conversion, released when dropped
+pub struct DiskReservation {
+    bytes: u64,
+}
+
+impl Drop for DiskReservation {
+    fn drop(&mut self) {
+        RESERVED_BYTES.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
+
+/// Reserve the disk space required to convert a source file of `source_size` bytes
+/// within `temp_path`, fails with a `BUSY` error if the projected usage of this and the
+/// other in progress conversions would exceed the free space
+pub fn reserve_disk_space(
+    temp_path: &Path,
+    source_size: u64,
+) -> Result<DiskReservation, ConvertError> {
+    let config = app_config();
+    let headroom = config.disk_headroom;
+    let required = source_size.saturating_mul(config.disk_usage_multiplier);
+
+    let Some(available) = available_space(temp_path) else {
+        // Unable to determine the free space, admit the request without tracking
+        return Ok(DiskReservation { bytes: 0 });
+    };
+
+    let reserved = RESERVED_BYTES.fetch_add(required, Ordering::SeqCst);
+    let reservation = DiskReservation { bytes: required };
+
+    // Other conversions may not have written all of their reserved space yet
+    let projected = reserved.saturating_add(required).saturating_add(headroom);
+
+    if projected > available {
+        tracing::warn!(
+            required,
+            reserved,
+            available,
+            "insufficient disk space for conversion"
+        );
+
+        return Err(ConvertError {
+            reason: Some("BUSY"),
+            x2t_code: None,
+            message: "insufficient temporary disk space, try again later".to_string(),
+        });
+    }
+
+    Ok(reservation)
+}
+
+/// Get the available space in bytes of the file system containing `path`
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    // Safety: path is a valid null terminated string and stat is a valid statvfs
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        tracing::warn!(error = ?std::io::Error::last_os_error(), "failed to stat temp file system");
+        return None;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
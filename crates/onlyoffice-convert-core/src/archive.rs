@@ -0,0 +1,104 @@
+use std::{
+    fs::File,
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{error::ConvertError, format::OutputFormat};
+
+/// Content type of the output archive
+pub const ARCHIVE_CONTENT_TYPE: &str = "application/zip";
+
+/// Name of the manifest file embedded within the archive
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// File to include in the output archive
+pub struct ArchiveEntry {
+    /// Name of the file within the archive
+    pub name: String,
+    /// Path to the file on disk
+    pub path: PathBuf,
+    /// Format of the file
+    pub format: OutputFormat,
+}
+
+#[derive(Serialize)]
+struct ArchiveManifest<'a> {
+    request_id: &'a str,
+    files: Vec<ArchiveManifestFile<'a>>,
+}
+
+#[derive(Serialize)]
+struct ArchiveManifestFile<'a> {
+    name: &'a str,
+    format: OutputFormat,
+    content_type: &'static str,
+    size: u64,
+}
+
+/// Package the `entries` into a single ZIP archive at `archive_path` along with
+/// a manifest.json describing the files within the archive
+pub async fn create_output_archive(
+    request_id: &str,
+    archive_path: &Path,
+    entries: Vec<ArchiveEntry>,
+) -> Result<(), ConvertError> {
+    let request_id = request_id.to_string();
+    let archive_path = archive_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || write_archive(&request_id, &archive_path, &entries))
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "archive task failed");
+            archive_error()
+        })?
+        .map_err(|err| {
+            tracing::error!(?err, "failed to create output archive");
+            archive_error()
+        })
+}
+
+fn write_archive(
+    request_id: &str,
+    archive_path: &Path,
+    entries: &[ArchiveEntry],
+) -> zip::result::ZipResult<()> {
+    let mut writer = ZipWriter::new(File::create(archive_path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut files = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let mut file = BufReader::new(File::open(&entry.path)?);
+
+        writer.start_file(entry.name.as_str(), options)?;
+        let size = std::io::copy(&mut file, &mut writer)?;
+
+        files.push(ArchiveManifestFile {
+            name: &entry.name,
+            format: entry.format,
+            content_type: entry.format.content_type(),
+            size,
+        });
+    }
+
+    let manifest = serde_json::to_vec_pretty(&ArchiveManifest { request_id, files })
+        .map_err(std::io::Error::other)?;
+
+    writer.start_file(MANIFEST_FILE_NAME, options)?;
+    writer.write_all(&manifest)?;
+    writer.finish()?.sync_all()?;
+
+    Ok(())
+}
+
+fn archive_error() -> ConvertError {
+    ConvertError {
+        reason: Some("ARCHIVE_OUTPUT"),
+        x2t_code: None,
+        message: "failed to create output archive".to_string(),
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    config::config_var,
+    storage::{PutBody, PutOptions, Storage},
+};
+
+/// Environment variable for the bucket failure artifacts are stored within,
+/// failure artifacts are only persisted when this is set
+const FAILURE_ARTIFACTS_BUCKET_ENV: &str = "FAILURE_ARTIFACTS_BUCKET";
+
+/// Environment variable for the key prefix failure artifacts are stored under
+const FAILURE_ARTIFACTS_PREFIX_ENV: &str = "FAILURE_ARTIFACTS_PREFIX";
+
+const DEFAULT_FAILURE_ARTIFACTS_PREFIX: &str = "failures/";
+
+/// Details about a failed conversion that are stored alongside the
+/// artifacts to allow reproducing the failure
+pub struct FailedConversion<'a> {
+    /// Unique ID of the request that failed
+    pub request_id: &'a str,
+    pub source_bucket: Option<&'a str>,
+    pub source_key: Option<&'a str>,
+    pub source_version_id: Option<&'a str>,
+    pub source_url: Option<&'a str>,
+    /// Hex encoded SHA-256 checksum of the source file
+    pub source_sha256: &'a str,
+    pub dest_bucket: Option<&'a str>,
+    pub dest_key: Option<&'a str>,
+
+    /// Path to the downloaded input file
+    pub input_path: &'a Path,
+    /// Generated x2t config XML
+    pub config_bytes: &'a [u8],
+    /// Captured stderr output from x2t
+    pub stderr: &'a [u8],
+
+    pub reason: Option<&'static str>,
+    pub x2t_code: Option<i32>,
+    pub message: &'a str,
+}
+
+#[derive(Serialize)]
+struct FailureManifest<'a> {
+    request_id: &'a str,
+    created_at: u64,
+    source_bucket: Option<&'a str>,
+    source_key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_version_id: Option<&'a str>,
+    source_url: Option<&'a str>,
+    source_sha256: &'a str,
+    dest_bucket: Option<&'a str>,
+    dest_key: Option<&'a str>,
+    reason: Option<&'static str>,
+    x2t_code: Option<i32>,
+    message: &'a str,
+    files: FailureManifestFiles,
+}
+
+#[derive(Serialize)]
+struct FailureManifestFiles {
+    input: &'static str,
+    config: &'static str,
+    stderr: &'static str,
+}
+
+const INPUT_FILE_NAME: &str = "input";
+const CONFIG_FILE_NAME: &str = "config.xml";
+const STDERR_FILE_NAME: &str = "stderr.txt";
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Copy the input file, config and stderr of a failed conversion to the
+/// configured failure artifacts location (When enabled)
+///
+/// Failing to persist the artifacts is logged but otherwise ignored as
+/// it should not mask the original conversion error
+pub async fn persist_failure_artifacts(storage: &dyn Storage, failure: FailedConversion<'_>) {
+    let bucket = match config_var(FAILURE_ARTIFACTS_BUCKET_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        // Failure artifacts are not enabled
+        _ => return,
+    };
+
+    let prefix = config_var(FAILURE_ARTIFACTS_PREFIX_ENV)
+        .unwrap_or_else(|_| DEFAULT_FAILURE_ARTIFACTS_PREFIX.to_string());
+    let prefix = format!("{prefix}{}/", failure.request_id);
+
+    tracing::debug!(%bucket, %prefix, "persisting failure artifacts");
+
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default();
+
+    let manifest = FailureManifest {
+        request_id: failure.request_id,
+        created_at,
+        source_bucket: failure.source_bucket,
+        source_key: failure.source_key,
+        source_version_id: failure.source_version_id,
+        source_url: failure.source_url,
+        source_sha256: failure.source_sha256,
+        dest_bucket: failure.dest_bucket,
+        dest_key: failure.dest_key,
+        reason: failure.reason,
+        x2t_code: failure.x2t_code,
+        message: failure.message,
+        files: FailureManifestFiles {
+            input: INPUT_FILE_NAME,
+            config: CONFIG_FILE_NAME,
+            stderr: STDERR_FILE_NAME,
+        },
+    };
+
+    let manifest_bytes = match serde_json::to_vec_pretty(&manifest) {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "failed to serialize failure manifest");
+            return;
+        }
+    };
+
+    put_artifact(
+        storage,
+        &bucket,
+        &prefix,
+        INPUT_FILE_NAME,
+        PutBody::File(failure.input_path),
+    )
+    .await;
+
+    put_artifact(
+        storage,
+        &bucket,
+        &prefix,
+        CONFIG_FILE_NAME,
+        PutBody::Bytes(failure.config_bytes.to_vec()),
+    )
+    .await;
+
+    put_artifact(
+        storage,
+        &bucket,
+        &prefix,
+        STDERR_FILE_NAME,
+        PutBody::Bytes(failure.stderr.to_vec()),
+    )
+    .await;
+
+    // Manifest is written last so its presence indicates the other artifacts were written
+    put_artifact(
+        storage,
+        &bucket,
+        &prefix,
+        MANIFEST_FILE_NAME,
+        PutBody::Bytes(manifest_bytes),
+    )
+    .await;
+}
+
+/// Upload a single artifact, logging any errors
+async fn put_artifact(
+    storage: &dyn Storage,
+    bucket: &str,
+    prefix: &str,
+    name: &str,
+    body: PutBody<'_>,
+) {
+    if let Err(err) = storage
+        .put_object(
+            bucket,
+            &format!("{prefix}{name}"),
+            body,
+            PutOptions::default(),
+        )
+        .await
+    {
+        tracing::error!(?err, %name, "failed to upload failure artifact");
+    }
+}
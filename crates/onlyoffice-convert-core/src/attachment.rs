@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use lopdf::{Dictionary, Document, Object, Stream, StringFormat, dictionary, text_string};
+
+use crate::error::ConvertError;
+
+/// Maximum depth of an existing embedded files name tree that is read
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+/// Embed the source file at `source_path` as an attachment named `name` within
+/// the PDF at `output_path` in place. The attached PDF is written to
+/// `attached_path` before replacing the output
+pub async fn attach_source(
+    output_path: &Path,
+    attached_path: &Path,
+    source_path: &Path,
+    name: &str,
+) -> Result<(), ConvertError> {
+    let output_path = output_path.to_path_buf();
+    let attached_path = attached_path.to_path_buf();
+    let source_path = source_path.to_path_buf();
+    let name = name.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let content = std::fs::read(&source_path)?;
+        let mut document = Document::load(&output_path)?;
+        attach_file(&mut document, &name, content)?;
+        document.save(&attached_path)?.sync_all()?;
+        std::fs::rename(&attached_path, &output_path)?;
+        Ok::<_, lopdf::Error>(())
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "attach source task failed");
+        attach_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to attach source to pdf");
+        attach_error()
+    })
+}
+
+/// Add the `content` as an embedded file of the document, the file is listed in
+/// the embedded files of the document and associated with the document as its
+/// source (PDF 2.0 associated files)
+fn attach_file(document: &mut Document, name: &str, content: Vec<u8>) -> lopdf::Result<()> {
+    let size = i64::try_from(content.len()).unwrap_or(i64::MAX);
+
+    let mut file = Stream::new(
+        dictionary! {
+            "Type" => "EmbeddedFile",
+            "Params" => dictionary! { "Size" => size },
+        },
+        content,
+    );
+    file.compress()?;
+    let file_id = document.add_object(file);
+
+    let file_name = text_string(name);
+    let file_spec_id = document.add_object(dictionary! {
+        "Type" => "Filespec",
+        "F" => Object::string_literal(ascii_name(name)),
+        "UF" => file_name.clone(),
+        "Desc" => Object::string_literal("Original source file"),
+        "AFRelationship" => "Source",
+        "EF" => dictionary! { "F" => file_id, "UF" => file_id },
+    });
+
+    let catalog = document.catalog()?;
+
+    let mut names = match catalog.get(b"Names") {
+        Ok(value) => document.dereference(value)?.1.as_dict()?.clone(),
+        Err(_) => Dictionary::new(),
+    };
+
+    let mut associated_files = match catalog.get(b"AF") {
+        Ok(value) => document.dereference(value)?.1.as_array()?.clone(),
+        Err(_) => Vec::new(),
+    };
+    associated_files.push(file_spec_id.into());
+
+    // Existing entries are flattened into a single node with the new entry as
+    // the entries of a name tree must remain sorted
+    let mut entries = Vec::new();
+    if let Ok(node) = names.get(b"EmbeddedFiles") {
+        collect_names(document, node, 0, &mut entries)?;
+    }
+    entries.push((file_name.as_str()?.to_vec(), file_spec_id.into()));
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let entries: Vec<Object> = entries
+        .into_iter()
+        .flat_map(|(key, value)| [Object::String(key, StringFormat::Literal), value])
+        .collect();
+    names.set("EmbeddedFiles", dictionary! { "Names" => entries });
+
+    let catalog = document.catalog_mut()?;
+    catalog.set("Names", names);
+    catalog.set("AF", associated_files);
+    // Open the attachments panel so the source is visible to recipients
+    catalog.set("PageMode", "UseAttachments");
+
+    Ok(())
+}
+
+/// Collect the key and value pairs of a name tree `node` and its descendants
+fn collect_names(
+    document: &Document,
+    node: &Object,
+    depth: usize,
+    entries: &mut Vec<(Vec<u8>, Object)>,
+) -> lopdf::Result<()> {
+    // Bounded to protect against reference cycles
+    if depth > MAX_NAME_TREE_DEPTH {
+        return Err(lopdf::Error::ReferenceCycle);
+    }
+
+    let node = document.dereference(node)?.1.as_dict()?;
+
+    if let Ok(names) = node.get(b"Names") {
+        for pair in document.dereference(names)?.1.as_array()?.chunks_exact(2) {
+            let key = document.dereference(&pair[0])?.1.as_str()?.to_vec();
+            entries.push((key, pair[1].clone()));
+        }
+    }
+
+    if let Ok(kids) = node.get(b"Kids") {
+        for kid in document.dereference(kids)?.1.as_array()? {
+            collect_names(document, kid, depth + 1, entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// File name for readers that only support the `F` entry, characters outside
+/// of the ASCII range are replaced with `_`
+fn ascii_name(name: &str) -> String {
+    name.chars()
+        .map(|char| match char {
+            ' '..='~' => char,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn attach_error() -> ConvertError {
+    ConvertError {
+        reason: Some("ATTACH_SOURCE"),
+        x2t_code: None,
+        message: "failed to attach source to pdf output".to_string(),
+    }
+}
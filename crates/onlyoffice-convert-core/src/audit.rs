@@ -0,0 +1,149 @@
+use std::{io::Write, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    config::config_var,
+    error::ConvertError,
+    format::OutputFormat,
+    storage::{PutBody, PutOptions, S3Storage, Storage},
+};
+
+/// Environment variable for where audit records are written, either `cloudwatch`
+/// (stdout, which Lambda forwards to CloudWatch Logs) or `s3`. Conversions are
+/// only audited when this is set
+const AUDIT_LOG_ENV: &str = "AUDIT_LOG";
+
+/// Environment variable for the bucket audit records are stored within when
+/// using the `s3` audit log
+const AUDIT_BUCKET_ENV: &str = "AUDIT_BUCKET";
+
+/// Environment variable for the key prefix audit records are stored under
+const AUDIT_PREFIX_ENV: &str = "AUDIT_PREFIX";
+
+const DEFAULT_AUDIT_PREFIX: &str = "audit/";
+
+/// Destination audit records are written to
+enum AuditLog {
+    CloudWatch,
+    S3 { bucket: String, prefix: String },
+}
+
+fn audit_log() -> Option<AuditLog> {
+    match config_var(AUDIT_LOG_ENV).ok()?.as_str() {
+        "cloudwatch" => Some(AuditLog::CloudWatch),
+        "s3" => {
+            let Some(bucket) = config_var(AUDIT_BUCKET_ENV)
+                .ok()
+                .filter(|value| !value.is_empty())
+            else {
+                tracing::warn!("s3 audit log is missing the audit bucket");
+                return None;
+            };
+
+            let prefix =
+                config_var(AUDIT_PREFIX_ENV).unwrap_or_else(|_| DEFAULT_AUDIT_PREFIX.to_string());
+
+            Some(AuditLog::S3 { bucket, prefix })
+        }
+        value => {
+            tracing::warn!(%value, "unknown audit log");
+            None
+        }
+    }
+}
+
+/// Whether conversions are audited
+pub fn is_audit_enabled() -> bool {
+    config_var(AUDIT_LOG_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+/// Record of a single conversion for the audit log, written as a line of JSON
+#[derive(Serialize)]
+pub struct AuditRecord {
+    pub request_id: String,
+    /// Time the conversion started
+    pub started_at: DateTime<Utc>,
+    /// Identity of the caller that requested the conversion (i.e IAM user ARN or
+    /// source IP) when known
+    pub caller: Option<String>,
+    /// Tenant the conversion was requested on behalf of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Location of the source, URL sources are recorded without their query.
+    /// [None] for invalid requests without a source
+    pub source: Option<String>,
+    /// Location of the destination, [None] when the output was returned inline
+    pub destination: Option<String>,
+    pub output_formats: Vec<OutputFormat>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x2t_code: Option<i32>,
+    pub duration_ms: u64,
+}
+
+impl AuditRecord {
+    /// Set the outcome of the conversion
+    pub fn finish<T>(&mut self, result: &Result<T, ConvertError>, duration: Duration) {
+        self.duration_ms = duration.as_millis() as u64;
+        self.success = result.is_ok();
+
+        if let Err(error) = result {
+            self.reason = error.reason;
+            self.x2t_code = error.x2t_code;
+        }
+    }
+}
+
+/// Write the `record` to the audit log, failing to write the record is
+/// logged but does not fail the conversion
+pub async fn write_audit_record(record: &AuditRecord) {
+    let Some(log) = audit_log() else {
+        return;
+    };
+
+    let mut line = match serde_json::to_vec(record) {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "failed to serialize audit record");
+            return;
+        }
+    };
+    line.push(b'\n');
+
+    match log {
+        AuditLog::CloudWatch => {
+            // Written as a single line so the record is a single log event
+            if let Err(err) = std::io::stdout().lock().write_all(&line) {
+                tracing::error!(?err, "failed to write audit record");
+            }
+        }
+        AuditLog::S3 { bucket, prefix } => {
+            let key = format!(
+                "{prefix}{}/{}.jsonl",
+                record.started_at.format("%Y/%m/%d"),
+                record.request_id
+            );
+
+            let result = S3Storage::from_env()
+                .await
+                .put_object(
+                    &bucket,
+                    &key,
+                    PutBody::Bytes(line),
+                    PutOptions {
+                        content_type: Some("application/x-ndjson"),
+                        ..Default::default()
+                    },
+                )
+                .await;
+
+            if let Err(err) = result {
+                tracing::error!(?err, %key, "failed to store audit record");
+            }
+        }
+    }
+}
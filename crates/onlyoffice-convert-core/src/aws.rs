@@ -0,0 +1,220 @@
+use aws_config::{
+    BehaviorVersion, Region, SdkConfig, meta::region::RegionProviderChain, retry::RetryConfig,
+    timeout::TimeoutConfig,
+};
+use aws_types::service_config::{LoadServiceConfig, ServiceConfigKey};
+use url::Url;
+
+use crate::{config::app_config, config_check::configuration_error, error::ConvertError};
+
+/// Environment variable the SDK clients load their endpoint URL from, used
+/// to identify the endpoint URL key of the service configuration
+const SDK_ENDPOINT_URL_ENV: &str = "AWS_ENDPOINT_URL";
+
+/// Create the AWS production configuration
+pub async fn aws_config() -> SdkConfig {
+    let config = app_config();
+
+    // Regions are only valid within their partition so the fallback is chosen by partition
+    let fallback_region = config.aws_partition.unwrap_or_default().default_region();
+
+    // The configured region takes precedence over the default provider chain
+    let region_provider = RegionProviderChain::first_try(config.region.clone().map(Region::new))
+        .or_default_provider()
+        .or_else(fallback_region);
+
+    // Load the configuration from env variables (See https://docs.aws.amazon.com/sdkref/latest/guide/settings-reference.html#EVarSettings)
+    let mut loader = aws_config::defaults(BehaviorVersion::v2025_08_07())
+        // Setup the region provider
+        .region(region_provider);
+
+    if config.use_fips_endpoints {
+        loader = loader.use_fips(true);
+    }
+
+    if config.aws_retry_mode.is_some() || config.aws_max_attempts.is_some() {
+        let mut retry_config = RetryConfig::standard();
+
+        if let Some(retry_mode) = config.aws_retry_mode {
+            retry_config = retry_config.with_retry_mode(retry_mode);
+        }
+
+        if let Some(max_attempts) = config.aws_max_attempts {
+            retry_config = retry_config.with_max_attempts(max_attempts.get());
+        }
+
+        loader = loader.retry_config(retry_config);
+    }
+
+    // Timeouts that are not configured keep the SDK defaults
+    let mut timeout_config = TimeoutConfig::builder();
+
+    if let Some(timeout) = config.aws_connect_timeout {
+        timeout_config = timeout_config.connect_timeout(timeout);
+    }
+
+    if let Some(timeout) = config.aws_read_timeout {
+        timeout_config = timeout_config.read_timeout(timeout);
+    }
+
+    if let Some(timeout) = config.aws_operation_timeout {
+        timeout_config = timeout_config.operation_timeout(timeout);
+    }
+
+    if let Some(timeout) = config.aws_operation_attempt_timeout {
+        timeout_config = timeout_config.operation_attempt_timeout(timeout);
+    }
+
+    loader = loader.timeout_config(timeout_config.build());
+
+    let sdk_config = loader.load().await;
+
+    if config.aws_endpoint_urls.is_empty() {
+        return sdk_config;
+    }
+
+    // Clients read their endpoint from the service configuration when created
+    sdk_config
+        .to_builder()
+        .service_config(ServiceEndpoints {
+            endpoints: config.aws_endpoint_urls.clone(),
+            inner: sdk_config,
+        })
+        .build()
+}
+
+/// Check the resolved region is within the configured partition, called at cold start
+/// so a region from the provider chain (i.e a profile) of another partition fails the
+/// start rather than sending requests to the wrong partition
+pub async fn check_aws_region() -> Result<(), ConvertError> {
+    let Some(partition) = app_config().aws_partition else {
+        return Ok(());
+    };
+
+    let sdk_config = aws_config().await;
+    let region = sdk_config.region().map(Region::as_ref).unwrap_or_default();
+
+    if AwsPartition::from_region(region) != partition {
+        tracing::error!(%region, partition = partition.name(), "region is not within the configured partition");
+        return Err(configuration_error(&format!(
+            "region {region} is not within the {} partition",
+            partition.name()
+        )));
+    }
+
+    Ok(())
+}
+
+/// AWS partition, a group of regions isolated from the regions of other partitions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AwsPartition {
+    /// Commercial regions
+    #[default]
+    Aws,
+    /// China regions
+    AwsCn,
+    /// GovCloud (US) regions
+    AwsUsGov,
+    /// US ISO regions
+    AwsIso,
+    /// US ISOB regions
+    AwsIsoB,
+}
+
+impl AwsPartition {
+    pub fn from_name(name: &str) -> Option<AwsPartition> {
+        match name {
+            "aws" => Some(AwsPartition::Aws),
+            "aws-cn" => Some(AwsPartition::AwsCn),
+            "aws-us-gov" => Some(AwsPartition::AwsUsGov),
+            "aws-iso" => Some(AwsPartition::AwsIso),
+            "aws-iso-b" => Some(AwsPartition::AwsIsoB),
+            _ => None,
+        }
+    }
+
+    /// Partition containing the `region`, determined from the region prefix
+    pub fn from_region(region: &str) -> AwsPartition {
+        if region.starts_with("cn-") {
+            AwsPartition::AwsCn
+        } else if region.starts_with("us-gov-") {
+            AwsPartition::AwsUsGov
+        } else if region.starts_with("us-iso-") {
+            AwsPartition::AwsIso
+        } else if region.starts_with("us-isob-") {
+            AwsPartition::AwsIsoB
+        } else {
+            AwsPartition::Aws
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AwsPartition::Aws => "aws",
+            AwsPartition::AwsCn => "aws-cn",
+            AwsPartition::AwsUsGov => "aws-us-gov",
+            AwsPartition::AwsIso => "aws-iso",
+            AwsPartition::AwsIsoB => "aws-iso-b",
+        }
+    }
+
+    /// Region used when no region is configured or resolved from the provider chain
+    pub fn default_region(&self) -> &'static str {
+        match self {
+            AwsPartition::Aws => "ap-southeast-2",
+            AwsPartition::AwsCn => "cn-north-1",
+            AwsPartition::AwsUsGov => "us-gov-west-1",
+            AwsPartition::AwsIso => "us-iso-east-1",
+            AwsPartition::AwsIsoB => "us-isob-east-1",
+        }
+    }
+}
+
+/// Service configuration providing the configured endpoints (i.e VPC interface
+/// endpoints) of each service, other values are loaded from the `inner` configuration
+#[derive(Debug)]
+struct ServiceEndpoints {
+    /// Endpoints keyed by the lowercase service name
+    endpoints: Vec<(String, Url)>,
+    inner: SdkConfig,
+}
+
+impl LoadServiceConfig for ServiceEndpoints {
+    fn load_config(&self, key: ServiceConfigKey<'_>) -> Option<String> {
+        if key.env() == SDK_ENDPOINT_URL_ENV {
+            // Service IDs are named by the SDK (i.e `Secrets Manager` for `secretsmanager`)
+            let service: String = key
+                .service_id()
+                .chars()
+                .filter(|char| !char.is_whitespace())
+                .flat_map(char::to_lowercase)
+                .collect();
+
+            if let Some((_, url)) = self.endpoints.iter().find(|(name, _)| *name == service) {
+                return Some(url.as_str().trim_end_matches('/').to_string());
+            }
+        }
+
+        self.inner.service_config()?.load_config(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AwsPartition;
+
+    #[test]
+    fn test_partition_from_region() {
+        for (region, partition) in [
+            ("us-east-1", AwsPartition::Aws),
+            ("eu-west-2", AwsPartition::Aws),
+            ("cn-north-1", AwsPartition::AwsCn),
+            ("cn-northwest-1", AwsPartition::AwsCn),
+            ("us-gov-west-1", AwsPartition::AwsUsGov),
+            ("us-iso-east-1", AwsPartition::AwsIso),
+            ("us-isob-east-1", AwsPartition::AwsIsoB),
+        ] {
+            assert_eq!(AwsPartition::from_region(region), partition, "{region}");
+        }
+    }
+}
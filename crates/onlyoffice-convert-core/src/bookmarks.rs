@@ -0,0 +1,556 @@
+use std::{collections::HashMap, fs::File, io::Read, path::Path};
+
+use lopdf::{Document, Object, ObjectId, dictionary, text_string};
+use zip::ZipArchive;
+
+use crate::{error::ConvertError, template::unescape_xml};
+
+/// Part of a DOCX containing the document body
+const DOCUMENT_PART: &str = "word/document.xml";
+
+/// Part of a DOCX containing the paragraph styles
+const STYLES_PART: &str = "word/styles.xml";
+
+/// Maximum size of a DOCX part that is read for headings
+const MAX_PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Maximum number of headings turned into bookmarks
+const MAX_BOOKMARKS: usize = 2048;
+
+/// Maximum length of a bookmark title in characters
+const MAX_TITLE_LENGTH: usize = 256;
+
+/// Number of outline levels supported by Word headings
+const OUTLINE_LEVELS: u8 = 9;
+
+/// Heading paragraph of the source document
+struct Heading {
+    /// Outline level of the heading, 0 for top level headings
+    level: u8,
+    title: String,
+}
+
+/// Add bookmarks (the document outline) for the headings of the DOCX at `source_path`
+/// to the PDF at `output_path` in place. Each bookmark links to the first page after
+/// the previous bookmark containing the heading text, headings that cannot be found
+/// link to the page of the previous bookmark. PDFs that already have an outline and
+/// sources that are not DOCX are left as is. The bookmarked file is written to
+/// `bookmarked_path` before replacing the output
+pub async fn add_heading_bookmarks(
+    output_path: &Path,
+    bookmarked_path: &Path,
+    source_path: &Path,
+) -> Result<(), ConvertError> {
+    let output_path = output_path.to_path_buf();
+    let bookmarked_path = bookmarked_path.to_path_buf();
+    let source_path = source_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || add_bookmarks(&output_path, &bookmarked_path, &source_path))
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "bookmark task failed");
+            bookmark_error()
+        })?
+}
+
+fn add_bookmarks(
+    output_path: &Path,
+    bookmarked_path: &Path,
+    source_path: &Path,
+) -> Result<(), ConvertError> {
+    let headings = match read_headings(source_path) {
+        Ok(Some(value)) => value,
+        Ok(None) => {
+            tracing::warn!("bookmarks are only generated for DOCX sources");
+            return Ok(());
+        }
+        Err(err) => {
+            tracing::error!(?err, "failed to read source headings");
+            return Err(bookmark_error());
+        }
+    };
+
+    if headings.is_empty() {
+        return Ok(());
+    }
+
+    bookmark_pdf(output_path, bookmarked_path, &headings).map_err(|err| {
+        tracing::error!(?err, "failed to add bookmarks to pdf");
+        bookmark_error()
+    })
+}
+
+fn bookmark_pdf(
+    output_path: &Path,
+    bookmarked_path: &Path,
+    headings: &[Heading],
+) -> lopdf::Result<()> {
+    let mut document = Document::load(output_path)?;
+
+    // Outlines created by x2t are kept
+    if document.catalog()?.has(b"Outlines") {
+        return Ok(());
+    }
+
+    add_outline(&mut document, headings)?;
+    document.save(bookmarked_path)?.sync_all()?;
+    std::fs::rename(bookmarked_path, output_path)?;
+    Ok(())
+}
+
+/// Read the headings of the DOCX at `source_path`, [None] when the source is not a DOCX
+fn read_headings(source_path: &Path) -> zip::result::ZipResult<Option<Vec<Heading>>> {
+    let Ok(mut archive) = ZipArchive::new(File::open(source_path)?) else {
+        return Ok(None);
+    };
+
+    let Some(document) = read_part(&mut archive, DOCUMENT_PART)? else {
+        return Ok(None);
+    };
+
+    let styles = read_part(&mut archive, STYLES_PART)?
+        .map(|styles| heading_styles(&styles))
+        .unwrap_or_default();
+
+    Ok(Some(find_headings(&document, &styles)))
+}
+
+/// Read a text part of the archive, [None] when the part does not exist
+fn read_part(archive: &mut ZipArchive<File>, name: &str) -> zip::result::ZipResult<Option<String>> {
+    let Some(index) = archive.index_for_name(name) else {
+        return Ok(None);
+    };
+
+    let mut value = String::new();
+    archive
+        .by_index(index)?
+        .take(MAX_PART_SIZE)
+        .read_to_string(&mut value)?;
+
+    Ok(Some(value))
+}
+
+/// Outline levels of the paragraph styles keyed by style ID, styles are headings
+/// when named "heading N" or given an outline level
+fn heading_styles(xml: &str) -> HashMap<String, u8> {
+    let mut styles = HashMap::new();
+
+    for style in elements(xml, "<w:style", "</w:style>") {
+        let Some(style_id) = attribute(style, "<w:style", "w:styleId") else {
+            continue;
+        };
+
+        let level = attribute(style, "<w:outlineLvl", "w:val")
+            .and_then(|value| value.parse::<u8>().ok())
+            .or_else(|| {
+                let name = attribute(style, "<w:name", "w:val")?.to_ascii_lowercase();
+                let level = name.strip_prefix("heading ")?.parse::<u8>().ok()?;
+                level.checked_sub(1)
+            });
+
+        if let Some(level) = level.filter(|level| *level < OUTLINE_LEVELS) {
+            styles.insert(style_id.to_string(), level);
+        }
+    }
+
+    styles
+}
+
+/// Find the heading paragraphs of the document body in order
+fn find_headings(xml: &str, styles: &HashMap<String, u8>) -> Vec<Heading> {
+    let mut headings = Vec::new();
+
+    for paragraph in elements(xml, "<w:p", "</w:p>") {
+        // Paragraph outline levels take precedence over the style
+        let level = attribute(paragraph, "<w:outlineLvl", "w:val")
+            .and_then(|value| value.parse::<u8>().ok())
+            .or_else(|| {
+                let style_id = attribute(paragraph, "<w:pStyle", "w:val")?;
+                styles.get(style_id).copied()
+            });
+
+        let Some(level) = level.filter(|level| *level < OUTLINE_LEVELS) else {
+            continue;
+        };
+
+        let title: String = paragraph_text(paragraph)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(MAX_TITLE_LENGTH)
+            .collect();
+
+        if title.is_empty() {
+            continue;
+        }
+
+        headings.push(Heading { level, title });
+
+        if headings.len() >= MAX_BOOKMARKS {
+            break;
+        }
+    }
+
+    headings
+}
+
+/// Find the elements opened by `open` (i.e `<w:p`) and closed by `close`, elements
+/// sharing the prefix of the open tag (i.e `<w:pPr>`) are skipped
+fn elements<'a>(xml: &'a str, open: &str, close: &str) -> Vec<&'a str> {
+    let mut elements = Vec::new();
+    let mut position = 0;
+
+    while let Some(offset) = xml[position..].find(open) {
+        let start = position + offset;
+        position = start + open.len();
+
+        if !matches!(xml.as_bytes().get(position), Some(b'>' | b' ')) {
+            continue;
+        }
+
+        let Some(end) = xml[position..].find(close).map(|index| position + index) else {
+            break;
+        };
+
+        elements.push(&xml[start..end]);
+        position = end + close.len();
+    }
+
+    elements
+}
+
+/// Get the value of an `attribute` of the first element opened by `open`
+fn attribute<'a>(xml: &'a str, open: &str, attribute: &str) -> Option<&'a str> {
+    let start = xml.find(open)? + open.len();
+    let tag = &xml[start..start + xml[start..].find('>')?];
+
+    let pattern = format!(" {attribute}=\"");
+    let value_start = tag.find(&pattern)? + pattern.len();
+    let value_end = value_start + tag[value_start..].find('"')?;
+
+    Some(&tag[value_start..value_end])
+}
+
+/// Text of the `<w:t>` elements within a paragraph
+fn paragraph_text(xml: &str) -> String {
+    elements(xml, "<w:t", "</w:t>")
+        .into_iter()
+        .filter_map(|element| {
+            let content_start = element.find('>')? + 1;
+            Some(unescape_xml(&element[content_start..]))
+        })
+        .collect()
+}
+
+/// Normalize text for matching headings against the page text, whitespace is
+/// removed as the extracted text does not reliably preserve it
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|char| !char.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Add the document outline for the `headings`, opening the outline panel
+/// when the document is viewed
+fn add_outline(document: &mut Document, headings: &[Heading]) -> lopdf::Result<()> {
+    let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+    let Some(&first_page) = pages.first() else {
+        return Ok(());
+    };
+
+    // Text of each page is only extracted once it is searched
+    let mut page_texts: Vec<Option<String>> = vec![None; pages.len()];
+    let mut page_index = 0;
+    let mut targets = Vec::with_capacity(headings.len());
+
+    for heading in headings {
+        let title = normalize(&heading.title);
+
+        let found = (page_index..pages.len()).find(|&index| {
+            let text = page_texts[index].get_or_insert_with(|| {
+                let text = document
+                    .extract_text(&[index as u32 + 1])
+                    .unwrap_or_default();
+                normalize(&text)
+            });
+
+            text.contains(&title)
+        });
+
+        if let Some(index) = found {
+            page_index = index;
+        }
+
+        targets.push(pages.get(page_index).copied().unwrap_or(first_page));
+    }
+
+    // Nest each heading under the closest previous heading of a higher level
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); headings.len()];
+    let mut roots = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+
+    for (index, heading) in headings.iter().enumerate() {
+        while stack
+            .last()
+            .is_some_and(|&parent| headings[parent].level >= heading.level)
+        {
+            stack.pop();
+        }
+
+        match stack.last() {
+            Some(&parent) => children[parent].push(index),
+            None => roots.push(index),
+        }
+
+        stack.push(index);
+    }
+
+    let ids: Vec<ObjectId> = headings.iter().map(|_| document.new_object_id()).collect();
+    let outlines_id = document.new_object_id();
+
+    // Items are written closed so only the top level headings are initially visible
+    let mut pending = vec![(outlines_id, roots.clone())];
+
+    while let Some((parent_id, items)) = pending.pop() {
+        for (position, &index) in items.iter().enumerate() {
+            let mut item = dictionary! {
+                "Title" => text_string(&headings[index].title),
+                "Parent" => parent_id,
+                "Dest" => vec![targets[index].into(), "Fit".into()],
+            };
+
+            if position > 0 {
+                item.set("Prev", ids[items[position - 1]]);
+            }
+
+            if let Some(&next) = items.get(position + 1) {
+                item.set("Next", ids[next]);
+            }
+
+            let item_children = &children[index];
+            if let (Some(&first), Some(&last)) = (item_children.first(), item_children.last()) {
+                item.set("First", ids[first]);
+                item.set("Last", ids[last]);
+                item.set("Count", -(item_children.len() as i64));
+                pending.push((ids[index], item_children.clone()));
+            }
+
+            document.set_object(ids[index], item);
+        }
+    }
+
+    let (Some(&first), Some(&last)) = (roots.first(), roots.last()) else {
+        return Ok(());
+    };
+
+    document.set_object(
+        outlines_id,
+        dictionary! {
+            "Type" => "Outlines",
+            "First" => ids[first],
+            "Last" => ids[last],
+            "Count" => roots.len() as i64,
+        },
+    );
+
+    let catalog = document.catalog_mut()?;
+    catalog.set("Outlines", outlines_id);
+    catalog.set("PageMode", Object::Name(b"UseOutlines".to_vec()));
+
+    Ok(())
+}
+
+fn bookmark_error() -> ConvertError {
+    ConvertError {
+        reason: Some("BOOKMARK_OUTPUT"),
+        x2t_code: None,
+        message: "failed to add bookmarks to pdf output".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+
+    use super::{Heading, add_outline, find_headings, heading_styles};
+
+    #[test]
+    fn test_heading_styles() {
+        let xml = concat!(
+            r#"<w:styles>"#,
+            r#"<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/></w:style>"#,
+            r#"<w:style w:type="paragraph" w:styleId="Custom"><w:name w:val="Custom"/><w:pPr><w:outlineLvl w:val="2"/></w:pPr></w:style>"#,
+            r#"<w:style w:type="paragraph" w:styleId="Normal"><w:name w:val="Normal"/></w:style>"#,
+            // Body text outline level
+            r#"<w:style w:type="paragraph" w:styleId="Body"><w:name w:val="Body"/><w:pPr><w:outlineLvl w:val="9"/></w:pPr></w:style>"#,
+            r#"</w:styles>"#
+        );
+
+        assert_eq!(
+            heading_styles(xml),
+            HashMap::from([("Heading1".to_string(), 0), ("Custom".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn test_find_headings() {
+        let styles = HashMap::from([("Heading1".to_string(), 0), ("Heading2".to_string(), 1)]);
+        let xml = concat!(
+            r#"<w:body>"#,
+            r#"<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Intro</w:t></w:r><w:r><w:t xml:space="preserve">duction  &amp; Scope</w:t></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t>Body text</w:t></w:r></w:p>"#,
+            // Paragraph outline levels take precedence over the style
+            r#"<w:p><w:pPr><w:pStyle w:val="Heading1"/><w:outlineLvl w:val="3"/></w:pPr><w:r><w:t>Detail</w:t></w:r></w:p>"#,
+            // Empty headings are skipped
+            r#"<w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr></w:p>"#,
+            r#"<w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Results</w:t></w:r></w:p>"#,
+            r#"</w:body>"#
+        );
+
+        let headings: Vec<(u8, String)> = find_headings(xml, &styles)
+            .into_iter()
+            .map(|heading| (heading.level, heading.title))
+            .collect();
+
+        assert_eq!(
+            headings,
+            [
+                (0, "Introduction & Scope".to_string()),
+                (3, "Detail".to_string()),
+                (1, "Results".to_string()),
+            ]
+        );
+    }
+
+    /// Create a document with a page for each of the `texts`, returns the document
+    /// and the IDs of its pages
+    fn document(texts: &[&str]) -> (Document, Vec<ObjectId>) {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+
+        let page_ids: Vec<ObjectId> = texts
+            .iter()
+            .map(|text| {
+                let content = format!("BT /F1 12 Tf 72 720 Td ({text}) Tj ET");
+                let content_id =
+                    document.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+
+                document.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                    "Contents" => content_id,
+                    "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                })
+            })
+            .collect();
+
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => page_ids.iter().map(|id| Object::from(*id)).collect::<Vec<_>>(),
+                "Count" => page_ids.len() as i64,
+            }),
+        );
+
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+
+        (document, page_ids)
+    }
+
+    fn heading(level: u8, title: &str) -> Heading {
+        Heading {
+            level,
+            title: title.to_string(),
+        }
+    }
+
+    /// Get the outline item referenced by the `key` of the `item`
+    fn item<'a>(document: &'a Document, item: &Dictionary, key: &[u8]) -> &'a Dictionary {
+        let id = item.get(key).and_then(Object::as_reference).unwrap();
+        document.get_dictionary(id).unwrap()
+    }
+
+    fn title(item: &Dictionary) -> String {
+        let title = item.get(b"Title").and_then(Object::as_str).unwrap();
+        String::from_utf8_lossy(title).into_owned()
+    }
+
+    fn target(item: &Dictionary) -> ObjectId {
+        let dest = item.get(b"Dest").and_then(Object::as_array).unwrap();
+        dest[0].as_reference().unwrap()
+    }
+
+    #[test]
+    fn test_add_outline() {
+        let (mut document, pages) = document(&["Introduction Background", "Scope", "Results"]);
+        let headings = [
+            heading(0, "Introduction"),
+            heading(1, "Background"),
+            heading(1, "Scope"),
+            // Headings that are not found link to the page of the previous heading
+            heading(2, "Missing"),
+            heading(0, "Results"),
+        ];
+
+        add_outline(&mut document, &headings).unwrap();
+
+        let catalog = document.catalog().unwrap();
+        assert_eq!(
+            catalog.get(b"PageMode").and_then(Object::as_name).unwrap(),
+            b"UseOutlines"
+        );
+
+        let outlines = item(&document, catalog, b"Outlines");
+        assert_eq!(outlines.get(b"Count").and_then(Object::as_i64).unwrap(), 2);
+
+        let introduction = item(&document, outlines, b"First");
+        assert_eq!(title(introduction), "Introduction");
+        assert_eq!(target(introduction), pages[0]);
+        assert_eq!(
+            introduction.get(b"Count").and_then(Object::as_i64).unwrap(),
+            -2
+        );
+
+        let background = item(&document, introduction, b"First");
+        assert_eq!(title(background), "Background");
+        assert_eq!(target(background), pages[0]);
+
+        let scope = item(&document, background, b"Next");
+        assert_eq!(title(scope), "Scope");
+        assert_eq!(target(scope), pages[1]);
+        assert_eq!(
+            introduction.get(b"Last").unwrap(),
+            background.get(b"Next").unwrap()
+        );
+
+        let missing = item(&document, scope, b"First");
+        assert_eq!(title(missing), "Missing");
+        assert_eq!(target(missing), pages[1]);
+
+        let results = item(&document, introduction, b"Next");
+        assert_eq!(title(results), "Results");
+        assert_eq!(target(results), pages[2]);
+        assert_eq!(
+            outlines.get(b"Last").unwrap(),
+            introduction.get(b"Next").unwrap()
+        );
+        assert!(results.get(b"Next").is_err());
+    }
+}
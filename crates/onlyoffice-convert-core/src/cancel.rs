@@ -0,0 +1,47 @@
+use tokio::sync::watch;
+
+use crate::error::ConvertError;
+
+/// Create a linked [CancelHandle] and [CancelSignal], the signal is provided to the
+/// conversion through the convert options and the handle is kept by the caller
+pub fn cancel_signal() -> (CancelHandle, CancelSignal) {
+    let (sender, receiver) = watch::channel(false);
+    (CancelHandle(sender), CancelSignal(receiver))
+}
+
+/// Cancels the conversions using the linked [CancelSignal]
+pub struct CancelHandle(watch::Sender<bool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// Signal a conversion checks to stop early, a running x2t process is killed and
+/// the outputs are not uploaded once cancelled
+#[derive(Clone)]
+pub struct CancelSignal(watch::Receiver<bool>);
+
+impl CancelSignal {
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Wait until cancelled, never completes when the handle is dropped
+    /// without cancelling
+    pub async fn cancelled(&self) {
+        let mut receiver = self.0.clone();
+        if receiver.wait_for(|cancelled| *cancelled).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+pub fn cancelled_error() -> ConvertError {
+    ConvertError {
+        reason: Some("CANCELLED"),
+        x2t_code: None,
+        message: "conversion was cancelled".to_string(),
+    }
+}
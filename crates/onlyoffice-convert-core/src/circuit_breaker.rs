@@ -0,0 +1,184 @@
+#[cfg(feature = "dynamodb")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "dynamodb")]
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "dynamodb")]
+use tokio::sync::OnceCell;
+
+#[cfg(feature = "dynamodb")]
+use crate::{aws::aws_config, config::app_config};
+use crate::{config::config_var, error::ConvertError, spreadsheet::SpreadsheetLimits};
+
+/// Environment variable for the DynamoDB table failed conversions are tracked within,
+/// the circuit breaker is only used when this is set. The table must have a string
+/// `source_etag` partition key (See [failure_key]), with `expires_at` as its time to
+/// live attribute
+pub const FAILURE_TABLE_ENV: &str = "FAILURE_TABLE";
+
+/// Client for the failure table, cached across warm invocations
+#[cfg(feature = "dynamodb")]
+static DYNAMODB_CLIENT: OnceCell<aws_sdk_dynamodb::Client> = OnceCell::const_new();
+
+/// Whether failed conversions are tracked
+pub fn is_circuit_breaker_enabled() -> bool {
+    cfg!(feature = "dynamodb") && config_var(FAILURE_TABLE_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+#[cfg(feature = "dynamodb")]
+async fn dynamodb_client() -> &'static aws_sdk_dynamodb::Client {
+    DYNAMODB_CLIENT
+        .get_or_init(|| async { aws_sdk_dynamodb::Client::new(&aws_config().await) })
+        .await
+}
+
+/// Whether the error was caused by the source document rather than the environment
+/// or the options of the request, these x2t failures for the same source will continue
+/// to fail when retried. Failures fixed by configuration (i.e `X2T_ICU_DATA`) or by
+/// request options (i.e `SOURCE_LIMITS_EXCEEDED` and `FILE_LIKELY_ENCRYPTED`) are not counted
+pub fn is_document_failure(error: &ConvertError) -> bool {
+    match error.reason {
+        Some("FILE_LIKELY_CORRUPTED" | "FILE_INVALID_ARCHIVE" | "X2T_CRASHED") => true,
+        // Unrecognized x2t failures
+        None => error.x2t_code.is_some(),
+        _ => false,
+    }
+}
+
+/// Options of a request that change whether x2t can convert the source, a request
+/// with different options is not rejected by the failures of another
+#[derive(Default, Serialize)]
+pub struct FailureOptions<'a> {
+    pub password_secret_arn: Option<&'a str>,
+    pub password_key: Option<&'a str>,
+    pub spreadsheet_limits: Option<&'a SpreadsheetLimits>,
+}
+
+/// Key the failures of converting the source with the `source_etag` using the
+/// `options` are tracked under, the entity tag alone when no options are set
+pub fn failure_key(source_etag: &str, options: &FailureOptions<'_>) -> String {
+    if options.password_secret_arn.is_none() && options.spreadsheet_limits.is_none() {
+        return source_etag.to_string();
+    }
+
+    let Ok(options) = serde_json::to_vec(options) else {
+        return source_etag.to_string();
+    };
+
+    let hash = Sha256::digest(&options);
+    format!("{source_etag}:{hash:x}")
+}
+
+/// Check the source with the `failure_key` (See [failure_key]) has not failed to convert
+/// too many times, fails with `PERMANENT_FAILURE` once the threshold is reached. Failures
+/// to read the table are logged and the conversion is allowed
+#[cfg(feature = "dynamodb")]
+pub async fn check_source(failure_key: &str) -> Result<(), ConvertError> {
+    let Ok(table) = config_var(FAILURE_TABLE_ENV) else {
+        return Ok(());
+    };
+
+    let response = match dynamodb_client()
+        .await
+        .get_item()
+        .table_name(table)
+        .key("source_etag", AttributeValue::S(failure_key.to_string()))
+        .send()
+        .await
+    {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::warn!(?err, "failed to read source failures");
+            return Ok(());
+        }
+    };
+
+    let Some(item) = response.item else {
+        return Ok(());
+    };
+
+    // Expired items are kept until DynamoDB removes them
+    let expired = item
+        .get("expires_at")
+        .and_then(|value| value.as_n().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .is_some_and(|expires_at| expires_at <= now_seconds());
+
+    let failures = item
+        .get("failures")
+        .and_then(|value| value.as_n().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or_default();
+
+    if expired || failures < app_config().failure_threshold {
+        return Ok(());
+    }
+
+    tracing::warn!(%failure_key, failures, "rejecting source that repeatedly failed to convert");
+
+    Err(ConvertError {
+        reason: Some("PERMANENT_FAILURE"),
+        x2t_code: None,
+        message: "source has repeatedly failed to convert".to_string(),
+    })
+}
+
+#[cfg(not(feature = "dynamodb"))]
+pub async fn check_source(_failure_key: &str) -> Result<(), ConvertError> {
+    Ok(())
+}
+
+/// Record a failed conversion of the source with the `failure_key`, failing to
+/// record the failure is logged but otherwise ignored
+#[cfg(feature = "dynamodb")]
+pub async fn record_failure(failure_key: &str, error: &ConvertError) {
+    let Ok(table) = config_var(FAILURE_TABLE_ENV) else {
+        return;
+    };
+
+    let expires_at = now_seconds() + app_config().failure_ttl.as_secs();
+
+    let result = dynamodb_client()
+        .await
+        .update_item()
+        .table_name(table)
+        .key("source_etag", AttributeValue::S(failure_key.to_string()))
+        .update_expression(
+            "ADD failures :one SET expires_at = :expires_at, last_reason = :reason, last_x2t_code = :x2t_code",
+        )
+        .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+        .expression_attribute_values(":expires_at", AttributeValue::N(expires_at.to_string()))
+        .expression_attribute_values(
+            ":reason",
+            match error.reason {
+                Some(reason) => AttributeValue::S(reason.to_string()),
+                None => AttributeValue::Null(true),
+            },
+        )
+        .expression_attribute_values(
+            ":x2t_code",
+            match error.x2t_code {
+                Some(code) => AttributeValue::N(code.to_string()),
+                None => AttributeValue::Null(true),
+            },
+        )
+        .send()
+        .await;
+
+    if let Err(err) = result {
+        tracing::error!(?err, "failed to record source failure");
+    }
+}
+
+#[cfg(not(feature = "dynamodb"))]
+pub async fn record_failure(_failure_key: &str, _error: &ConvertError) {}
+
+#[cfg(feature = "dynamodb")]
+fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,71 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use serde::Deserialize;
+
+use crate::error::ConvertError;
+
+/// Compression applied to the output file before it is uploaded
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputCompression {
+    Gzip,
+    Zstd,
+}
+
+impl OutputCompression {
+    /// Value for the Content-Encoding of the compressed output
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            OutputCompression::Gzip => "gzip",
+            OutputCompression::Zstd => "zstd",
+        }
+    }
+}
+
+/// Compress the file at `input_path` writing the compressed output to `output_path`
+pub async fn compress_file(
+    compression: OutputCompression,
+    input_path: &Path,
+    output_path: &Path,
+) -> Result<(), ConvertError> {
+    let input_path = input_path.to_path_buf();
+    let output_path = output_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+        let mut input = BufReader::new(File::open(input_path)?);
+        let output = File::create(output_path)?;
+
+        match compression {
+            OutputCompression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                std::io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.sync_all()?;
+            }
+            OutputCompression::Zstd => {
+                let mut encoder = zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+                std::io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.sync_all()?;
+            }
+        }
+
+        Ok(())
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "compression task failed");
+        compress_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to compress output");
+        compress_error()
+    })
+}
+
+fn compress_error() -> ConvertError {
+    ConvertError {
+        reason: Some("COMPRESS_OUTPUT"),
+        x2t_code: None,
+        message: "failed to compress output".to_string(),
+    }
+}
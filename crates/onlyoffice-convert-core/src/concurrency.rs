@@ -0,0 +1,57 @@
+use std::{num::NonZeroUsize, sync::OnceLock, thread::available_parallelism};
+
+use tokio::{
+    process::Command,
+    sync::{AcquireError, Semaphore, SemaphorePermit},
+};
+
+use crate::config::app_config;
+
+static X2T_SEMAPHORE: OnceLock<Semaphore> = OnceLock::new();
+
+/// Determine how many x2t processes can run at once, defaults to the number of
+/// vCPUs available to the Lambda (Which scales with the configured memory)
+pub fn x2t_concurrency() -> usize {
+    if let Some(value) = app_config().x2t_concurrency {
+        return value.get();
+    }
+
+    available_parallelism().map(NonZeroUsize::get).unwrap_or(1)
+}
+
+/// Acquire a permit to run an x2t process, waits until one of the
+/// concurrent x2t slots is available
+pub async fn acquire_x2t_permit() -> Result<SemaphorePermit<'static>, AcquireError> {
+    X2T_SEMAPHORE
+        .get_or_init(|| {
+            let concurrency = x2t_concurrency();
+            tracing::debug!(concurrency, "created x2t semaphore");
+            Semaphore::new(concurrency)
+        })
+        .acquire()
+        .await
+}
+
+/// Apply the configured scheduling priority and thread limits to an x2t
+/// command so a conversion doesn't starve other concurrent jobs
+pub fn apply_x2t_process_limits(command: &mut Command) {
+    let config = app_config();
+
+    if let Some(threads) = config.x2t_threads {
+        command.env("OMP_NUM_THREADS", threads.to_string());
+    }
+
+    #[cfg(unix)]
+    if let Some(nice) = config.x2t_nice {
+        // Safety: setpriority is async-signal-safe and only affects the child process
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+    }
+}
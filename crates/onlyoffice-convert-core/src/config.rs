@@ -0,0 +1,1081 @@
+use std::{
+    collections::HashMap,
+    env::{VarError, temp_dir},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, OnceLock, RwLock},
+    time::Duration,
+};
+
+use aws_config::retry::RetryMode;
+use url::Url;
+
+use crate::{
+    aws::AwsPartition,
+    circuit_breaker::FAILURE_TABLE_ENV,
+    config_check::{configuration_error, feature_disabled_error},
+    error::ConvertError,
+    quota::QUOTA_TABLE_ENV,
+    retry::RetryClass,
+    signing::SIGNING_KEY_ID_ENV,
+    tenant::TENANTS_TABLE_ENV,
+    timestamp::TSA_URL_ENV,
+    url_source::ALLOWED_HOSTS_ENV,
+    usage::USAGE_DELIVERY_STREAM_ENV,
+};
+
+/// Environment variable for the directory containing the x2t binary
+const X2T_PATH_ENV: &str = "X2T_PATH";
+
+/// Environment variable for the x2t installations that requests can select between, a
+/// comma separated list of `{version}={path}` pairs (i.e `7=/opt/x2t-7/bin,8=/opt/x2t-8/bin`)
+const X2T_VERSIONS_ENV: &str = "X2T_VERSIONS";
+
+/// Environment variable for the version from [X2T_VERSIONS_ENV] used by requests that
+/// do not select a version, the installed x2t is used when not set
+const X2T_DEFAULT_VERSION_ENV: &str = "X2T_DEFAULT_VERSION";
+
+/// Environment variable for the directory containing the fonts bundled with x2t
+const X2T_FONTS_PATH_ENV: &str = "X2T_FONTS_PATH";
+
+/// Environment variable for the directory containing the themes bundled with x2t
+const X2T_THEMES_PATH_ENV: &str = "X2T_THEMES_PATH";
+
+/// Environment variable for the directory containing the ICU data (`icudt*.dat`) x2t
+/// loads, passed to x2t as `ICU_DATA`. Relative paths are resolved against the x2t directory
+const X2T_ICU_DATA_PATH_ENV: &str = "X2T_ICU_DATA_PATH";
+
+/// Environment variable enabling the FIPS endpoints of every AWS service client
+/// (i.e for GovCloud deployments)
+const USE_FIPS_ENDPOINTS_ENV: &str = "USE_FIPS_ENDPOINTS";
+
+/// Environment variable for the endpoints AWS service clients send requests to in place
+/// of the public endpoints, a comma separated list of `{service}={url}` pairs (i.e
+/// `s3=https://bucket.vpce-0123-abcd.s3.us-east-1.vpce.amazonaws.com`) for VPC interface
+/// endpoints. Services are named by their SDK (`s3`, `kms`, `dynamodb`, `ssm`,
+/// `secretsmanager`, `firehose`, `sqs`, `lambda`)
+const AWS_ENDPOINT_URLS_ENV: &str = "AWS_ENDPOINT_URLS";
+
+/// Environment variable for the retry mode of AWS service clients (`standard` or
+/// `adaptive`, which also rate limits requests once throttled)
+const AWS_RETRY_MODE_ENV: &str = "AWS_RETRY_MODE";
+
+/// Environment variable for the maximum number of attempts of each AWS request made
+/// by the SDK, including the first attempt. Does not apply to the S3 requests of
+/// conversions, which are only retried by the retry policy (See `RETRY_MAX_ATTEMPTS`)
+const AWS_MAX_ATTEMPTS_ENV: &str = "AWS_MAX_ATTEMPTS";
+
+/// Environment variable for the time in milliseconds AWS service clients wait to
+/// establish a connection
+const AWS_CONNECT_TIMEOUT_MS_ENV: &str = "AWS_CONNECT_TIMEOUT_MS";
+
+/// Environment variable for the time in milliseconds AWS service clients wait for the
+/// first byte of a response
+const AWS_READ_TIMEOUT_MS_ENV: &str = "AWS_READ_TIMEOUT_MS";
+
+/// Environment variable for the time in milliseconds an AWS operation may take
+/// including all of its attempts
+const AWS_OPERATION_TIMEOUT_MS_ENV: &str = "AWS_OPERATION_TIMEOUT_MS";
+
+/// Environment variable for the time in milliseconds a single attempt of an AWS
+/// operation may take
+const AWS_OPERATION_ATTEMPT_TIMEOUT_MS_ENV: &str = "AWS_OPERATION_ATTEMPT_TIMEOUT_MS";
+
+/// Environment variable for the maximum rate in bytes per second that S3 objects are
+/// downloaded at, shared by all conversions of the process
+const S3_DOWNLOAD_RATE_LIMIT_ENV: &str = "S3_DOWNLOAD_RATE_LIMIT";
+
+/// Environment variable for the maximum rate in bytes per second that S3 objects are
+/// uploaded at, shared by all conversions of the process
+const S3_UPLOAD_RATE_LIMIT_ENV: &str = "S3_UPLOAD_RATE_LIMIT";
+
+/// Environment variable for the size in bytes from which files are uploaded as a
+/// multipart upload rather than a single request
+const S3_MULTIPART_THRESHOLD_ENV: &str = "S3_MULTIPART_THRESHOLD";
+
+/// Environment variable for the maximum number of parts of a multipart upload that
+/// are uploaded concurrently
+const S3_MULTIPART_MAX_CONCURRENCY_ENV: &str = "S3_MULTIPART_MAX_CONCURRENCY";
+
+/// Environment variable for the maximum number of attempts of a retried operation,
+/// including the first attempt
+const RETRY_MAX_ATTEMPTS_ENV: &str = "RETRY_MAX_ATTEMPTS";
+
+/// Environment variable for the delay in milliseconds before the first retry,
+/// doubled for each retry
+const RETRY_BASE_DELAY_MS_ENV: &str = "RETRY_BASE_DELAY_MS";
+
+/// Environment variable for the maximum delay in milliseconds between retries
+const RETRY_MAX_DELAY_MS_ENV: &str = "RETRY_MAX_DELAY_MS";
+
+/// Environment variable for the comma separated error classes that are retried
+/// (i.e `throttled,transient`)
+const RETRY_ON_ENV: &str = "RETRY_ON";
+
+/// Environment variable for the number of times requests throttled by S3 are
+/// retried, used for the maximum attempts when [RETRY_MAX_ATTEMPTS_ENV] is not set
+const S3_THROTTLE_RETRIES_ENV: &str = "S3_THROTTLE_RETRIES";
+
+/// Environment variable for a memory backed directory (i.e /dev/shm or a tmpfs mount)
+/// used for the files of small conversions, memory temp files are disabled when unset
+const MEMORY_TEMP_DIR_ENV: &str = "MEMORY_TEMP_DIR";
+
+/// Environment variable for the maximum source size in bytes that will use the
+/// memory backed temp directory
+const MEMORY_TEMP_MAX_SIZE_ENV: &str = "MEMORY_TEMP_MAX_SIZE";
+
+/// Environment variable for the AWS region, the region is otherwise resolved
+/// from the default AWS provider chain
+const AWS_REGION_ENV: &str = "AWS_REGION";
+
+/// Environment variable for the AWS partition (`aws`, `aws-cn`, `aws-us-gov`, `aws-iso`,
+/// `aws-iso-b`) the service is deployed within, used to choose the fallback region
+/// when no region is resolved and to reject regions of other partitions
+const AWS_PARTITION_ENV: &str = "AWS_PARTITION";
+
+/// Environment variable to override the number of x2t processes that can run concurrently
+const X2T_CONCURRENCY_ENV: &str = "X2T_CONCURRENCY";
+
+/// Environment variable for the number of threads x2t is allowed to use
+const X2T_THREADS_ENV: &str = "X2T_THREADS";
+
+/// Environment variable for the niceness increment applied to x2t processes (0-19)
+const X2T_NICE_ENV: &str = "X2T_NICE";
+
+/// Environment variable for the maximum size in bytes of outputs returned inline
+const INLINE_OUTPUT_MAX_SIZE_ENV: &str = "INLINE_OUTPUT_MAX_SIZE";
+
+/// Environment variable for the multiplier applied to the source size to estimate the
+/// disk space used by a conversion (Source file, output file and x2t temporary files)
+const DISK_USAGE_MULTIPLIER_ENV: &str = "DISK_USAGE_MULTIPLIER";
+
+/// Environment variable for the number of bytes of disk space that are always kept free
+const DISK_HEADROOM_ENV: &str = "DISK_HEADROOM";
+
+/// Environment variable for the maximum number of redirects URL sources will follow
+const URL_SOURCE_MAX_REDIRECTS_ENV: &str = "URL_SOURCE_MAX_REDIRECTS";
+
+/// Environment variable for the maximum size in bytes of a URL source
+const URL_SOURCE_MAX_SIZE_ENV: &str = "URL_SOURCE_MAX_SIZE";
+
+/// Environment variable for the maximum size in bytes of a core dump, larger
+/// dumps are truncated by the kernel and are not uploaded
+const CORE_DUMP_MAX_SIZE_ENV: &str = "CORE_DUMP_MAX_SIZE";
+
+/// Environment variable for the number of failed conversions of the same source
+/// before further conversions are rejected by the circuit breaker
+const FAILURE_THRESHOLD_ENV: &str = "FAILURE_THRESHOLD";
+
+/// Environment variable for the number of seconds failures are tracked for
+const FAILURE_TTL_ENV: &str = "FAILURE_TTL_SECONDS";
+
+/// Environment variable for the number of requests converted by each invocation
+/// of a fanned out batch, larger batches are split into chunks of this size
+const BATCH_CHUNK_SIZE_ENV: &str = "BATCH_CHUNK_SIZE";
+
+/// Environment variable for the number of seconds the presigned output URLs of a
+/// completed job are valid for
+const JOB_OUTPUT_URL_EXPIRY_ENV: &str = "JOB_OUTPUT_URL_EXPIRY_SECONDS";
+
+/// Environment variable for the number of seconds a chunk of a running job may go
+/// without reporting progress before the job is expired
+const JOB_STALE_TIMEOUT_ENV: &str = "JOB_STALE_TIMEOUT_SECONDS";
+
+/// Environment variable for the number of seconds a chunk of a running job may stay
+/// queued without starting before the job is expired
+const JOB_START_TIMEOUT_ENV: &str = "JOB_START_TIMEOUT_SECONDS";
+
+/// Environment variable for the maximum size of HTTP request bodies in bytes,
+/// larger requests are rejected with `REQUEST_TOO_LARGE`
+const MAX_REQUEST_BODY_SIZE_ENV: &str = "MAX_REQUEST_BODY_SIZE";
+
+/// Environment variable for the maximum time in seconds an invocation is handled
+/// for, invocations are always limited to the Lambda deadline
+const REQUEST_TIMEOUT_SECONDS_ENV: &str = "REQUEST_TIMEOUT_SECONDS";
+
+/// Environment variable for the fuel (roughly the number of instructions) a WASM
+/// plugin may use transforming a single output
+const WASM_PLUGIN_FUEL_ENV: &str = "WASM_PLUGIN_FUEL";
+
+/// Environment variable for the maximum memory of a WASM plugin in MB
+const WASM_PLUGIN_MAX_MEMORY_MB_ENV: &str = "WASM_PLUGIN_MAX_MEMORY_MB";
+
+/// Environment variable enabling debug mode for every conversion, the temporary
+/// files are kept on disk rather than deleted and uploaded as debug artifacts
+const DEBUG_KEEP_TEMP_ENV: &str = "DEBUG_KEEP_TEMP";
+
+/// Environment variable enabling core dumps for x2t, the dumps of x2t processes
+/// killed by a signal are uploaded to the debug artifacts location. Requires the
+/// debug artifacts bucket and a `core_pattern` that writes to the working directory
+const X2T_CORE_DUMPS_ENV: &str = "X2T_CORE_DUMPS";
+
+/// Environment variable enabling the diagnostics route, reporting the x2t install,
+/// shared libraries, fonts, themes and temporary disk space of the environment
+const DIAGNOSTICS_ENABLED_ENV: &str = "DIAGNOSTICS_ENABLED";
+
+/// Environment variables for the proxy outbound HTTP requests (URL sources, tenant
+/// callbacks and timestamps) are sent through, the lowercase variables are used when
+/// the uppercase variables are not set
+const HTTP_PROXY_ENV: &str = "HTTP_PROXY";
+const HTTP_PROXY_LOWERCASE_ENV: &str = "http_proxy";
+const HTTPS_PROXY_ENV: &str = "HTTPS_PROXY";
+const HTTPS_PROXY_LOWERCASE_ENV: &str = "https_proxy";
+
+/// Environment variable for the comma separated hosts requests are sent to directly
+/// rather than through the proxy. Entries can be a host, a domain (with or without a
+/// leading `.`) matching its subdomains, an IP address or `*` for every host
+const NO_PROXY_ENV: &str = "NO_PROXY";
+const NO_PROXY_LOWERCASE_ENV: &str = "no_proxy";
+
+/// Environment variable for the SSM Parameter Store path configuration is loaded
+/// from (i.e `/onlyoffice-convert/prod/`). Each parameter directly under the path
+/// overrides the environment variable of the same name, configuration is only
+/// loaded from SSM when this is set
+pub const CONFIG_SSM_PATH_ENV: &str = "CONFIG_SSM_PATH";
+
+const DEFAULT_X2T_FONTS_PATH: &str = "/var/www/onlyoffice/documentserver/fonts";
+const DEFAULT_X2T_THEMES_PATH: &str = "/var/www/onlyoffice/documentserver/sdkjs/slide/themes";
+const DEFAULT_MEMORY_TEMP_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Default maximum size of output files that can be returned inline, responses are limited
+/// to 6MB and base64 encoding inflates the size by 4/3
+const DEFAULT_INLINE_OUTPUT_MAX_SIZE: u64 = 4 * 1024 * 1024;
+
+const DEFAULT_DISK_USAGE_MULTIPLIER: u64 = 3;
+const DEFAULT_DISK_HEADROOM: u64 = 32 * 1024 * 1024;
+const DEFAULT_URL_SOURCE_MAX_REDIRECTS: usize = 3;
+const DEFAULT_URL_SOURCE_MAX_SIZE: u64 = 100 * 1024 * 1024;
+const DEFAULT_CORE_DUMP_MAX_SIZE: u64 = 256 * 1024 * 1024;
+const DEFAULT_S3_MULTIPART_THRESHOLD: u64 = 64 * 1024 * 1024;
+const DEFAULT_S3_MULTIPART_MAX_CONCURRENCY: usize = 16;
+const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 6;
+const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(250);
+const DEFAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(8);
+const DEFAULT_RETRY_ON: &[RetryClass] = &[RetryClass::Throttled, RetryClass::Transient];
+const DEFAULT_FAILURE_THRESHOLD: u64 = 3;
+const DEFAULT_FAILURE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEFAULT_BATCH_CHUNK_SIZE: usize = 25;
+const DEFAULT_JOB_OUTPUT_URL_EXPIRY: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_JOB_STALE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// Asynchronous invocations may be queued for up to 6 hours before they are discarded
+const DEFAULT_JOB_START_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+const DEFAULT_WASM_PLUGIN_FUEL: u64 = 10_000_000_000;
+const DEFAULT_WASM_PLUGIN_MAX_MEMORY_MB: usize = 1024;
+
+/// Maximum niceness increment for x2t processes
+const MAX_X2T_NICE: i32 = 19;
+
+/// Configuration loaded from the environment, set by [load_app_config] and
+/// replaced when the configuration overrides change
+static APP_CONFIG: OnceLock<RwLock<Arc<AppConfig>>> = OnceLock::new();
+
+/// Values that take precedence over the environment variables of the same name
+/// (i.e parameters loaded from SSM Parameter Store)
+static CONFIG_OVERRIDES: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+
+/// Configuration shared by every conversion, parsed and validated from the
+/// environment variables (See [config_var]). Feature specific settings (i.e the buckets for optional
+/// features) are read by the features themselves
+#[derive(Debug)]
+pub struct AppConfig {
+    /// Directory containing the x2t binary when explicitly configured
+    pub x2t_path: Option<PathBuf>,
+    /// x2t installations that requests can select between, keyed by version
+    pub x2t_versions: Vec<(String, PathBuf)>,
+    /// Version of x2t used by requests that do not select a version
+    pub x2t_default_version: Option<String>,
+    /// Directory containing the fonts bundled with x2t
+    pub x2t_fonts_path: PathBuf,
+    /// Directory containing the presentation themes bundled with x2t
+    pub x2t_themes_path: PathBuf,
+    /// Directory containing the ICU data when explicitly configured, relative to
+    /// the x2t directory unless absolute
+    pub x2t_icu_data_path: Option<PathBuf>,
+    /// Directory temporary files are stored within
+    pub temp_dir: PathBuf,
+    /// Memory backed directory used for the files of small conversions
+    pub memory_temp_dir: Option<PathBuf>,
+    /// Maximum source size in bytes that will use the memory backed temp directory
+    pub memory_temp_max_size: u64,
+    /// AWS region to use in place of the default provider chain
+    pub region: Option<String>,
+    /// AWS partition the service is deployed within when explicitly configured
+    pub aws_partition: Option<AwsPartition>,
+    /// Whether AWS service clients use the FIPS endpoints
+    pub use_fips_endpoints: bool,
+    /// Endpoints of AWS service clients keyed by the lowercase service name
+    pub aws_endpoint_urls: Vec<(String, Url)>,
+    /// Retry mode of AWS service clients, the SDK default when not set
+    pub aws_retry_mode: Option<RetryMode>,
+    /// Maximum attempts of each AWS request, the SDK default when not set
+    pub aws_max_attempts: Option<NonZeroU32>,
+    /// Time AWS service clients wait to establish a connection
+    pub aws_connect_timeout: Option<Duration>,
+    /// Time AWS service clients wait for the first byte of a response
+    pub aws_read_timeout: Option<Duration>,
+    /// Time an AWS operation may take including all of its attempts
+    pub aws_operation_timeout: Option<Duration>,
+    /// Time a single attempt of an AWS operation may take
+    pub aws_operation_attempt_timeout: Option<Duration>,
+    /// Maximum rate in bytes per second of S3 downloads, unlimited when not set
+    pub s3_download_rate_limit: Option<NonZeroU64>,
+    /// Maximum rate in bytes per second of S3 uploads, unlimited when not set
+    pub s3_upload_rate_limit: Option<NonZeroU64>,
+    /// Size in bytes from which files are uploaded as a multipart upload
+    pub s3_multipart_threshold: u64,
+    /// Maximum number of parts of a multipart upload uploaded concurrently
+    pub s3_multipart_max_concurrency: usize,
+    /// Maximum attempts of a retried operation including the first attempt
+    pub retry_max_attempts: u32,
+    /// Delay before the first retry, doubled for each retry
+    pub retry_base_delay: Duration,
+    /// Maximum delay between retries
+    pub retry_max_delay: Duration,
+    /// Error classes that are retried
+    pub retry_on: Vec<RetryClass>,
+    /// Number of x2t processes that can run concurrently, defaults
+    /// to the number of vCPUs when not set
+    pub x2t_concurrency: Option<NonZeroUsize>,
+    /// Number of threads x2t is allowed to use
+    pub x2t_threads: Option<NonZeroUsize>,
+    /// Niceness increment applied to x2t processes
+    pub x2t_nice: Option<i32>,
+    /// Maximum size in bytes of outputs returned inline
+    pub inline_output_max_size: u64,
+    /// Multiplier applied to the source size to estimate the disk space of a conversion
+    pub disk_usage_multiplier: u64,
+    /// Number of bytes of disk space that are always kept free
+    pub disk_headroom: u64,
+    /// Maximum number of redirects URL sources will follow
+    pub url_source_max_redirects: usize,
+    /// Maximum size in bytes of a URL source
+    pub url_source_max_size: u64,
+    /// Maximum size in bytes of a core dump that will be uploaded
+    pub core_dump_max_size: u64,
+    /// Failed conversions of the same source before further conversions are rejected
+    pub failure_threshold: u64,
+    /// Duration failed conversions are tracked for
+    pub failure_ttl: Duration,
+    /// Number of requests converted by each invocation of a fanned out batch
+    pub batch_chunk_size: usize,
+    /// Duration the presigned output URLs of a completed job are valid for
+    pub job_output_url_expiry: Duration,
+    /// Duration a chunk of a running job may go without reporting progress
+    pub job_stale_timeout: Duration,
+    /// Duration a chunk of a running job may stay queued before it starts
+    pub job_start_timeout: Duration,
+    /// Maximum size in bytes of HTTP request bodies, unlimited when not set
+    pub max_request_body_size: Option<NonZeroUsize>,
+    /// Maximum time an invocation is handled for, limited to the Lambda deadline
+    /// when not set
+    pub request_timeout: Option<Duration>,
+    /// Fuel a WASM plugin may use transforming a single output
+    pub wasm_plugin_fuel: u64,
+    /// Maximum memory of a WASM plugin in bytes
+    pub wasm_plugin_max_memory: usize,
+    /// Whether temporary files are kept rather than deleted
+    pub debug_keep_temp: bool,
+    /// Whether core dumps are enabled for x2t
+    pub x2t_core_dumps: bool,
+    /// Whether the environment diagnostics can be requested
+    pub diagnostics_enabled: bool,
+    /// Proxy for outbound HTTP requests
+    pub http_proxy: Option<Url>,
+    /// Proxy for outbound HTTPS requests
+    pub https_proxy: Option<Url>,
+    /// Hosts outbound requests are sent to directly rather than through the proxy
+    pub no_proxy: Vec<String>,
+}
+
+impl AppConfig {
+    /// Parse the configuration from the environment variables, fails with
+    /// a configuration error naming the invalid variables
+    pub fn from_env() -> Result<AppConfig, ConvertError> {
+        let (config, invalid) = AppConfig::parse_env();
+
+        if !invalid.is_empty() {
+            return Err(configuration_error(&format!(
+                "invalid value for {}",
+                invalid.join(", ")
+            )));
+        }
+
+        Ok(config)
+    }
+
+    /// Parse the configuration from the environment variables, invalid variables
+    /// use their defaults and are returned alongside the configuration
+    fn parse_env() -> (AppConfig, Vec<&'static str>) {
+        let mut env = EnvParser::default();
+        let aws_partition = env.aws_partition();
+
+        let config = AppConfig {
+            x2t_path: env_string(X2T_PATH_ENV).map(PathBuf::from),
+            x2t_versions: env.x2t_versions(),
+            x2t_default_version: env_string(X2T_DEFAULT_VERSION_ENV),
+            x2t_fonts_path: env_string(X2T_FONTS_PATH_ENV)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_X2T_FONTS_PATH)),
+            x2t_themes_path: env_string(X2T_THEMES_PATH_ENV)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_X2T_THEMES_PATH)),
+            x2t_icu_data_path: env_string(X2T_ICU_DATA_PATH_ENV).map(PathBuf::from),
+            temp_dir: temp_dir(),
+            memory_temp_dir: env_string(MEMORY_TEMP_DIR_ENV).map(PathBuf::from),
+            memory_temp_max_size: env
+                .parse(MEMORY_TEMP_MAX_SIZE_ENV)
+                .unwrap_or(DEFAULT_MEMORY_TEMP_MAX_SIZE),
+            region: env.region(aws_partition),
+            aws_partition,
+            use_fips_endpoints: env.bool(USE_FIPS_ENDPOINTS_ENV),
+            aws_endpoint_urls: env.aws_endpoint_urls(),
+            aws_retry_mode: env.parse(AWS_RETRY_MODE_ENV),
+            aws_max_attempts: env.parse(AWS_MAX_ATTEMPTS_ENV),
+            aws_connect_timeout: env.timeout_ms(AWS_CONNECT_TIMEOUT_MS_ENV),
+            aws_read_timeout: env.timeout_ms(AWS_READ_TIMEOUT_MS_ENV),
+            aws_operation_timeout: env.timeout_ms(AWS_OPERATION_TIMEOUT_MS_ENV),
+            aws_operation_attempt_timeout: env.timeout_ms(AWS_OPERATION_ATTEMPT_TIMEOUT_MS_ENV),
+            s3_download_rate_limit: env.parse(S3_DOWNLOAD_RATE_LIMIT_ENV),
+            s3_upload_rate_limit: env.parse(S3_UPLOAD_RATE_LIMIT_ENV),
+            s3_multipart_threshold: env
+                .parse(S3_MULTIPART_THRESHOLD_ENV)
+                .unwrap_or(DEFAULT_S3_MULTIPART_THRESHOLD),
+            s3_multipart_max_concurrency: env
+                .parse(S3_MULTIPART_MAX_CONCURRENCY_ENV)
+                .map(NonZeroUsize::get)
+                .unwrap_or(DEFAULT_S3_MULTIPART_MAX_CONCURRENCY),
+            retry_max_attempts: env.retry_max_attempts(),
+            retry_base_delay: env
+                .parse(RETRY_BASE_DELAY_MS_ENV)
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_RETRY_BASE_DELAY),
+            retry_max_delay: env
+                .parse(RETRY_MAX_DELAY_MS_ENV)
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_RETRY_MAX_DELAY),
+            retry_on: env.retry_on(),
+            x2t_concurrency: env.parse(X2T_CONCURRENCY_ENV),
+            x2t_threads: env.parse(X2T_THREADS_ENV),
+            x2t_nice: env.x2t_nice(),
+            inline_output_max_size: env
+                .parse(INLINE_OUTPUT_MAX_SIZE_ENV)
+                .unwrap_or(DEFAULT_INLINE_OUTPUT_MAX_SIZE),
+            disk_usage_multiplier: env
+                .parse(DISK_USAGE_MULTIPLIER_ENV)
+                .unwrap_or(DEFAULT_DISK_USAGE_MULTIPLIER),
+            disk_headroom: env
+                .parse(DISK_HEADROOM_ENV)
+                .unwrap_or(DEFAULT_DISK_HEADROOM),
+            url_source_max_redirects: env
+                .parse(URL_SOURCE_MAX_REDIRECTS_ENV)
+                .unwrap_or(DEFAULT_URL_SOURCE_MAX_REDIRECTS),
+            url_source_max_size: env
+                .parse(URL_SOURCE_MAX_SIZE_ENV)
+                .unwrap_or(DEFAULT_URL_SOURCE_MAX_SIZE),
+            core_dump_max_size: env
+                .parse(CORE_DUMP_MAX_SIZE_ENV)
+                .filter(|value| *value > 0)
+                .unwrap_or(DEFAULT_CORE_DUMP_MAX_SIZE),
+            failure_threshold: env
+                .parse(FAILURE_THRESHOLD_ENV)
+                .map(NonZeroU64::get)
+                .unwrap_or(DEFAULT_FAILURE_THRESHOLD),
+            failure_ttl: env
+                .parse(FAILURE_TTL_ENV)
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_FAILURE_TTL),
+            batch_chunk_size: env
+                .parse(BATCH_CHUNK_SIZE_ENV)
+                .map(NonZeroUsize::get)
+                .unwrap_or(DEFAULT_BATCH_CHUNK_SIZE),
+            job_output_url_expiry: env
+                .parse(JOB_OUTPUT_URL_EXPIRY_ENV)
+                .map(|value: NonZeroU64| Duration::from_secs(value.get()))
+                .unwrap_or(DEFAULT_JOB_OUTPUT_URL_EXPIRY),
+            job_stale_timeout: env
+                .parse(JOB_STALE_TIMEOUT_ENV)
+                .map(|value: NonZeroU64| Duration::from_secs(value.get()))
+                .unwrap_or(DEFAULT_JOB_STALE_TIMEOUT),
+            job_start_timeout: env
+                .parse(JOB_START_TIMEOUT_ENV)
+                .map(|value: NonZeroU64| Duration::from_secs(value.get()))
+                .unwrap_or(DEFAULT_JOB_START_TIMEOUT),
+            max_request_body_size: env.parse(MAX_REQUEST_BODY_SIZE_ENV),
+            request_timeout: env
+                .parse(REQUEST_TIMEOUT_SECONDS_ENV)
+                .map(|value: NonZeroU64| Duration::from_secs(value.get())),
+            wasm_plugin_fuel: env
+                .parse(WASM_PLUGIN_FUEL_ENV)
+                .unwrap_or(DEFAULT_WASM_PLUGIN_FUEL),
+            wasm_plugin_max_memory: env
+                .parse(WASM_PLUGIN_MAX_MEMORY_MB_ENV)
+                .unwrap_or(DEFAULT_WASM_PLUGIN_MAX_MEMORY_MB)
+                .saturating_mul(1024 * 1024),
+            debug_keep_temp: env.bool(DEBUG_KEEP_TEMP_ENV),
+            x2t_core_dumps: env.bool(X2T_CORE_DUMPS_ENV),
+            diagnostics_enabled: env.bool(DIAGNOSTICS_ENABLED_ENV),
+            http_proxy: env.proxy(HTTP_PROXY_ENV, HTTP_PROXY_LOWERCASE_ENV),
+            https_proxy: env.proxy(HTTPS_PROXY_ENV, HTTPS_PROXY_LOWERCASE_ENV),
+            no_proxy: env_string(NO_PROXY_ENV)
+                .or_else(|| env_string(NO_PROXY_LOWERCASE_ENV))
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|host| host.trim().to_ascii_lowercase())
+                        .filter(|host| !host.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        (config, env.invalid)
+    }
+
+    /// Path to the x2t installation for the `version` when it is configured
+    pub fn x2t_version_path(&self, version: &str) -> Option<&Path> {
+        self.x2t_versions
+            .iter()
+            .find(|(name, _)| name == version)
+            .map(|(_, path)| path.as_path())
+    }
+}
+
+/// Load and validate the configuration from the environment, called at cold start
+/// so misconfiguration fails the start rather than the first conversion
+pub fn load_app_config() -> Result<Arc<AppConfig>, ConvertError> {
+    let config = Arc::new(AppConfig::from_env()?);
+    store_app_config(config.clone());
+    Ok(config)
+}
+
+/// Get the configuration, loading it from the environment when it has not been
+/// loaded. Invalid variables that were not validated at start are logged and
+/// replaced with their defaults
+pub fn app_config() -> Arc<AppConfig> {
+    APP_CONFIG
+        .get_or_init(|| {
+            let (config, invalid) = AppConfig::parse_env();
+
+            if !invalid.is_empty() {
+                tracing::error!(?invalid, "invalid environment variables, using defaults");
+            }
+
+            RwLock::new(Arc::new(config))
+        })
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+fn store_app_config(config: Arc<AppConfig>) {
+    let lock = APP_CONFIG.get_or_init(|| RwLock::new(config.clone()));
+    *lock.write().unwrap_or_else(|err| err.into_inner()) = config;
+}
+
+/// Check every configured integration was compiled in, fails when an integration is
+/// configured without its cargo feature rather than silently ignoring the configuration
+pub fn check_features() -> Result<(), ConvertError> {
+    let integrations = [
+        (FAILURE_TABLE_ENV, "dynamodb", cfg!(feature = "dynamodb")),
+        (QUOTA_TABLE_ENV, "dynamodb", cfg!(feature = "dynamodb")),
+        (TENANTS_TABLE_ENV, "dynamodb", cfg!(feature = "dynamodb")),
+        (
+            USAGE_DELIVERY_STREAM_ENV,
+            "firehose",
+            cfg!(feature = "firehose"),
+        ),
+        (SIGNING_KEY_ID_ENV, "kms", cfg!(feature = "kms")),
+        (CONFIG_SSM_PATH_ENV, "ssm", cfg!(feature = "ssm")),
+        (TSA_URL_ENV, "http-client", cfg!(feature = "http-client")),
+        (
+            ALLOWED_HOSTS_ENV,
+            "http-client",
+            cfg!(feature = "http-client"),
+        ),
+    ];
+
+    for (key, feature, enabled) in integrations {
+        if !enabled && config_var(key).is_ok_and(|value| !value.is_empty()) {
+            tracing::error!(%key, %feature, "integration is configured but not compiled in");
+            return Err(feature_disabled_error(key, feature));
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the configuration overrides and reload the configuration, the previous
+/// overrides are kept when the new values produce an invalid configuration
+pub fn set_config_overrides(values: HashMap<String, String>) -> Result<(), ConvertError> {
+    let overrides = CONFIG_OVERRIDES.get_or_init(Default::default);
+
+    let previous = std::mem::replace(
+        &mut *overrides.write().unwrap_or_else(|err| err.into_inner()),
+        values,
+    );
+
+    match AppConfig::from_env() {
+        Ok(config) => {
+            store_app_config(Arc::new(config));
+            Ok(())
+        }
+        Err(err) => {
+            *overrides.write().unwrap_or_else(|err| err.into_inner()) = previous;
+            Err(err)
+        }
+    }
+}
+
+/// Read a configuration variable, the configuration overrides take precedence
+/// over the environment variables
+pub fn config_var(key: &str) -> Result<String, VarError> {
+    if let Some(value) = CONFIG_OVERRIDES.get().and_then(|overrides| {
+        overrides
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(key)
+            .cloned()
+    }) {
+        return Ok(value);
+    }
+
+    std::env::var(key)
+}
+
+/// Read a non-empty configuration variable
+fn env_string(key: &str) -> Option<String> {
+    config_var(key).ok().filter(|value| !value.is_empty())
+}
+
+/// Parser for the environment variables that records the invalid variables
+#[derive(Default)]
+struct EnvParser {
+    invalid: Vec<&'static str>,
+}
+
+impl EnvParser {
+    /// Parse an environment variable, [None] when not set or invalid
+    fn parse<T: FromStr>(&mut self, key: &'static str) -> Option<T> {
+        let value = env_string(key)?;
+
+        match value.trim().parse() {
+            Ok(value) => Some(value),
+            Err(_) => self.invalid(key),
+        }
+    }
+
+    /// Parse a boolean environment variable (`true`/`1` or `false`/`0`),
+    /// false when not set or invalid
+    fn bool(&mut self, key: &'static str) -> bool {
+        match env_string(key).as_deref() {
+            None | Some("false" | "0") => false,
+            Some("true" | "1") => true,
+            Some(_) => self.invalid(key).unwrap_or_default(),
+        }
+    }
+
+    /// Parse a timeout in milliseconds, a zero timeout is invalid
+    fn timeout_ms(&mut self, key: &'static str) -> Option<Duration> {
+        let timeout: u64 = self.parse(key)?;
+
+        if timeout == 0 {
+            return self.invalid(key);
+        }
+
+        Some(Duration::from_millis(timeout))
+    }
+
+    fn x2t_versions(&mut self) -> Vec<(String, PathBuf)> {
+        let Some(value) = env_string(X2T_VERSIONS_ENV) else {
+            return Vec::new();
+        };
+
+        let versions: Option<Vec<(String, PathBuf)>> = value
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (name, path) = entry.split_once('=')?;
+                let (name, path) = (name.trim(), path.trim());
+
+                if name.is_empty() || path.is_empty() {
+                    return None;
+                }
+
+                Some((name.to_string(), PathBuf::from(path)))
+            })
+            .collect();
+
+        match versions {
+            Some(versions) => versions,
+            None => self.invalid(X2T_VERSIONS_ENV).unwrap_or_default(),
+        }
+    }
+
+    fn aws_endpoint_urls(&mut self) -> Vec<(String, Url)> {
+        let Some(value) = env_string(AWS_ENDPOINT_URLS_ENV) else {
+            return Vec::new();
+        };
+
+        let endpoints: Option<Vec<(String, Url)>> = value
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (service, url) = entry.split_once('=')?;
+                let service = service.trim().to_ascii_lowercase();
+                let url: Url = url.trim().parse().ok()?;
+
+                if service.is_empty() || !matches!(url.scheme(), "http" | "https") {
+                    return None;
+                }
+
+                Some((service, url))
+            })
+            .collect();
+
+        match endpoints {
+            Some(endpoints) => endpoints,
+            None => self.invalid(AWS_ENDPOINT_URLS_ENV).unwrap_or_default(),
+        }
+    }
+
+    fn aws_partition(&mut self) -> Option<AwsPartition> {
+        let value = env_string(AWS_PARTITION_ENV)?;
+
+        match AwsPartition::from_name(value.trim()) {
+            Some(partition) => Some(partition),
+            None => self.invalid(AWS_PARTITION_ENV),
+        }
+    }
+
+    /// Parse the region, regions outside of the configured `partition` are invalid
+    fn region(&mut self, partition: Option<AwsPartition>) -> Option<String> {
+        let region = env_string(AWS_REGION_ENV)?;
+
+        if partition.is_some_and(|partition| AwsPartition::from_region(&region) != partition) {
+            return self.invalid(AWS_REGION_ENV);
+        }
+
+        Some(region)
+    }
+
+    /// Parse the maximum attempts of retried operations, falling back to the
+    /// S3 throttle retries when not set
+    fn retry_max_attempts(&mut self) -> u32 {
+        if env_string(RETRY_MAX_ATTEMPTS_ENV).is_some() {
+            return self
+                .parse(RETRY_MAX_ATTEMPTS_ENV)
+                .map(NonZeroU32::get)
+                .unwrap_or(DEFAULT_RETRY_MAX_ATTEMPTS);
+        }
+
+        self.parse::<u32>(S3_THROTTLE_RETRIES_ENV)
+            .map(|retries| retries.saturating_add(1))
+            .unwrap_or(DEFAULT_RETRY_MAX_ATTEMPTS)
+    }
+
+    /// Parse the retried error classes, an empty list disables retrying
+    fn retry_on(&mut self) -> Vec<RetryClass> {
+        let Ok(value) = config_var(RETRY_ON_ENV) else {
+            return DEFAULT_RETRY_ON.to_vec();
+        };
+
+        let classes: Option<Vec<RetryClass>> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(RetryClass::from_name)
+            .collect();
+
+        match classes {
+            Some(classes) => classes,
+            None => self
+                .invalid(RETRY_ON_ENV)
+                .unwrap_or_else(|| DEFAULT_RETRY_ON.to_vec()),
+        }
+    }
+
+    fn x2t_nice(&mut self) -> Option<i32> {
+        let nice: i32 = self.parse(X2T_NICE_ENV)?;
+
+        if !(0..=MAX_X2T_NICE).contains(&nice) {
+            return self.invalid(X2T_NICE_ENV);
+        }
+
+        // A zero increment is the same as not changing the priority
+        (nice > 0).then_some(nice)
+    }
+
+    /// Parse a proxy URL from the `key` or the `lowercase_key` when not set, only
+    /// HTTP and HTTPS proxies are supported
+    fn proxy(&mut self, key: &'static str, lowercase_key: &'static str) -> Option<Url> {
+        let key = match env_string(key) {
+            Some(_) => key,
+            None => lowercase_key,
+        };
+
+        let url: Url = self.parse(key)?;
+
+        if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+            return self.invalid(key);
+        }
+
+        Some(url)
+    }
+
+    /// Record the `key` as invalid, the value is not logged as it may hold a secret
+    /// (i.e the credentials of a proxy URL)
+    fn invalid<T>(&mut self, key: &'static str) -> Option<T> {
+        tracing::error!(%key, "invalid environment variable");
+        self.invalid.push(key);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf, sync::Mutex, time::Duration};
+
+    use super::{
+        AWS_ENDPOINT_URLS_ENV, AWS_REGION_ENV, CONFIG_OVERRIDES, DEFAULT_RETRY_ON, EnvParser,
+        JOB_STALE_TIMEOUT_ENV, RETRY_ON_ENV, X2T_VERSIONS_ENV, app_config, config_var,
+        set_config_overrides,
+    };
+    use crate::{aws::AwsPartition, retry::RetryClass};
+
+    /// The configuration overrides are shared so the tests using them run one at a time
+    static OVERRIDES_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Run the `parse` with the `values` as the configuration overrides, returns the
+    /// parsed value and the keys recorded as invalid
+    fn parse_with<T>(
+        values: &[(&str, &str)],
+        parse: impl FnOnce(&mut EnvParser) -> T,
+    ) -> (T, Vec<&'static str>) {
+        let _lock = OVERRIDES_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let overrides = CONFIG_OVERRIDES.get_or_init(Default::default);
+
+        *overrides.write().unwrap() = values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        let mut env = EnvParser::default();
+        let value = parse(&mut env);
+
+        overrides.write().unwrap().clear();
+        (value, env.invalid)
+    }
+
+    #[test]
+    fn test_parse() {
+        let (value, invalid) = parse_with(&[("TEST_PARSE", " 42 ")], |env| {
+            env.parse::<u32>("TEST_PARSE")
+        });
+        assert_eq!(value, Some(42));
+        assert!(invalid.is_empty());
+
+        let (value, invalid) = parse_with(&[("TEST_PARSE", "abc")], |env| {
+            env.parse::<u32>("TEST_PARSE")
+        });
+        assert_eq!(value, None);
+        assert_eq!(invalid, ["TEST_PARSE"]);
+
+        // Empty values are the same as not being set
+        let (value, invalid) =
+            parse_with(&[("TEST_PARSE", "")], |env| env.parse::<u32>("TEST_PARSE"));
+        assert_eq!(value, None);
+        assert!(invalid.is_empty());
+    }
+
+    #[test]
+    fn test_bool() {
+        for (value, expected) in [("true", true), ("1", true), ("false", false), ("0", false)] {
+            let (parsed, invalid) =
+                parse_with(&[("TEST_BOOL", value)], |env| env.bool("TEST_BOOL"));
+            assert_eq!(parsed, expected, "{value}");
+            assert!(invalid.is_empty());
+        }
+
+        let (parsed, invalid) = parse_with(&[], |env| env.bool("TEST_BOOL"));
+        assert!(!parsed);
+        assert!(invalid.is_empty());
+
+        let (parsed, invalid) = parse_with(&[("TEST_BOOL", "yes")], |env| env.bool("TEST_BOOL"));
+        assert!(!parsed);
+        assert_eq!(invalid, ["TEST_BOOL"]);
+    }
+
+    #[test]
+    fn test_timeout_ms() {
+        let (timeout, invalid) = parse_with(&[("TEST_TIMEOUT", "250")], |env| {
+            env.timeout_ms("TEST_TIMEOUT")
+        });
+        assert_eq!(timeout, Some(Duration::from_millis(250)));
+        assert!(invalid.is_empty());
+
+        let (timeout, invalid) = parse_with(&[("TEST_TIMEOUT", "0")], |env| {
+            env.timeout_ms("TEST_TIMEOUT")
+        });
+        assert_eq!(timeout, None);
+        assert_eq!(invalid, ["TEST_TIMEOUT"]);
+    }
+
+    #[test]
+    fn test_x2t_versions() {
+        let (versions, invalid) = parse_with(
+            &[(X2T_VERSIONS_ENV, "7.5=/opt/x2t-7.5, 8.0 = /opt/x2t-8.0,")],
+            EnvParser::x2t_versions,
+        );
+        assert_eq!(
+            versions,
+            [
+                ("7.5".to_string(), PathBuf::from("/opt/x2t-7.5")),
+                ("8.0".to_string(), PathBuf::from("/opt/x2t-8.0")),
+            ]
+        );
+        assert!(invalid.is_empty());
+
+        for value in ["7.5", "7.5=", "=/opt/x2t"] {
+            let (versions, invalid) =
+                parse_with(&[(X2T_VERSIONS_ENV, value)], EnvParser::x2t_versions);
+            assert!(versions.is_empty(), "{value}");
+            assert_eq!(invalid, [X2T_VERSIONS_ENV]);
+        }
+    }
+
+    #[test]
+    fn test_aws_endpoint_urls() {
+        let (endpoints, invalid) = parse_with(
+            &[(
+                AWS_ENDPOINT_URLS_ENV,
+                "S3=http://localhost:4566,sqs=https://sqs.local",
+            )],
+            EnvParser::aws_endpoint_urls,
+        );
+        let endpoints: Vec<(&str, &str)> = endpoints
+            .iter()
+            .map(|(service, url)| (service.as_str(), url.as_str()))
+            .collect();
+        assert_eq!(
+            endpoints,
+            [
+                ("s3", "http://localhost:4566/"),
+                ("sqs", "https://sqs.local/")
+            ]
+        );
+        assert!(invalid.is_empty());
+
+        for value in [
+            "s3=ftp://localhost",
+            "s3",
+            "=http://localhost",
+            "s3=not a url",
+        ] {
+            let (endpoints, invalid) = parse_with(
+                &[(AWS_ENDPOINT_URLS_ENV, value)],
+                EnvParser::aws_endpoint_urls,
+            );
+            assert!(endpoints.is_empty(), "{value}");
+            assert_eq!(invalid, [AWS_ENDPOINT_URLS_ENV]);
+        }
+    }
+
+    #[test]
+    fn test_region() {
+        let (region, invalid) = parse_with(&[(AWS_REGION_ENV, "cn-north-1")], |env| {
+            env.region(Some(AwsPartition::AwsCn))
+        });
+        assert_eq!(region.as_deref(), Some("cn-north-1"));
+        assert!(invalid.is_empty());
+
+        // Regions are not checked without a configured partition
+        let (region, invalid) =
+            parse_with(&[(AWS_REGION_ENV, "cn-north-1")], |env| env.region(None));
+        assert_eq!(region.as_deref(), Some("cn-north-1"));
+        assert!(invalid.is_empty());
+
+        let (region, invalid) = parse_with(&[(AWS_REGION_ENV, "us-east-1")], |env| {
+            env.region(Some(AwsPartition::AwsCn))
+        });
+        assert_eq!(region, None);
+        assert_eq!(invalid, [AWS_REGION_ENV]);
+    }
+
+    #[test]
+    fn test_retry_on() {
+        let (classes, invalid) = parse_with(
+            &[(RETRY_ON_ENV, "throttled, x2t_crash")],
+            EnvParser::retry_on,
+        );
+        assert_eq!(classes, [RetryClass::Throttled, RetryClass::X2tCrash]);
+        assert!(invalid.is_empty());
+
+        // An empty list disables retrying
+        let (classes, invalid) = parse_with(&[(RETRY_ON_ENV, "")], EnvParser::retry_on);
+        assert!(classes.is_empty());
+        assert!(invalid.is_empty());
+
+        let (classes, invalid) =
+            parse_with(&[(RETRY_ON_ENV, "throttled,unknown")], EnvParser::retry_on);
+        assert_eq!(classes, DEFAULT_RETRY_ON);
+        assert_eq!(invalid, [RETRY_ON_ENV]);
+    }
+
+    #[test]
+    fn test_proxy() {
+        let (proxy, invalid) = parse_with(&[("test_proxy", "http://proxy:3128")], |env| {
+            env.proxy("TEST_PROXY", "test_proxy")
+        });
+        assert_eq!(
+            proxy.as_ref().map(|url| url.as_str()),
+            Some("http://proxy:3128/")
+        );
+        assert!(invalid.is_empty());
+
+        // The uppercase variable takes precedence
+        let (proxy, _) = parse_with(
+            &[
+                ("TEST_PROXY", "https://upper:3128"),
+                ("test_proxy", "http://lower:3128"),
+            ],
+            |env| env.proxy("TEST_PROXY", "test_proxy"),
+        );
+        assert_eq!(
+            proxy.as_ref().map(|url| url.as_str()),
+            Some("https://upper:3128/")
+        );
+
+        for value in ["socks5://proxy:1080", "not a url"] {
+            let (proxy, invalid) = parse_with(&[("TEST_PROXY", value)], |env| {
+                env.proxy("TEST_PROXY", "test_proxy")
+            });
+            assert_eq!(proxy, None, "{value}");
+            assert_eq!(invalid, ["TEST_PROXY"]);
+        }
+    }
+
+    #[test]
+    fn test_set_config_overrides_rollback() {
+        let _lock = OVERRIDES_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        set_config_overrides(HashMap::from([(
+            JOB_STALE_TIMEOUT_ENV.to_string(),
+            "120".to_string(),
+        )]))
+        .unwrap();
+        assert_eq!(app_config().job_stale_timeout, Duration::from_secs(120));
+
+        // Invalid overrides keep the previous overrides and configuration
+        let err = set_config_overrides(HashMap::from([(
+            JOB_STALE_TIMEOUT_ENV.to_string(),
+            "0".to_string(),
+        )]))
+        .unwrap_err();
+        assert_eq!(err.reason, Some("CONFIGURATION_ERROR"));
+        assert_eq!(config_var(JOB_STALE_TIMEOUT_ENV).as_deref(), Ok("120"));
+        assert_eq!(app_config().job_stale_timeout, Duration::from_secs(120));
+
+        set_config_overrides(HashMap::new()).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use uuid::Uuid;
+
+use crate::{error::ConvertError, x2t::X2T_BIN};
+
+/// Temporary directories that have been checked as writable, cached across
+/// warm invocations
+static WRITABLE_TEMP_PATHS: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+
+/// Configuration error from initialization, reported to each invocation
+static INIT_ERROR: OnceLock<ConvertError> = OnceLock::new();
+
+/// Record a configuration `error` from initialization so invocations fail with it
+/// (See [check_init_error]) rather than the function failing to initialize
+pub fn set_init_error(error: ConvertError) {
+    _ = INIT_ERROR.set(error);
+}
+
+/// Check initialization did not fail with a configuration error
+pub fn check_init_error() -> Result<(), ConvertError> {
+    match INIT_ERROR.get() {
+        Some(error) => Err(error.clone()),
+        None => Ok(()),
+    }
+}
+
+/// Check the x2t install, fonts directory and temporary directory are usable before
+/// starting a conversion, misconfigured environments fail with a configuration
+/// error rather than an error from x2t
+pub async fn check_configuration(
+    x2t_path: &Path,
+    fonts_path: &Path,
+    temp_path: &Path,
+) -> Result<(), ConvertError> {
+    if !x2t_path.join(X2T_BIN).is_file() {
+        tracing::error!(path = %x2t_path.display(), "x2t binary not found within the x2t path");
+        return Err(configuration_error("x2t binary not found"));
+    }
+
+    if !fonts_path.is_dir() {
+        tracing::error!(path = %fonts_path.display(), "fonts directory does not exist");
+        return Err(configuration_error("fonts directory not found"));
+    }
+
+    check_temp_writable(temp_path).await
+}
+
+/// Check files can be created within the `temp_path` by writing and removing a probe file
+async fn check_temp_writable(temp_path: &Path) -> Result<(), ConvertError> {
+    let checked = WRITABLE_TEMP_PATHS.get_or_init(Default::default);
+
+    if checked
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .contains(temp_path)
+    {
+        return Ok(());
+    }
+
+    let probe_path = temp_path.join(format!("tmp_probe_{}", Uuid::new_v4().simple()));
+
+    if let Err(err) = tokio::fs::write(&probe_path, b"").await {
+        tracing::error!(?err, path = %temp_path.display(), "temporary directory is not writable");
+        return Err(configuration_error("temporary directory is not writable"));
+    }
+
+    if let Err(err) = tokio::fs::remove_file(&probe_path).await {
+        tracing::warn!(?err, path = %probe_path.display(), "failed to remove temporary probe file");
+    }
+
+    checked
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(temp_path.to_path_buf());
+
+    Ok(())
+}
+
+/// Error for using an integration that was not compiled in, `name` is the
+/// environment variable or request field that uses the integration
+pub fn feature_disabled_error(name: &str, feature: &str) -> ConvertError {
+    configuration_error(&format!("{name} requires the {feature} feature"))
+}
+
+pub fn configuration_error(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("CONFIGURATION_ERROR"),
+        x2t_code: None,
+        message: format!("service is misconfigured: {message}"),
+    }
+}
@@ -0,0 +1,2904 @@
+use std::{
+    path::{Path, PathBuf, absolute},
+    sync::Arc,
+    time::Instant,
+};
+
+use chrono::Utc;
+use futures::{
+    StreamExt,
+    future::{join_all, try_join_all},
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    admission::DiskReservation,
+    archive::{ARCHIVE_CONTENT_TYPE, ArchiveEntry, create_output_archive},
+    artifacts::{FailedConversion, persist_failure_artifacts},
+    attachment::attach_source,
+    audit::{AuditRecord, is_audit_enabled, write_audit_record},
+    bookmarks::add_heading_bookmarks,
+    cancel::{CancelSignal, cancelled_error},
+    circuit_breaker::{
+        FailureOptions, check_source, failure_key, is_circuit_breaker_enabled, is_document_failure,
+        record_failure,
+    },
+    compress::{OutputCompression, compress_file},
+    concurrency::{acquire_x2t_permit, apply_x2t_process_limits},
+    config::app_config,
+    config_check::{check_configuration, configuration_error},
+    crash_dump::{
+        CrashedConversion, enable_core_dumps, exit_signal, is_core_dump_enabled,
+        upload_crash_artifacts,
+    },
+    debug_artifacts::{is_debug_artifacts_enabled, is_debug_keep_temp, upload_debug_artifacts},
+    detect::{detect_input_format, detect_unsupported_format},
+    diagnose::diagnose_x2t_failure,
+    encrypted::{FileCondition, get_file_condition},
+    error::ConvertError,
+    flatten::{AnnotationMode, FlattenOptions, flatten_pdf},
+    font_cache::{ALL_FONTS_FILE_NAME, FontCache, font_cache},
+    font_report::substituted_fonts,
+    fonts::{fonts_path, profile_fonts_path},
+    format::{InputFormat, OutputFormat},
+    linearize::linearize_pdf,
+    output_check::check_output,
+    password::{DocumentPassword, PasswordSecret},
+    pipeline::{
+        OutputContext, SourceContext, pipeline_names, run_post_processors, run_pre_processors,
+    },
+    presentation::{PresentationOptions, fit_slides},
+    progress::{ConvertStage, ProgressReporter},
+    quota::{QuotaUsage, acquire_quota},
+    raster::RasterOptions,
+    result_cache::{ResultCacheEntry, ResultCacheParams, is_result_cache_enabled},
+    retry::{RetryClass, RetryOptions, RetryPolicy, with_retry_policy},
+    signing::{SignOptions, is_signing_enabled, sign_pdf},
+    source::{SourceFile, SourceFileWriter},
+    spreadsheet::{SpreadsheetLimits, SpreadsheetPrintOptions},
+    stamp::{StampOptions, StampValues, stamp_pdf},
+    storage::{
+        DeleteOptions, GetOptions, ObjectAcl, ObjectChecksumAlgorithm, ObjectHead, ObjectMetadata,
+        ObjectStorageClass, PutBody, PutOptions, S3Storage, Storage, StorageError, WriteOptions,
+        count_throttled,
+    },
+    tagged::check_tagged_pdf,
+    temp::{is_memory_temp_enabled, select_temp_path},
+    template::fill_template,
+    tenant::{TenantSettings, is_tenancy_enabled, load_tenant, send_tenant_callback},
+    themes::themes_path,
+    url_source::stream_url_source,
+    usage::{UsageMetrics, UsageRecord, is_usage_enabled, write_usage_record},
+    validate::{
+        validate_account_id, validate_bucket, validate_etag, validate_key, validate_name,
+        validate_version_id,
+    },
+    workbook::{CsvOptions, WorkbookExport},
+    x2t::{
+        X2T_BIN, default_x2t_version, get_error_code_message, is_icu_error_code,
+        is_limits_error_code, resolve_x2t_path, x2t_command,
+    },
+    x2t_config::X2tConfig,
+};
+
+/// Name of the attached source file when the name cannot be taken from the source
+const DEFAULT_SOURCE_FILE_NAME: &str = "source";
+
+/// Result of a successful conversion
+pub struct ConvertResult {
+    pub output: ConvertOutput,
+    /// Fonts referenced by the source that were substituted
+    pub substituted_fonts: Vec<String>,
+}
+
+/// Output of a successful conversion
+pub enum ConvertOutput {
+    /// Output was uploaded to the destination bucket
+    Uploaded,
+    /// Output is returned inline in the response
+    Inline {
+        bytes: Vec<u8>,
+        content_type: &'static str,
+    },
+}
+
+/// Options controlling how a conversion is run
+#[derive(Default)]
+pub struct ConvertOptions {
+    /// Directory to store the conversion files within, overrides the
+    /// directory selected from the source size when set
+    pub temp_dir: Option<PathBuf>,
+    /// Storage for the source and outputs, defaults to S3
+    pub storage: Option<Arc<dyn Storage>>,
+    /// Signal to stop the conversion early, see [crate::cancel::cancel_signal]
+    pub cancel: Option<CancelSignal>,
+    /// Identity of the caller recorded in the audit log (i.e IAM user ARN or source IP)
+    pub caller: Option<String>,
+}
+
+/// Convert the source file of the `request` into the requested formats, the outputs
+/// are uploaded to the destination or returned inline when no destination is provided
+pub async fn convert(
+    request_id: &str,
+    request: ConvertRequest,
+) -> Result<ConvertResult, ConvertError> {
+    convert_with_options(request_id, request, ConvertOptions::default()).await
+}
+
+/// Convert the source file of the `request` using the provided `options`, see [convert]
+pub async fn convert_with_options(
+    request_id: &str,
+    request: ConvertRequest,
+    options: ConvertOptions,
+) -> Result<ConvertResult, ConvertError> {
+    let mut metrics = UsageMetrics::default();
+
+    // Operations of the conversion are retried using the policy of the request
+    let retry_policy = match &request.retry {
+        Some(retry) => RetryPolicy::from_env().with_options(retry),
+        None => RetryPolicy::from_env(),
+    };
+
+    if !is_audit_enabled() && !is_usage_enabled() {
+        return with_retry_policy(
+            retry_policy,
+            run_conversion(request_id, request, options, &mut metrics),
+        )
+        .await;
+    }
+
+    // Audit and usage details are taken before the request is consumed by the conversion
+    let audit_record =
+        is_audit_enabled().then(|| request.audit_record(request_id, options.caller.clone()));
+    let usage_record = is_usage_enabled().then(|| request.usage_record(request_id));
+    let started = Instant::now();
+
+    let (result, throttled_requests) = count_throttled(with_retry_policy(
+        retry_policy,
+        run_conversion(request_id, request, options, &mut metrics),
+    ))
+    .await;
+    metrics.throttled_requests = throttled_requests;
+
+    if throttled_requests > 0 {
+        tracing::warn!(%request_id, throttled_requests, "conversion was throttled by s3");
+    }
+
+    if let Some(mut record) = audit_record {
+        record.finish(&result, started.elapsed());
+        write_audit_record(&record).await;
+    }
+
+    if let Some(mut record) = usage_record {
+        record.finish(&result, &metrics, started.elapsed());
+        write_usage_record(&record).await;
+    }
+
+    result
+}
+
+async fn run_conversion(
+    request_id: &str,
+    mut request: ConvertRequest,
+    options: ConvertOptions,
+    metrics: &mut UsageMetrics,
+) -> Result<ConvertResult, ConvertError> {
+    request.validate()?;
+
+    let Some(tenant_id) = request.tenant.take() else {
+        return convert_request(request_id, request, options, metrics, None, None).await;
+    };
+
+    let tenant = load_tenant(&tenant_id).await?;
+    request.check_tenant(&tenant)?;
+
+    let quota = match &tenant.quota {
+        Some(quota) => acquire_quota(&tenant_id, quota).await?,
+        None => None,
+    };
+
+    let result = convert_request(
+        request_id,
+        request,
+        options,
+        metrics,
+        Some(&tenant),
+        quota.as_ref(),
+    )
+    .await;
+
+    if let Some(callback_url) = &tenant.callback_url {
+        send_tenant_callback(callback_url, request_id, &tenant_id, &result).await;
+    }
+
+    result
+}
+
+/// Convert a validated request, restricted by the settings of the `tenant`
+/// the request was made on behalf of (When provided). The size of the source
+/// is counted against the `quota` of the tenant and recorded in the `metrics`
+async fn convert_request(
+    request_id: &str,
+    request: ConvertRequest,
+    options: ConvertOptions,
+    metrics: &mut UsageMetrics,
+    tenant: Option<&TenantSettings>,
+    quota: Option<&QuotaUsage<'_>>,
+) -> Result<ConvertResult, ConvertError> {
+    if options
+        .cancel
+        .as_ref()
+        .is_some_and(CancelSignal::is_cancelled)
+    {
+        return Err(cancelled_error());
+    }
+
+    let storage = match options.storage {
+        Some(storage) => storage,
+        None => Arc::new(S3Storage::from_env().await),
+    };
+
+    // Source metadata is only needed for the result cache, the circuit breaker and
+    // choosing between the memory and disk temp directories
+    let source_head = async {
+        match request.source()? {
+            Source::S3 {
+                bucket,
+                key,
+                options: get_options,
+            } if is_result_cache_enabled()
+                || is_circuit_breaker_enabled()
+                || (options.temp_dir.is_none() && is_memory_temp_enabled()) =>
+            {
+                Ok(head_source(storage.as_ref(), bucket, key, get_options).await)
+            }
+            _ => Ok(None),
+        }
+    };
+
+    // The converter is prepared while the source metadata is requested, failures to
+    // prepare are only reported once the result cache has been checked
+    let (source_head, converter) = tokio::join!(source_head, prepare_converter(&request));
+    let source_head = source_head?;
+
+    // Sources that repeatedly fail to convert are not attempted again
+    let failure_key = source_head
+        .as_ref()
+        .and_then(|head| head.etag.as_deref())
+        .filter(|_| is_circuit_breaker_enabled())
+        .map(|etag| failure_key(etag, &request.failure_options()));
+
+    if let Some(failure_key) = &failure_key {
+        check_source(failure_key).await?;
+    }
+
+    // Uploaded outputs of unchanged sources are reused from the result cache
+    let result_cache = match (&source_head, request.destination()) {
+        (
+            Some(ObjectHead {
+                etag: Some(etag), ..
+            }),
+            Some(_),
+        ) => request
+            .result_cache_params(etag)
+            .and_then(|params| ResultCacheEntry::new(&params)),
+        _ => None,
+    };
+
+    if let Some(cache) = &result_cache
+        && let Some((dest_bucket, dest_key)) = request.destination()
+        && let Some(substituted_fonts) = cache
+            .restore(
+                storage.as_ref(),
+                dest_bucket,
+                &request.destination_keys(dest_key),
+                request.write_options(),
+            )
+            .await
+    {
+        if request.delete_source {
+            let etag = source_head.as_ref().and_then(|head| head.etag.as_deref());
+            delete_source_object(storage.as_ref(), &request, etag).await?;
+        }
+
+        return Ok(ConvertResult {
+            output: ConvertOutput::Uploaded,
+            substituted_fonts,
+        });
+    }
+
+    let Converter {
+        x2t_path,
+        fonts_path,
+        font_cache,
+        themes_path,
+    } = converter?;
+
+    let temp_path = match options.temp_dir {
+        Some(temp_dir) => temp_dir,
+        None => select_temp_path(source_head.and_then(|head| head.content_length)),
+    };
+
+    // Ensure temporary path exists
+    if !temp_path.exists() {
+        tokio::fs::create_dir_all(&temp_path).await.map_err(|err| {
+            tracing::error!(?err, "failed to create temporary directory");
+
+            ConvertError {
+                reason: Some("SETUP_TEMP_DIR_FAILED"),
+                x2t_code: None,
+                message: "failed to create temporary directory".to_string(),
+            }
+        })?;
+    }
+
+    // Password for encrypted sources is read from the secret
+    let resolve_password = async {
+        match &request.password_secret {
+            Some(password_secret) => password_secret.resolve().await.map(Some),
+            None => Ok(None),
+        }
+    };
+
+    let ((), password) = tokio::try_join!(
+        check_configuration(&x2t_path, &fonts_path, &temp_path),
+        resolve_password
+    )?;
+
+    // Formats to produce, the source is only downloaded once for all of them
+    let formats = request.output_formats();
+
+    // Create temporary path
+    let paths = create_convert_temp_paths(&temp_path, &formats).map_err(|err| {
+        tracing::error!(?err, "failed to setup temporary paths");
+        ConvertError {
+            reason: Some("SETUP_TEMP_FAILED"),
+            x2t_code: None,
+            message: "failed to setup temporary file paths".to_string(),
+        }
+    })?;
+
+    // Temporary files are removed once the conversion finishes or is dropped (i.e by
+    // the invocation timing out), the reserved disk space is released afterwards
+    let mut temp_files = TempFiles {
+        paths,
+        disk_reservation: None,
+        keep: false,
+    };
+
+    // Copying out of a requester pays destination is charged to the requester
+    let cache_copy_options = WriteOptions {
+        requester_pays: request.requester_pays,
+        ..Default::default()
+    };
+
+    // Debug artifacts are uploaded for requests asking for them and when keeping
+    // the temporary files of every conversion
+    let keep_temp = is_debug_keep_temp();
+    let debug = request.debug || keep_temp;
+
+    // Keys the outputs are uploaded to, for storing in the result cache
+    let cache_destination = result_cache.as_ref().and_then(|_| {
+        let (dest_bucket, dest_keys) = request.output_locations()?;
+        Some((dest_bucket.to_string(), dest_keys))
+    });
+
+    let result = x2t(X2tInput {
+        request_id,
+        disk_reservation: &mut temp_files.disk_reservation,
+        metrics,
+        storage: storage.as_ref(),
+        paths: &temp_files.paths,
+        request,
+        x2t_path: &x2t_path,
+        fonts_path: &fonts_path,
+        font_cache: font_cache.as_ref(),
+        themes_path: themes_path.as_deref(),
+        password: password.as_ref(),
+        tenant,
+        quota,
+        moved_source: None,
+        cancel: options.cancel,
+    })
+    .await;
+
+    if debug {
+        upload_debug_artifacts(
+            storage.as_ref(),
+            request_id,
+            &temp_files.paths.debug_paths(),
+        )
+        .await;
+    }
+
+    if keep_temp {
+        tracing::info!(input_path = ?temp_files.paths.input_path, "keeping temporary files");
+        temp_files.keep = true;
+    }
+
+    // Spawn a cleanup task
+    drop(temp_files);
+
+    if let (Ok(result), Some(cache), Some((dest_bucket, dest_keys))) =
+        (&result, &result_cache, &cache_destination)
+    {
+        cache
+            .store(
+                storage.as_ref(),
+                dest_bucket,
+                dest_keys,
+                &result.substituted_fonts,
+                cache_copy_options,
+            )
+            .await;
+    }
+
+    if let (Err(error), Some(failure_key)) = (&result, &failure_key)
+        && is_document_failure(error)
+    {
+        record_failure(failure_key, error).await;
+    }
+
+    result
+}
+
+/// Installation and resources used to run x2t for a request
+struct Converter {
+    x2t_path: PathBuf,
+    fonts_path: PathBuf,
+    font_cache: Option<FontCache>,
+    themes_path: Option<PathBuf>,
+}
+
+/// Resolve the x2t installation and fonts used to convert the `request`
+async fn prepare_converter(request: &ConvertRequest) -> Result<Converter, ConvertError> {
+    // Check a path was provided
+    let x2t_path = match resolve_x2t_path(request.x2t_version().as_deref())? {
+        Some(value) => absolute(value).map_err(|err| {
+            tracing::error!(?err, "failed to make x2t path absolute");
+
+            ConvertError {
+                reason: Some("X2T_PATH_ABSOLUTE"),
+                x2t_code: None,
+                message: "failed to make x2t path absolute".to_string(),
+            }
+        })?,
+        None => {
+            tracing::error!("no x2t install path provided, cannot convert");
+            return Err(configuration_error("x2t is not installed"));
+        }
+    };
+
+    // Font profiles use their own font set in place of the default fonts
+    let fonts_path = match &request.font_profile {
+        Some(profile) => profile_fonts_path(profile).await?,
+        None => fonts_path(),
+    };
+
+    let fonts_path = absolute(fonts_path).map_err(|err| {
+        tracing::error!(?err, "failed to make fonts path absolute");
+
+        ConvertError {
+            reason: Some("X2T_FONTS_PATH_ABSOLUTE"),
+            x2t_code: None,
+            message: "failed to make x2t fonts path absolute".to_string(),
+        }
+    })?;
+
+    // Regenerate the font caches when the fonts have changed
+    let font_cache = font_cache(&fonts_path, &x2t_path).await?;
+
+    Ok(Converter {
+        x2t_path,
+        fonts_path,
+        font_cache,
+        themes_path: themes_path(),
+    })
+}
+
+/// Temporary files of a conversion along with the disk space reserved for them,
+/// removed in the background once dropped unless kept
+struct TempFiles {
+    paths: ConvertTempPaths,
+    /// Disk space reserved for the conversion, set once the source is downloaded
+    disk_reservation: Option<DiskReservation>,
+    /// Whether the files are kept rather than removed (debug mode)
+    keep: bool,
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+
+        spawn_temp_cleanup(
+            std::mem::take(&mut self.paths),
+            self.disk_reservation.take(),
+        );
+    }
+}
+
+/// Remove the temporary files of a conversion in the background, the disk
+/// reservation is released once the files are removed
+fn spawn_temp_cleanup(paths: ConvertTempPaths, disk_reservation: Option<DiskReservation>) {
+    tokio::spawn(async move {
+        remove_temp_file(&paths.input_path).await;
+        remove_temp_file(&paths.archive_path).await;
+        remove_temp_file(&paths.template_path).await;
+        remove_temp_file(&paths.processed_path).await;
+        remove_temp_file(&paths.compressed_path).await;
+
+        for output in &paths.outputs {
+            remove_temp_file(&output.config_path).await;
+            remove_temp_file(&output.output_path).await;
+            remove_temp_file(&output.compressed_path).await;
+            remove_temp_file(&output.processed_path).await;
+            remove_temp_file(&output.workbook_path).await;
+
+            if output.temp_path.exists()
+                && let Err(err) = tokio::fs::remove_dir_all(&output.temp_path).await
+            {
+                tracing::error!(?err, "failed to remove converter temporary files");
+            }
+
+            if output.dump_path.exists()
+                && let Err(err) = tokio::fs::remove_dir_all(&output.dump_path).await
+            {
+                tracing::error!(?err, "failed to remove core dumps");
+            }
+        }
+
+        drop(disk_reservation);
+    });
+}
+
+/// Remove a temporary file if it exists
+async fn remove_temp_file(path: &Path) {
+    if path.exists()
+        && let Err(err) = tokio::fs::remove_file(path).await
+    {
+        tracing::error!(?err, ?path, "failed to delete temporary file");
+    }
+}
+
+struct X2tInput<'a> {
+    request_id: &'a str,
+    disk_reservation: &'a mut Option<DiskReservation>,
+    metrics: &'a mut UsageMetrics,
+    storage: &'a dyn Storage,
+    paths: &'a ConvertTempPaths,
+    request: ConvertRequest,
+    x2t_path: &'a Path,
+    fonts_path: &'a Path,
+    font_cache: Option<&'a FontCache>,
+    themes_path: Option<&'a Path>,
+    password: Option<&'a DocumentPassword>,
+    tenant: Option<&'a TenantSettings>,
+    quota: Option<&'a QuotaUsage<'a>>,
+    /// Source being moved to the destination, set once the source is downloaded
+    moved_source: Option<MovedSource>,
+    cancel: Option<CancelSignal>,
+}
+
+/// Source of a move request, the source is deleted once every output is uploaded
+struct MovedSource {
+    /// Entity tag of the converted source
+    etag: Option<String>,
+    /// Metadata and tags of the source that are copied onto the outputs
+    metadata: ObjectMetadata,
+}
+
+async fn x2t(mut input: X2tInput<'_>) -> Result<ConvertResult, ConvertError> {
+    tracing::debug!("streaming source file");
+
+    // Stream the input file to disk
+    let progress = ProgressReporter::new(input.request_id);
+
+    let mut source = match input.request.source()? {
+        Source::S3 {
+            bucket,
+            key,
+            options,
+        } => {
+            progress
+                .track(
+                    ConvertStage::Downloading,
+                    stream_source_file(
+                        input.storage,
+                        bucket,
+                        key,
+                        options,
+                        &input.paths.input_path,
+                    ),
+                )
+                .await?
+        }
+        Source::Url(url) => {
+            progress
+                .track(
+                    ConvertStage::Downloading,
+                    stream_url_source(url, &input.paths.input_path, input.request.presigned_source),
+                )
+                .await?
+        }
+    };
+
+    tracing::debug!(size = source.size, sha256 = %source.sha256, "streamed source file");
+
+    *input.disk_reservation = source.disk_reservation.take();
+
+    input.metrics.source_bytes = source.size;
+
+    source.check_size()?;
+
+    if let Some(tenant) = input.tenant {
+        tenant.check_source_size(source.size)?;
+    }
+
+    if let Some(quota) = input.quota {
+        quota.record_source_bytes(source.size).await;
+    }
+
+    // Metadata is read before converting so a move fails before anything is uploaded
+    if input.request.move_source {
+        let metadata =
+            get_source_metadata(input.storage, &input.request, source.etag.as_deref()).await?;
+
+        input.moved_source = Some(MovedSource {
+            etag: source.etag.clone(),
+            metadata,
+        });
+    }
+
+    if let Some(format) = detect_unsupported_format(&source.header) {
+        tracing::warn!(?format, "source is an unsupported format");
+        return Err(format.error());
+    }
+
+    // Text based formats and drawings are passed to x2t as they are not reliably detected
+    let source_format = input
+        .request
+        .source_format
+        .or_else(|| detect_input_format(&source.header))
+        .or_else(|| input.request.source_extension_format());
+
+    if let Some(format) = source_format
+        && let Some(output) = input
+            .request
+            .output_formats()
+            .into_iter()
+            .find(|output| !format.supports_output(*output))
+    {
+        tracing::warn!(
+            ?format,
+            ?output,
+            "source cannot be converted to the output format"
+        );
+        return Err(ConvertError {
+            reason: Some("UNSUPPORTED_FORMAT"),
+            x2t_code: None,
+            message: format!(
+                "{} outputs are not supported for the source format",
+                output.extension()
+            ),
+        });
+    }
+
+    if let Some(template_data) = &input.request.template_data {
+        fill_template(
+            &input.paths.input_path,
+            &input.paths.template_path,
+            template_data,
+        )
+        .await?;
+    }
+
+    run_pre_processors(&SourceContext {
+        request_id: input.request_id,
+        source_path: &input.paths.input_path,
+        processed_path: &input.paths.processed_path,
+        source_format,
+    })
+    .await?;
+
+    // Convert into each of the formats, every conversion is allowed to finish
+    // so that failure artifacts are captured for all the failed formats
+    let results = join_all(
+        input
+            .paths
+            .outputs
+            .iter()
+            .map(|output| convert_output(&input, &source, source_format, &progress, output)),
+    )
+    .await;
+
+    let output_files = results
+        .into_iter()
+        .collect::<Result<Vec<Vec<PathBuf>>, ConvertError>>()?;
+
+    for path in output_files.iter().flatten() {
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            input.metrics.output_bytes += metadata.len();
+        }
+    }
+
+    // Compare the fonts used by the source against the fonts available to x2t
+    let all_fonts_path = match input.font_cache {
+        Some(cache) => cache.all_fonts_path.clone(),
+        None => input.x2t_path.join(ALL_FONTS_FILE_NAME),
+    };
+    let substituted_fonts = substituted_fonts(&input.paths.input_path, &all_fonts_path).await;
+
+    if !substituted_fonts.is_empty() {
+        tracing::warn!(
+            ?substituted_fonts,
+            "source uses fonts that are not available"
+        );
+    }
+
+    let output = deliver_outputs(&input, &progress, &output_files).await?;
+
+    // Moved sources are deleted once the outputs are uploaded
+    if input.request.delete_source && input.moved_source.is_none() {
+        delete_source_object(input.storage, &input.request, source.etag.as_deref()).await?;
+    }
+
+    Ok(ConvertResult {
+        output,
+        substituted_fonts,
+    })
+}
+
+/// Upload the converted outputs to the destination, or read the output to be
+/// returned inline when no destination was provided. The `output_files` are
+/// the files written for each of the outputs
+async fn deliver_outputs(
+    input: &X2tInput<'_>,
+    progress: &ProgressReporter<'_>,
+    output_files: &[Vec<PathBuf>],
+) -> Result<ConvertOutput, ConvertError> {
+    let outputs = &input.paths.outputs;
+
+    // Package the outputs into an archive
+    if input.request.archive {
+        create_output_archive(
+            input.request_id,
+            &input.paths.archive_path,
+            outputs
+                .iter()
+                .zip(output_files)
+                .flat_map(|(output, files)| {
+                    files.iter().enumerate().map(|(index, path)| ArchiveEntry {
+                        name: if input.request.is_sheet_export(output.format) {
+                            format!("output.{index}.{}", output.format.key_suffix())
+                        } else {
+                            format!("output.{}", output.format.key_suffix())
+                        },
+                        path: path.clone(),
+                        format: output.format,
+                    })
+                })
+                .collect(),
+        )
+        .await?;
+
+        let Some((dest_bucket, dest_key)) = input.request.destination() else {
+            let bytes = read_inline_output(&input.paths.archive_path).await?;
+
+            return Ok(ConvertOutput::Inline {
+                bytes,
+                content_type: ARCHIVE_CONTENT_TYPE,
+            });
+        };
+
+        let result = upload_output(
+            input,
+            progress,
+            dest_bucket,
+            dest_key,
+            &input.paths.archive_path,
+            &input.paths.compressed_path,
+            ARCHIVE_CONTENT_TYPE,
+        )
+        .await;
+
+        finish_move(input, dest_bucket, &[dest_key.to_string()], result).await?;
+
+        return Ok(ConvertOutput::Uploaded);
+    }
+
+    let Some((dest_bucket, dest_key)) = input.request.destination() else {
+        // Validation ensures there is only a single output when returning inline
+        let output = &outputs[0];
+        let bytes = read_inline_output(&output.output_path).await?;
+
+        return Ok(ConvertOutput::Inline {
+            bytes,
+            content_type: output.format.content_type(),
+        });
+    };
+
+    let dest_keys = input.request.destination_keys(dest_key);
+
+    // Each sheet of a sheet export is stored at its own key
+    let uploads = outputs.iter().zip(output_files).zip(dest_keys).flat_map(
+        |((output, files), output_key)| {
+            let sheet_export = input.request.is_sheet_export(output.format);
+
+            files.iter().enumerate().map(move |(index, path)| {
+                if sheet_export {
+                    OutputUpload {
+                        key: format!("{dest_key}.{index}.{}", output.format.key_suffix()),
+                        path,
+                        compressed_path: path.with_extension("csv.compressed"),
+                        format: output.format,
+                    }
+                } else {
+                    OutputUpload {
+                        key: output_key.clone(),
+                        path,
+                        compressed_path: output.compressed_path.clone(),
+                        format: output.format,
+                    }
+                }
+            })
+        },
+    );
+    let uploads: Vec<OutputUpload> = uploads.collect();
+
+    let result = try_join_all(uploads.iter().map(|upload| async move {
+        upload_output(
+            input,
+            progress,
+            dest_bucket,
+            &upload.key,
+            upload.path,
+            &upload.compressed_path,
+            upload.format.content_type(),
+        )
+        .await
+    }))
+    .await
+    .map(|_| ());
+
+    let dest_keys: Vec<String> = uploads.into_iter().map(|upload| upload.key).collect();
+    finish_move(input, dest_bucket, &dest_keys, result).await?;
+
+    Ok(ConvertOutput::Uploaded)
+}
+
+/// Complete moving the source once the outputs have been uploaded (When moving), the
+/// source is deleted after every output is uploaded. The uploaded outputs are removed
+/// when an upload or deleting the source fails so the source is left in place rather
+/// than partially moved
+async fn finish_move(
+    input: &X2tInput<'_>,
+    dest_bucket: &str,
+    dest_keys: &[String],
+    upload_result: Result<(), ConvertError>,
+) -> Result<(), ConvertError> {
+    let Some(moved_source) = &input.moved_source else {
+        return upload_result;
+    };
+
+    let result = match upload_result {
+        Ok(()) => {
+            delete_source_object(input.storage, &input.request, moved_source.etag.as_deref()).await
+        }
+        Err(error) => Err(error),
+    };
+
+    if result.is_err() {
+        tracing::warn!(%dest_bucket, ?dest_keys, "move failed, removing uploaded outputs");
+
+        let source = match input.request.source() {
+            Ok(Source::S3 { bucket, key, .. }) => Some((bucket, key)),
+            _ => None,
+        };
+
+        for dest_key in dest_keys {
+            // The source is never removed when rolling back
+            if source == Some((dest_bucket, dest_key.as_str())) {
+                continue;
+            }
+
+            let options = DeleteOptions {
+                requester_pays: input.request.requester_pays,
+                ..Default::default()
+            };
+
+            if let Err(err) = input
+                .storage
+                .delete_object(dest_bucket, dest_key, options)
+                .await
+            {
+                tracing::error!(?err, %dest_bucket, %dest_key, "failed to remove uploaded output");
+            }
+        }
+    }
+
+    result
+}
+
+/// File to upload to the destination
+struct OutputUpload<'a> {
+    key: String,
+    path: &'a Path,
+    /// Path the file is compressed to (When requested)
+    compressed_path: PathBuf,
+    format: OutputFormat,
+}
+
+/// Run x2t to convert the source file into a single output format
+async fn convert_output(
+    input: &X2tInput<'_>,
+    source: &SourceFile,
+    source_format: Option<InputFormat>,
+    progress: &ProgressReporter<'_>,
+    output_paths: &OutputPaths,
+) -> Result<Vec<PathBuf>, ConvertError> {
+    let format = output_paths.format;
+
+    // JSON and worksheet selections are exported from an XLSX workbook produced by x2t
+    let workbook_export = match (format, &input.request.csv) {
+        (OutputFormat::Json, _) => Some(WorkbookExport::Json),
+        (OutputFormat::Csv, Some(csv)) if csv.requires_workbook() => Some(WorkbookExport::Csv(csv)),
+        _ => None,
+    };
+
+    let (x2t_format, x2t_output_path) = match workbook_export {
+        Some(_) => (OutputFormat::Xlsx, &output_paths.workbook_path),
+        None => (format, &output_paths.output_path),
+    };
+
+    // Presentation and spreadsheet options only apply to the paged PDF outputs
+    let json_params = match (&input.request.presentation, &input.request.spreadsheet) {
+        (Some(presentation), _) if format.is_pdf() => Some(presentation.json_params()),
+        (_, Some(spreadsheet)) if format.is_pdf() => Some(spreadsheet.json_params()),
+        _ => None,
+    };
+
+    // Generate the convert config
+    let x2t_config = X2tConfig {
+        file_from: &input.paths.input_path,
+        file_to: x2t_output_path,
+        font_dir: input.fonts_path,
+        all_fonts_path: input.font_cache.map(|cache| cache.all_fonts_path.as_path()),
+        theme_dir: input.themes_path,
+        temp_dir: &output_paths.temp_path,
+        format_from: source_format,
+        embedded_fonts: input.request.embed_fonts,
+        json_params: json_params.as_deref(),
+        thumbnail_size: input
+            .request
+            .raster
+            .as_ref()
+            .and_then(RasterOptions::thumbnail_size),
+        password: input.password.map(DocumentPassword::as_str),
+        spreadsheet_limit: input
+            .request
+            .spreadsheet_limits
+            .as_ref()
+            .map(|limits| limits.max_uncompressed_size),
+        format: x2t_format,
+    };
+    let config = x2t_config.to_xml();
+
+    tracing::debug!(?format, "writing config file");
+
+    // Write the config file to disk
+    tokio::fs::write(&output_paths.config_path, config.as_bytes())
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to write config file");
+            ConvertError {
+                reason: Some("WRITE_CONFIG_FILE"),
+                x2t_code: None,
+                message: "failed to write config file".to_string(),
+            }
+        })?;
+
+    // x2t runs that crash or exit with a truncated output are retried using the
+    // retry policy of the conversion
+    let run = RetryPolicy::current()
+        .run("x2t", x2t_retry_class, || {
+            run_x2t(
+                input,
+                source,
+                progress,
+                output_paths,
+                x2t_format,
+                x2t_output_path,
+            )
+        })
+        .await;
+
+    // Remove the password from the config once x2t has finished with it, so it is
+    // not kept on disk or captured within the debug and failure artifacts
+    let config = match x2t_config.password {
+        Some(_) => redact_config_file(&output_paths.config_path, x2t_config).await,
+        None => config,
+    };
+
+    let X2tRun { stderr, result } = run?;
+
+    // Post-process after validating so only complete outputs are processed
+    let result = match result {
+        Ok(()) if format.is_pdf() => process_pdf_output(input, output_paths).await,
+        Ok(()) if format == OutputFormat::Thumbnail => match &input.request.raster {
+            Some(raster) if raster.requires_processing() => {
+                raster.process(&output_paths.output_path).await
+            }
+            _ => Ok(()),
+        },
+        result => result,
+    };
+
+    let result = match (result, workbook_export) {
+        (Ok(()), Some(export)) => {
+            export
+                .export(
+                    &output_paths.workbook_path,
+                    &output_paths.output_path,
+                    &output_paths.temp_path.join("sheets"),
+                )
+                .await
+        }
+        (result, _) => result.map(|()| vec![output_paths.output_path.clone()]),
+    };
+
+    // PDF outputs run the post-processors before they are linearized and signed
+    let result = match result {
+        Ok(output_files) if !format.is_pdf() => {
+            async {
+                for output_file in &output_files {
+                    run_post_processors(&OutputContext {
+                        request_id: input.request_id,
+                        source_path: &input.paths.input_path,
+                        output_path: output_file,
+                        processed_path: &output_paths.processed_path,
+                        format,
+                    })
+                    .await?;
+                }
+
+                Ok(output_files)
+            }
+            .await
+        }
+        result => result,
+    };
+
+    if let Err(error) = &result {
+        // Store the failure artifacts for reproducing the failure
+        persist_failure_artifacts(
+            input.storage,
+            FailedConversion {
+                request_id: input.request_id,
+                source_bucket: input.request.source_bucket.as_deref(),
+                source_key: input.request.source_key.as_deref(),
+                source_version_id: input.request.source_version_id.as_deref(),
+                source_url: input.request.source_url.as_deref(),
+                source_sha256: &source.sha256,
+                dest_bucket: input.request.dest_bucket.as_deref(),
+                dest_key: input.request.dest_key.as_deref(),
+                input_path: &input.paths.input_path,
+                config_bytes: config.as_bytes(),
+                stderr: &stderr,
+                reason: error.reason,
+                x2t_code: error.x2t_code,
+                message: &error.message,
+            },
+        )
+        .await;
+    }
+
+    result
+}
+
+/// Outcome of a single x2t run
+struct X2tRun {
+    stderr: Vec<u8>,
+    /// Result of the run, failing when x2t failed or produced an invalid output
+    result: Result<(), ConvertError>,
+}
+
+/// Run x2t with the config file written for the output, fails when x2t could not be run
+async fn run_x2t(
+    input: &X2tInput<'_>,
+    source: &SourceFile,
+    progress: &ProgressReporter<'_>,
+    output_paths: &OutputPaths,
+    x2t_format: OutputFormat,
+    x2t_output_path: &Path,
+) -> Result<X2tRun, ConvertError> {
+    let format = output_paths.format;
+
+    // Wait for a free x2t slot
+    let _permit = acquire_x2t_permit().await.map_err(|err| {
+        tracing::error!(?err, "failed to acquire x2t permit");
+        ConvertError {
+            reason: Some("RUN_X2T"),
+            x2t_code: None,
+            message: "failed to run x2t".to_string(),
+        }
+    })?;
+
+    let x2t_bin = input.x2t_path.join(X2T_BIN);
+    let mut command = x2t_command(&x2t_bin, input.x2t_path);
+    command.arg(output_paths.config_path.display().to_string());
+    apply_x2t_process_limits(&mut command);
+
+    // Core dumps are written into their own directory so they can be collected
+    let core_dumps = is_core_dump_enabled()
+        && match tokio::fs::create_dir_all(&output_paths.dump_path).await {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::error!(?err, "failed to create core dump directory");
+                false
+            }
+        };
+
+    if core_dumps {
+        enable_core_dumps(&mut command, &output_paths.dump_path);
+    }
+
+    // Cancelling drops the running command which kills x2t
+    command.kill_on_drop(true);
+
+    tracing::debug!(?format, "running x2t");
+
+    let output = progress
+        .track(ConvertStage::Converting, async {
+            match &input.cancel {
+                Some(cancel) => tokio::select! {
+                    output = command.output() => Ok(output),
+                    _ = cancel.cancelled() => Err(cancelled_error()),
+                },
+                None => Ok(command.output().await),
+            }
+        })
+        .await;
+
+    let output = output?.map_err(|err| {
+        tracing::error!(?err, "failed to run x2t");
+        ConvertError {
+            reason: Some("RUN_X2T"),
+            x2t_code: None,
+            message: "failed to run x2t".to_string(),
+        }
+    })?;
+
+    tracing::debug!(?format, "x2t complete");
+
+    if core_dumps && let Some(signal) = exit_signal(&output.status) {
+        tracing::error!(?format, signal, "x2t was killed by a signal");
+
+        upload_crash_artifacts(
+            input.storage,
+            CrashedConversion {
+                request_id: input.request_id,
+                index: output_paths.index,
+                format,
+                signal,
+                x2t_bin: &x2t_bin,
+                dump_path: &output_paths.dump_path,
+                stderr: &output.stderr,
+            },
+        )
+        .await;
+    }
+
+    let result = if output.status.success() {
+        // x2t occasionally exits successfully with a truncated output
+        check_output(x2t_output_path, x2t_format).await
+    } else {
+        let error_code = output.status.code();
+        let message = error_code
+            .and_then(get_error_code_message)
+            .unwrap_or("unknown error occurred");
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let file_condition = get_file_condition(&source.header);
+        let diagnosis = diagnose_x2t_failure(&stderr, exit_signal(&output.status));
+
+        tracing::error!(
+            "error processing file (format = {format:?}, stderr = {stderr}, exit code = {error_code:?}, file_condition = {file_condition:?}, diagnosis = {diagnosis:?})"
+        );
+
+        Err(match diagnosis {
+            Some(diagnosis) => diagnosis.error(error_code),
+            None if error_code.is_some_and(is_icu_error_code) => ConvertError {
+                reason: Some("X2T_ICU_DATA"),
+                x2t_code: error_code,
+                message: format!(
+                    "{message}, x2t could not load its ICU data, the location can be configured \
+                    using X2T_ICU_DATA_PATH"
+                ),
+            },
+            None if error_code.is_some_and(is_limits_error_code) => ConvertError {
+                reason: Some("SOURCE_LIMITS_EXCEEDED"),
+                x2t_code: error_code,
+                message: format!("{message}, the limits can be raised using spreadsheet_limits"),
+            },
+            None => match file_condition {
+                FileCondition::LikelyCorrupted => ConvertError {
+                    reason: Some("FILE_LIKELY_CORRUPTED"),
+                    x2t_code: error_code,
+                    message: "file is corrupted".to_string(),
+                },
+                FileCondition::LikelyEncrypted => ConvertError {
+                    reason: Some("FILE_LIKELY_ENCRYPTED"),
+                    x2t_code: error_code,
+                    message: "file is encrypted".to_string(),
+                },
+                _ => ConvertError {
+                    reason: None,
+                    x2t_code: error_code,
+                    message: message.to_string(),
+                },
+            },
+        })
+    };
+
+    Ok(X2tRun {
+        stderr: output.stderr,
+        result,
+    })
+}
+
+/// Retry class of a x2t run, crashes and truncated outputs may succeed when retried
+fn x2t_retry_class(run: &Result<X2tRun, ConvertError>) -> Option<RetryClass> {
+    match run {
+        Ok(X2tRun {
+            result: Err(error), ..
+        }) if matches!(error.reason, Some("X2T_CRASHED" | "OUTPUT_INVALID")) => {
+            Some(RetryClass::X2tCrash)
+        }
+        _ => None,
+    }
+}
+
+/// Rewrite the config file without the password, the config file is removed
+/// instead when it cannot be rewritten. Returns the redacted config
+async fn redact_config_file(config_path: &Path, config: X2tConfig<'_>) -> String {
+    let redacted = X2tConfig {
+        password: None,
+        ..config
+    }
+    .to_xml();
+
+    if let Err(err) = tokio::fs::write(config_path, redacted.as_bytes()).await {
+        tracing::error!(?err, "failed to redact config file");
+        remove_temp_file(config_path).await;
+    }
+
+    redacted
+}
+
+/// Fit slides, flatten forms and annotations, bookmark, stamp, attach the source to,
+/// run the registered post-processors, linearize and sign a PDF output (When requested).
+/// Linearizing follows the changes to the content as rewriting the PDF afterwards would
+/// undo the linearization, signing is last as it covers the final bytes of the output
+async fn process_pdf_output(
+    input: &X2tInput<'_>,
+    output_paths: &OutputPaths,
+) -> Result<(), ConvertError> {
+    // Outputs are checked before processing so untagged outputs fail early
+    if input.request.tagged {
+        check_tagged_pdf(&output_paths.output_path).await?;
+    }
+
+    // Slides are fitted first so the stamp is placed on the resized pages
+    if let Some(presentation) = &input.request.presentation
+        && let Some(page_size) = presentation.page_size
+    {
+        fit_slides(
+            &output_paths.output_path,
+            &output_paths.processed_path,
+            page_size,
+            presentation.fit,
+        )
+        .await?;
+    }
+
+    let flatten = FlattenOptions {
+        forms: input.request.flatten_forms,
+        annotations: input.request.annotations,
+    };
+
+    if flatten.is_enabled() {
+        flatten_pdf(
+            &output_paths.output_path,
+            &output_paths.processed_path,
+            flatten,
+        )
+        .await?;
+    }
+
+    if input.request.bookmarks {
+        add_heading_bookmarks(
+            &output_paths.output_path,
+            &output_paths.processed_path,
+            &input.paths.input_path,
+        )
+        .await?;
+    }
+
+    // PDF/A requires embedded fonts which the stamp does not use
+    if let Some(stamp) = &input.request.stamp
+        && output_paths.format == OutputFormat::Pdf
+    {
+        let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+        stamp_pdf(
+            &output_paths.output_path,
+            &output_paths.processed_path,
+            stamp,
+            StampValues {
+                request_id: input.request_id,
+                timestamp: &timestamp,
+            },
+        )
+        .await?;
+    }
+
+    // PDF/A does not allow embedding files that are not themselves PDF/A
+    if input.request.attach_source && output_paths.format == OutputFormat::Pdf {
+        attach_source(
+            &output_paths.output_path,
+            &output_paths.processed_path,
+            &input.paths.input_path,
+            &input.request.source_file_name(),
+        )
+        .await?;
+    }
+
+    run_post_processors(&OutputContext {
+        request_id: input.request_id,
+        source_path: &input.paths.input_path,
+        output_path: &output_paths.output_path,
+        processed_path: &output_paths.processed_path,
+        format: output_paths.format,
+    })
+    .await?;
+
+    if input.request.linearize {
+        linearize_pdf(&output_paths.output_path, &output_paths.processed_path).await?;
+    }
+
+    if let Some(sign) = &input.request.sign {
+        sign_pdf(
+            &output_paths.output_path,
+            &output_paths.processed_path,
+            sign,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Compress (When requested) and upload an output file to the destination
+async fn upload_output(
+    input: &X2tInput<'_>,
+    progress: &ProgressReporter<'_>,
+    dest_bucket: &str,
+    dest_key: &str,
+    output_path: &Path,
+    compressed_path: &Path,
+    content_type: &str,
+) -> Result<(), ConvertError> {
+    // Outputs of cancelled conversions are not stored
+    if input
+        .cancel
+        .as_ref()
+        .is_some_and(CancelSignal::is_cancelled)
+    {
+        return Err(cancelled_error());
+    }
+
+    // Compress the output before uploading
+    let (upload_path, content_encoding) = match input.request.compression {
+        Some(compression) => {
+            compress_file(compression, output_path, compressed_path).await?;
+            (compressed_path, Some(compression.content_encoding()))
+        }
+        None => (output_path, None),
+    };
+
+    progress
+        .track(
+            ConvertStage::Uploading,
+            stream_output_file(
+                input.storage,
+                dest_bucket,
+                dest_key,
+                upload_path,
+                PutOptions {
+                    content_type: Some(content_type),
+                    content_encoding,
+                    metadata: input
+                        .moved_source
+                        .as_ref()
+                        .map(|moved_source| &moved_source.metadata),
+                    if_match: None,
+                    write: input.request.write_options(),
+                },
+            ),
+        )
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct ConvertRequest {
+    /// Bucket the input source file is within
+    #[serde(default)]
+    source_bucket: Option<String>,
+    /// Key within the source bucket for the source file
+    #[serde(default)]
+    source_key: Option<String>,
+    /// Version of the source object to convert, the latest version is
+    /// converted when not provided
+    #[serde(default)]
+    source_version_id: Option<String>,
+    /// ETag the source object is expected to have (As returned by S3), the
+    /// conversion fails with `SOURCE_CHANGED` when the object has been modified
+    #[serde(default)]
+    expected_etag: Option<String>,
+    /// Format of the source file (i.e `rtf`, `html`, `md` or `vsdx`), detected from the
+    /// contents of text based sources and drawings or the source extension when not provided
+    #[serde(default)]
+    source_format: Option<InputFormat>,
+    /// URL to download the source file from instead of S3, only
+    /// allowed for hosts within the URL source allowlist
+    #[serde(default)]
+    source_url: Option<String>,
+    /// Whether the `source_url` is a presigned S3 URL provided by AWS rather
+    /// than the caller, see [ConvertRequest::set_presigned_source_url]
+    #[serde(skip)]
+    presigned_source: bool,
+
+    /// Bucket to store the output file, when the destination is omitted
+    /// the output is returned inline (HTTP events only)
+    #[serde(default)]
+    dest_bucket: Option<String>,
+    /// Key within the `dest_bucket` for the output file
+    #[serde(default)]
+    dest_key: Option<String>,
+    /// Canned ACL to apply to the stored outputs (i.e `bucket-owner-full-control`
+    /// for outputs written into buckets owned by other accounts)
+    #[serde(default)]
+    dest_acl: Option<ObjectAcl>,
+    /// Storage class of the stored outputs (i.e `STANDARD_IA` for outputs that
+    /// are rarely read), the bucket default is used when not provided
+    #[serde(default)]
+    storage_class: Option<ObjectStorageClass>,
+    /// Additional checksum (`CRC32C` or `SHA256`) S3 verifies the stored outputs
+    /// against, readable later through `GetObjectAttributes`
+    #[serde(default)]
+    checksum_algorithm: Option<ObjectChecksumAlgorithm>,
+    /// Account ID expected to own the `dest_bucket`, outputs are not written when
+    /// the bucket is owned by another account
+    #[serde(default)]
+    expected_bucket_owner: Option<String>,
+
+    /// Acknowledge that reading the source and writing the outputs is charged to
+    /// the requester, required for buckets with requester pays enabled
+    #[serde(default)]
+    requester_pays: bool,
+
+    /// Format to convert the source file into, defaults to PDF
+    #[serde(default)]
+    output_format: OutputFormat,
+
+    /// Multiple formats to convert the source file into, replaces `output_format`
+    /// when provided. Each output is stored at `{dest_key}.{format}` (i.e
+    /// `report.pdf` and `report.thumbnail.png` for the `report` key)
+    #[serde(default)]
+    output_formats: Option<Vec<OutputFormat>>,
+
+    /// Compression to apply to the output before it is uploaded to the
+    /// destination, the Content-Encoding of the object is set accordingly
+    #[serde(default)]
+    compression: Option<OutputCompression>,
+
+    /// Whether fonts should be embedded within the output, disabling embedding
+    /// produces smaller PDFs that rely on the fonts installed where they are viewed
+    #[serde(default)]
+    embed_fonts: Option<bool>,
+
+    /// Named font profile to render with, the profile fonts are loaded from the
+    /// font profiles prefix of the fonts bucket alongside the x2t fonts
+    #[serde(default)]
+    font_profile: Option<String>,
+
+    /// Version of x2t to convert with, one of the configured x2t installations.
+    /// Uses the default x2t version when not provided
+    #[serde(default)]
+    x2t_version: Option<String>,
+
+    /// Package the outputs into a single ZIP archive with an embedded
+    /// manifest.json describing the outputs
+    #[serde(default)]
+    archive: bool,
+
+    /// Linearize ("fast web view") the PDF outputs so viewers can render the
+    /// first page while the rest of the file streams, requires qpdf
+    #[serde(default)]
+    linearize: bool,
+
+    /// Generate PDF bookmarks from the headings of DOCX sources, each bookmark
+    /// links to the page the heading is found on
+    #[serde(default)]
+    bookmarks: bool,
+
+    /// Require a tagged (accessible) PDF output, conversions fail when x2t
+    /// does not produce a tagged PDF for the source
+    #[serde(default)]
+    tagged: bool,
+
+    /// Flatten the form fields of PDF outputs (i.e from DOCXF/OFORM or PDF form sources),
+    /// the filled values are drawn as static content and the fields can no longer be edited
+    #[serde(default)]
+    flatten_forms: bool,
+
+    /// Flatten or remove the markup annotations (comments, highlights, shapes) of
+    /// PDF outputs, annotations are kept as is when not set
+    #[serde(default)]
+    annotations: Option<AnnotationMode>,
+
+    /// DPI, maximum dimensions and background for thumbnail outputs
+    #[serde(default)]
+    raster: Option<RasterOptions>,
+
+    /// Slide selection and speaker notes for presentation sources converted to PDF
+    #[serde(default)]
+    presentation: Option<PresentationOptions>,
+
+    /// Margins, scaling, gridlines and headings for spreadsheet sources converted to PDF
+    #[serde(default)]
+    spreadsheet: Option<SpreadsheetPrintOptions>,
+
+    /// Raised x2t limits for large spreadsheet sources
+    #[serde(default)]
+    spreadsheet_limits: Option<SpreadsheetLimits>,
+
+    /// Worksheet selection for CSV outputs, x2t only exports the first worksheet.
+    /// When exporting all sheets each sheet is stored at `{dest_key}.{index}.csv`
+    #[serde(default)]
+    csv: Option<CsvOptions>,
+
+    /// Values for the `{{key}}` placeholders of a DOCX source, the placeholders
+    /// are filled before converting. Nested values are referenced by their dotted
+    /// path (i.e `{{customer.name}}`)
+    #[serde(default)]
+    template_data: Option<Map<String, Value>>,
+
+    /// Header, footer and page numbers to stamp onto the PDF outputs, not
+    /// applied to PDF/A outputs as the stamp font is not embedded
+    #[serde(default)]
+    stamp: Option<StampOptions>,
+
+    /// Embed the source file as an attachment within the PDF outputs so the
+    /// editable original is kept alongside the rendered version, not applied to
+    /// PDF/A outputs. The attachment is named after the source key or URL
+    #[serde(default)]
+    attach_source: bool,
+
+    /// Sign the PDF outputs with a PAdES signature using the configured KMS key
+    /// and certificate, the signature is appended after every other change
+    #[serde(default)]
+    sign: Option<SignOptions>,
+
+    /// Secrets Manager secret containing the password of an encrypted source, the
+    /// password is read from the secret when converting rather than being provided
+    #[serde(default)]
+    password_secret: Option<PasswordSecret>,
+
+    /// Delete the source object once the outputs have been uploaded to the destination,
+    /// only the converted version of the source is deleted. Requires an S3 source
+    #[serde(default)]
+    delete_source: bool,
+
+    /// Move the source to the destination, the outputs are stored with the metadata
+    /// and tags of the source which is deleted once every output is uploaded. The
+    /// uploaded outputs are removed when the move fails. Requires an S3 source
+    #[serde(default, rename = "move")]
+    move_source: bool,
+
+    /// Tenant the request is made on behalf of, the settings of the tenant restrict
+    /// the buckets, formats, font profiles and source size of the request. Required
+    /// when tenants are configured. HTTP requests may only name the tenant of the
+    /// authenticated caller, taken from the JWT claims or `TENANT_CALLERS`
+    #[serde(default)]
+    tenant: Option<String>,
+
+    /// Upload the config XML, raw x2t output and intermediate files of the
+    /// conversion to the debug artifacts location for diagnosing failures
+    #[serde(default)]
+    debug: bool,
+
+    /// Attempts, backoff and error classes retried for the S3 requests, tenant
+    /// callback and x2t runs of the conversion, replacing the configured retry policy
+    #[serde(default)]
+    retry: Option<RetryOptions>,
+}
+
+/// Location of the source file
+enum Source<'a> {
+    S3 {
+        bucket: &'a str,
+        key: &'a str,
+        /// Version and precondition for retrieving the object
+        options: GetOptions<'a>,
+    },
+    Url(&'a str),
+}
+
+impl ConvertRequest {
+    /// Location the source file should be loaded from
+    fn source(&self) -> Result<Source<'_>, ConvertError> {
+        match (
+            self.source_bucket.as_deref(),
+            self.source_key.as_deref(),
+            self.source_url.as_deref(),
+        ) {
+            (Some(bucket), Some(key), None) => Ok(Source::S3 {
+                bucket,
+                key,
+                options: GetOptions {
+                    version_id: self.source_version_id.as_deref(),
+                    if_match: self.expected_etag.as_deref(),
+                    requester_pays: self.requester_pays,
+                },
+            }),
+            (None, None, Some(url)) => Ok(Source::Url(url)),
+            _ => Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "either source_bucket and source_key or source_url must be provided"
+                    .to_string(),
+            }),
+        }
+    }
+
+    /// Use a presigned S3 URL provided by AWS (i.e the input of an S3 Object Lambda)
+    /// as the source, replacing the requested source. The URL is trusted so the
+    /// hosts of the URL source policy are not applied
+    pub fn set_presigned_source_url(&mut self, url: String) {
+        self.source_bucket = None;
+        self.source_key = None;
+        self.source_version_id = None;
+        self.expected_etag = None;
+        self.source_url = Some(url);
+        self.presigned_source = true;
+    }
+
+    /// Bucket and key the output should be stored at, [None] when the
+    /// output should be returned inline
+    pub fn destination(&self) -> Option<(&str, &str)> {
+        Some((self.dest_bucket.as_deref()?, self.dest_key.as_deref()?))
+    }
+
+    /// Options that change whether the source can be converted, see [FailureOptions]
+    fn failure_options(&self) -> FailureOptions<'_> {
+        FailureOptions {
+            password_secret_arn: self
+                .password_secret
+                .as_ref()
+                .map(|secret| secret.secret_arn.as_str()),
+            password_key: self
+                .password_secret
+                .as_ref()
+                .and_then(|secret| secret.key.as_deref()),
+            spreadsheet_limits: self.spreadsheet_limits.as_ref(),
+        }
+    }
+
+    /// Tenant the request is made on behalf of
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Bucket and keys the outputs are uploaded to, in the same order as the
+    /// [ConvertRequest::output_formats]. [None] when the output is returned inline
+    pub fn output_locations(&self) -> Option<(&str, Vec<String>)> {
+        let (dest_bucket, dest_key) = self.destination()?;
+        Some((dest_bucket, self.destination_keys(dest_key)))
+    }
+
+    /// Audit record for converting this request, the outcome is set once converted
+    fn audit_record(&self, request_id: &str, caller: Option<String>) -> AuditRecord {
+        let source = match self.source() {
+            Ok(Source::S3 { bucket, key, .. }) => Some(format!("s3://{bucket}/{key}")),
+            // Queries are removed as they can contain credentials (i.e presigned URLs)
+            Ok(Source::Url(url)) => Url::parse(url).ok().map(|mut url| {
+                url.set_query(None);
+                url.set_fragment(None);
+                url.to_string()
+            }),
+            Err(_) => None,
+        };
+
+        AuditRecord {
+            request_id: request_id.to_string(),
+            started_at: Utc::now(),
+            caller,
+            tenant: self.tenant.clone(),
+            source,
+            destination: self
+                .destination()
+                .map(|(bucket, key)| format!("s3://{bucket}/{key}")),
+            output_formats: self.output_formats(),
+            success: false,
+            reason: None,
+            x2t_code: None,
+            duration_ms: 0,
+        }
+    }
+
+    /// Check the buckets, formats and font profile of the request are allowed by the `tenant`
+    fn check_tenant(&self, tenant: &TenantSettings) -> Result<(), ConvertError> {
+        let buckets = [
+            ("source_bucket", self.source_bucket.as_deref()),
+            ("dest_bucket", self.dest_bucket.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(field, bucket)| Some((field, bucket?)));
+
+        tenant.check_request(
+            buckets,
+            &self.output_formats(),
+            self.font_profile.as_deref(),
+        )
+    }
+
+    /// Usage record for converting this request, the outcome is set once converted
+    fn usage_record(&self, request_id: &str) -> UsageRecord {
+        let source_format = self
+            .source_file_name()
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+
+        UsageRecord {
+            request_id: request_id.to_string(),
+            started_at: Utc::now(),
+            tenant: self.tenant.clone(),
+            source_format,
+            output_formats: self.output_formats(),
+            success: false,
+            reason: None,
+            source_bytes: 0,
+            output_bytes: 0,
+            throttled_requests: 0,
+            duration_ms: 0,
+            memory_mb: None,
+            gb_seconds: None,
+        }
+    }
+
+    /// Ownership options for the outputs written to the destination
+    fn write_options(&self) -> WriteOptions<'_> {
+        WriteOptions {
+            storage_class: self.storage_class,
+            acl: self.dest_acl,
+            expected_bucket_owner: self.expected_bucket_owner.as_deref(),
+            requester_pays: self.requester_pays,
+            checksum_algorithm: self.checksum_algorithm,
+        }
+    }
+
+    /// Keys the uploaded outputs are stored at within the destination bucket, in
+    /// the same order as the [ConvertRequest::output_formats]
+    fn destination_keys(&self, dest_key: &str) -> Vec<String> {
+        if self.archive {
+            return vec![dest_key.to_string()];
+        }
+
+        match self.output_formats {
+            // Multiple outputs are stored under format specific keys
+            Some(_) => self
+                .output_formats()
+                .iter()
+                .map(|format| format!("{dest_key}.{}", format.key_suffix()))
+                .collect(),
+            None => vec![dest_key.to_string()],
+        }
+    }
+
+    /// File name of the source, taken from the last segment of the source key or URL
+    fn source_file_name(&self) -> String {
+        let name = match (self.source_key.as_deref(), self.source_url.as_deref()) {
+            (Some(key), _) => key.rsplit('/').next().map(str::to_string),
+            (None, Some(url)) => Url::parse(url)
+                .ok()
+                .and_then(|url| url.path_segments()?.next_back().map(str::to_string)),
+            (None, None) => None,
+        };
+
+        name.filter(|name| !name.is_empty())
+            .unwrap_or_else(|| DEFAULT_SOURCE_FILE_NAME.to_string())
+    }
+
+    /// Format of the source taken from the extension of the source file name, for
+    /// formats that cannot be detected from their contents
+    fn source_extension_format(&self) -> Option<InputFormat> {
+        let name = self.source_file_name();
+        let (_, extension) = name.rsplit_once('.')?;
+        InputFormat::from_extension(extension)
+    }
+
+    /// Whether the `format` output is exported as a separate file per worksheet
+    fn is_sheet_export(&self, format: OutputFormat) -> bool {
+        format == OutputFormat::Csv && self.csv.as_ref().is_some_and(|csv| csv.all_sheets)
+    }
+
+    /// Parameters identifying the outputs of the conversion within the result cache,
+    /// [None] when the outputs cannot be cached
+    fn result_cache_params<'a>(&'a self, source_etag: &'a str) -> Option<ResultCacheParams<'a>> {
+        // The number of sheet export outputs is only known after converting
+        if self.is_sheet_export(OutputFormat::Csv) {
+            return None;
+        }
+
+        // Stamps that include the request would be reused by later requests
+        if self
+            .stamp
+            .as_ref()
+            .is_some_and(StampOptions::is_per_request)
+        {
+            return None;
+        }
+
+        // Signatures attest to the time the output was signed
+        if self.sign.is_some() {
+            return None;
+        }
+
+        // Moved outputs carry the metadata and tags of their own source
+        if self.move_source {
+            return None;
+        }
+
+        // Decrypted outputs must only reach callers that can read the password secret
+        if self.password_secret.is_some() {
+            return None;
+        }
+
+        // Archived outputs cannot be copied into the cache without restoring them
+        if self
+            .storage_class
+            .is_some_and(|storage_class| storage_class.is_archival())
+        {
+            return None;
+        }
+
+        Some(ResultCacheParams {
+            source_etag,
+            source_format: self.source_format,
+            formats: self.output_formats(),
+            compression: self
+                .compression
+                .map(|compression| compression.content_encoding()),
+            embed_fonts: self.embed_fonts,
+            font_profile: self.font_profile.as_deref(),
+            x2t_version: self.x2t_version(),
+            pipeline: pipeline_names(),
+            archive: self.archive,
+            linearize: self.linearize,
+            bookmarks: self.bookmarks,
+            tagged: self.tagged,
+            flatten_forms: self.flatten_forms,
+            annotations: self.annotations,
+            raster: self.raster.as_ref(),
+            presentation: self.presentation.as_ref(),
+            spreadsheet: self.spreadsheet.as_ref(),
+            csv: self.csv.as_ref(),
+            template_data: self.template_data.as_ref(),
+            stamp: self.stamp.as_ref(),
+            // The attachment is named after the source which is not part of the key
+            attach_source: self.attach_source.then(|| self.source_file_name()),
+        })
+    }
+
+    /// Version of x2t the request is converted with, [None] for the installed x2t
+    fn x2t_version(&self) -> Option<String> {
+        self.x2t_version.clone().or_else(default_x2t_version)
+    }
+
+    /// Formats the source file should be converted into
+    fn output_formats(&self) -> Vec<OutputFormat> {
+        match &self.output_formats {
+            Some(formats) => {
+                let mut unique: Vec<OutputFormat> = Vec::with_capacity(formats.len());
+                for format in formats {
+                    if !unique.contains(format) {
+                        unique.push(*format);
+                    }
+                }
+                unique
+            }
+            None => vec![self.output_format],
+        }
+    }
+
+    /// Validate the caller provided buckets and keys
+    fn validate(&self) -> Result<(), ConvertError> {
+        if let Some(formats) = &self.output_formats {
+            if formats.is_empty() {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "output_formats: at least one format is required".to_string(),
+                });
+            }
+
+            // Multiple outputs can only be returned inline as a single archive
+            if self.destination().is_none() && !self.archive {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "output_formats: a destination or archive is required".to_string(),
+                });
+            }
+        }
+
+        match self.source()? {
+            Source::S3 {
+                bucket,
+                key,
+                options,
+            } => {
+                validate_bucket("source_bucket", bucket)?;
+                validate_key("source_key", key)?;
+
+                if let Some(version_id) = options.version_id {
+                    validate_version_id("source_version_id", version_id)?;
+                }
+
+                if let Some(etag) = options.if_match {
+                    validate_etag("expected_etag", etag)?;
+                }
+            }
+            Source::Url(_) => {
+                if self.source_version_id.is_some() {
+                    return Err(ConvertError {
+                        reason: Some("INVALID_REQUEST"),
+                        x2t_code: None,
+                        message: "source_version_id: only supported for S3 sources".to_string(),
+                    });
+                }
+
+                if self.expected_etag.is_some() {
+                    return Err(ConvertError {
+                        reason: Some("INVALID_REQUEST"),
+                        x2t_code: None,
+                        message: "expected_etag: only supported for S3 sources".to_string(),
+                    });
+                }
+            }
+        }
+
+        if let Some(dest_bucket) = &self.dest_bucket {
+            validate_bucket("dest_bucket", dest_bucket)?;
+        }
+
+        if let Some(dest_key) = &self.dest_key {
+            validate_key("dest_key", dest_key)?;
+        }
+
+        if self.destination().is_none() {
+            for (field, value) in [
+                ("dest_acl", self.dest_acl.is_some()),
+                ("storage_class", self.storage_class.is_some()),
+                ("checksum_algorithm", self.checksum_algorithm.is_some()),
+                (
+                    "expected_bucket_owner",
+                    self.expected_bucket_owner.is_some(),
+                ),
+            ] {
+                if value {
+                    return Err(ConvertError {
+                        reason: Some("INVALID_REQUEST"),
+                        x2t_code: None,
+                        message: format!("{field}: a destination is required"),
+                    });
+                }
+            }
+        }
+
+        if let Some(expected_bucket_owner) = &self.expected_bucket_owner {
+            validate_account_id("expected_bucket_owner", expected_bucket_owner)?;
+        }
+
+        if self.requester_pays
+            && self.destination().is_none()
+            && matches!(self.source()?, Source::Url(_))
+        {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "requester_pays: an S3 source or destination is required".to_string(),
+            });
+        }
+
+        if let Some(font_profile) = &self.font_profile {
+            validate_name("font_profile", font_profile)?;
+        }
+
+        if self.linearize && !self.output_formats().iter().any(OutputFormat::is_pdf) {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "linearize: only supported for pdf outputs".to_string(),
+            });
+        }
+
+        if self.bookmarks && !self.output_formats().iter().any(OutputFormat::is_pdf) {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "bookmarks: only supported for pdf outputs".to_string(),
+            });
+        }
+
+        if self.tagged && !self.output_formats().iter().any(OutputFormat::is_pdf) {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "tagged: only supported for pdf outputs".to_string(),
+            });
+        }
+
+        if self.flatten_forms && !self.output_formats().iter().any(OutputFormat::is_pdf) {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "flatten_forms: only supported for pdf outputs".to_string(),
+            });
+        }
+
+        if self.annotations.is_some() && !self.output_formats().iter().any(OutputFormat::is_pdf) {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "annotations: only supported for pdf outputs".to_string(),
+            });
+        }
+
+        if let Some(raster) = &self.raster {
+            if !self.output_formats().contains(&OutputFormat::Thumbnail) {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "raster: only supported for thumbnail outputs".to_string(),
+                });
+            }
+
+            raster.validate()?;
+        }
+
+        if let Some(spreadsheet) = &self.spreadsheet {
+            if !self.output_formats().iter().any(OutputFormat::is_pdf) {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "spreadsheet: only supported for pdf outputs".to_string(),
+                });
+            }
+
+            // Presentation and spreadsheet sources are rendered with separate options
+            if self.presentation.is_some() {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "spreadsheet: cannot be combined with presentation".to_string(),
+                });
+            }
+
+            spreadsheet.validate()?;
+        }
+
+        if let Some(spreadsheet_limits) = &self.spreadsheet_limits {
+            spreadsheet_limits.validate()?;
+        }
+
+        if let Some(presentation) = &self.presentation {
+            if !self.output_formats().iter().any(OutputFormat::is_pdf) {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "presentation: only supported for pdf outputs".to_string(),
+                });
+            }
+
+            presentation.validate()?;
+        }
+
+        if let Some(stamp) = &self.stamp {
+            if !self.output_formats().contains(&OutputFormat::Pdf) {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "stamp: only supported for pdf outputs".to_string(),
+                });
+            }
+
+            stamp.validate()?;
+        }
+
+        if self.attach_source && !self.output_formats().contains(&OutputFormat::Pdf) {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "attach_source: only supported for pdf outputs".to_string(),
+            });
+        }
+
+        if let Some(sign) = &self.sign {
+            if !self.output_formats().iter().any(OutputFormat::is_pdf) {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "sign: only supported for pdf outputs".to_string(),
+                });
+            }
+
+            if !is_signing_enabled() {
+                return Err(ConvertError {
+                    reason: Some("SIGNING_UNAVAILABLE"),
+                    x2t_code: None,
+                    message: "sign: signing is not configured".to_string(),
+                });
+            }
+
+            sign.validate()?;
+        }
+
+        if let Some(password_secret) = &self.password_secret {
+            password_secret.validate()?;
+        }
+
+        if let Some(retry) = &self.retry {
+            retry.validate()?;
+        }
+
+        for (field, value) in [
+            ("delete_source", self.delete_source),
+            ("move", self.move_source),
+        ] {
+            if !value {
+                continue;
+            }
+
+            let Source::S3 {
+                bucket: source_bucket,
+                key: source_key,
+                ..
+            } = self.source()?
+            else {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: format!("{field}: an S3 source is required"),
+                });
+            };
+
+            let Some((dest_bucket, dest_key)) = self.destination() else {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: format!("{field}: a destination is required"),
+                });
+            };
+
+            // Deleting the source would delete an output written over it
+            if dest_bucket == source_bucket
+                && self
+                    .destination_keys(dest_key)
+                    .iter()
+                    .any(|key| key == source_key)
+            {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: format!("{field}: an output cannot be stored over the source"),
+                });
+            }
+        }
+
+        match &self.tenant {
+            Some(tenant) => validate_name("tenant", tenant)?,
+            None if is_tenancy_enabled() => {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "tenant: a tenant is required".to_string(),
+                });
+            }
+            None => {}
+        }
+
+        if self.debug && !is_debug_artifacts_enabled() {
+            return Err(ConvertError {
+                reason: Some("DEBUG_UNAVAILABLE"),
+                x2t_code: None,
+                message: "debug: debug artifacts are not configured".to_string(),
+            });
+        }
+
+        if let Some(csv) = &self.csv {
+            if !self.output_formats().contains(&OutputFormat::Csv) {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "csv: only supported for csv outputs".to_string(),
+                });
+            }
+
+            // Multiple sheets can only be returned inline as a single archive
+            if csv.all_sheets && self.destination().is_none() && !self.archive {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: "csv.all_sheets: a destination or archive is required".to_string(),
+                });
+            }
+
+            csv.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct ConvertTempPaths {
+    input_path: PathBuf,
+    /// Path for the archive of the outputs (When requested)
+    archive_path: PathBuf,
+    /// Path the filled template is written to before replacing the input
+    template_path: PathBuf,
+    /// Path pre-processed sources are written to before replacing the input
+    processed_path: PathBuf,
+    /// Path for the compressed archive
+    compressed_path: PathBuf,
+    /// Paths for each of the output formats
+    outputs: Vec<OutputPaths>,
+}
+
+impl ConvertTempPaths {
+    /// Paths uploaded as debug artifacts, including the config XML, raw x2t output
+    /// and the x2t temp directory of each output
+    fn debug_paths(&self) -> Vec<&Path> {
+        let mut paths = vec![
+            self.input_path.as_path(),
+            self.archive_path.as_path(),
+            self.template_path.as_path(),
+            self.processed_path.as_path(),
+            self.compressed_path.as_path(),
+        ];
+
+        for output in &self.outputs {
+            paths.extend([
+                output.config_path.as_path(),
+                output.temp_path.as_path(),
+                output.output_path.as_path(),
+                output.compressed_path.as_path(),
+                output.processed_path.as_path(),
+                output.workbook_path.as_path(),
+                output.dump_path.as_path(),
+            ]);
+        }
+
+        paths
+    }
+}
+
+/// Temporary paths for converting into a single output format
+struct OutputPaths {
+    /// Index of the output within the requested formats
+    index: usize,
+    format: OutputFormat,
+    config_path: PathBuf,
+    temp_path: PathBuf,
+    output_path: PathBuf,
+    compressed_path: PathBuf,
+    /// Path post-processed outputs are written to before replacing the output
+    processed_path: PathBuf,
+    /// Path for the XLSX workbook worksheets are exported from
+    workbook_path: PathBuf,
+    /// Directory x2t is run within when collecting core dumps
+    dump_path: PathBuf,
+}
+
+/// Stream a file from storage to disk, computing the checksum and capturing the
+/// file header as the chunks are written
+async fn stream_source_file(
+    storage: &dyn Storage,
+    source_bucket: &str,
+    source_key: &str,
+    options: GetOptions<'_>,
+    file_path: &Path,
+) -> Result<SourceFile, ConvertError> {
+    let object = match storage.get_object(source_bucket, source_key, options).await {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "error streaming source file");
+
+            return Err(match err {
+                StorageError::NoSuchKey => ConvertError {
+                    reason: Some("NO_SUCH_KEY"),
+                    x2t_code: None,
+                    message: "key not found in source bucket".to_string(),
+                },
+                StorageError::NoSuchVersion => ConvertError {
+                    reason: Some("NO_SUCH_VERSION"),
+                    x2t_code: None,
+                    message: "version not found for source key".to_string(),
+                },
+                StorageError::PreconditionFailed => ConvertError {
+                    reason: Some("SOURCE_CHANGED"),
+                    x2t_code: None,
+                    message: "source object does not match the expected_etag".to_string(),
+                },
+                StorageError::Throttled => throttled_error(),
+                err => ConvertError {
+                    reason: Some("GET_OBJECT"),
+                    x2t_code: None,
+                    message: err.to_string(),
+                },
+            });
+        }
+    };
+
+    let mut body = object.body;
+    let mut writer = SourceFileWriter::create(file_path, object.content_length).await?;
+
+    while let Some(chunk_result) = body.next().await {
+        let chunk = chunk_result.map_err(|err| {
+            tracing::error!(?err, "failed to read object chunk");
+            ConvertError {
+                reason: Some("READ_OBJECT_CHUNK"),
+                x2t_code: None,
+                message: "failed to read chunk".to_string(),
+            }
+        })?;
+
+        writer.write_chunk(&chunk).await?;
+    }
+
+    let mut source = writer.finish().await?;
+    source.etag = object.etag;
+
+    Ok(source)
+}
+
+/// Get the metadata and tags of the source object, only when the source
+/// still matches the converted `etag` (or version)
+async fn get_source_metadata(
+    storage: &dyn Storage,
+    request: &ConvertRequest,
+    etag: Option<&str>,
+) -> Result<ObjectMetadata, ConvertError> {
+    let Source::S3 {
+        bucket,
+        key,
+        options,
+    } = request.source()?
+    else {
+        return Ok(ObjectMetadata::default());
+    };
+
+    let get_options = GetOptions {
+        if_match: etag.filter(|_| options.version_id.is_none()),
+        ..options
+    };
+
+    storage
+        .get_object_metadata(bucket, key, get_options)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %bucket, %key, "failed to get source metadata");
+
+            match err {
+                StorageError::PreconditionFailed => ConvertError {
+                    reason: Some("SOURCE_CHANGED"),
+                    x2t_code: None,
+                    message: "source object changed while converting".to_string(),
+                },
+                _ => ConvertError {
+                    reason: Some("SOURCE_METADATA"),
+                    x2t_code: None,
+                    message: "failed to get source metadata".to_string(),
+                },
+            }
+        })
+}
+
+/// Delete the source object once the outputs have been delivered, the object is only
+/// deleted when it still matches the converted `etag` (or version) so changes made
+/// while converting are kept
+async fn delete_source_object(
+    storage: &dyn Storage,
+    request: &ConvertRequest,
+    etag: Option<&str>,
+) -> Result<(), ConvertError> {
+    let Source::S3 {
+        bucket,
+        key,
+        options,
+    } = request.source()?
+    else {
+        return Ok(());
+    };
+
+    tracing::debug!(%bucket, %key, "deleting source object");
+
+    // Versions cannot change so only the latest version is conditionally deleted
+    let delete_options = DeleteOptions {
+        version_id: options.version_id,
+        if_match: etag.filter(|_| options.version_id.is_none()),
+        requester_pays: options.requester_pays,
+    };
+
+    storage
+        .delete_object(bucket, key, delete_options)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %bucket, %key, "failed to delete source object");
+
+            match err {
+                StorageError::PreconditionFailed => ConvertError {
+                    reason: Some("SOURCE_CHANGED"),
+                    x2t_code: None,
+                    message: "source object changed before it could be deleted".to_string(),
+                },
+                _ => ConvertError {
+                    reason: Some("DELETE_SOURCE"),
+                    x2t_code: None,
+                    message: "failed to delete source object".to_string(),
+                },
+            }
+        })
+}
+
+/// Get the metadata of the source object, errors are logged and ignored as they
+/// will be reported when the source is downloaded
+async fn head_source(
+    storage: &dyn Storage,
+    source_bucket: &str,
+    source_key: &str,
+    options: GetOptions<'_>,
+) -> Option<ObjectHead> {
+    storage
+        .head_object(source_bucket, source_key, options)
+        .await
+        .inspect_err(|err| tracing::warn!(?err, "failed to head source object"))
+        .ok()
+}
+
+/// Stream a file upload from disk to storage
+async fn stream_output_file(
+    storage: &dyn Storage,
+    dest_bucket: &str,
+    dest_key: &str,
+    file_path: &Path,
+    options: PutOptions<'_>,
+) -> Result<(), ConvertError> {
+    storage
+        .put_object(dest_bucket, dest_key, PutBody::File(file_path), options)
+        .await
+        .map_err(|err| match err {
+            StorageError::ReadBody(err) => {
+                tracing::error!(?err, "failed to create output stream");
+                ConvertError {
+                    reason: Some("CREATE_OUTPUT_STREAM"),
+                    x2t_code: None,
+                    message: "failed to create output stream".to_string(),
+                }
+            }
+            StorageError::Throttled => {
+                tracing::error!("output upload was throttled");
+                throttled_error()
+            }
+            err => {
+                tracing::error!(?err, "failed to upload output");
+                ConvertError {
+                    reason: Some("UPLOAD_OUTPUT_STREAM"),
+                    x2t_code: None,
+                    message: "failed to upload output stream".to_string(),
+                }
+            }
+        })
+}
+
+/// Error for storage requests that remained throttled after retrying, reported as
+/// unavailable so the conversion can be retried once the request rate drops
+fn throttled_error() -> ConvertError {
+    ConvertError {
+        reason: Some("STORAGE_THROTTLED"),
+        x2t_code: None,
+        message: "storage requests are being throttled".to_string(),
+    }
+}
+
+/// Read the output file into memory to be returned inline
+async fn read_inline_output(file_path: &Path) -> Result<Vec<u8>, ConvertError> {
+    let max_size = app_config().inline_output_max_size;
+
+    let metadata = tokio::fs::metadata(file_path).await.map_err(|err| {
+        tracing::error!(?err, "failed to read output file metadata");
+        ConvertError {
+            reason: Some("READ_OUTPUT"),
+            x2t_code: None,
+            message: "failed to read output file".to_string(),
+        }
+    })?;
+
+    if metadata.len() > max_size {
+        return Err(ConvertError {
+            reason: Some("OUTPUT_TOO_LARGE"),
+            x2t_code: None,
+            message: format!(
+                "output is too large to return inline ({} > {max_size} bytes), specify a destination",
+                metadata.len()
+            ),
+        });
+    }
+
+    tokio::fs::read(file_path).await.map_err(|err| {
+        tracing::error!(?err, "failed to read output file");
+        ConvertError {
+            reason: Some("READ_OUTPUT"),
+            x2t_code: None,
+            message: "failed to read output file".to_string(),
+        }
+    })
+}
+
+fn create_convert_temp_paths(
+    temp_dir: &Path,
+    formats: &[OutputFormat],
+) -> std::io::Result<ConvertTempPaths> {
+    // Generate random unique ID
+    let random_id = Uuid::new_v4().simple();
+
+    // Make the temp directory absolute so the paths within are absolute
+    let temp_dir = absolute(temp_dir)
+        .inspect_err(|err| tracing::error!(?err, "failed to make temp path absolute"))?;
+
+    // Create paths in temp directory
+    let input_path = temp_dir.join(format!("tmp_native_input_{random_id}"));
+    let archive_path = temp_dir.join(format!("tmp_native_archive_{random_id}.zip"));
+    let template_path = temp_dir.join(format!("tmp_native_template_{random_id}"));
+    let processed_path = temp_dir.join(format!("tmp_native_processed_{random_id}"));
+    let compressed_path = temp_dir.join(format!("tmp_native_compressed_{random_id}"));
+
+    // Each format gets its own config, output and x2t temp directory
+    let outputs = formats
+        .iter()
+        .enumerate()
+        .map(|(index, format)| OutputPaths {
+            index,
+            format: *format,
+            config_path: temp_dir.join(format!("tmp_native_config_{random_id}_{index}.xml")),
+            temp_path: temp_dir.join(format!("tmp_native_temp_{random_id}_{index}")),
+            output_path: temp_dir.join(format!(
+                "tmp_native_output_{random_id}_{index}.{}",
+                format.extension()
+            )),
+            compressed_path: temp_dir.join(format!("tmp_native_compressed_{random_id}_{index}")),
+            processed_path: temp_dir.join(format!("tmp_native_processed_{random_id}_{index}")),
+            workbook_path: temp_dir.join(format!("tmp_native_workbook_{random_id}_{index}.xlsx")),
+            dump_path: temp_dir.join(format!("tmp_native_dump_{random_id}_{index}")),
+        })
+        .collect();
+
+    Ok(ConvertTempPaths {
+        input_path,
+        archive_path,
+        template_path,
+        processed_path,
+        compressed_path,
+        outputs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, path::PathBuf};
+
+    use bytes::Bytes;
+    use futures::{FutureExt, future::BoxFuture, stream};
+    use uuid::Uuid;
+
+    use super::{ConvertRequest, head_source, stream_output_file, stream_source_file};
+    use crate::storage::{
+        DeleteOptions, GetOptions, ObjectHead, ObjectMetadata, PutBody, PutOptions, Storage,
+        StorageError, StorageObject, WriteOptions,
+    };
+
+    /// Storage that responds to every request with a fixed behavior
+    enum MockStorage {
+        /// Objects do not exist
+        NoSuchKey,
+        /// Requests fail
+        RequestFailed,
+        /// Objects are returned with the chunks, a `None` chunk fails
+        Chunks(Vec<Option<&'static [u8]>>),
+    }
+
+    impl Storage for MockStorage {
+        fn get_object<'a>(
+            &'a self,
+            _bucket: &'a str,
+            _key: &'a str,
+            _options: GetOptions<'a>,
+        ) -> BoxFuture<'a, Result<StorageObject, StorageError>> {
+            let result = match self {
+                MockStorage::NoSuchKey => Err(StorageError::NoSuchKey),
+                MockStorage::RequestFailed => Err(StorageError::Request("failed".to_string())),
+                MockStorage::Chunks(chunks) => {
+                    let chunks: Vec<Result<Bytes, StorageError>> = chunks
+                        .iter()
+                        .map(|chunk| match chunk {
+                            Some(chunk) => Ok(Bytes::from_static(chunk)),
+                            None => Err(StorageError::Request("chunk failed".to_string())),
+                        })
+                        .collect();
+
+                    Ok(StorageObject {
+                        content_length: None,
+                        etag: None,
+                        body: Box::pin(stream::iter(chunks)),
+                    })
+                }
+            };
+
+            async move { result }.boxed()
+        }
+
+        fn head_object<'a>(
+            &'a self,
+            _bucket: &'a str,
+            _key: &'a str,
+            _options: GetOptions<'a>,
+        ) -> BoxFuture<'a, Result<ObjectHead, StorageError>> {
+            let result = match self {
+                MockStorage::NoSuchKey => Err(StorageError::NoSuchKey),
+                MockStorage::RequestFailed => Err(StorageError::Request("failed".to_string())),
+                MockStorage::Chunks(chunks) => Ok(ObjectHead {
+                    content_length: Some(
+                        chunks
+                            .iter()
+                            .flatten()
+                            .map(|chunk| chunk.len() as u64)
+                            .sum(),
+                    ),
+                    etag: None,
+                }),
+            };
+
+            async move { result }.boxed()
+        }
+
+        fn put_object<'a>(
+            &'a self,
+            _bucket: &'a str,
+            _key: &'a str,
+            body: PutBody<'a>,
+            _options: PutOptions<'a>,
+        ) -> BoxFuture<'a, Result<(), StorageError>> {
+            async move {
+                if let PutBody::File(path) = body {
+                    tokio::fs::metadata(path)
+                        .await
+                        .map_err(StorageError::ReadBody)?;
+                }
+
+                match self {
+                    MockStorage::Chunks(_) => Ok(()),
+                    _ => Err(StorageError::Request("failed".to_string())),
+                }
+            }
+            .boxed()
+        }
+
+        fn copy_object<'a>(
+            &'a self,
+            _source_bucket: &'a str,
+            _source_key: &'a str,
+            _dest_bucket: &'a str,
+            _dest_key: &'a str,
+            _options: WriteOptions<'a>,
+        ) -> BoxFuture<'a, Result<(), StorageError>> {
+            let result = match self {
+                MockStorage::Chunks(_) => Ok(()),
+                _ => Err(StorageError::Request("failed".to_string())),
+            };
+
+            async move { result }.boxed()
+        }
+
+        fn get_object_metadata<'a>(
+            &'a self,
+            _bucket: &'a str,
+            _key: &'a str,
+            _options: GetOptions<'a>,
+        ) -> BoxFuture<'a, Result<ObjectMetadata, StorageError>> {
+            let result = match self {
+                MockStorage::NoSuchKey => Err(StorageError::NoSuchKey),
+                MockStorage::RequestFailed => Err(StorageError::Request("failed".to_string())),
+                MockStorage::Chunks(_) => Ok(ObjectMetadata::default()),
+            };
+
+            async move { result }.boxed()
+        }
+
+        fn delete_object<'a>(
+            &'a self,
+            _bucket: &'a str,
+            _key: &'a str,
+            _options: DeleteOptions<'a>,
+        ) -> BoxFuture<'a, Result<(), StorageError>> {
+            let result = match self {
+                MockStorage::NoSuchKey => Err(StorageError::NoSuchKey),
+                MockStorage::RequestFailed => Err(StorageError::Request("failed".to_string())),
+                MockStorage::Chunks(_) => Ok(()),
+            };
+
+            async move { result }.boxed()
+        }
+    }
+
+    fn test_file_path() -> PathBuf {
+        temp_dir().join(format!(
+            "onlyoffice-convert-test-{}",
+            Uuid::new_v4().simple()
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_stream_source_missing_key() {
+        let path = test_file_path();
+        let err = stream_source_file(
+            &MockStorage::NoSuchKey,
+            "bucket",
+            "key",
+            GetOptions::default(),
+            &path,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert_eq!(err.reason, Some("NO_SUCH_KEY"));
+        assert_eq!(err.status_code(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_stream_source_request_failed() {
+        let path = test_file_path();
+        let err = stream_source_file(
+            &MockStorage::RequestFailed,
+            "bucket",
+            "key",
+            GetOptions::default(),
+            &path,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert_eq!(err.reason, Some("GET_OBJECT"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_source_chunk_failed() {
+        let path = test_file_path();
+        let storage = MockStorage::Chunks(vec![Some(b"first"), None, Some(b"last")]);
+        let err = stream_source_file(&storage, "bucket", "key", GetOptions::default(), &path)
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(err.reason, Some("READ_OBJECT_CHUNK"));
+        _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[tokio::test]
+    async fn test_stream_source() {
+        let path = test_file_path();
+        let storage = MockStorage::Chunks(vec![Some(b"hello "), Some(b"world")]);
+        let source = stream_source_file(&storage, "bucket", "key", GetOptions::default(), &path)
+            .await
+            .unwrap();
+
+        assert_eq!(source.size, 11);
+        assert_eq!(source.header, b"hello world");
+        assert_eq!(
+            source.sha256,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"hello world");
+        _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[tokio::test]
+    async fn test_head_source() {
+        let storage = MockStorage::Chunks(vec![Some(b"hello "), Some(b"world")]);
+        let head = head_source(&storage, "bucket", "key", GetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(head.content_length, Some(11));
+
+        let head = head_source(
+            &MockStorage::NoSuchKey,
+            "bucket",
+            "key",
+            GetOptions::default(),
+        )
+        .await;
+        assert!(head.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_output_upload_failed() {
+        let path = test_file_path();
+        tokio::fs::write(&path, b"output").await.unwrap();
+
+        let err = stream_output_file(
+            &MockStorage::RequestFailed,
+            "bucket",
+            "key",
+            &path,
+            PutOptions::default(),
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert_eq!(err.reason, Some("UPLOAD_OUTPUT_STREAM"));
+        _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[tokio::test]
+    async fn test_stream_output_missing_file() {
+        let path = test_file_path();
+        let storage = MockStorage::Chunks(Vec::new());
+        let err = stream_output_file(&storage, "bucket", "key", &path, PutOptions::default())
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(err.reason, Some("CREATE_OUTPUT_STREAM"));
+    }
+
+    #[test]
+    fn test_move_onto_source_rejected() {
+        for field in ["move", "delete_source"] {
+            let request: ConvertRequest = serde_json::from_value(serde_json::json!({
+                "source_bucket": "bucket",
+                "source_key": "document.docx",
+                "dest_bucket": "bucket",
+                "dest_key": "document.docx",
+                field: true,
+            }))
+            .unwrap();
+
+            let err = request.validate().err().unwrap();
+            assert_eq!(err.reason, Some("INVALID_REQUEST"));
+            assert_eq!(
+                err.message,
+                format!("{field}: an output cannot be stored over the source")
+            );
+        }
+    }
+}
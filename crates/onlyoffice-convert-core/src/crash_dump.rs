@@ -0,0 +1,215 @@
+use std::{
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::UNIX_EPOCH,
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+
+use crate::{
+    config::app_config,
+    debug_artifacts::{debug_artifacts_location, is_debug_artifacts_enabled},
+    format::OutputFormat,
+    storage::{PutBody, PutOptions, Storage},
+};
+
+/// Name of the manifest describing the crash within the crash prefix
+const CRASH_MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Whether core dumps of crashed x2t processes are collected
+pub fn is_core_dump_enabled() -> bool {
+    app_config().x2t_core_dumps && is_debug_artifacts_enabled()
+}
+
+/// Allow the x2t `command` to write a core dump of at most the maximum size,
+/// the process is run within the `dump_path` so the dump is written there
+pub fn enable_core_dumps(command: &mut Command, dump_path: &Path) {
+    command.current_dir(dump_path);
+
+    #[cfg(unix)]
+    {
+        let max_size = app_config().core_dump_max_size as libc::rlim_t;
+
+        // Safety: getrlimit and setrlimit are async-signal-safe and only affect the
+        // child process. Failing to raise the limit only prevents the core dump
+        unsafe {
+            command.pre_exec(move || {
+                let mut limit = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+
+                if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) == 0 {
+                    limit.rlim_cur = max_size.min(limit.rlim_max);
+                    libc::setrlimit(libc::RLIMIT_CORE, &limit);
+                }
+
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Signal that killed the process, [None] when the process exited
+pub fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+
+    #[cfg(not(unix))]
+    {
+        _ = status;
+        None
+    }
+}
+
+/// Details about a crashed x2t process that are uploaded alongside the core dump
+pub struct CrashedConversion<'a> {
+    pub request_id: &'a str,
+    /// Index of the output that crashed
+    pub index: usize,
+    pub format: OutputFormat,
+    pub signal: i32,
+    /// Path to the x2t binary that crashed
+    pub x2t_bin: &'a Path,
+    /// Directory x2t was run within that the core dump is written to
+    pub dump_path: &'a Path,
+    /// Captured stderr output from x2t
+    pub stderr: &'a [u8],
+}
+
+#[derive(Serialize)]
+struct CrashManifest<'a> {
+    request_id: &'a str,
+    format: OutputFormat,
+    signal: i32,
+    x2t: X2tBinaryInfo,
+    core_dumps: Vec<String>,
+    /// Core dumps that were not uploaded as they were truncated at the maximum size
+    skipped_core_dumps: Vec<String>,
+    stderr: String,
+}
+
+/// Version information of the x2t binary for reporting the crash upstream
+#[derive(Serialize)]
+struct X2tBinaryInfo {
+    path: String,
+    size: Option<u64>,
+    /// Last modified time in seconds since the unix epoch
+    modified: Option<u64>,
+    sha256: Option<String>,
+}
+
+/// Upload the core dumps of a crashed x2t process to the debug artifacts location
+/// along with a manifest containing the x2t binary version information
+///
+/// Failing to upload the artifacts is logged but otherwise ignored as it should
+/// not mask the original conversion error
+pub async fn upload_crash_artifacts(storage: &dyn Storage, crash: CrashedConversion<'_>) {
+    let Some((bucket, prefix)) = debug_artifacts_location(crash.request_id) else {
+        return;
+    };
+    let prefix = format!("{prefix}crash/{}/", crash.index);
+
+    let max_size = app_config().core_dump_max_size;
+    let mut core_dumps = Vec::new();
+    let mut skipped_core_dumps = Vec::new();
+
+    for (name, path) in find_core_dumps(crash.dump_path).await {
+        let size = tokio::fs::metadata(&path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+
+        // Dumps at the limit have been truncated and are not useful
+        if size >= max_size {
+            tracing::warn!(%name, size, "skipping truncated core dump");
+            skipped_core_dumps.push(name);
+            continue;
+        }
+
+        let key = format!("{prefix}{name}");
+        match storage
+            .put_object(&bucket, &key, PutBody::File(&path), PutOptions::default())
+            .await
+        {
+            Ok(()) => core_dumps.push(name),
+            Err(err) => tracing::error!(?err, %key, "failed to upload core dump"),
+        }
+    }
+
+    if core_dumps.is_empty() && skipped_core_dumps.is_empty() {
+        tracing::warn!("x2t crashed without writing a core dump");
+    }
+
+    let manifest = CrashManifest {
+        request_id: crash.request_id,
+        format: crash.format,
+        signal: crash.signal,
+        x2t: x2t_binary_info(crash.x2t_bin).await,
+        core_dumps,
+        skipped_core_dumps,
+        stderr: String::from_utf8_lossy(crash.stderr).to_string(),
+    };
+
+    let manifest_bytes = match serde_json::to_vec_pretty(&manifest) {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "failed to serialize crash manifest");
+            return;
+        }
+    };
+
+    let key = format!("{prefix}{CRASH_MANIFEST_FILE_NAME}");
+    if let Err(err) = storage
+        .put_object(
+            &bucket,
+            &key,
+            PutBody::Bytes(manifest_bytes),
+            PutOptions::default(),
+        )
+        .await
+    {
+        tracing::error!(?err, %key, "failed to upload crash manifest");
+    }
+}
+
+/// Find the core dumps within the `dump_path`, named `core` or `core.{pid}`
+async fn find_core_dumps(dump_path: &Path) -> Vec<(String, PathBuf)> {
+    let mut dumps = Vec::new();
+
+    let Ok(mut entries) = tokio::fs::read_dir(dump_path).await else {
+        return dumps;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == "core" || name.starts_with("core.") {
+            dumps.push((name, entry.path()));
+        }
+    }
+
+    dumps
+}
+
+async fn x2t_binary_info(x2t_bin: &Path) -> X2tBinaryInfo {
+    let metadata = tokio::fs::metadata(x2t_bin).await.ok();
+    let sha256 = tokio::fs::read(x2t_bin)
+        .await
+        .ok()
+        .map(|bytes| format!("{:x}", Sha256::digest(&bytes)));
+
+    X2tBinaryInfo {
+        path: x2t_bin.display().to_string(),
+        size: metadata.as_ref().map(|metadata| metadata.len()),
+        modified: metadata
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs()),
+        sha256,
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::{app_config, config_var},
+    storage::{PutBody, PutOptions, Storage},
+};
+
+/// Environment variable for the bucket debug artifacts are stored within, debug
+/// artifacts are only uploaded when this is set
+const DEBUG_ARTIFACTS_BUCKET_ENV: &str = "DEBUG_ARTIFACTS_BUCKET";
+
+/// Environment variable for the key prefix debug artifacts are stored under
+const DEBUG_ARTIFACTS_PREFIX_ENV: &str = "DEBUG_ARTIFACTS_PREFIX";
+
+const DEFAULT_DEBUG_ARTIFACTS_PREFIX: &str = "debug/";
+
+/// Whether temporary files should be kept rather than deleted
+pub fn is_debug_keep_temp() -> bool {
+    app_config().debug_keep_temp
+}
+
+/// Whether debug artifacts can be uploaded
+pub fn is_debug_artifacts_enabled() -> bool {
+    config_var(DEBUG_ARTIFACTS_BUCKET_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+/// Bucket and key prefix the debug artifacts of the request with the `request_id`
+/// are stored under, [None] when debug artifacts are not enabled
+pub fn debug_artifacts_location(request_id: &str) -> Option<(String, String)> {
+    let bucket = config_var(DEBUG_ARTIFACTS_BUCKET_ENV)
+        .ok()
+        .filter(|value| !value.is_empty())?;
+
+    let prefix = config_var(DEBUG_ARTIFACTS_PREFIX_ENV)
+        .unwrap_or_else(|_| DEFAULT_DEBUG_ARTIFACTS_PREFIX.to_string());
+
+    Some((bucket, format!("{prefix}{request_id}/")))
+}
+
+/// Upload the temporary files of a conversion to the configured debug artifacts
+/// location (When enabled). Each path is uploaded under its file name, directories
+/// (i.e the x2t temp directory) are uploaded with their contents
+///
+/// Failing to upload an artifact is logged but otherwise ignored as it should
+/// not change the outcome of the conversion
+pub async fn upload_debug_artifacts(storage: &dyn Storage, request_id: &str, paths: &[&Path]) {
+    let Some((bucket, prefix)) = debug_artifacts_location(request_id) else {
+        // Debug artifacts are not enabled
+        return;
+    };
+
+    tracing::debug!(%bucket, %prefix, "uploading debug artifacts");
+
+    for path in paths {
+        for (name, file_path) in collect_files(path).await {
+            let key = format!("{prefix}{name}");
+
+            if let Err(err) = storage
+                .put_object(
+                    &bucket,
+                    &key,
+                    PutBody::File(&file_path),
+                    PutOptions::default(),
+                )
+                .await
+            {
+                tracing::error!(?err, %key, "failed to upload debug artifact");
+            }
+        }
+    }
+}
+
+/// Collect the files at the `path` along with their artifact names, the path is
+/// either a file or a directory that is walked. Missing paths have no files
+async fn collect_files(path: &Path) -> Vec<(String, PathBuf)> {
+    let Some(root_name) = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+    else {
+        return Vec::new();
+    };
+
+    let mut files = Vec::new();
+    let mut pending = vec![(root_name, path.to_path_buf())];
+
+    while let Some((name, path)) = pending.pop() {
+        let Ok(metadata) = tokio::fs::metadata(&path).await else {
+            continue;
+        };
+
+        if metadata.is_file() {
+            files.push((name, path));
+            continue;
+        }
+
+        if !metadata.is_dir() {
+            continue;
+        }
+
+        let mut entries = match tokio::fs::read_dir(&path).await {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::error!(?err, ?path, "failed to read debug artifact directory");
+                continue;
+            }
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let entry_name = format!("{name}/{}", entry.file_name().to_string_lossy());
+            pending.push((entry_name, entry.path()));
+        }
+    }
+
+    files
+}
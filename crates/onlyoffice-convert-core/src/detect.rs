@@ -0,0 +1,306 @@
+use crate::{error::ConvertError, format::InputFormat};
+
+/// Signature of a ZIP local file header
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+/// Known container formats that x2t cannot convert
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsupportedFormat {
+    Numbers,
+    Keynote,
+    /// iWork document that is not a Keynote or Numbers document (i.e Pages) or
+    /// where the application could not be determined from the header
+    IWork,
+    SevenZip,
+    Rar,
+    Gzip,
+}
+
+impl UnsupportedFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnsupportedFormat::Numbers => "Apple Numbers",
+            UnsupportedFormat::Keynote => "Apple Keynote",
+            UnsupportedFormat::IWork => "Apple iWork (Pages)",
+            UnsupportedFormat::SevenZip => "7-Zip archive",
+            UnsupportedFormat::Rar => "RAR archive",
+            UnsupportedFormat::Gzip => "gzip archive",
+        }
+    }
+
+    pub fn error(&self) -> ConvertError {
+        ConvertError {
+            reason: Some("UNSUPPORTED_FORMAT"),
+            x2t_code: None,
+            message: format!("{} files are not supported", self.name()),
+        }
+    }
+}
+
+/// Detect a known unsupported format from the leading bytes of the source file
+pub fn detect_unsupported_format(header: &[u8]) -> Option<UnsupportedFormat> {
+    if header.starts_with(b"7z\xBC\xAF\x27\x1C") {
+        return Some(UnsupportedFormat::SevenZip);
+    }
+
+    if header.starts_with(b"Rar!\x1A\x07") {
+        return Some(UnsupportedFormat::Rar);
+    }
+
+    if header.starts_with(b"\x1F\x8B") {
+        return Some(UnsupportedFormat::Gzip);
+    }
+
+    if header.starts_with(ZIP_SIGNATURE) {
+        return detect_iwork(header);
+    }
+
+    None
+}
+
+/// Detect an iWork document from the names of the ZIP entries within the header,
+/// iWork documents are ZIP archives of IWA (iWork Archive) files
+fn detect_iwork(header: &[u8]) -> Option<UnsupportedFormat> {
+    // Keynote '09 presentations store their content as index.apxl
+    if contains(header, b"index.apxl") {
+        return Some(UnsupportedFormat::Keynote);
+    }
+
+    if !contains(header, b"Index/Document.iwa") {
+        return None;
+    }
+
+    if contains(header, b"Index/Slide") || contains(header, b"Index/MasterSlide") {
+        return Some(UnsupportedFormat::Keynote);
+    }
+
+    if contains(header, b"Index/CalculationEngine") || contains(header, b"Index/Tables/") {
+        return Some(UnsupportedFormat::Numbers);
+    }
+
+    Some(UnsupportedFormat::IWork)
+}
+
+/// Number of leading bytes checked for the markers of text based formats
+const TEXT_MARKER_RANGE: usize = 4096;
+
+/// Detect the format of text based sources and Visio drawings that x2t does not
+/// reliably detect from the leading bytes of the source file, [None] for other formats
+pub fn detect_input_format(header: &[u8]) -> Option<InputFormat> {
+    // Visio drawings are OPC packages with their parts under visio/
+    if header.starts_with(ZIP_SIGNATURE) {
+        return contains(header, b"visio/document.xml").then_some(InputFormat::Vsdx);
+    }
+
+    let text = header.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(header);
+    let text = &text[..text.len().min(TEXT_MARKER_RANGE)];
+    let start = text.trim_ascii_start();
+
+    if start.starts_with(b"{\\rtf") {
+        return Some(InputFormat::Rtf);
+    }
+
+    let lowercase = start.to_ascii_lowercase();
+
+    // MHTML archives begin with the MIME headers of the multipart message
+    if contains(&lowercase, b"mime-version:") && contains(&lowercase, b"multipart/related") {
+        return Some(InputFormat::Mht);
+    }
+
+    if lowercase.starts_with(b"<?xml") {
+        if contains(text, b"<w:wordDocument") {
+            return Some(InputFormat::WordXml);
+        }
+
+        if contains(text, b"<pkg:package") {
+            return Some(InputFormat::FlatDocx);
+        }
+
+        if contains(text, b"<office:document") {
+            return Some(InputFormat::FlatOdt);
+        }
+
+        // XHTML documents
+        if contains(&lowercase, b"<html") {
+            return Some(InputFormat::Html);
+        }
+
+        return None;
+    }
+
+    if lowercase.starts_with(b"<!doctype html") || lowercase.starts_with(b"<html") {
+        return Some(InputFormat::Html);
+    }
+
+    None
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        TEXT_MARKER_RANGE, UnsupportedFormat, detect_input_format, detect_unsupported_format,
+    };
+    use crate::format::InputFormat;
+
+    /// Create the header of a ZIP archive with a local file header for each entry
+    /// `name`, the remaining fields of the headers are left empty
+    fn zip_header(names: &[&str]) -> Vec<u8> {
+        let mut header = Vec::new();
+
+        for name in names {
+            header.extend_from_slice(b"PK\x03\x04");
+            header.extend_from_slice(&[0; 22]);
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&[0; 2]);
+            header.extend_from_slice(name.as_bytes());
+        }
+
+        header
+    }
+
+    #[test]
+    fn test_detect_iwork() {
+        for (names, expected) in [
+            (
+                &["Index/Document.iwa", "Index/Slide-1.iwa"][..],
+                UnsupportedFormat::Keynote,
+            ),
+            (
+                &["Index/Document.iwa", "Index/MasterSlide-1.iwa"],
+                UnsupportedFormat::Keynote,
+            ),
+            (&["index.apxl"], UnsupportedFormat::Keynote),
+            (
+                &["Index/Document.iwa", "Index/CalculationEngine.iwa"],
+                UnsupportedFormat::Numbers,
+            ),
+            (
+                &["Index/Document.iwa", "Index/Tables/DataList.iwa"],
+                UnsupportedFormat::Numbers,
+            ),
+            (
+                &["Index/Document.iwa", "Index/DocumentStylesheet.iwa"],
+                UnsupportedFormat::IWork,
+            ),
+        ] {
+            assert_eq!(
+                detect_unsupported_format(&zip_header(names)),
+                Some(expected),
+                "{names:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_unsupported_containers() {
+        for (header, expected) in [
+            (
+                &b"7z\xBC\xAF\x27\x1C\x00\x04"[..],
+                UnsupportedFormat::SevenZip,
+            ),
+            (b"Rar!\x1A\x07\x00", UnsupportedFormat::Rar),
+            (b"Rar!\x1A\x07\x01\x00", UnsupportedFormat::Rar),
+            (b"\x1F\x8B\x08\x00", UnsupportedFormat::Gzip),
+        ] {
+            assert_eq!(detect_unsupported_format(header), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_detect_supported_formats() {
+        // OOXML and ODF documents are ZIP archives without iWork entries
+        let docx = zip_header(&["[Content_Types].xml", "word/document.xml"]);
+        assert_eq!(detect_unsupported_format(&docx), None);
+
+        let odt = zip_header(&["mimetype", "content.xml"]);
+        assert_eq!(detect_unsupported_format(&odt), None);
+
+        // Legacy OLE compound documents (i.e doc and xls)
+        assert_eq!(
+            detect_unsupported_format(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1"),
+            None
+        );
+        assert_eq!(detect_unsupported_format(b"%PDF-1.7"), None);
+        assert_eq!(detect_unsupported_format(b""), None);
+    }
+
+    #[test]
+    fn test_unsupported_format_error() {
+        let error = UnsupportedFormat::Numbers.error();
+        assert_eq!(error.reason, Some("UNSUPPORTED_FORMAT"));
+        assert_eq!(error.message, "Apple Numbers files are not supported");
+    }
+
+    #[test]
+    fn test_detect_text_formats() {
+        for (header, expected) in [
+            (&b"{\\rtf1\\ansi\\deff0"[..], InputFormat::Rtf),
+            // Byte order mark and leading whitespace
+            (b"\xEF\xBB\xBF  \r\n{\\rtf1", InputFormat::Rtf),
+            (b"<!DOCTYPE html>\n<html>", InputFormat::Html),
+            (b"<html lang=\"en\">", InputFormat::Html),
+            (b"<HTML><BODY>", InputFormat::Html),
+            (
+                b"<?xml version=\"1.0\"?>\n<html xmlns=\"http://www.w3.org/1999/xhtml\">",
+                InputFormat::Html,
+            ),
+            (
+                b"From: <Saved by Blink>\r\nMIME-Version: 1.0\r\nContent-Type: multipart/related;\r\n",
+                InputFormat::Mht,
+            ),
+            (
+                b"<?xml version=\"1.0\"?>\n<w:wordDocument xmlns:w=\"http://schemas.microsoft.com/office/word/2003/wordml\">",
+                InputFormat::WordXml,
+            ),
+            (
+                b"<?xml version=\"1.0\"?>\n<pkg:package xmlns:pkg=\"http://schemas.microsoft.com/office/2006/xmlPackage\">",
+                InputFormat::FlatDocx,
+            ),
+            (
+                b"<?xml version=\"1.0\"?>\n<office:document office:mimetype=\"application/vnd.oasis.opendocument.text\">",
+                InputFormat::FlatOdt,
+            ),
+        ] {
+            assert_eq!(
+                detect_input_format(header),
+                Some(expected),
+                "{}",
+                String::from_utf8_lossy(header)
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_text_formats_unknown() {
+        for header in [
+            &b""[..],
+            b"plain text",
+            b"<?xml version=\"1.0\"?>\n<root/>",
+            // MIME messages that are not MHTML archives
+            b"MIME-Version: 1.0\r\nContent-Type: text/plain\r\n",
+            b"%PDF-1.7",
+        ] {
+            assert_eq!(
+                detect_input_format(header),
+                None,
+                "{}",
+                String::from_utf8_lossy(header)
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_text_formats_marker_range() {
+        // Markers past the checked range are not detected
+        let mut header = b"MIME-Version: 1.0\r\n".to_vec();
+        header.resize(TEXT_MARKER_RANGE, b' ');
+        header.extend_from_slice(b"Content-Type: multipart/related;");
+        assert_eq!(detect_input_format(&header), None);
+    }
+}
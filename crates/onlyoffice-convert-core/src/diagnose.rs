@@ -0,0 +1,234 @@
+use crate::error::ConvertError;
+
+/// Signal numbers of the signals x2t is commonly killed by (Linux)
+const SIGBUS: i32 = 7;
+const SIGKILL: i32 = 9;
+const SIGSEGV: i32 = 11;
+
+/// Cause of a x2t failure determined from its stderr output and exit signal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum X2tDiagnosis {
+    /// x2t threw a `std::out_of_range` exception, commonly caused by encrypted
+    /// sources that x2t attempts to read as regular documents
+    OutOfRange,
+    /// Source claims to be an OOXML / ODF document but is not a valid ZIP archive
+    BadZip,
+    /// A shared library required by x2t could not be loaded
+    MissingLibrary { library: Option<String> },
+    /// x2t was killed with SIGKILL, which the kernel uses when out of memory
+    OutOfMemory,
+    /// x2t crashed from a segmentation fault or bus error
+    Segfault { signal: i32 },
+}
+
+/// Markers within the stderr output of x2t for a source that is not a valid ZIP archive
+const BAD_ZIP_MARKERS: &[&str] = &[
+    "invalid zip",
+    "bad zip",
+    "not a zip",
+    "zip file is corrupt",
+    "unzip error",
+    "end of central directory",
+];
+
+/// Marker of the dynamic linker failing to load a library, followed by
+/// `{library}: cannot open shared object file`
+const MISSING_LIBRARY_MARKER: &str = "error while loading shared libraries:";
+
+/// Determine the cause of a x2t failure from its `stderr` and the `signal` that
+/// killed it (When killed), [None] when the cause is not recognized
+pub fn diagnose_x2t_failure(stderr: &str, signal: Option<i32>) -> Option<X2tDiagnosis> {
+    if let Some(library) = missing_library(stderr) {
+        return Some(X2tDiagnosis::MissingLibrary { library });
+    }
+
+    match signal {
+        Some(SIGKILL) => return Some(X2tDiagnosis::OutOfMemory),
+        Some(signal @ (SIGSEGV | SIGBUS)) => {
+            // Exceptions reported before the crash are the more specific cause
+            if stderr.contains("std::out_of_range") {
+                return Some(X2tDiagnosis::OutOfRange);
+            }
+
+            return Some(X2tDiagnosis::Segfault { signal });
+        }
+        _ => {}
+    }
+
+    if stderr.contains("std::out_of_range") {
+        return Some(X2tDiagnosis::OutOfRange);
+    }
+
+    let lowercase = stderr.to_ascii_lowercase();
+    if BAD_ZIP_MARKERS
+        .iter()
+        .any(|marker| lowercase.contains(marker))
+    {
+        return Some(X2tDiagnosis::BadZip);
+    }
+
+    None
+}
+
+/// Name of the library the dynamic linker failed to load, the outer [Option] is
+/// [None] when no library failed to load
+fn missing_library(stderr: &str) -> Option<Option<String>> {
+    let (_, rest) = stderr.split_once(MISSING_LIBRARY_MARKER)?;
+    let library = rest
+        .trim_start()
+        .split(':')
+        .next()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string);
+
+    Some(library)
+}
+
+impl X2tDiagnosis {
+    /// Error reported for the diagnosed failure
+    pub fn error(&self, x2t_code: Option<i32>) -> ConvertError {
+        let (reason, message) = match self {
+            // Assume encryption for out of range crashes
+            X2tDiagnosis::OutOfRange => ("FILE_LIKELY_ENCRYPTED", "file is encrypted".to_string()),
+            X2tDiagnosis::BadZip => (
+                "FILE_INVALID_ARCHIVE",
+                "file is not a valid document archive".to_string(),
+            ),
+            X2tDiagnosis::MissingLibrary { library } => (
+                "X2T_MISSING_LIBRARY",
+                match library {
+                    Some(library) => format!("x2t is missing the {library} library"),
+                    None => "x2t is missing a shared library".to_string(),
+                },
+            ),
+            X2tDiagnosis::OutOfMemory => ("X2T_OUT_OF_MEMORY", "x2t ran out of memory".to_string()),
+            X2tDiagnosis::Segfault { signal } => {
+                ("X2T_CRASHED", format!("x2t crashed (signal {signal})"))
+            }
+        };
+
+        ConvertError {
+            reason: Some(reason),
+            x2t_code,
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SIGBUS, SIGKILL, SIGSEGV, X2tDiagnosis, diagnose_x2t_failure};
+
+    #[test]
+    fn test_diagnose_x2t_failure() {
+        for (stderr, signal, expected) in [
+            (
+                "terminate called after throwing an instance of 'std::out_of_range'",
+                None,
+                X2tDiagnosis::OutOfRange,
+            ),
+            ("Unzip error: bad zip", None, X2tDiagnosis::BadZip),
+            (
+                "End Of Central Directory record not found",
+                None,
+                X2tDiagnosis::BadZip,
+            ),
+            (
+                "x2t: error while loading shared libraries: libicuuc.so.58: cannot open shared object file: No such file or directory",
+                None,
+                X2tDiagnosis::MissingLibrary {
+                    library: Some("libicuuc.so.58".to_string()),
+                },
+            ),
+            (
+                "x2t: error while loading shared libraries: ",
+                None,
+                X2tDiagnosis::MissingLibrary { library: None },
+            ),
+            ("", Some(SIGKILL), X2tDiagnosis::OutOfMemory),
+            (
+                "",
+                Some(SIGSEGV),
+                X2tDiagnosis::Segfault { signal: SIGSEGV },
+            ),
+            ("", Some(SIGBUS), X2tDiagnosis::Segfault { signal: SIGBUS }),
+            // Exceptions before a crash are the more specific cause
+            (
+                "what(): std::out_of_range",
+                Some(SIGSEGV),
+                X2tDiagnosis::OutOfRange,
+            ),
+            // Libraries that fail to load take precedence over the signal
+            (
+                "error while loading shared libraries: libz.so.1: cannot open shared object file",
+                Some(SIGSEGV),
+                X2tDiagnosis::MissingLibrary {
+                    library: Some("libz.so.1".to_string()),
+                },
+            ),
+        ] {
+            assert_eq!(
+                diagnose_x2t_failure(stderr, signal),
+                Some(expected),
+                "{stderr:?} {signal:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_diagnose_x2t_failure_unrecognized() {
+        assert_eq!(diagnose_x2t_failure("", None), None);
+        assert_eq!(diagnose_x2t_failure("conversion failed", None), None);
+
+        // Signals other than the recognized crash signals fall through to the stderr
+        assert_eq!(diagnose_x2t_failure("", Some(15)), None);
+        assert_eq!(
+            diagnose_x2t_failure("invalid zip", Some(15)),
+            Some(X2tDiagnosis::BadZip)
+        );
+    }
+
+    #[test]
+    fn test_diagnosis_error() {
+        for (diagnosis, reason, message) in [
+            (
+                X2tDiagnosis::OutOfRange,
+                "FILE_LIKELY_ENCRYPTED",
+                "file is encrypted",
+            ),
+            (
+                X2tDiagnosis::BadZip,
+                "FILE_INVALID_ARCHIVE",
+                "file is not a valid document archive",
+            ),
+            (
+                X2tDiagnosis::MissingLibrary {
+                    library: Some("libz.so.1".to_string()),
+                },
+                "X2T_MISSING_LIBRARY",
+                "x2t is missing the libz.so.1 library",
+            ),
+            (
+                X2tDiagnosis::MissingLibrary { library: None },
+                "X2T_MISSING_LIBRARY",
+                "x2t is missing a shared library",
+            ),
+            (
+                X2tDiagnosis::OutOfMemory,
+                "X2T_OUT_OF_MEMORY",
+                "x2t ran out of memory",
+            ),
+            (
+                X2tDiagnosis::Segfault { signal: SIGSEGV },
+                "X2T_CRASHED",
+                "x2t crashed (signal 11)",
+            ),
+        ] {
+            let error = diagnosis.error(Some(1));
+            assert_eq!(error.reason, Some(reason));
+            assert_eq!(error.message, message);
+            assert_eq!(error.x2t_code, Some(1));
+        }
+    }
+}
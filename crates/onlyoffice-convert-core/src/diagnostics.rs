@@ -0,0 +1,385 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    admission::available_space,
+    config::app_config,
+    error::ConvertError,
+    fonts::fonts_path,
+    themes::themes_path,
+    x2t::{X2T_BIN, default_x2t_version, find_icu_data_file, icu_data_path, resolve_x2t_path},
+};
+
+/// Directories searched for shared libraries after the x2t directory and the
+/// `LD_LIBRARY_PATH`, the default paths of the dynamic linker and Lambda layers
+const SYSTEM_LIBRARY_PATHS: &[&str] = &[
+    "/opt/lib",
+    "/lib64",
+    "/usr/lib64",
+    "/lib",
+    "/usr/lib",
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib/aarch64-linux-gnu",
+    "/usr/lib/aarch64-linux-gnu",
+];
+
+/// Maximum number of shared libraries resolved, guards against unexpectedly
+/// large dependency trees
+const MAX_LIBRARIES: usize = 512;
+
+/// Maximum depth of the fonts and themes directories that is summarized
+const MAX_DIRECTORY_DEPTH: usize = 8;
+
+/// Maximum size of an ELF table that will be read
+const MAX_ELF_TABLE_SIZE: u64 = 16 * 1024 * 1024;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELF_HEADER_SIZE: u64 = 64;
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+
+/// Program header types
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+/// Dynamic section tags
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+
+/// Environment of the converter for debugging broken deployments
+#[derive(Debug, Serialize)]
+pub struct EnvironmentReport {
+    /// Directory of the x2t used by requests that do not select a version
+    pub x2t_path: Option<PathBuf>,
+    /// Why the x2t directory could not be resolved (i.e an unknown default version)
+    pub x2t_error: Option<String>,
+    /// Whether the x2t binary exists within the x2t directory
+    pub x2t_binary_found: bool,
+    /// Shared libraries x2t depends on, including the dependencies of the libraries
+    pub libraries: Vec<SharedLibrary>,
+    /// Names of the shared libraries that could not be found
+    pub missing_libraries: Vec<String>,
+    /// Configured ICU data directory of the x2t
+    pub icu_data_path: Option<PathBuf>,
+    /// ICU data file found within the ICU data directory
+    pub icu_data_file: Option<PathBuf>,
+    pub fonts: DirectorySummary,
+    /// Themes directory, [None] when no themes are available
+    pub themes: Option<DirectorySummary>,
+    /// Library path of the process, x2t is run with the x2t directory prepended
+    pub ld_library_path: Option<String>,
+    pub temp_path: PathBuf,
+    /// Free space of the file system containing the temporary directory
+    pub temp_available_bytes: Option<u64>,
+}
+
+/// Shared library required by x2t
+#[derive(Debug, Serialize)]
+pub struct SharedLibrary {
+    pub name: String,
+    /// Path the library was found at, [None] when the library is missing
+    pub path: Option<PathBuf>,
+}
+
+/// Summary of the contents of a directory
+#[derive(Debug, Serialize)]
+pub struct DirectorySummary {
+    pub path: PathBuf,
+    pub exists: bool,
+    /// Number of files within the directory and its subdirectories
+    pub files: u64,
+    /// Total size of the files in bytes
+    pub total_bytes: u64,
+    /// Number of files of each extension (i.e `ttf`)
+    pub extensions: BTreeMap<String, u64>,
+}
+
+/// Whether the environment diagnostics can be requested
+pub fn is_diagnostics_enabled() -> bool {
+    app_config().diagnostics_enabled
+}
+
+/// Report the resolved x2t installation, the shared libraries and ICU data it requires,
+/// the fonts and themes directories, the library path and the free temporary disk space
+pub async fn environment_report() -> Result<EnvironmentReport, ConvertError> {
+    // Resolved before blocking as the bundled x2t is only known to the runtime
+    let x2t_path = resolve_x2t_path(default_x2t_version().as_deref());
+    let fonts_path = fonts_path();
+    let themes_path = themes_path();
+
+    tokio::task::spawn_blocking(move || {
+        let (x2t_path, x2t_error) = match x2t_path {
+            Ok(path) => (path, None),
+            Err(error) => (None, Some(error.message)),
+        };
+
+        let ld_library_path = std::env::var("LD_LIBRARY_PATH").ok();
+
+        let x2t_binary = x2t_path.as_ref().map(|path| path.join(X2T_BIN));
+        let x2t_binary_found = x2t_binary.as_ref().is_some_and(|path| path.is_file());
+
+        let libraries = match (&x2t_path, &x2t_binary) {
+            (Some(x2t_path), Some(x2t_binary)) if x2t_binary_found => {
+                let search_paths = library_search_paths(x2t_path, ld_library_path.as_deref());
+                resolve_libraries(x2t_binary, &search_paths)
+            }
+            _ => Vec::new(),
+        };
+
+        let missing_libraries = libraries
+            .iter()
+            .filter(|library| library.path.is_none())
+            .map(|library| library.name.clone())
+            .collect();
+
+        let icu_data_path = x2t_path.as_deref().and_then(icu_data_path);
+        let icu_data_file = icu_data_path.as_deref().and_then(find_icu_data_file);
+
+        let temp_path = std::env::temp_dir();
+
+        EnvironmentReport {
+            x2t_path,
+            x2t_error,
+            x2t_binary_found,
+            libraries,
+            missing_libraries,
+            icu_data_path,
+            icu_data_file,
+            fonts: summarize_directory(&fonts_path),
+            themes: themes_path.as_deref().map(summarize_directory),
+            ld_library_path,
+            temp_available_bytes: available_space(&temp_path),
+            temp_path,
+        }
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "environment diagnostics task failed");
+        ConvertError {
+            reason: Some("DIAGNOSTICS"),
+            x2t_code: None,
+            message: "failed to collect environment diagnostics".to_string(),
+        }
+    })
+}
+
+/// Directories shared libraries are searched within, in the order x2t loads them
+fn library_search_paths(x2t_path: &Path, ld_library_path: Option<&str>) -> Vec<PathBuf> {
+    let mut paths = vec![x2t_path.to_path_buf()];
+
+    if let Some(ld_library_path) = ld_library_path {
+        paths.extend(
+            ld_library_path
+                .split(':')
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+        );
+    }
+
+    paths.extend(SYSTEM_LIBRARY_PATHS.iter().map(PathBuf::from));
+    paths
+}
+
+/// Resolve the shared libraries required by the `binary` and the libraries it loads
+fn resolve_libraries(binary: &Path, search_paths: &[PathBuf]) -> Vec<SharedLibrary> {
+    let mut libraries = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([binary.to_path_buf()]);
+
+    while let Some(path) = queue.pop_front() {
+        let Some(needed) = needed_libraries(&path) else {
+            tracing::warn!(path = %path.display(), "failed to read shared library dependencies");
+            continue;
+        };
+
+        for name in needed {
+            if libraries.len() >= MAX_LIBRARIES || !seen.insert(name.clone()) {
+                continue;
+            }
+
+            let path = search_paths
+                .iter()
+                .map(|search_path| search_path.join(&name))
+                .find(|path| path.is_file());
+
+            if let Some(path) = &path {
+                queue.push_back(path.clone());
+            }
+
+            libraries.push(SharedLibrary { name, path });
+        }
+    }
+
+    libraries
+}
+
+/// Names of the shared libraries (`DT_NEEDED`) the ELF file at `path` depends on,
+/// [None] when the file is not a 64-bit little endian ELF file
+fn needed_libraries(path: &Path) -> Option<Vec<String>> {
+    let mut file = File::open(path).ok()?;
+
+    let header = read_at(&mut file, 0, ELF_HEADER_SIZE)?;
+    if !header.starts_with(ELF_MAGIC)
+        || header[4] != ELF_CLASS_64
+        || header[5] != ELF_DATA_LITTLE_ENDIAN
+    {
+        return None;
+    }
+
+    let ph_offset = u64_at(&header, 0x20)?;
+    let ph_size = u16_at(&header, 0x36)? as u64;
+    let ph_count = u16_at(&header, 0x38)? as u64;
+
+    // Program headers are at least 56 bytes for 64-bit files
+    if ph_size < 56 {
+        return None;
+    }
+
+    let program_headers = read_at(&mut file, ph_offset, ph_size * ph_count)?;
+
+    // Virtual address, file offset and file size of the loaded segments
+    let mut loads = Vec::new();
+    let mut dynamic = None;
+
+    for program_header in program_headers.chunks_exact(ph_size as usize) {
+        match u32_at(program_header, 0)? {
+            PT_LOAD => loads.push((
+                u64_at(program_header, 16)?,
+                u64_at(program_header, 8)?,
+                u64_at(program_header, 32)?,
+            )),
+            PT_DYNAMIC => {
+                dynamic = Some((u64_at(program_header, 8)?, u64_at(program_header, 32)?));
+            }
+            _ => {}
+        }
+    }
+
+    // Statically linked files have no dependencies
+    let Some((dynamic_offset, dynamic_size)) = dynamic else {
+        return Some(Vec::new());
+    };
+
+    let entries = read_at(&mut file, dynamic_offset, dynamic_size)?;
+
+    let mut needed = Vec::new();
+    let mut strtab = None;
+    let mut strsz = None;
+
+    for entry in entries.chunks_exact(16) {
+        let value = u64_at(entry, 8)?;
+
+        match u64_at(entry, 0)? {
+            DT_NULL => break,
+            DT_NEEDED => needed.push(value),
+            DT_STRTAB => strtab = Some(value),
+            DT_STRSZ => strsz = Some(value),
+            _ => {}
+        }
+    }
+
+    // The string table is referenced by its virtual address
+    let strtab = strtab?;
+    let strtab_offset = loads
+        .iter()
+        .find(|(address, _, size)| strtab >= *address && strtab < address.saturating_add(*size))
+        .map(|(address, offset, _)| strtab - address + offset)?;
+
+    let strings = read_at(&mut file, strtab_offset, strsz?)?;
+
+    Some(
+        needed
+            .into_iter()
+            .filter_map(|offset| {
+                let string = strings.get(usize::try_from(offset).ok()?..)?;
+                let end = string.iter().position(|byte| *byte == 0)?;
+                Some(String::from_utf8_lossy(&string[..end]).into_owned())
+            })
+            .collect(),
+    )
+}
+
+fn read_at(file: &mut File, offset: u64, length: u64) -> Option<Vec<u8>> {
+    if length > MAX_ELF_TABLE_SIZE {
+        return None;
+    }
+
+    file.seek(SeekFrom::Start(offset)).ok()?;
+
+    let mut bytes = vec![0; length as usize];
+    file.read_exact(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Count the files within the directory at `path` and its subdirectories
+fn summarize_directory(path: &Path) -> DirectorySummary {
+    let mut summary = DirectorySummary {
+        path: path.to_path_buf(),
+        exists: path.is_dir(),
+        files: 0,
+        total_bytes: 0,
+        extensions: BTreeMap::new(),
+    };
+
+    let mut directories = vec![(path.to_path_buf(), 0)];
+
+    while let Some((directory, depth)) = directories.pop() {
+        let Ok(entries) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            // Symbolic links are followed as the synced fonts link to the x2t fonts
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+
+            if metadata.is_dir() {
+                if depth < MAX_DIRECTORY_DEPTH {
+                    directories.push((path, depth + 1));
+                }
+                continue;
+            }
+
+            summary.files += 1;
+            summary.total_bytes += metadata.len();
+
+            let extension = path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            *summary.extensions.entry(extension).or_default() += 1;
+        }
+    }
+
+    summary
+}
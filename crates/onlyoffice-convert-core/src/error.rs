@@ -0,0 +1,72 @@
+use serde::Serialize;
+
+/// Error from a conversion, serialized as the error response
+#[derive(Serialize, Debug, Clone)]
+pub struct ConvertError {
+    pub reason: Option<&'static str>,
+    pub x2t_code: Option<i32>,
+    pub message: String,
+}
+
+impl ConvertError {
+    /// HTTP status code to use when responding to HTTP requests with this error
+    pub fn status_code(&self) -> u16 {
+        match self.reason {
+            Some(
+                "PARSE_REQUEST"
+                | "PARSE_HTTP_EVENT"
+                | "PARSE_OBJECT_LAMBDA_EVENT"
+                | "UNKNOWN_OUTPUT_FORMAT"
+                | "INVALID_REQUEST",
+            ) => 400,
+            Some(
+                "MISSING_DESTINATION"
+                | "UNKNOWN_FONT_PROFILE"
+                | "UNKNOWN_X2T_VERSION"
+                | "UNKNOWN_TENANT"
+                | "SIGNING_UNAVAILABLE"
+                | "DEBUG_UNAVAILABLE",
+            ) => 400,
+            Some("UNAUTHORIZED") => 401,
+            Some("URL_SOURCE_NOT_ALLOWED" | "TENANT_NOT_ALLOWED") => 403,
+            Some(
+                "NO_SUCH_KEY"
+                | "NO_SUCH_VERSION"
+                | "SHEET_NOT_FOUND"
+                | "JOB_NOT_FOUND"
+                | "DIAGNOSTICS_DISABLED"
+                | "PASSWORD_SECRET_NOT_FOUND",
+            ) => 404,
+            Some(
+                "OUTPUT_TOO_LARGE"
+                | "URL_SOURCE_TOO_LARGE"
+                | "SOURCE_TOO_LARGE"
+                | "REQUEST_TOO_LARGE",
+            ) => 413,
+            Some("SOURCE_CHANGED") => 412,
+            Some("UNSUPPORTED_FORMAT") => 415,
+            Some("CANCELLED") => 409,
+            Some("BUSY" | "QUOTA_EXCEEDED") => 429,
+            Some(
+                "URL_SOURCE_REQUEST"
+                | "URL_SOURCE_STATUS"
+                | "URL_SOURCE_TOO_MANY_REDIRECTS"
+                | "TIMESTAMP_SIGNATURE",
+            ) => 502,
+            Some(
+                "FILE_LIKELY_CORRUPTED"
+                | "FILE_LIKELY_ENCRYPTED"
+                | "FILE_INVALID_ARCHIVE"
+                | "INPUT_EMPTY"
+                | "TEMPLATE_INVALID_SOURCE"
+                | "PASSWORD_SECRET_INVALID"
+                | "SOURCE_LIMITS_EXCEEDED"
+                | "TAGGED_PDF_UNSUPPORTED"
+                | "PERMANENT_FAILURE",
+            ) => 422,
+            Some("STORAGE_THROTTLED") => 503,
+            Some("REQUEST_TIMEOUT") => 504,
+            _ => 500,
+        }
+    }
+}
@@ -0,0 +1,581 @@
+use std::path::Path;
+
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ConvertError, stamp::inherited};
+
+/// Prefix of the names the flattened appearances are drawn with
+const XOBJECT_PREFIX: &str = "OOConvertFlat";
+
+/// Annotation flag for annotations that are not displayed
+const HIDDEN_FLAG: i64 = 1 << 1;
+
+/// How markup annotations (comments, highlights, shapes, ink) of PDF outputs are handled
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationMode {
+    /// Draw the annotations into the page content so they always appear
+    /// and can no longer be edited
+    Flatten,
+    /// Remove the annotations from the output
+    Remove,
+}
+
+/// Annotations flattened into the page content or removed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FlattenOptions {
+    /// Flatten the form fields (widget annotations), the document is no
+    /// longer a fillable form
+    pub forms: bool,
+    /// Flatten or remove the markup annotations, links are always kept
+    pub annotations: Option<AnnotationMode>,
+}
+
+/// Action taken for an annotation
+#[derive(PartialEq, Eq)]
+enum AnnotationAction {
+    Keep,
+    Flatten,
+    Remove,
+}
+
+impl FlattenOptions {
+    /// Whether any annotations are flattened or removed
+    pub fn is_enabled(&self) -> bool {
+        self.forms || self.annotations.is_some()
+    }
+
+    /// Action taken for an annotation of the `subtype`
+    fn action(&self, subtype: &[u8]) -> AnnotationAction {
+        match (subtype, self.annotations) {
+            (b"Widget", _) if self.forms => AnnotationAction::Flatten,
+            (b"Widget" | b"Link", _) | (_, None) => AnnotationAction::Keep,
+            // Popups are only shown when opened from their parent annotation
+            (b"Popup", Some(_)) | (_, Some(AnnotationMode::Remove)) => AnnotationAction::Remove,
+            (_, Some(AnnotationMode::Flatten)) => AnnotationAction::Flatten,
+        }
+    }
+}
+
+/// Flatten the annotations of the PDF at `output_path` in place, the appearance of
+/// each flattened annotation is drawn into the page content and the annotation is
+/// removed. The flattened file is written to `flattened_path` before replacing the output
+pub async fn flatten_pdf(
+    output_path: &Path,
+    flattened_path: &Path,
+    options: FlattenOptions,
+) -> Result<(), ConvertError> {
+    let output_path = output_path.to_path_buf();
+    let flattened_path = flattened_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let mut document = Document::load(&output_path)?;
+        flatten_document(&mut document, options)?;
+        document.save(&flattened_path)?.sync_all()?;
+        std::fs::rename(&flattened_path, &output_path)?;
+        Ok::<_, lopdf::Error>(())
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "flatten task failed");
+        flatten_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to flatten pdf");
+        flatten_error()
+    })
+}
+
+fn flatten_document(document: &mut Document, options: FlattenOptions) -> lopdf::Result<()> {
+    let mut next_name = 0;
+
+    for page_id in document.get_pages().into_values() {
+        let annotations = match document.get_dictionary(page_id)?.get(b"Annots") {
+            Ok(value) => document.dereference(value)?.1.as_array()?.clone(),
+            Err(_) => continue,
+        };
+
+        let mut content = String::new();
+        let mut xobjects = Vec::new();
+        let mut remaining = Vec::new();
+
+        for annotation in annotations {
+            let Some(dictionary) = document
+                .dereference(&annotation)
+                .ok()
+                .and_then(|(_, value)| value.as_dict().ok())
+            else {
+                continue;
+            };
+
+            let action = dictionary
+                .get(b"Subtype")
+                .and_then(Object::as_name)
+                .map_or(AnnotationAction::Keep, |subtype| options.action(subtype));
+
+            match action {
+                AnnotationAction::Keep => {
+                    remaining.push(annotation);
+                    continue;
+                }
+                AnnotationAction::Remove => continue,
+                AnnotationAction::Flatten => {}
+            }
+
+            let hidden = dictionary
+                .get(b"F")
+                .and_then(Object::as_i64)
+                .is_ok_and(|flags| flags & HIDDEN_FLAG != 0);
+
+            if hidden {
+                continue;
+            }
+
+            let Some((appearance_id, transform)) = appearance(document, dictionary) else {
+                tracing::debug!("annotation has no appearance, removing without drawing");
+                continue;
+            };
+
+            let name = format!("{XOBJECT_PREFIX}{next_name}");
+            next_name += 1;
+
+            let [a, b, c, d, e, f] = transform;
+            content.push_str(&format!(
+                "q\n{a:.6} {b:.6} {c:.6} {d:.6} {e:.4} {f:.4} cm\n/{name} Do\nQ\n"
+            ));
+            xobjects.push((name, appearance_id));
+        }
+
+        if !xobjects.is_empty() {
+            add_page_xobjects(document, page_id, &xobjects)?;
+
+            // The existing content is wrapped in a saved graphics state so the
+            // appearances are drawn with the default state
+            let save_id = document.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+            let flattened_id = document.add_object(Stream::new(
+                Dictionary::new(),
+                format!("\nQ\n{content}").into_bytes(),
+            ));
+
+            let mut contents: Vec<Object> = vec![save_id.into()];
+            contents.extend(
+                document
+                    .get_page_contents(page_id)
+                    .into_iter()
+                    .map(Object::from),
+            );
+            contents.push(flattened_id.into());
+
+            document
+                .get_dictionary_mut(page_id)?
+                .set("Contents", contents);
+        }
+
+        let page = document.get_dictionary_mut(page_id)?;
+        if remaining.is_empty() {
+            page.remove(b"Annots");
+        } else {
+            page.set("Annots", remaining);
+        }
+    }
+
+    // Flattened documents are no longer fillable forms
+    if options.forms {
+        document.catalog_mut()?.remove(b"AcroForm");
+    }
+
+    Ok(())
+}
+
+/// Get the normal appearance stream of the `annotation` along with the transform
+/// mapping the appearance onto the annotation rectangle
+fn appearance(document: &Document, annotation: &Dictionary) -> Option<(ObjectId, [f32; 6])> {
+    let rect = numbers::<4>(document, annotation.get(b"Rect").ok()?)?;
+
+    let appearances = document
+        .dereference(annotation.get(b"AP").ok()?)
+        .ok()?
+        .1
+        .as_dict()
+        .ok()?;
+    let normal = appearances.get(b"N").ok()?;
+
+    // Appearances with states (i.e checkboxes) are selected by the appearance state
+    let appearance_id = match normal {
+        Object::Reference(id) if document.get_object(*id).ok()?.as_stream().is_ok() => *id,
+        _ => {
+            let state = annotation.get(b"AS").and_then(Object::as_name).ok()?;
+            let states = document.dereference(normal).ok()?.1.as_dict().ok()?;
+            states.get(state).and_then(Object::as_reference).ok()?
+        }
+    };
+
+    let stream = document.get_object(appearance_id).ok()?.as_stream().ok()?;
+    let bbox = numbers::<4>(document, stream.dict.get(b"BBox").ok()?)?;
+    let matrix = stream
+        .dict
+        .get(b"Matrix")
+        .ok()
+        .and_then(|value| numbers::<6>(document, value))
+        .unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    // Bounds of the appearance box once transformed by the appearance matrix
+    let corners = [
+        (bbox[0], bbox[1]),
+        (bbox[2], bbox[1]),
+        (bbox[0], bbox[3]),
+        (bbox[2], bbox[3]),
+    ]
+    .map(|(x, y)| {
+        (
+            matrix[0] * x + matrix[2] * y + matrix[4],
+            matrix[1] * x + matrix[3] * y + matrix[5],
+        )
+    });
+
+    let min_x = corners
+        .iter()
+        .map(|(x, _)| *x)
+        .fold(f32::INFINITY, f32::min);
+    let max_x = corners
+        .iter()
+        .map(|(x, _)| *x)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let min_y = corners
+        .iter()
+        .map(|(_, y)| *y)
+        .fold(f32::INFINITY, f32::min);
+    let max_y = corners
+        .iter()
+        .map(|(_, y)| *y)
+        .fold(f32::NEG_INFINITY, f32::max);
+
+    let (rect_x, rect_y) = (rect[0].min(rect[2]), rect[1].min(rect[3]));
+    let (rect_width, rect_height) = ((rect[2] - rect[0]).abs(), (rect[3] - rect[1]).abs());
+    let (width, height) = (max_x - min_x, max_y - min_y);
+
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+
+    let (scale_x, scale_y) = (rect_width / width, rect_height / height);
+
+    Some((
+        appearance_id,
+        [
+            scale_x,
+            0.0,
+            0.0,
+            scale_y,
+            rect_x - min_x * scale_x,
+            rect_y - min_y * scale_y,
+        ],
+    ))
+}
+
+/// Read an array of `N` numbers
+fn numbers<const N: usize>(document: &Document, value: &Object) -> Option<[f32; N]> {
+    let values = document.dereference(value).ok()?.1.as_array().ok()?;
+    if values.len() != N {
+        return None;
+    }
+
+    let mut output = [0.0; N];
+    for (output, value) in output.iter_mut().zip(values) {
+        *output = document.dereference(value).ok()?.1.as_float().ok()?;
+    }
+
+    Some(output)
+}
+
+/// Add the appearance streams to the XObject resources of the page, the (possibly
+/// inherited) resources are copied onto the page so other pages are unaffected
+fn add_page_xobjects(
+    document: &mut Document,
+    page_id: ObjectId,
+    xobjects: &[(String, ObjectId)],
+) -> lopdf::Result<()> {
+    let mut resources = match inherited(document, page_id, b"Resources")? {
+        Some(value) => document.dereference(value)?.1.as_dict()?.clone(),
+        None => Dictionary::new(),
+    };
+
+    let mut page_xobjects = match resources.get(b"XObject") {
+        Ok(value) => document.dereference(value)?.1.as_dict()?.clone(),
+        Err(_) => Dictionary::new(),
+    };
+
+    for (name, appearance_id) in xobjects {
+        // Appearance streams are form XObjects but may omit the type
+        if let Ok(Object::Stream(stream)) = document.get_object_mut(*appearance_id) {
+            stream.dict.set("Type", Object::Name(b"XObject".to_vec()));
+            stream.dict.set("Subtype", Object::Name(b"Form".to_vec()));
+        }
+
+        page_xobjects.set(name.as_str(), *appearance_id);
+    }
+
+    resources.set("XObject", page_xobjects);
+
+    document
+        .get_dictionary_mut(page_id)?
+        .set("Resources", resources);
+
+    Ok(())
+}
+
+fn flatten_error() -> ConvertError {
+    ConvertError {
+        reason: Some("FLATTEN_OUTPUT"),
+        x2t_code: None,
+        message: "failed to flatten pdf output".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+
+    use super::{AnnotationMode, FlattenOptions, appearance, flatten_document};
+
+    /// Create a single page document with the `annotations`, returns the document
+    /// and the ID of its page
+    fn document(annotations: Vec<Dictionary>) -> (Document, ObjectId) {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+
+        let annotations: Vec<Object> = annotations
+            .into_iter()
+            .map(|annotation| document.add_object(annotation).into())
+            .collect();
+
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Annots" => annotations,
+        });
+
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "AcroForm" => dictionary! { "Fields" => Vec::<Object>::new() },
+        });
+        document.trailer.set("Root", catalog_id);
+
+        (document, page_id)
+    }
+
+    fn numbers(values: &[f32]) -> Vec<Object> {
+        values.iter().map(|value| Object::Real(*value)).collect()
+    }
+
+    /// Add an appearance stream with the `bbox` and optional `matrix`
+    fn add_appearance(document: &mut Document, bbox: &[f32], matrix: Option<&[f32]>) -> ObjectId {
+        let mut dict = dictionary! { "BBox" => numbers(bbox) };
+        if let Some(matrix) = matrix {
+            dict.set("Matrix", numbers(matrix));
+        }
+
+        document.add_object(Stream::new(dict, b"0 0 m".to_vec()))
+    }
+
+    /// Create an annotation of the `subtype` with the appearance `appearance_id`
+    fn annotation(subtype: &str, appearance_id: Option<ObjectId>) -> Dictionary {
+        let mut annotation = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => subtype,
+            "Rect" => numbers(&[100.0, 200.0, 300.0, 250.0]),
+        };
+
+        if let Some(appearance_id) = appearance_id {
+            annotation.set("AP", dictionary! { "N" => appearance_id });
+        }
+
+        annotation
+    }
+
+    #[test]
+    fn test_appearance_transform() {
+        for (bbox, matrix, expected) in [
+            // Appearance box matching the rectangle
+            (
+                &[0.0, 0.0, 200.0, 50.0][..],
+                None,
+                [1.0, 0.0, 0.0, 1.0, 100.0, 200.0],
+            ),
+            // Appearance box scaled onto the rectangle
+            (
+                &[0.0, 0.0, 100.0, 25.0],
+                None,
+                [2.0, 0.0, 0.0, 2.0, 100.0, 200.0],
+            ),
+            // Appearance box with an offset origin
+            (
+                &[10.0, 10.0, 110.0, 35.0],
+                None,
+                [2.0, 0.0, 0.0, 2.0, 80.0, 180.0],
+            ),
+            // Appearance rotated by its matrix, the transformed box is mapped
+            (
+                &[0.0, 0.0, 50.0, 200.0],
+                Some(&[0.0, 1.0, -1.0, 0.0, 0.0, 0.0][..]),
+                [1.0, 0.0, 0.0, 1.0, 300.0, 200.0],
+            ),
+        ] {
+            let (mut document, _) = document(Vec::new());
+            let appearance_id = add_appearance(&mut document, bbox, matrix);
+
+            let (id, transform) =
+                appearance(&document, &annotation("Square", Some(appearance_id))).unwrap();
+            assert_eq!(id, appearance_id);
+            assert_eq!(transform, expected, "{bbox:?} {matrix:?}");
+        }
+    }
+
+    #[test]
+    fn test_appearance_inverted_rect() {
+        let (mut document, _) = document(Vec::new());
+        let appearance_id = add_appearance(&mut document, &[0.0, 0.0, 200.0, 50.0], None);
+
+        let mut annotation = annotation("Square", Some(appearance_id));
+        annotation.set("Rect", numbers(&[300.0, 250.0, 100.0, 200.0]));
+
+        let (_, transform) = appearance(&document, &annotation).unwrap();
+        assert_eq!(transform, [1.0, 0.0, 0.0, 1.0, 100.0, 200.0]);
+    }
+
+    #[test]
+    fn test_appearance_state() {
+        let (mut document, _) = document(Vec::new());
+        let off_id = add_appearance(&mut document, &[0.0, 0.0, 200.0, 50.0], None);
+        let on_id = add_appearance(&mut document, &[0.0, 0.0, 200.0, 50.0], None);
+
+        let mut annotation = annotation("Widget", None);
+        annotation.set(
+            "AP",
+            dictionary! { "N" => dictionary! { "Off" => off_id, "Yes" => on_id } },
+        );
+        annotation.set("AS", Object::Name(b"Yes".to_vec()));
+
+        let (id, _) = appearance(&document, &annotation).unwrap();
+        assert_eq!(id, on_id);
+    }
+
+    #[test]
+    fn test_appearance_invalid() {
+        let (mut document, _) = document(Vec::new());
+
+        // Empty appearance box
+        let appearance_id = add_appearance(&mut document, &[0.0, 0.0, 0.0, 50.0], None);
+        assert!(appearance(&document, &annotation("Square", Some(appearance_id))).is_none());
+
+        // Malformed appearance box
+        let appearance_id = add_appearance(&mut document, &[0.0, 0.0, 200.0], None);
+        assert!(appearance(&document, &annotation("Square", Some(appearance_id))).is_none());
+
+        assert!(appearance(&document, &annotation("Square", None)).is_none());
+    }
+
+    #[test]
+    fn test_flatten_forms() {
+        let (mut document, page_id) = document(Vec::new());
+        let appearance_id = add_appearance(&mut document, &[0.0, 0.0, 200.0, 50.0], None);
+        let widget_id = document.add_object(annotation("Widget", Some(appearance_id)));
+        let link_id = document.add_object(annotation("Link", None));
+        let text_id = document.add_object(annotation("Text", Some(appearance_id)));
+        document.get_dictionary_mut(page_id).unwrap().set(
+            "Annots",
+            vec![widget_id.into(), link_id.into(), text_id.into()],
+        );
+
+        let options = FlattenOptions {
+            forms: true,
+            annotations: None,
+        };
+        flatten_document(&mut document, options).unwrap();
+
+        // Only the widget is flattened, links and markup annotations are kept
+        let page = document.get_dictionary(page_id).unwrap();
+        let annotations = page.get(b"Annots").unwrap().as_array().unwrap();
+        assert_eq!(annotations, &[link_id.into(), text_id.into()]);
+
+        let xobjects = page
+            .get(b"Resources")
+            .and_then(Object::as_dict)
+            .and_then(|resources| resources.get(b"XObject"))
+            .and_then(Object::as_dict)
+            .unwrap();
+        assert_eq!(
+            xobjects.get(b"OOConvertFlat0").unwrap(),
+            &Object::Reference(appearance_id)
+        );
+
+        let content = document.get_page_content(page_id).unwrap();
+        let content = String::from_utf8(content).unwrap();
+        assert!(content.starts_with("q\n"));
+        assert!(content.contains(
+            "1.000000 0.000000 0.000000 1.000000 100.0000 200.0000 cm\n/OOConvertFlat0 Do"
+        ));
+
+        assert!(document.catalog().unwrap().get(b"AcroForm").is_err());
+    }
+
+    #[test]
+    fn test_remove_annotations() {
+        let (mut document, page_id) = document(vec![
+            annotation("Text", None),
+            annotation("Popup", None),
+            annotation("Highlight", None),
+        ]);
+
+        let options = FlattenOptions {
+            forms: false,
+            annotations: Some(AnnotationMode::Remove),
+        };
+        flatten_document(&mut document, options).unwrap();
+
+        let page = document.get_dictionary(page_id).unwrap();
+        assert!(page.get(b"Annots").is_err());
+        assert!(page.get(b"Resources").is_err());
+
+        // Forms are kept when only the annotations are removed
+        assert!(document.catalog().unwrap().get(b"AcroForm").is_ok());
+    }
+
+    #[test]
+    fn test_flatten_hidden_annotation() {
+        let (mut document, page_id) = document(Vec::new());
+        let appearance_id = add_appearance(&mut document, &[0.0, 0.0, 200.0, 50.0], None);
+
+        let mut hidden = annotation("Square", Some(appearance_id));
+        hidden.set("F", 2);
+        let hidden_id = document.add_object(hidden);
+        document
+            .get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Annots", vec![hidden_id.into()]);
+
+        let options = FlattenOptions {
+            forms: false,
+            annotations: Some(AnnotationMode::Flatten),
+        };
+        flatten_document(&mut document, options).unwrap();
+
+        // Hidden annotations are removed without drawing their appearance
+        let page = document.get_dictionary(page_id).unwrap();
+        assert!(page.get(b"Annots").is_err());
+        assert!(page.get(b"Resources").is_err());
+        assert!(document.get_page_contents(page_id).is_empty());
+    }
+}
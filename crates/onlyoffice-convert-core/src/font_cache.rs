@@ -0,0 +1,191 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use crate::{
+    config::{app_config, config_var},
+    error::ConvertError,
+    x2t::x2t_command,
+};
+
+/// Environment variable for the path to the OnlyOffice allfontsgen tool
+const ALLFONTSGEN_PATH_ENV: &str = "ALLFONTSGEN_PATH";
+
+/// Environment variable for the writable directory the generated caches are stored within
+const FONT_CACHE_DIR_ENV: &str = "FONT_CACHE_DIR";
+
+const DEFAULT_ALLFONTSGEN_PATH: &str =
+    "/var/www/onlyoffice/documentserver/server/tools/allfontsgen";
+
+/// Name of the directory the caches are stored within when no cache directory is configured
+const FONT_CACHE_DIR_NAME: &str = "onlyoffice-font-cache";
+
+/// Name of the font cache file listing the available fonts, x2t bundles
+/// one alongside its binary
+pub const ALL_FONTS_FILE_NAME: &str = "AllFonts.js";
+const FONT_SELECTION_FILE_NAME: &str = "font_selection.bin";
+
+/// Generated font caches for a fonts directory
+#[derive(Clone)]
+pub struct FontCache {
+    /// Path to the generated AllFonts.js, the font_selection.bin is stored alongside it
+    pub all_fonts_path: PathBuf,
+}
+
+/// Lazily generated font cache, [None] when allfontsgen is not available
+type FontCacheCell = Arc<OnceCell<Option<FontCache>>>;
+
+/// Font caches that have been generated, keyed by fonts directory
+static FONT_CACHES: OnceLock<Mutex<HashMap<PathBuf, FontCacheCell>>> = OnceLock::new();
+
+/// Get the font caches for the `fonts_path`, the caches are regenerated using allfontsgen
+/// when the contents of the fonts directory have changed since the caches
+/// were last generated. Returns [None] when allfontsgen is not available in which case
+/// x2t uses the caches bundled alongside it
+pub async fn font_cache(
+    fonts_path: &Path,
+    x2t_path: &Path,
+) -> Result<Option<FontCache>, ConvertError> {
+    let cell = FONT_CACHES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .entry(fonts_path.to_path_buf())
+        .or_default()
+        .clone();
+
+    let cache = cell
+        .get_or_try_init(|| generate_font_cache(fonts_path, x2t_path))
+        .await?;
+
+    Ok(cache.clone())
+}
+
+async fn generate_font_cache(
+    fonts_path: &Path,
+    x2t_path: &Path,
+) -> Result<Option<FontCache>, ConvertError> {
+    let allfontsgen = config_var(ALLFONTSGEN_PATH_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_ALLFONTSGEN_PATH));
+
+    if !allfontsgen.is_file() {
+        tracing::debug!(path = %allfontsgen.display(), "allfontsgen not found, using bundled font caches");
+        return Ok(None);
+    }
+
+    // The cache directory is keyed by the fonts directory contents so that
+    // changes to the fonts produce a fresh cache
+    let fingerprint = fonts_fingerprint(fonts_path).await?;
+    let cache_path = config_var(FONT_CACHE_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| app_config().temp_dir.join(FONT_CACHE_DIR_NAME))
+        .join(fingerprint);
+
+    let all_fonts_path = cache_path.join(ALL_FONTS_FILE_NAME);
+    let selection_path = cache_path.join(FONT_SELECTION_FILE_NAME);
+
+    if all_fonts_path.is_file() && selection_path.is_file() {
+        tracing::debug!(path = %cache_path.display(), "using existing font cache");
+
+        return Ok(Some(FontCache { all_fonts_path }));
+    }
+
+    tokio::fs::create_dir_all(&cache_path)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to create font cache directory");
+            font_cache_error()
+        })?;
+
+    tracing::debug!(path = %cache_path.display(), "generating font cache");
+
+    let output = x2t_command(&allfontsgen, x2t_path)
+        .arg(format!("--input={}", fonts_path.display()))
+        .arg(format!("--allfonts={}", all_fonts_path.display()))
+        .arg(format!("--selection={}", selection_path.display()))
+        .arg("--use-system=false")
+        .output()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to run allfontsgen");
+            font_cache_error()
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        tracing::error!(status = ?output.status.code(), %stderr, "allfontsgen failed");
+        return Err(font_cache_error());
+    }
+
+    Ok(Some(FontCache { all_fonts_path }))
+}
+
+/// Create a fingerprint of the fonts directory from its path and the path and size of
+/// each of the files within it (Following links), the directory path is included as
+/// the generated caches reference the fonts by absolute path. Modified times are not included as synced fonts
+/// are re-downloaded on each cold start
+async fn fonts_fingerprint(fonts_path: &Path) -> Result<String, ConvertError> {
+    let root = fonts_path.to_path_buf();
+
+    let mut entries = tokio::task::spawn_blocking(move || {
+        let mut entries = Vec::new();
+        collect_font_entries(&root, &root, &mut entries);
+        entries
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "font fingerprint task failed");
+        font_cache_error()
+    })?;
+
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    hasher.update(fonts_path.display().to_string().as_bytes());
+    hasher.update(b"\n");
+    for entry in entries {
+        hasher.update(entry.as_bytes());
+        hasher.update(b"\n");
+    }
+
+    let hash = hex::encode(hasher.finalize());
+    Ok(hash[..16].to_string())
+}
+
+fn collect_font_entries(root: &Path, path: &Path, entries: &mut Vec<String>) {
+    let Ok(read_dir) = std::fs::read_dir(path) else {
+        return;
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+
+        // Metadata follows links so linked font directories are included
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            collect_font_entries(root, &path, entries);
+            continue;
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+
+        entries.push(format!("{}:{}", relative.display(), metadata.len()));
+    }
+}
+
+fn font_cache_error() -> ConvertError {
+    ConvertError {
+        reason: Some("FONT_CACHE"),
+        x2t_code: None,
+        message: "failed to generate font cache".to_string(),
+    }
+}
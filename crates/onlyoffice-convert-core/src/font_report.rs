@@ -0,0 +1,178 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use zip::ZipArchive;
+
+/// Maximum size of a document part that will be scanned for font references
+const MAX_PART_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Document parts that declare the fonts used by OOXML and ODF documents
+const FONT_PARTS: &[&str] = &[
+    "word/fontTable.xml",
+    "xl/styles.xml",
+    "content.xml",
+    "styles.xml",
+];
+
+/// Directory of the parts containing the theme fonts for OOXML documents
+const THEME_PARTS_PREFIXES: &[&str] = &["ppt/theme/", "word/theme/", "xl/theme/"];
+
+/// Patterns preceding a font family name
+const FONT_PATTERNS: &[&str] = &[
+    // Word font table
+    " w:name=\"",
+    // DrawingML theme and text run latin fonts, the per-script theme
+    // fonts are skipped as they only apply to text in those scripts
+    "<a:latin typeface=\"",
+    // ODF font face declarations
+    " svg:font-family=\"",
+    // SpreadsheetML fonts
+    "<name val=\"",
+];
+
+/// Font family names available to x2t, keyed by the AllFonts.js they were loaded from
+static AVAILABLE_FONTS: OnceLock<Mutex<HashMap<PathBuf, Arc<HashSet<String>>>>> = OnceLock::new();
+
+/// Find the fonts referenced by the source document that are not available to x2t
+/// and will be substituted in the output. Only OOXML and ODF (ZIP based) documents
+/// are inspected, other documents and any failures produce an empty list
+pub async fn substituted_fonts(input_path: &Path, all_fonts_path: &Path) -> Vec<String> {
+    let input_path = input_path.to_path_buf();
+    let all_fonts_path = all_fonts_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let available = available_fonts(&all_fonts_path)?;
+        let referenced = referenced_fonts(&input_path)?;
+
+        Some(
+            referenced
+                .into_iter()
+                .filter(|name| !available.contains(&name.to_lowercase()))
+                .collect(),
+        )
+    })
+    .await
+    .inspect_err(|err| tracing::warn!(?err, "font report task failed"))
+    .ok()
+    .flatten()
+    .unwrap_or_default()
+}
+
+/// Load the lowercase font family names listed within AllFonts.js
+fn available_fonts(all_fonts_path: &Path) -> Option<Arc<HashSet<String>>> {
+    let cache = AVAILABLE_FONTS.get_or_init(Default::default);
+
+    if let Some(fonts) = cache
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(all_fonts_path)
+    {
+        return Some(fonts.clone());
+    }
+
+    let contents = std::fs::read_to_string(all_fonts_path)
+        .inspect_err(|err| tracing::debug!(?err, "failed to read AllFonts.js"))
+        .ok()?;
+
+    let fonts = Arc::new(parse_all_fonts(&contents));
+
+    cache
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(all_fonts_path.to_path_buf(), fonts.clone());
+
+    Some(fonts)
+}
+
+/// Parse the font names from the `__fonts_infos` array of AllFonts.js, each
+/// entry is an array starting with the quoted font family name
+fn parse_all_fonts(contents: &str) -> HashSet<String> {
+    let Some(start) = contents.find("__fonts_infos") else {
+        return HashSet::new();
+    };
+
+    let infos = &contents[start..];
+    let infos = infos.find("];").map(|end| &infos[..end]).unwrap_or(infos);
+
+    infos
+        .split("[\"")
+        .skip(1)
+        .filter_map(|entry| entry.split_once('"'))
+        .map(|(name, _)| name.to_lowercase())
+        .collect()
+}
+
+/// Collect the font family names referenced by the document
+fn referenced_fonts(input_path: &Path) -> Option<BTreeSet<String>> {
+    let file = File::open(input_path).ok()?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).ok()?;
+
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|name| {
+            FONT_PARTS.contains(name)
+                || THEME_PARTS_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix) && name.ends_with(".xml"))
+        })
+        .map(str::to_string)
+        .collect();
+
+    let mut fonts = BTreeSet::new();
+
+    for name in names {
+        let Ok(part) = archive.by_name(&name) else {
+            continue;
+        };
+
+        let mut contents = String::new();
+        if part
+            .take(MAX_PART_SIZE)
+            .read_to_string(&mut contents)
+            .is_err()
+        {
+            continue;
+        }
+
+        collect_font_names(&contents, &mut fonts);
+    }
+
+    Some(fonts)
+}
+
+/// Collect the font names following the [FONT_PATTERNS] within the XML
+pub fn collect_font_names(contents: &str, fonts: &mut BTreeSet<String>) {
+    for pattern in FONT_PATTERNS {
+        for (index, _) in contents.match_indices(pattern) {
+            let value = &contents[index + pattern.len()..];
+            let Some((value, _)) = value.split_once('"') else {
+                continue;
+            };
+
+            let value = unescape_xml(value);
+            // ODF font families may be quoted when they contain spaces
+            let value = value.trim().trim_matches('\'').trim();
+
+            // Theme font references (i.e "+mn-lt") are resolved from the theme fonts
+            if value.is_empty() || value.starts_with('+') {
+                continue;
+            }
+
+            fonts.insert(value.to_string());
+        }
+    }
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use futures::{StreamExt, TryStreamExt, stream};
+use tokio::sync::OnceCell;
+
+use crate::{
+    aws::aws_config,
+    config::{app_config, config_var},
+    error::ConvertError,
+};
+
+/// Environment variable for the bucket containing the custom fonts bundle, custom
+/// fonts are only synced when this is set
+const FONTS_BUCKET_ENV: &str = "FONTS_BUCKET";
+
+/// Environment variable for the key prefix the custom fonts are stored under
+const FONTS_PREFIX_ENV: &str = "FONTS_PREFIX";
+
+/// Environment variable for the key prefix font profiles are stored under, each
+/// profile is a directory of fonts within the prefix (i.e `font-profiles/{name}/`)
+const FONT_PROFILES_PREFIX_ENV: &str = "FONT_PROFILES_PREFIX";
+
+const DEFAULT_FONTS_PREFIX: &str = "fonts/";
+const DEFAULT_FONT_PROFILES_PREFIX: &str = "font-profiles/";
+
+/// Name of the directory the fonts are synced into
+const SYNCED_FONTS_DIR_NAME: &str = "onlyoffice-fonts";
+
+/// Number of font files downloaded at once
+const FONT_DOWNLOAD_CONCURRENCY: usize = 8;
+
+/// Directory containing the synced fonts, set once the fonts are synced
+static SYNCED_FONTS_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Directories for the font profiles that have been synced, profiles are synced
+/// on first use and cached across warm invocations
+static FONT_PROFILES: OnceLock<Mutex<HashMap<String, Arc<OnceCell<PathBuf>>>>> = OnceLock::new();
+
+/// Directory x2t should load fonts from, the synced fonts directory when
+/// custom fonts are enabled otherwise the x2t fonts directory
+pub fn fonts_path() -> PathBuf {
+    match SYNCED_FONTS_PATH.get() {
+        Some(path) => path.clone(),
+        None => base_fonts_path(),
+    }
+}
+
+/// Directory containing the fonts bundled with x2t
+fn base_fonts_path() -> PathBuf {
+    app_config().x2t_fonts_path.clone()
+}
+
+/// Download the custom fonts from the configured S3 prefix into a temporary directory
+/// alongside a link to the x2t fonts, called once at cold start before any requests
+/// are handled (When enabled)
+pub async fn sync_fonts() -> Result<(), ConvertError> {
+    let bucket = match config_var(FONTS_BUCKET_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        // Custom fonts are not enabled
+        _ => return Ok(()),
+    };
+
+    let prefix = config_var(FONTS_PREFIX_ENV).unwrap_or_else(|_| DEFAULT_FONTS_PREFIX.to_string());
+
+    let fonts_path = app_config().temp_dir.join(SYNCED_FONTS_DIR_NAME);
+
+    let aws_config = aws_config().await;
+    let s3_client = aws_sdk_s3::Client::new(&aws_config);
+
+    sync_fonts_dir(&s3_client, &bucket, &prefix, &fonts_path).await?;
+
+    _ = SYNCED_FONTS_PATH.set(fonts_path);
+
+    Ok(())
+}
+
+/// Directory containing the fonts of a named font profile, the profile fonts are
+/// downloaded on first use alongside a link to the x2t fonts
+pub async fn profile_fonts_path(profile: &str) -> Result<PathBuf, ConvertError> {
+    let bucket = match config_var(FONTS_BUCKET_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        _ => {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "font_profile: font profiles are not enabled".to_string(),
+            });
+        }
+    };
+
+    let prefix = config_var(FONT_PROFILES_PREFIX_ENV)
+        .unwrap_or_else(|_| DEFAULT_FONT_PROFILES_PREFIX.to_string());
+    let prefix = format!("{prefix}{profile}/");
+
+    let cell = FONT_PROFILES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .entry(profile.to_string())
+        .or_default()
+        .clone();
+
+    let fonts_path = cell
+        .get_or_try_init(|| async {
+            let fonts_path = app_config()
+                .temp_dir
+                .join(format!("{SYNCED_FONTS_DIR_NAME}-{profile}"));
+
+            let aws_config = aws_config().await;
+            let s3_client = aws_sdk_s3::Client::new(&aws_config);
+
+            let count = sync_fonts_dir(&s3_client, &bucket, &prefix, &fonts_path).await?;
+            if count == 0 {
+                return Err(ConvertError {
+                    reason: Some("UNKNOWN_FONT_PROFILE"),
+                    x2t_code: None,
+                    message: format!("unknown font profile \"{profile}\""),
+                });
+            }
+
+            Ok(fonts_path)
+        })
+        .await?;
+
+    Ok(fonts_path.clone())
+}
+
+/// Download the fonts under `prefix` into the `custom` directory of `fonts_path` and link
+/// the x2t fonts as `base`, returns the number of custom fonts that were downloaded
+async fn sync_fonts_dir(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &str,
+    prefix: &str,
+    fonts_path: &Path,
+) -> Result<usize, ConvertError> {
+    let custom_path = fonts_path.join("custom");
+
+    if fonts_path.exists() {
+        tokio::fs::remove_dir_all(&fonts_path)
+            .await
+            .map_err(sync_error)?;
+    }
+
+    tokio::fs::create_dir_all(&custom_path)
+        .await
+        .map_err(sync_error)?;
+
+    // Link the bundled fonts so x2t sees both sets of fonts within the one directory
+    #[cfg(unix)]
+    tokio::fs::symlink(base_fonts_path(), fonts_path.join("base"))
+        .await
+        .map_err(sync_error)?;
+
+    let mut pages = s3_client
+        .list_objects_v2()
+        .bucket(bucket)
+        .prefix(prefix)
+        .into_paginator()
+        .send();
+
+    let mut keys: Vec<String> = Vec::new();
+
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            tracing::error!(?err, "failed to list custom fonts");
+            ConvertError {
+                reason: Some("SYNC_FONTS"),
+                x2t_code: None,
+                message: "failed to list custom fonts".to_string(),
+            }
+        })?;
+
+        keys.extend(
+            page.contents()
+                .iter()
+                .filter_map(|object| object.key.clone()),
+        );
+    }
+
+    // Font paths within the custom fonts directory
+    let fonts: Vec<(String, PathBuf)> = keys
+        .into_iter()
+        .filter_map(|key| {
+            let path = custom_path.join(font_relative_path(&key, prefix)?);
+            Some((key, path))
+        })
+        .collect();
+
+    tracing::debug!(count = fonts.len(), %bucket, %prefix, "syncing custom fonts");
+
+    let count = fonts.len();
+
+    stream::iter(fonts)
+        .map(|(key, path)| download_font(s3_client, bucket, key, path))
+        .buffer_unordered(FONT_DOWNLOAD_CONCURRENCY)
+        .try_collect::<Vec<()>>()
+        .await?;
+
+    Ok(count)
+}
+
+/// Get the path of a font relative to the fonts prefix, [None] for directory
+/// markers and keys that would escape the fonts directory
+fn font_relative_path<'a>(key: &'a str, prefix: &str) -> Option<&'a str> {
+    let relative = key.strip_prefix(prefix)?.trim_start_matches('/');
+
+    if relative.is_empty()
+        || relative.ends_with('/')
+        || relative
+            .split(['/', '\\'])
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return None;
+    }
+
+    Some(relative)
+}
+
+/// Download a single font file to disk
+async fn download_font(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: String,
+    path: PathBuf,
+) -> Result<(), ConvertError> {
+    let response = s3_client
+        .get_object()
+        .bucket(bucket)
+        .key(&key)
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %key, "failed to download custom font");
+            ConvertError {
+                reason: Some("SYNC_FONTS"),
+                x2t_code: None,
+                message: "failed to download custom font".to_string(),
+            }
+        })?;
+
+    let bytes = response.body.collect().await.map_err(|err| {
+        tracing::error!(?err, %key, "failed to read custom font");
+        ConvertError {
+            reason: Some("SYNC_FONTS"),
+            x2t_code: None,
+            message: "failed to download custom font".to_string(),
+        }
+    })?;
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(sync_error)?;
+    }
+
+    tokio::fs::write(&path, bytes.into_bytes())
+        .await
+        .map_err(sync_error)
+}
+
+fn sync_error(err: std::io::Error) -> ConvertError {
+    tracing::error!(?err, "failed to setup custom fonts directory");
+    ConvertError {
+        reason: Some("SYNC_FONTS"),
+        x2t_code: None,
+        message: "failed to setup custom fonts directory".to_string(),
+    }
+}
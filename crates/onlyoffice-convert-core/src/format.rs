@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+
+/// x2t format code (AVS_OFFICESTUDIO_FILE_DOCUMENT_MD) for Markdown documents
+const MARKDOWN_X2T_CODE: u32 = 0x005a;
+
+/// Formats that x2t can produce as the output of a conversion
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Pdf,
+    PdfA,
+    Docx,
+    Odt,
+    Rtf,
+    Txt,
+    Html,
+    Epub,
+    /// Markdown, requires a x2t version with Markdown support
+    Md,
+    Xlsx,
+    Ods,
+    Csv,
+    Pptx,
+    Odp,
+    /// PNG image of the first page
+    Thumbnail,
+    /// Cells of every worksheet of a spreadsheet as JSON
+    Json,
+}
+
+impl OutputFormat {
+    /// Parse a format from its name (i.e "pdf" or "docx"), case insensitive
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "pdf" => OutputFormat::Pdf,
+            "pdfa" => OutputFormat::PdfA,
+            "docx" => OutputFormat::Docx,
+            "odt" => OutputFormat::Odt,
+            "rtf" => OutputFormat::Rtf,
+            "txt" => OutputFormat::Txt,
+            "html" => OutputFormat::Html,
+            "epub" => OutputFormat::Epub,
+            "md" | "markdown" => OutputFormat::Md,
+            "xlsx" => OutputFormat::Xlsx,
+            "ods" => OutputFormat::Ods,
+            "csv" => OutputFormat::Csv,
+            "pptx" => OutputFormat::Pptx,
+            "odp" => OutputFormat::Odp,
+            "thumbnail" => OutputFormat::Thumbnail,
+            "json" => OutputFormat::Json,
+            _ => return None,
+        })
+    }
+
+    /// x2t format code (AVS_OFFICESTUDIO_FILE_*) for the format
+    pub fn x2t_code(&self) -> u32 {
+        match self {
+            OutputFormat::Pdf => 0x0201,
+            OutputFormat::PdfA => 0x0209,
+            OutputFormat::Docx => 0x0041,
+            OutputFormat::Odt => 0x0043,
+            OutputFormat::Rtf => 0x0044,
+            OutputFormat::Txt => 0x0045,
+            OutputFormat::Html => 0x0046,
+            OutputFormat::Epub => 0x0048,
+            OutputFormat::Md => MARKDOWN_X2T_CODE,
+            OutputFormat::Xlsx => 0x0101,
+            OutputFormat::Ods => 0x0103,
+            OutputFormat::Csv => 0x0104,
+            OutputFormat::Pptx => 0x0081,
+            OutputFormat::Odp => 0x0083,
+            OutputFormat::Thumbnail => 0x0405,
+            // Extracted from a XLSX workbook produced by x2t
+            OutputFormat::Json => OutputFormat::Xlsx.x2t_code(),
+        }
+    }
+
+    /// File extension used for the output file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf | OutputFormat::PdfA => "pdf",
+            OutputFormat::Docx => "docx",
+            OutputFormat::Odt => "odt",
+            OutputFormat::Rtf => "rtf",
+            OutputFormat::Txt => "txt",
+            OutputFormat::Html => "html",
+            OutputFormat::Epub => "epub",
+            OutputFormat::Md => "md",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Ods => "ods",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Pptx => "pptx",
+            OutputFormat::Odp => "odp",
+            OutputFormat::Thumbnail => "png",
+            OutputFormat::Json => "json",
+        }
+    }
+
+    /// Whether the format produces a PDF
+    pub fn is_pdf(&self) -> bool {
+        matches!(self, OutputFormat::Pdf | OutputFormat::PdfA)
+    }
+
+    /// Suffix appended to the destination key when the format is one of
+    /// multiple outputs, distinguishes formats that share an extension
+    pub fn key_suffix(&self) -> &'static str {
+        match self {
+            OutputFormat::PdfA => "pdfa.pdf",
+            OutputFormat::Thumbnail => "thumbnail.png",
+            _ => self.extension(),
+        }
+    }
+
+    /// MIME type of the output format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Pdf | OutputFormat::PdfA => "application/pdf",
+            OutputFormat::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            OutputFormat::Odt => "application/vnd.oasis.opendocument.text",
+            OutputFormat::Rtf => "application/rtf",
+            OutputFormat::Txt => "text/plain",
+            OutputFormat::Html => "text/html",
+            OutputFormat::Epub => "application/epub+zip",
+            OutputFormat::Md => "text/markdown",
+            OutputFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            OutputFormat::Ods => "application/vnd.oasis.opendocument.spreadsheet",
+            OutputFormat::Csv => "text/csv",
+            OutputFormat::Pptx => {
+                "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+            }
+            OutputFormat::Odp => "application/vnd.oasis.opendocument.presentation",
+            OutputFormat::Thumbnail => "image/png",
+            OutputFormat::Json => "application/json",
+        }
+    }
+}
+
+/// Source formats that are passed to x2t explicitly, x2t reliably detects the
+/// remaining formats from their contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputFormat {
+    Rtf,
+    Html,
+    /// MHTML web archive
+    Mht,
+    /// Word 2003 XML document
+    WordXml,
+    /// Flat OPC (single file XML) Word document
+    FlatDocx,
+    /// Flat (single file XML) OpenDocument text
+    FlatOdt,
+    /// Markdown, requires a x2t version with Markdown support
+    #[serde(rename = "md", alias = "markdown")]
+    Markdown,
+    /// Visio drawing, requires a x2t version with Visio support
+    Vsdx,
+}
+
+impl InputFormat {
+    /// x2t format code (AVS_OFFICESTUDIO_FILE_*) for the format
+    pub fn x2t_code(&self) -> u32 {
+        match self {
+            InputFormat::Rtf => 0x0044,
+            InputFormat::Html => 0x0046,
+            InputFormat::Mht => 0x0047,
+            InputFormat::FlatOdt => 0x004e,
+            InputFormat::WordXml => 0x0050,
+            InputFormat::FlatDocx => 0x0051,
+            InputFormat::Markdown => MARKDOWN_X2T_CODE,
+            InputFormat::Vsdx => 0x4001,
+        }
+    }
+
+    /// Whether x2t can convert a source of the format into the `output` format,
+    /// drawings can only be rendered
+    pub fn supports_output(&self, output: OutputFormat) -> bool {
+        match self {
+            InputFormat::Vsdx => output.is_pdf() || output == OutputFormat::Thumbnail,
+            _ => true,
+        }
+    }
+
+    /// Format of a source with the file `extension` that cannot be detected from
+    /// its contents (i.e Markdown, which is plain text)
+    pub fn from_extension(extension: &str) -> Option<InputFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(InputFormat::Markdown),
+            "vsdx" => Some(InputFormat::Vsdx),
+            _ => None,
+        }
+    }
+}
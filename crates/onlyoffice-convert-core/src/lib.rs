@@ -0,0 +1,74 @@
+//! Core conversion logic for converting documents using the OnlyOffice x2t
+//! converter, used by the Lambda handler and available to embed within other services
+
+pub mod archive;
+pub mod audit;
+pub mod aws;
+pub mod cancel;
+pub mod compress;
+pub mod concurrency;
+pub mod config;
+pub mod config_check;
+pub mod convert;
+pub mod diagnostics;
+pub mod encrypted;
+pub mod error;
+pub mod fonts;
+pub mod format;
+pub mod pipeline;
+pub mod progress;
+pub mod source;
+#[cfg(feature = "ssm")]
+pub mod ssm_config;
+pub mod storage;
+pub mod tenant;
+pub mod themes;
+#[cfg(feature = "wasm-plugins")]
+pub mod wasm_plugin;
+pub mod x2t;
+pub mod x2t_bundle;
+pub mod x2t_config;
+
+mod admission;
+mod artifacts;
+mod attachment;
+mod bookmarks;
+mod circuit_breaker;
+mod crash_dump;
+mod debug_artifacts;
+mod detect;
+mod diagnose;
+mod flatten;
+mod font_cache;
+mod font_report;
+mod linearize;
+mod output_check;
+mod password;
+mod presentation;
+mod proxy;
+mod quota;
+mod raster;
+mod rate_limit;
+mod result_cache;
+mod retry;
+mod signing;
+mod spreadsheet;
+mod stamp;
+mod tagged;
+mod temp;
+mod template;
+mod timestamp;
+mod url_source;
+mod usage;
+mod validate;
+mod workbook;
+
+/// Parsers of untrusted input exposed for the fuzz targets
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzz {
+    pub use crate::{
+        detect::{detect_input_format, detect_unsupported_format},
+        font_report::collect_font_names,
+    };
+}
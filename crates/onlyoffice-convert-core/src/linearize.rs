@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use tokio::process::Command;
+
+use crate::{config::config_var, error::ConvertError};
+
+/// Environment variable for the path to the qpdf binary, used to
+/// linearize the PDF outputs
+const QPDF_PATH_ENV: &str = "QPDF_PATH";
+
+const DEFAULT_QPDF_PATH: &str = "qpdf";
+
+/// Exit code qpdf uses when the output was written but warnings were reported
+const QPDF_WARNING_EXIT_CODE: i32 = 3;
+
+/// Linearize ("fast web view") the PDF at `output_path` in place, allowing viewers to
+/// render the first page before the whole file has loaded. The linearized file is
+/// written to `linearized_path` before replacing the output
+pub async fn linearize_pdf(output_path: &Path, linearized_path: &Path) -> Result<(), ConvertError> {
+    let qpdf_path = config_var(QPDF_PATH_ENV).unwrap_or_else(|_| DEFAULT_QPDF_PATH.to_string());
+
+    let output = Command::new(qpdf_path)
+        .arg("--linearize")
+        .arg(output_path)
+        .arg(linearized_path)
+        .output()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to run qpdf");
+            linearize_error()
+        })?;
+
+    if !output.status.success() && output.status.code() != Some(QPDF_WARNING_EXIT_CODE) {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        tracing::error!(exit_code = ?output.status.code(), %stderr, "failed to linearize pdf");
+        return Err(linearize_error());
+    }
+
+    // Replace the output with the linearized file
+    tokio::fs::rename(linearized_path, output_path)
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to replace output with linearized pdf");
+            linearize_error()
+        })
+}
+
+fn linearize_error() -> ConvertError {
+    ConvertError {
+        reason: Some("LINEARIZE_OUTPUT"),
+        x2t_code: None,
+        message: "failed to linearize pdf output".to_string(),
+    }
+}
@@ -0,0 +1,166 @@
+use std::{io::SeekFrom, path::Path};
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::{error::ConvertError, format::OutputFormat};
+
+/// Number of trailing bytes searched for the end of file markers, the ZIP end of central
+/// directory record (22 bytes) can be followed by a comment of up to 65535 bytes
+const TAIL_SIZE: u64 = 22 + 65535;
+
+/// Number of leading bytes checked for the file signatures
+const HEAD_SIZE: usize = 8;
+
+/// Number of trailing bytes the PDF `%%EOF` marker must appear within
+const PDF_EOF_SEARCH_SIZE: usize = 1024;
+
+const PDF_SIGNATURE: &[u8] = b"%PDF-";
+const PDF_EOF: &[u8] = b"%%EOF";
+const PDF_STARTXREF: &[u8] = b"startxref";
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+const ZIP_END_OF_CENTRAL_DIRECTORY: &[u8] = b"PK\x05\x06";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_END: &[u8] = b"IEND";
+const RTF_SIGNATURE: &[u8] = b"{\\rtf";
+
+/// Check the output written by x2t is non-empty and structurally valid for its
+/// format, x2t occasionally exits successfully having written a truncated file
+pub async fn check_output(path: &Path, format: OutputFormat) -> Result<(), ConvertError> {
+    let (head, tail) = read_head_tail(path).await.map_err(|err| {
+        tracing::error!(?err, "failed to read output for validation");
+        output_invalid("output file could not be read")
+    })?;
+
+    if head.is_empty() {
+        return Err(output_invalid("output file is empty"));
+    }
+
+    let valid = match format {
+        OutputFormat::Pdf | OutputFormat::PdfA => {
+            let eof_search = &tail[tail.len().saturating_sub(PDF_EOF_SEARCH_SIZE)..];
+
+            head.starts_with(PDF_SIGNATURE)
+                && contains(eof_search, PDF_EOF)
+                && contains(&tail, PDF_STARTXREF)
+        }
+        OutputFormat::Docx
+        | OutputFormat::Odt
+        | OutputFormat::Epub
+        | OutputFormat::Xlsx
+        | OutputFormat::Ods
+        | OutputFormat::Pptx
+        | OutputFormat::Odp => {
+            head.starts_with(ZIP_SIGNATURE) && contains(&tail, ZIP_END_OF_CENTRAL_DIRECTORY)
+        }
+        OutputFormat::Thumbnail => head.starts_with(PNG_SIGNATURE) && contains(&tail, PNG_END),
+        OutputFormat::Rtf => head.starts_with(RTF_SIGNATURE),
+        // Plain text formats have no structure to check, JSON is
+        // written from the already validated workbook
+        OutputFormat::Txt
+        | OutputFormat::Html
+        | OutputFormat::Md
+        | OutputFormat::Csv
+        | OutputFormat::Json => true,
+    };
+
+    if !valid {
+        tracing::error!(?format, "output file is not structurally valid");
+        return Err(output_invalid("output file is truncated or invalid"));
+    }
+
+    Ok(())
+}
+
+/// Read the leading and trailing bytes of the file
+async fn read_head_tail(path: &Path) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+
+    let mut head = vec![0; HEAD_SIZE.min(size as usize)];
+    file.read_exact(&mut head).await?;
+
+    let tail_size = size.min(TAIL_SIZE);
+    let mut tail = vec![0; tail_size as usize];
+    file.seek(SeekFrom::Start(size - tail_size)).await?;
+    file.read_exact(&mut tail).await?;
+
+    Ok((head, tail))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+fn output_invalid(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("OUTPUT_INVALID"),
+        x2t_code: None,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use super::check_output;
+    use crate::format::OutputFormat;
+
+    /// Write the `contents` to a new temporary file
+    async fn output_file(contents: &[u8]) -> PathBuf {
+        let path = temp_dir().join(format!("output-check-{}", Uuid::new_v4().simple()));
+        tokio::fs::write(&path, contents).await.unwrap();
+        path
+    }
+
+    async fn check(contents: &[u8], format: OutputFormat) -> bool {
+        let path = output_file(contents).await;
+        let result = check_output(&path, format).await;
+        _ = tokio::fs::remove_file(&path).await;
+        result.is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_check_output_pdf() {
+        assert!(
+            check(
+                b"%PDF-1.7\n1 0 obj\nstartxref\n123\n%%EOF\n",
+                OutputFormat::Pdf
+            )
+            .await
+        );
+        assert!(check(b"%PDF-1.7\nstartxref\n123\n%%EOF", OutputFormat::PdfA).await);
+
+        // Truncated before the trailer
+        assert!(!check(b"%PDF-1.7\n1 0 obj\n", OutputFormat::Pdf).await);
+        assert!(!check(b"PK\x03\x04startxref\n%%EOF", OutputFormat::Pdf).await);
+    }
+
+    #[tokio::test]
+    async fn test_check_output_zip() {
+        let mut contents = b"PK\x03\x04".to_vec();
+        contents.extend_from_slice(&[0; 128]);
+        contents.extend_from_slice(b"PK\x05\x06");
+        contents.extend_from_slice(&[0; 18]);
+        assert!(check(&contents, OutputFormat::Docx).await);
+
+        // Missing the end of central directory
+        assert!(!check(&contents[..132], OutputFormat::Xlsx).await);
+    }
+
+    #[tokio::test]
+    async fn test_check_output_empty() {
+        assert!(!check(b"", OutputFormat::Txt).await);
+        assert!(check(b"text", OutputFormat::Txt).await);
+    }
+
+    #[tokio::test]
+    async fn test_check_output_missing() {
+        let path = temp_dir().join(format!("output-check-{}", Uuid::new_v4().simple()));
+        assert!(check_output(&path, OutputFormat::Pdf).await.is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use serde::Deserialize;
+use serde_json::Value;
+#[cfg(feature = "secrets-manager")]
+use tokio::sync::OnceCell;
+
+#[cfg(feature = "secrets-manager")]
+use crate::aws::aws_config;
+#[cfg(not(feature = "secrets-manager"))]
+use crate::config_check::feature_disabled_error;
+use crate::error::ConvertError;
+
+/// Maximum length of the JSON key within the secret
+const MAX_KEY_LENGTH: usize = 256;
+
+/// Secrets Manager client created on first use
+#[cfg(feature = "secrets-manager")]
+static SECRETS_CLIENT: OnceCell<aws_sdk_secretsmanager::Client> = OnceCell::const_new();
+
+/// Secrets Manager secret containing the password of an encrypted source, the
+/// password is only read when converting so it never appears within the request
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordSecret {
+    /// ARN of the secret (i.e `arn:aws:secretsmanager:us-east-1:123456789012:secret:reports-AbCdEf`)
+    pub secret_arn: String,
+    /// Key within the JSON secret value containing the password, the whole
+    /// secret value is used as the password when not provided
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+impl PasswordSecret {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        let parts: Vec<&str> = self.secret_arn.splitn(7, ':').collect();
+
+        let valid_arn = matches!(
+            parts.as_slice(),
+            ["arn", partition, "secretsmanager", region, account_id, "secret", name]
+                if !partition.is_empty()
+                    && !region.is_empty()
+                    && account_id.len() == 12
+                    && account_id.bytes().all(|value| value.is_ascii_digit())
+                    && !name.is_empty()
+        );
+
+        if !valid_arn {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "password_secret.secret_arn: invalid secret ARN".to_string(),
+            });
+        }
+
+        if let Some(key) = &self.key
+            && (key.is_empty() || key.len() > MAX_KEY_LENGTH || key.chars().any(char::is_control))
+        {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "password_secret.key: invalid value".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Read the password from the secret
+    pub async fn resolve(&self) -> Result<DocumentPassword, ConvertError> {
+        let secret_arn = &self.secret_arn;
+        let secret = get_secret_string(secret_arn).await?;
+        let secret = secret.as_str();
+
+        let password = match &self.key {
+            Some(key) => {
+                // The secret value is never logged as it contains the password
+                let value: Value = serde_json::from_str(secret).map_err(|_| {
+                    tracing::error!(%secret_arn, "password secret is not a JSON object");
+                    invalid_secret_error()
+                })?;
+
+                match value.get(key) {
+                    Some(Value::String(password)) => password.clone(),
+                    _ => {
+                        tracing::error!(%secret_arn, %key, "password secret key is not a string");
+                        return Err(invalid_secret_error());
+                    }
+                }
+            }
+            None => secret.to_string(),
+        };
+
+        Ok(DocumentPassword(password))
+    }
+}
+
+/// Read the string value of the secret with the `secret_arn`
+#[cfg(feature = "secrets-manager")]
+async fn get_secret_string(secret_arn: &str) -> Result<String, ConvertError> {
+    let client = SECRETS_CLIENT
+        .get_or_init(|| async {
+            let aws_config = aws_config().await;
+            aws_sdk_secretsmanager::Client::new(&aws_config)
+        })
+        .await;
+
+    let response = client
+        .get_secret_value()
+        .secret_id(secret_arn)
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %secret_arn, "failed to get password secret");
+
+            if err
+                .as_service_error()
+                .is_some_and(|value| value.is_resource_not_found_exception())
+            {
+                return ConvertError {
+                    reason: Some("PASSWORD_SECRET_NOT_FOUND"),
+                    x2t_code: None,
+                    message: "password secret not found".to_string(),
+                };
+            }
+
+            ConvertError {
+                reason: Some("PASSWORD_SECRET"),
+                x2t_code: None,
+                message: "failed to get password secret".to_string(),
+            }
+        })?;
+
+    match response.secret_string {
+        Some(secret) => Ok(secret),
+        None => {
+            tracing::error!(%secret_arn, "password secret is not a string secret");
+            Err(invalid_secret_error())
+        }
+    }
+}
+
+#[cfg(not(feature = "secrets-manager"))]
+async fn get_secret_string(_secret_arn: &str) -> Result<String, ConvertError> {
+    Err(feature_disabled_error("password_secret", "secrets-manager"))
+}
+
+/// Password of an encrypted source, the password is redacted when debug
+/// formatted so it cannot be logged by accident
+pub struct DocumentPassword(String);
+
+impl DocumentPassword {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for DocumentPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DocumentPassword(<redacted>)")
+    }
+}
+
+fn invalid_secret_error() -> ConvertError {
+    ConvertError {
+        reason: Some("PASSWORD_SECRET_INVALID"),
+        x2t_code: None,
+        message: "password secret does not contain a password".to_string(),
+    }
+}
@@ -0,0 +1,154 @@
+use std::{path::Path, sync::OnceLock};
+
+use futures::future::BoxFuture;
+
+use crate::{
+    config_check::configuration_error,
+    error::ConvertError,
+    format::{InputFormat, OutputFormat},
+};
+
+/// Processors registered for every conversion, set once at cold start
+static PIPELINE: OnceLock<Pipeline> = OnceLock::new();
+
+/// Source file of a conversion passed to the [PreProcessor]s
+pub struct SourceContext<'a> {
+    pub request_id: &'a str,
+    /// Path of the source file, processors modify the source in place
+    pub source_path: &'a Path,
+    /// Path processors can write the processed source to before replacing the source
+    pub processed_path: &'a Path,
+    /// Format of the source when it was provided or detected
+    pub source_format: Option<InputFormat>,
+}
+
+/// Output file of a conversion passed to the [PostProcessor]s
+pub struct OutputContext<'a> {
+    pub request_id: &'a str,
+    /// Path of the source file the output was converted from
+    pub source_path: &'a Path,
+    /// Path of the output file, processors modify the output in place
+    pub output_path: &'a Path,
+    /// Path processors can write the processed output to before replacing the output
+    pub processed_path: &'a Path,
+    pub format: OutputFormat,
+}
+
+/// Stage run on the source file before it is converted (i.e stripping macros)
+pub trait PreProcessor: Send + Sync {
+    /// Name of the processor, included in logs and the result cache key
+    fn name(&self) -> &str;
+
+    /// Process the source file, failing the conversion on error
+    fn process<'a>(
+        &'a self,
+        context: &'a SourceContext<'a>,
+    ) -> BoxFuture<'a, Result<(), ConvertError>>;
+}
+
+/// Stage run on each output file once it has been converted (i.e watermarking)
+pub trait PostProcessor: Send + Sync {
+    /// Name of the processor, included in logs and the result cache key
+    fn name(&self) -> &str;
+
+    /// Whether the processor is run for outputs of the `format`
+    fn applies_to(&self, format: OutputFormat) -> bool {
+        _ = format;
+        true
+    }
+
+    /// Process the output file, failing the conversion on error
+    fn process<'a>(
+        &'a self,
+        context: &'a OutputContext<'a>,
+    ) -> BoxFuture<'a, Result<(), ConvertError>>;
+}
+
+/// Ordered stages run around x2t for every conversion. Post-processors run after the
+/// requested processing (i.e stamping) but before PDF outputs are linearized and signed,
+/// so signatures cover the processed output
+#[derive(Default)]
+pub struct Pipeline {
+    pre_processors: Vec<Box<dyn PreProcessor>>,
+    post_processors: Vec<Box<dyn PostProcessor>>,
+}
+
+impl Pipeline {
+    /// Add a stage run on the source file, stages are run in the order they are added
+    pub fn with_pre_processor(mut self, processor: impl PreProcessor + 'static) -> Self {
+        self.pre_processors.push(Box::new(processor));
+        self
+    }
+
+    /// Add a stage run on each output file, stages are run in the order they are added
+    pub fn with_post_processor(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.post_processors.push(Box::new(processor));
+        self
+    }
+}
+
+/// Register the pipeline run for every conversion, called once at cold start before any
+/// requests are handled. Fails when a pipeline has already been registered
+pub fn register_pipeline(pipeline: Pipeline) -> Result<(), ConvertError> {
+    PIPELINE
+        .set(pipeline)
+        .map_err(|_| configuration_error("pipeline has already been registered"))
+}
+
+/// Names of the registered processors in the order they are run, [None] when
+/// no pipeline has been registered
+pub fn pipeline_names() -> Option<Vec<&'static str>> {
+    let pipeline = PIPELINE.get()?;
+
+    Some(
+        pipeline
+            .pre_processors
+            .iter()
+            .map(|processor| processor.name())
+            .chain(
+                pipeline
+                    .post_processors
+                    .iter()
+                    .map(|processor| processor.name()),
+            )
+            .collect(),
+    )
+}
+
+/// Run the registered pre-processors on the source file
+pub async fn run_pre_processors(context: &SourceContext<'_>) -> Result<(), ConvertError> {
+    let Some(pipeline) = PIPELINE.get() else {
+        return Ok(());
+    };
+
+    for processor in &pipeline.pre_processors {
+        tracing::debug!(processor = processor.name(), "running pre-processor");
+
+        processor.process(context).await.inspect_err(|err| {
+            tracing::error!(?err, processor = processor.name(), "pre-processor failed");
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Run the registered post-processors that apply to the format of the output file
+pub async fn run_post_processors(context: &OutputContext<'_>) -> Result<(), ConvertError> {
+    let Some(pipeline) = PIPELINE.get() else {
+        return Ok(());
+    };
+
+    for processor in &pipeline.post_processors {
+        if !processor.applies_to(context.format) {
+            continue;
+        }
+
+        tracing::debug!(processor = processor.name(), "running post-processor");
+
+        processor.process(context).await.inspect_err(|err| {
+            tracing::error!(?err, processor = processor.name(), "post-processor failed");
+        })?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use lopdf::{Dictionary, Document, Object, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{error::ConvertError, stamp::page_box};
+
+/// Maximum length of a slide selection
+const MAX_SLIDES_LENGTH: usize = 256;
+
+/// Smallest page width or height in millimetres
+const MIN_PAGE_DIMENSION: f32 = 10.0;
+
+/// Largest page width or height in millimetres, the largest page size
+/// supported by PDF viewers (200 inches)
+const MAX_PAGE_DIMENSION: f32 = 5080.0;
+
+/// Number of PDF points in a millimetre
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+/// Options for converting presentations
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct PresentationOptions {
+    /// Slides to export as 1-based numbers and inclusive ranges (i.e "1-3,5"),
+    /// all slides are exported when not provided
+    #[serde(default)]
+    pub slides: Option<String>,
+    /// Whether the speaker notes are included below each slide
+    #[serde(default)]
+    pub include_notes: bool,
+    /// Size of the output pages, slides are rendered at their own size when
+    /// not provided
+    #[serde(default)]
+    pub page_size: Option<PageSize>,
+    /// How slides with a different aspect ratio than the `page_size` are fitted
+    /// onto the page, defaults to letterboxing
+    #[serde(default)]
+    pub fit: SlideFit,
+}
+
+/// Size of an output page in millimetres (i.e 297 by 210 for landscape A4)
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+/// How a slide is fitted onto a page of a different aspect ratio
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlideFit {
+    /// Scale the slide to fit within the page keeping its aspect ratio, centered
+    /// with blank space along the remaining edges
+    #[default]
+    Letterbox,
+    /// Stretch the slide to fill the whole page
+    Scale,
+}
+
+impl PresentationOptions {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        if let Some(slides) = &self.slides {
+            validate_slides(slides)?;
+        }
+
+        if let Some(page_size) = &self.page_size
+            && [page_size.width, page_size.height]
+                .iter()
+                .any(|value| !(MIN_PAGE_DIMENSION..=MAX_PAGE_DIMENSION).contains(value))
+        {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: format!(
+                    "presentation.page_size: must be between {MIN_PAGE_DIMENSION} and {MAX_PAGE_DIMENSION} millimetres"
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// JSON params passed to x2t for the presentation renderer
+    pub fn json_params(&self) -> String {
+        let mut print_options = json!({ "printNotes": self.include_notes });
+
+        if let Some(slides) = &self.slides {
+            print_options["pages"] = json!(slides);
+        }
+
+        json!({ "printOptions": print_options }).to_string()
+    }
+}
+
+/// Fit each page of the PDF at `output_path` onto a page of the `page_size` in place.
+/// The fitted file is written to `fitted_path` before replacing the output
+pub async fn fit_slides(
+    output_path: &Path,
+    fitted_path: &Path,
+    page_size: PageSize,
+    fit: SlideFit,
+) -> Result<(), ConvertError> {
+    let output_path = output_path.to_path_buf();
+    let fitted_path = fitted_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let mut document = Document::load(&output_path)?;
+        fit_document(&mut document, page_size, fit)?;
+        document.save(&fitted_path)?.sync_all()?;
+        std::fs::rename(&fitted_path, &output_path)?;
+        Ok::<_, lopdf::Error>(())
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "fit slides task failed");
+        fit_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to fit slides");
+        fit_error()
+    })
+}
+
+fn fit_document(document: &mut Document, page_size: PageSize, fit: SlideFit) -> lopdf::Result<()> {
+    let width = page_size.width * POINTS_PER_MM;
+    let height = page_size.height * POINTS_PER_MM;
+
+    for page_id in document.get_pages().into_values() {
+        let [left, bottom, right, top] = page_box(document, page_id)?;
+        let (slide_width, slide_height) = (right - left, top - bottom);
+
+        if slide_width <= 0.0 || slide_height <= 0.0 {
+            continue;
+        }
+
+        let (scale_x, scale_y) = match fit {
+            SlideFit::Letterbox => {
+                let scale = (width / slide_width).min(height / slide_height);
+                (scale, scale)
+            }
+            SlideFit::Scale => (width / slide_width, height / slide_height),
+        };
+
+        // Slides are centered on the page when letterboxed
+        let offset_x = (width - slide_width * scale_x) / 2.0 - left * scale_x;
+        let offset_y = (height - slide_height * scale_y) / 2.0 - bottom * scale_y;
+        let transform = |x: f32, y: f32| (x * scale_x + offset_x, y * scale_y + offset_y);
+
+        // The existing content is drawn through the transform
+        let begin_id = document.add_object(Stream::new(
+            Dictionary::new(),
+            format!(
+                "q
+{scale_x:.6} 0 0 {scale_y:.6} {offset_x:.4} {offset_y:.4} cm
+"
+            )
+            .into_bytes(),
+        ));
+        let end_id = document.add_object(Stream::new(
+            Dictionary::new(),
+            b"
+Q
+"
+            .to_vec(),
+        ));
+
+        let mut contents: Vec<Object> = vec![begin_id.into()];
+        contents.extend(
+            document
+                .get_page_contents(page_id)
+                .into_iter()
+                .map(Object::from),
+        );
+        contents.push(end_id.into());
+
+        // Links and other annotations are moved along with the content
+        let annotations = match document.get_dictionary(page_id)?.get(b"Annots") {
+            Ok(value) => document.dereference(value)?.1.as_array()?.clone(),
+            Err(_) => Vec::new(),
+        };
+
+        for annotation in annotations {
+            let Ok(annotation_id) = annotation.as_reference() else {
+                continue;
+            };
+
+            let Ok(annotation) = document.get_dictionary_mut(annotation_id) else {
+                continue;
+            };
+
+            let rect: Option<Vec<f32>> = annotation
+                .get(b"Rect")
+                .and_then(Object::as_array)
+                .ok()
+                .and_then(|rect| rect.iter().map(|value| value.as_float().ok()).collect());
+
+            if let Some([x1, y1, x2, y2]) = rect.as_deref() {
+                let (x1, y1) = transform(*x1, *y1);
+                let (x2, y2) = transform(*x2, *y2);
+                annotation.set("Rect", vec![x1.into(), y1.into(), x2.into(), y2.into()]);
+            }
+        }
+
+        let fitted_box: Vec<Object> = vec![0.into(), 0.into(), width.into(), height.into()];
+        let page = document.get_dictionary_mut(page_id)?;
+        page.set("Contents", contents);
+        page.set("MediaBox", fitted_box.clone());
+        page.set("CropBox", fitted_box);
+
+        for key in [b"BleedBox".as_slice(), b"TrimBox", b"ArtBox"] {
+            page.remove(key);
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate a slide selection is made of slide numbers and inclusive ranges
+fn validate_slides(slides: &str) -> Result<(), ConvertError> {
+    if slides.len() > MAX_SLIDES_LENGTH {
+        return Err(invalid_slides("slide selection is too long"));
+    }
+
+    slides.split(',').try_for_each(|range| {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start = parse_slide_number(start)?;
+        let end = parse_slide_number(end)?;
+
+        if start > end {
+            return Err(invalid_slides("slide range must not be reversed"));
+        }
+
+        Ok(())
+    })
+}
+
+fn parse_slide_number(value: &str) -> Result<u32, ConvertError> {
+    match value.trim().parse::<u32>() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(invalid_slides(
+            "slides must be 1-based numbers or ranges (i.e 1-3,5)",
+        )),
+    }
+}
+
+fn fit_error() -> ConvertError {
+    ConvertError {
+        reason: Some("FIT_SLIDES"),
+        x2t_code: None,
+        message: "failed to fit slides to the page size".to_string(),
+    }
+}
+
+fn invalid_slides(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("INVALID_REQUEST"),
+        x2t_code: None,
+        message: format!("presentation.slides: {message}"),
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::config::config_var;
+
+/// Environment variable for the number of seconds between progress reports
+const PROGRESS_INTERVAL_ENV: &str = "PROGRESS_INTERVAL_SECS";
+
+const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+
+tokio::task_local! {
+    /// Listener of the conversions running within [with_progress_listener]
+    static PROGRESS_LISTENER: ProgressListener;
+}
+
+/// Stage of a conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConvertStage {
+    Downloading,
+    Converting,
+    Uploading,
+}
+
+impl ConvertStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConvertStage::Downloading => "downloading",
+            ConvertStage::Converting => "converting",
+            ConvertStage::Uploading => "uploading",
+        }
+    }
+}
+
+/// Create a linked [ProgressListener] and [ProgressReceiver], the listener is provided
+/// to the conversions through [with_progress_listener] and the receiver is kept by the caller
+pub fn progress_channel() -> (ProgressListener, ProgressReceiver) {
+    let (sender, receiver) = watch::channel(None);
+    (ProgressListener(sender), ProgressReceiver(receiver))
+}
+
+/// Sends the progress reports of conversions to the linked [ProgressReceiver]
+#[derive(Clone)]
+pub struct ProgressListener(watch::Sender<Option<ConvertStage>>);
+
+/// Latest stage reported by the conversions using the linked [ProgressListener]
+pub struct ProgressReceiver(watch::Receiver<Option<ConvertStage>>);
+
+impl ProgressReceiver {
+    /// Take the latest stage when there has been a report since the last call,
+    /// repeated reports of the same stage are included
+    pub fn take_report(&mut self) -> Option<ConvertStage> {
+        match self.0.has_changed() {
+            Ok(true) => *self.0.borrow_and_update(),
+            _ => None,
+        }
+    }
+}
+
+/// Run the `future` with the `listener` receiving the progress reports of the
+/// conversions within it (i.e the conversions of a batch chunk)
+pub async fn with_progress_listener<F: Future>(listener: ProgressListener, future: F) -> F::Output {
+    PROGRESS_LISTENER.scope(listener, future).await
+}
+
+/// Reports the stage and elapsed time of a conversion
+pub struct ProgressReporter<'a> {
+    request_id: &'a str,
+    started_at: Instant,
+    interval: Duration,
+}
+
+impl<'a> ProgressReporter<'a> {
+    pub fn new(request_id: &'a str) -> Self {
+        let interval = config_var(PROGRESS_INTERVAL_ENV)
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|value| *value > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_PROGRESS_INTERVAL);
+
+        Self {
+            request_id,
+            started_at: Instant::now(),
+            interval,
+        }
+    }
+
+    /// Report the current stage of the conversion
+    pub fn report(&self, stage: ConvertStage) {
+        let elapsed = self.started_at.elapsed();
+
+        tracing::info!(
+            request_id = self.request_id,
+            stage = stage.as_str(),
+            elapsed_secs = elapsed.as_secs(),
+            "{} {}s elapsed",
+            stage.as_str(),
+            elapsed.as_secs()
+        );
+
+        _ = PROGRESS_LISTENER.try_with(|listener| listener.0.send_replace(Some(stage)));
+    }
+
+    /// Run the provided `future` for the `stage` reporting progress at the
+    /// configured interval until the future completes
+    pub async fn track<F: Future>(&self, stage: ConvertStage, future: F) -> F::Output {
+        self.report(stage);
+
+        let mut interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + self.interval, self.interval);
+
+        tokio::pin!(future);
+
+        loop {
+            tokio::select! {
+                output = &mut future => return output,
+                _ = interval.tick() => self.report(stage),
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::net::IpAddr;
+
+#[cfg(feature = "http-client")]
+use reqwest::{ClientBuilder, Proxy};
+use url::Url;
+
+use crate::config::app_config;
+
+/// Builder for clients sending outbound HTTP requests, requests are sent through the
+/// configured proxy unless the host is excluded by `NO_PROXY`. The configured proxy
+/// replaces the proxy reqwest reads from the environment so overrides (i.e from SSM)
+/// are applied
+#[cfg(feature = "http-client")]
+pub fn http_client_builder() -> ClientBuilder {
+    reqwest::Client::builder()
+        .no_proxy()
+        .proxy(Proxy::custom(proxy_url))
+}
+
+/// Proxy the request to the `url` is sent through, [None] when sent directly
+pub fn proxy_url(url: &Url) -> Option<Url> {
+    let config = app_config();
+
+    let proxy = match url.scheme() {
+        "http" => config.http_proxy.as_ref(),
+        "https" => config.https_proxy.as_ref(),
+        _ => None,
+    }?;
+
+    let host = url.host_str()?;
+    if is_no_proxy_host(&config.no_proxy, host) {
+        return None;
+    }
+
+    Some(proxy.clone())
+}
+
+/// Whether the `host` matches one of the `no_proxy` entries
+fn is_no_proxy_host(no_proxy: &[String], host: &str) -> bool {
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+
+    no_proxy.iter().any(|entry| {
+        if entry == "*" {
+            return true;
+        }
+
+        // Addresses only match exactly, [IpAddr] normalizes the IPv6 forms
+        if let (Ok(address), Ok(entry)) = (host.parse::<IpAddr>(), entry.parse::<IpAddr>()) {
+            return address == entry;
+        }
+
+        let domain = entry.trim_start_matches('.');
+        host == domain || host.ends_with(&format!(".{domain}"))
+    })
+}
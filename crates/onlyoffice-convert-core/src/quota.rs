@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+#[cfg(feature = "dynamodb")]
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+#[cfg(feature = "dynamodb")]
+use tokio::sync::OnceCell;
+
+#[cfg(not(feature = "dynamodb"))]
+use crate::config_check::feature_disabled_error;
+use crate::error::ConvertError;
+#[cfg(feature = "dynamodb")]
+use crate::{aws::aws_config, config::config_var, config_check::configuration_error};
+
+/// Environment variable for the DynamoDB table tenant usage is counted within, required
+/// when any tenant has a quota. The table must have a string `quota_key` partition
+/// key, with `expires_at` as its time to live attribute
+pub const QUOTA_TABLE_ENV: &str = "QUOTA_TABLE";
+
+/// Client for the quota table, cached across warm invocations
+#[cfg(feature = "dynamodb")]
+static DYNAMODB_CLIENT: OnceCell<aws_sdk_dynamodb::Client> = OnceCell::const_new();
+
+/// Period usage is counted over, usage is reset at the start of each period (UTC)
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaPeriod {
+    Hour,
+    #[default]
+    Day,
+    Month,
+}
+
+#[cfg_attr(not(feature = "dynamodb"), allow(dead_code))]
+impl QuotaPeriod {
+    /// Identifier of the period containing `now`
+    fn period_id(self, now: DateTime<Utc>) -> String {
+        let format = match self {
+            QuotaPeriod::Hour => "%Y-%m-%dT%H",
+            QuotaPeriod::Day => "%Y-%m-%d",
+            QuotaPeriod::Month => "%Y-%m",
+        };
+
+        now.format(format).to_string()
+    }
+
+    /// Duration the usage of a period is kept for, long enough to outlast the period
+    fn retention(self) -> Duration {
+        match self {
+            QuotaPeriod::Hour => Duration::from_secs(2 * 60 * 60),
+            QuotaPeriod::Day => Duration::from_secs(2 * 24 * 60 * 60),
+            QuotaPeriod::Month => Duration::from_secs(62 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Usage allowed for a tenant within each period
+#[derive(Debug, Default, Deserialize)]
+pub struct TenantQuota {
+    /// Period the usage is counted over, defaults to daily
+    #[serde(default)]
+    pub period: QuotaPeriod,
+    /// Maximum number of conversions started within the period
+    #[serde(default)]
+    pub max_conversions: Option<u64>,
+    /// Maximum total size of the source files converted within the period in bytes,
+    /// conversions are rejected once the total has been reached
+    #[serde(default)]
+    pub max_source_bytes: Option<u64>,
+}
+
+/// Usage counter of a tenant for the current period
+#[cfg_attr(not(feature = "dynamodb"), allow(dead_code))]
+pub struct QuotaUsage<'a> {
+    table: String,
+    quota_key: String,
+    expires_at: u64,
+    quota: &'a TenantQuota,
+}
+
+/// Count a conversion against the quota of the `tenant_id`, fails with `QUOTA_EXCEEDED`
+/// when the tenant has reached its quota for the current period. Failures to update the
+/// table are logged and the conversion is allowed
+#[cfg(feature = "dynamodb")]
+pub async fn acquire_quota<'a>(
+    tenant_id: &str,
+    quota: &'a TenantQuota,
+) -> Result<Option<QuotaUsage<'a>>, ConvertError> {
+    let table = match config_var(QUOTA_TABLE_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        _ => {
+            tracing::error!(%tenant_id, "tenant has a quota but the quota table is not configured");
+            return Err(configuration_error("quota table is not configured"));
+        }
+    };
+
+    let now = Utc::now();
+    let usage = QuotaUsage {
+        table,
+        quota_key: format!("{tenant_id}#{}", quota.period.period_id(now)),
+        expires_at: now.timestamp().max(0) as u64 + quota.period.retention().as_secs(),
+        quota,
+    };
+
+    let mut conditions = Vec::new();
+    let mut request = dynamodb_client()
+        .await
+        .update_item()
+        .table_name(&usage.table)
+        .key("quota_key", AttributeValue::S(usage.quota_key.clone()))
+        .update_expression(
+            "ADD conversions :one SET expires_at = if_not_exists(expires_at, :expires_at)",
+        )
+        .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+        .expression_attribute_values(
+            ":expires_at",
+            AttributeValue::N(usage.expires_at.to_string()),
+        );
+
+    if let Some(max_conversions) = quota.max_conversions {
+        conditions.push("(attribute_not_exists(conversions) OR conversions < :max_conversions)");
+        request = request.expression_attribute_values(
+            ":max_conversions",
+            AttributeValue::N(max_conversions.to_string()),
+        );
+    }
+
+    if let Some(max_source_bytes) = quota.max_source_bytes {
+        conditions.push("(attribute_not_exists(source_bytes) OR source_bytes < :max_source_bytes)");
+        request = request.expression_attribute_values(
+            ":max_source_bytes",
+            AttributeValue::N(max_source_bytes.to_string()),
+        );
+    }
+
+    if !conditions.is_empty() {
+        request = request.condition_expression(conditions.join(" AND "));
+    }
+
+    match request.send().await {
+        Ok(_) => Ok(Some(usage)),
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|value| value.is_conditional_check_failed_exception()) =>
+        {
+            tracing::warn!(%tenant_id, quota_key = %usage.quota_key, "tenant quota exceeded");
+
+            Err(ConvertError {
+                reason: Some("QUOTA_EXCEEDED"),
+                x2t_code: None,
+                message: "tenant quota exceeded for the current period".to_string(),
+            })
+        }
+        Err(err) => {
+            tracing::error!(?err, %tenant_id, "failed to update tenant quota");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(not(feature = "dynamodb"))]
+pub async fn acquire_quota<'a>(
+    _tenant_id: &str,
+    _quota: &'a TenantQuota,
+) -> Result<Option<QuotaUsage<'a>>, ConvertError> {
+    Err(feature_disabled_error("quota", "dynamodb"))
+}
+
+#[cfg(feature = "dynamodb")]
+impl QuotaUsage<'_> {
+    /// Count the size of the converted source against the quota, failing to
+    /// record the size is logged but otherwise ignored
+    pub async fn record_source_bytes(&self, size: u64) {
+        // Only tracked for quotas that limit it
+        if self.quota.max_source_bytes.is_none() {
+            return;
+        }
+
+        let result = dynamodb_client()
+            .await
+            .update_item()
+            .table_name(&self.table)
+            .key("quota_key", AttributeValue::S(self.quota_key.clone()))
+            .update_expression(
+                "ADD source_bytes :size SET expires_at = if_not_exists(expires_at, :expires_at)",
+            )
+            .expression_attribute_values(":size", AttributeValue::N(size.to_string()))
+            .expression_attribute_values(
+                ":expires_at",
+                AttributeValue::N(self.expires_at.to_string()),
+            )
+            .send()
+            .await;
+
+        if let Err(err) = result {
+            tracing::error!(?err, quota_key = %self.quota_key, "failed to record tenant source bytes");
+        }
+    }
+}
+
+#[cfg(not(feature = "dynamodb"))]
+impl QuotaUsage<'_> {
+    pub async fn record_source_bytes(&self, _size: u64) {}
+}
+
+#[cfg(feature = "dynamodb")]
+async fn dynamodb_client() -> &'static aws_sdk_dynamodb::Client {
+    DYNAMODB_CLIENT
+        .get_or_init(|| async { aws_sdk_dynamodb::Client::new(&aws_config().await) })
+        .await
+}
@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage, imageops::FilterType};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ConvertError,
+    validate::{parse_hex_color, validate_range},
+    x2t_config::ThumbnailSize,
+};
+
+/// DPI x2t renders thumbnails at when using the page size
+const PAGE_DPI: u32 = 96;
+
+const MAX_DPI: u32 = 600;
+
+/// Maximum width or height of a raster output in pixels
+const MAX_DIMENSION: u32 = 8192;
+
+/// Options for raster (thumbnail) outputs, trading preview fidelity against size
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct RasterOptions {
+    /// Resolution to render the page at, the page is rendered at its full size
+    /// scaled to the DPI and then limited by the maximum dimensions
+    #[serde(default)]
+    pub dpi: Option<u32>,
+    /// Maximum width of the image in pixels
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    /// Maximum height of the image in pixels
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// Color in the `#rrggbb` form to fill transparent areas with, the
+    /// transparency is kept when not provided
+    #[serde(default)]
+    pub background: Option<String>,
+}
+
+impl RasterOptions {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        if let Some(dpi) = self.dpi {
+            validate_range("raster.dpi", dpi, 1, MAX_DPI)?;
+        }
+
+        if let Some(max_width) = self.max_width {
+            validate_range("raster.max_width", max_width, 1, MAX_DIMENSION)?;
+        }
+
+        if let Some(max_height) = self.max_height {
+            validate_range("raster.max_height", max_height, 1, MAX_DIMENSION)?;
+        }
+
+        if let Some(background) = &self.background {
+            parse_hex_color("raster.background", background)?;
+        }
+
+        Ok(())
+    }
+
+    /// Size x2t should render the thumbnail at
+    pub fn thumbnail_size(&self) -> Option<ThumbnailSize> {
+        // The DPI is applied by scaling the page sized render
+        if self.dpi.is_some() {
+            return Some(ThumbnailSize::Page);
+        }
+
+        match (self.max_width, self.max_height) {
+            (None, None) => None,
+            (width, height) => Some(ThumbnailSize::Fit {
+                width: width.unwrap_or(MAX_DIMENSION),
+                height: height.unwrap_or(MAX_DIMENSION),
+            }),
+        }
+    }
+
+    /// Whether the x2t output needs processing to apply the options
+    pub fn requires_processing(&self) -> bool {
+        self.dpi.is_some() || self.background.is_some()
+    }
+
+    /// Apply the DPI, maximum dimensions and background to the PNG at `path` in place
+    pub async fn process(&self, path: &Path) -> Result<(), ConvertError> {
+        let background = match &self.background {
+            Some(background) => Some(parse_hex_color("raster.background", background)?),
+            None => None,
+        };
+
+        let options = self.clone();
+        let path = path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || -> image::ImageResult<()> {
+            let mut image = image::open(&path)?;
+
+            let (width, height) = match options.dpi {
+                Some(dpi) => (
+                    scale_dimension(image.width(), dpi),
+                    scale_dimension(image.height(), dpi),
+                ),
+                None => (image.width(), image.height()),
+            };
+
+            // Scaling and the maximum dimensions are applied in a single resize
+            // that keeps the aspect ratio
+            let width = width.min(options.max_width.unwrap_or(MAX_DIMENSION));
+            let height = height.min(options.max_height.unwrap_or(MAX_DIMENSION));
+            if (width, height) != (image.width(), image.height()) {
+                image = image.resize(width, height, FilterType::Triangle);
+            }
+
+            if let Some(background) = background {
+                image = flatten(&image, background);
+            }
+
+            image.save_with_format(&path, ImageFormat::Png)
+        })
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "raster processing task failed");
+            process_error()
+        })?
+        .map_err(|err| {
+            tracing::error!(?err, "failed to process raster output");
+            process_error()
+        })
+    }
+}
+
+/// Scale a dimension rendered at [PAGE_DPI] to the `dpi`
+fn scale_dimension(value: u32, dpi: u32) -> u32 {
+    let scaled = u64::from(value) * u64::from(dpi) / u64::from(PAGE_DPI);
+    u32::try_from(scaled).unwrap_or(u32::MAX).max(1)
+}
+
+/// Composite the image over a solid `background` color
+fn flatten(image: &DynamicImage, background: [u8; 3]) -> DynamicImage {
+    let image = image.to_rgba8();
+
+    let output = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [red, green, blue, alpha] = image.get_pixel(x, y).0;
+        let blend = |value: u8, background: u8| {
+            let alpha = u16::from(alpha);
+            ((u16::from(value) * alpha + u16::from(background) * (255 - alpha)) / 255) as u8
+        };
+
+        Rgb([
+            blend(red, background[0]),
+            blend(green, background[1]),
+            blend(blue, background[2]),
+        ])
+    });
+
+    DynamicImage::ImageRgb8(output)
+}
+
+fn process_error() -> ConvertError {
+    ConvertError {
+        reason: Some("PROCESS_RASTER_OUTPUT"),
+        x2t_code: None,
+        message: "failed to process raster output".to_string(),
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    num::NonZeroU64,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+
+use aws_smithy_types::body::{Error as BodyError, SdkBody};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream::BoxStream};
+use http_body::{Body, Frame, SizeHint};
+use tokio::time::Sleep;
+
+use crate::config::app_config;
+
+/// Limiter shared by the S3 downloads of all conversions
+static DOWNLOAD_LIMITER: Mutex<Option<Arc<RateLimiter>>> = Mutex::new(None);
+
+/// Limiter shared by the S3 uploads of all conversions
+static UPLOAD_LIMITER: Mutex<Option<Arc<RateLimiter>>> = Mutex::new(None);
+
+/// Token bucket limiting the rate bytes are transferred at, transfers may burst
+/// up to one second of the rate after being idle
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    /// Bytes that can be transferred without waiting, negative when transfers
+    /// have reserved more than is available
+    available: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_second: u64) -> RateLimiter {
+        RateLimiter {
+            bytes_per_second,
+            state: Mutex::new(BucketState {
+                available: bytes_per_second as f64,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Reserve `bytes` from the bucket, returning how long to wait before they are
+    /// transferred. Reservations are taken in order so waiting transfers are not starved
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        let rate = self.bytes_per_second as f64;
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+
+        let now = Instant::now();
+        let refilled = now.duration_since(state.updated).as_secs_f64() * rate;
+        state.available = (state.available + refilled).min(rate);
+        state.updated = now;
+        state.available -= bytes as f64;
+
+        if state.available >= 0.0 {
+            return Duration::ZERO;
+        }
+
+        Duration::from_secs_f64(-state.available / rate)
+    }
+}
+
+/// Limiter for S3 downloads when a download rate limit is configured
+pub fn download_limiter() -> Option<Arc<RateLimiter>> {
+    shared_limiter(&DOWNLOAD_LIMITER, app_config().s3_download_rate_limit)
+}
+
+/// Limiter for S3 uploads when an upload rate limit is configured
+pub fn upload_limiter() -> Option<Arc<RateLimiter>> {
+    shared_limiter(&UPLOAD_LIMITER, app_config().s3_upload_rate_limit)
+}
+
+/// Shared limiter of the `slot`, replaced when the configured `rate` has changed
+/// (i.e from the SSM configuration overrides)
+fn shared_limiter(
+    slot: &Mutex<Option<Arc<RateLimiter>>>,
+    rate: Option<NonZeroU64>,
+) -> Option<Arc<RateLimiter>> {
+    let rate = rate?.get();
+    let mut slot = slot.lock().unwrap_or_else(|err| err.into_inner());
+
+    match slot.as_ref() {
+        Some(limiter) if limiter.bytes_per_second == rate => Some(limiter.clone()),
+        _ => {
+            let limiter = Arc::new(RateLimiter::new(rate));
+            *slot = Some(limiter.clone());
+            Some(limiter)
+        }
+    }
+}
+
+/// Limit the rate chunks of the `stream` are produced at
+pub fn limit_stream<S, E>(
+    stream: S,
+    limiter: Arc<RateLimiter>,
+) -> BoxStream<'static, Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    stream
+        .then(move |chunk| {
+            let delay = match &chunk {
+                Ok(bytes) => limiter.reserve(bytes.len()),
+                Err(_) => Duration::ZERO,
+            };
+
+            async move {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+
+                chunk
+            }
+        })
+        .boxed()
+}
+
+/// Limit the rate the `body` is sent at
+pub fn limit_body(body: SdkBody, limiter: Arc<RateLimiter>) -> SdkBody {
+    SdkBody::from_body_1_x(LimitedBody {
+        inner: body,
+        limiter,
+        pending: None,
+    })
+}
+
+/// Body holding each frame until the limiter allows it to be sent, the size of
+/// the inner body is kept so the request has a known content length
+struct LimitedBody {
+    inner: SdkBody,
+    limiter: Arc<RateLimiter>,
+    /// Frame waiting for its delay to elapse
+    pending: Option<(Pin<Box<Sleep>>, Frame<Bytes>)>,
+}
+
+impl Body for LimitedBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BodyError>>> {
+        let this = self.get_mut();
+
+        if let Some((sleep, _)) = &mut this.pending {
+            ready!(sleep.as_mut().poll(cx));
+
+            if let Some((_, frame)) = this.pending.take() {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+        }
+
+        let frame = match ready!(Body::poll_frame(Pin::new(&mut this.inner), cx)) {
+            Some(Ok(frame)) => frame,
+            other => return Poll::Ready(other),
+        };
+
+        let delay = frame
+            .data_ref()
+            .map(|data| this.limiter.reserve(data.len()))
+            .unwrap_or_default();
+
+        if delay.is_zero() {
+            return Poll::Ready(Some(Ok(frame)));
+        }
+
+        let mut sleep = Box::pin(tokio::time::sleep(delay));
+        if sleep.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Some(Ok(frame)));
+        }
+
+        this.pending = Some((sleep, frame));
+        Poll::Pending
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && Body::is_end_stream(&self.inner)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let inner = Body::size_hint(&self.inner);
+
+        // The pending frame has been taken from the inner body
+        let Some(len) = self
+            .pending
+            .as_ref()
+            .and_then(|(_, frame)| frame.data_ref())
+            .map(|data| data.len() as u64)
+        else {
+            return inner;
+        };
+
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + len);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + len);
+        }
+
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RateLimiter;
+
+    #[test]
+    fn test_reserve_within_burst() {
+        let limiter = RateLimiter::new(1000);
+        assert_eq!(limiter.reserve(400), Duration::ZERO);
+        assert_eq!(limiter.reserve(600), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_reserve_waits() {
+        let limiter = RateLimiter::new(1000);
+        assert_eq!(limiter.reserve(1000), Duration::ZERO);
+
+        // Refilled by at most a few bytes while the test runs
+        let wait = limiter.reserve(500);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+
+        // Later reservations wait behind the earlier ones
+        let wait = limiter.reserve(500);
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,252 @@
+use futures::{StreamExt, future::try_join_all};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::config_var,
+    flatten::AnnotationMode,
+    format::{InputFormat, OutputFormat},
+    presentation::PresentationOptions,
+    raster::RasterOptions,
+    spreadsheet::SpreadsheetPrintOptions,
+    stamp::StampOptions,
+    storage::{GetOptions, PutBody, PutOptions, Storage, StorageError, WriteOptions},
+    workbook::CsvOptions,
+};
+
+/// Environment variable for the bucket conversion results are cached within,
+/// the result cache is only used when this is set
+const RESULT_CACHE_BUCKET_ENV: &str = "RESULT_CACHE_BUCKET";
+
+/// Environment variable for the key prefix cached results are stored under
+const RESULT_CACHE_PREFIX_ENV: &str = "RESULT_CACHE_PREFIX";
+
+const DEFAULT_RESULT_CACHE_PREFIX: &str = "result-cache/";
+
+/// Version of the cached result layout, included in the cache key so
+/// changes to the layout don't reuse older entries
+const RESULT_CACHE_VERSION: u32 = 1;
+
+/// Name of the index object, written last so its presence indicates
+/// every cached object was stored
+const INDEX_FILE_NAME: &str = "index.json";
+
+/// Maximum size of an index object that will be read
+const MAX_INDEX_SIZE: usize = 64 * 1024;
+
+/// Parameters of a conversion that affect its outputs
+#[derive(Serialize)]
+pub struct ResultCacheParams<'a> {
+    /// Entity tag of the source object
+    pub source_etag: &'a str,
+    /// Format the source is passed to x2t as when provided by the request
+    pub source_format: Option<InputFormat>,
+    pub formats: Vec<OutputFormat>,
+    pub compression: Option<&'a str>,
+    pub embed_fonts: Option<bool>,
+    pub font_profile: Option<&'a str>,
+    pub x2t_version: Option<String>,
+    /// Names of the registered pipeline processors
+    pub pipeline: Option<Vec<&'static str>>,
+    pub archive: bool,
+    pub linearize: bool,
+    pub bookmarks: bool,
+    pub tagged: bool,
+    pub flatten_forms: bool,
+    pub annotations: Option<AnnotationMode>,
+    pub raster: Option<&'a RasterOptions>,
+    pub presentation: Option<&'a PresentationOptions>,
+    pub spreadsheet: Option<&'a SpreadsheetPrintOptions>,
+    pub csv: Option<&'a CsvOptions>,
+    pub template_data: Option<&'a Map<String, Value>>,
+    pub stamp: Option<&'a StampOptions>,
+    /// Name of the attached source file (When attaching the source)
+    pub attach_source: Option<String>,
+}
+
+/// Index stored alongside the cached outputs
+#[derive(Serialize, Deserialize)]
+struct ResultCacheIndex {
+    /// Number of cached output objects
+    objects: usize,
+    /// Fonts that were substituted when the outputs were created
+    substituted_fonts: Vec<String>,
+}
+
+/// Location of the cached results for a single set of conversion parameters
+pub struct ResultCacheEntry {
+    bucket: String,
+    /// Prefix the index and outputs are stored under
+    prefix: String,
+}
+
+/// Whether the result cache is enabled
+pub fn is_result_cache_enabled() -> bool {
+    config_var(RESULT_CACHE_BUCKET_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+impl ResultCacheEntry {
+    /// Get the cache entry for the conversion `params`, [None] when the
+    /// result cache is not enabled
+    pub fn new(params: &ResultCacheParams<'_>) -> Option<ResultCacheEntry> {
+        let bucket = config_var(RESULT_CACHE_BUCKET_ENV)
+            .ok()
+            .filter(|value| !value.is_empty())?;
+
+        let prefix = config_var(RESULT_CACHE_PREFIX_ENV)
+            .unwrap_or_else(|_| DEFAULT_RESULT_CACHE_PREFIX.to_string());
+
+        let params = serde_json::to_vec(params).ok()?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(RESULT_CACHE_VERSION.to_le_bytes());
+        hasher.update(&params);
+        let hash = format!("{:x}", hasher.finalize());
+
+        Some(ResultCacheEntry {
+            bucket,
+            prefix: format!("{prefix}{hash}/"),
+        })
+    }
+
+    fn object_key(&self, index: usize) -> String {
+        format!("{}{index}", self.prefix)
+    }
+
+    fn index_key(&self) -> String {
+        format!("{}{INDEX_FILE_NAME}", self.prefix)
+    }
+
+    /// Copy the cached outputs to the `dest_keys` using the ownership `options` of
+    /// the destination, returns the substituted fonts of the cached conversion on a
+    /// hit or [None] when the outputs must be converted
+    pub async fn restore(
+        &self,
+        storage: &dyn Storage,
+        dest_bucket: &str,
+        dest_keys: &[String],
+        options: WriteOptions<'_>,
+    ) -> Option<Vec<String>> {
+        let index = match self.read_index(storage).await {
+            Ok(value) => value?,
+            Err(err) => {
+                tracing::warn!(?err, "failed to read result cache index");
+                return None;
+            }
+        };
+
+        if index.objects != dest_keys.len() {
+            tracing::warn!(
+                cached = index.objects,
+                expected = dest_keys.len(),
+                "result cache entry does not match the requested outputs"
+            );
+            return None;
+        }
+
+        let copied = try_join_all(dest_keys.iter().enumerate().map(|(index, dest_key)| {
+            let source_key = self.object_key(index);
+            async move {
+                storage
+                    .copy_object(&self.bucket, &source_key, dest_bucket, dest_key, options)
+                    .await
+            }
+        }))
+        .await;
+
+        if let Err(err) = copied {
+            tracing::warn!(?err, "failed to copy cached result");
+            return None;
+        }
+
+        tracing::debug!(prefix = %self.prefix, "restored cached result");
+        Some(index.substituted_fonts)
+    }
+
+    /// Read the index of the cache entry, [None] when the entry doesn't exist
+    async fn read_index(
+        &self,
+        storage: &dyn Storage,
+    ) -> Result<Option<ResultCacheIndex>, StorageError> {
+        let object = match storage
+            .get_object(&self.bucket, &self.index_key(), GetOptions::default())
+            .await
+        {
+            Ok(value) => value,
+            Err(StorageError::NoSuchKey) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut body = object.body;
+        let mut bytes = Vec::new();
+
+        while let Some(chunk) = body.next().await {
+            bytes.extend_from_slice(&chunk?);
+
+            if bytes.len() > MAX_INDEX_SIZE {
+                return Err(StorageError::Request(
+                    "result cache index is too large".to_string(),
+                ));
+            }
+        }
+
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|err| StorageError::Request(err.to_string()))
+    }
+
+    /// Copy the uploaded outputs at the `dest_keys` into the cache using the copy
+    /// `options`, failing to store the result is logged but otherwise ignored
+    pub async fn store(
+        &self,
+        storage: &dyn Storage,
+        dest_bucket: &str,
+        dest_keys: &[String],
+        substituted_fonts: &[String],
+        options: WriteOptions<'_>,
+    ) {
+        let copied = try_join_all(dest_keys.iter().enumerate().map(|(index, dest_key)| {
+            let cache_key = self.object_key(index);
+            async move {
+                storage
+                    .copy_object(dest_bucket, dest_key, &self.bucket, &cache_key, options)
+                    .await
+            }
+        }))
+        .await;
+
+        if let Err(err) = copied {
+            tracing::error!(?err, "failed to copy outputs into the result cache");
+            return;
+        }
+
+        let index = ResultCacheIndex {
+            objects: dest_keys.len(),
+            substituted_fonts: substituted_fonts.to_vec(),
+        };
+
+        let index = match serde_json::to_vec(&index) {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::error!(?err, "failed to serialize result cache index");
+                return;
+            }
+        };
+
+        if let Err(err) = storage
+            .put_object(
+                &self.bucket,
+                &self.index_key(),
+                PutBody::Bytes(index),
+                PutOptions {
+                    content_type: Some("application/json"),
+                    ..Default::default()
+                },
+            )
+            .await
+        {
+            tracing::error!(?err, "failed to store result cache index");
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::app_config, error::ConvertError, validate::validate_range};
+
+/// Maximum attempts a request may ask for
+const MAX_REQUEST_ATTEMPTS: u32 = 10;
+
+/// Maximum delay between retries a request may ask for in milliseconds
+const MAX_REQUEST_DELAY_MS: u32 = 60_000;
+
+tokio::task_local! {
+    /// Policy of the request being converted while running [with_retry_policy]
+    static REQUEST_RETRY_POLICY: RetryPolicy;
+}
+
+/// Class of a failed operation used to decide whether the operation is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryClass {
+    /// Request was rejected due to the request rate (S3 `SlowDown` or HTTP 429)
+    Throttled,
+    /// Request failed from a network error, timeout or server error (5xx)
+    Transient,
+    /// x2t crashed or exited successfully with a truncated output
+    X2tCrash,
+}
+
+impl RetryClass {
+    pub fn from_name(name: &str) -> Option<RetryClass> {
+        match name {
+            "throttled" => Some(RetryClass::Throttled),
+            "transient" => Some(RetryClass::Transient),
+            "x2t_crash" => Some(RetryClass::X2tCrash),
+            _ => None,
+        }
+    }
+}
+
+/// Retry settings of a request, replacing the configured settings for the
+/// operations of the conversion
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct RetryOptions {
+    /// Maximum number of attempts including the first attempt, 1 disables retrying
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    /// Delay in milliseconds before the first retry, doubled for each retry
+    #[serde(default)]
+    pub base_delay_ms: Option<u32>,
+    /// Maximum delay in milliseconds between retries
+    #[serde(default)]
+    pub max_delay_ms: Option<u32>,
+    /// Error classes that are retried
+    #[serde(default)]
+    pub retry_on: Option<Vec<RetryClass>>,
+}
+
+impl RetryOptions {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        if let Some(max_attempts) = self.max_attempts {
+            validate_range("retry.max_attempts", max_attempts, 1, MAX_REQUEST_ATTEMPTS)?;
+        }
+
+        if let Some(base_delay_ms) = self.base_delay_ms {
+            validate_range(
+                "retry.base_delay_ms",
+                base_delay_ms,
+                0,
+                MAX_REQUEST_DELAY_MS,
+            )?;
+        }
+
+        if let Some(max_delay_ms) = self.max_delay_ms {
+            validate_range("retry.max_delay_ms", max_delay_ms, 0, MAX_REQUEST_DELAY_MS)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How failed S3 requests, webhooks and x2t runs are retried. Retries wait for a
+/// jittered exponential backoff so parallel conversions spread out their attempts
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl RetryPolicy {
+    /// Policy from the environment configuration
+    pub fn from_env() -> RetryPolicy {
+        let config = app_config();
+
+        RetryPolicy {
+            max_attempts: config.retry_max_attempts,
+            base_delay: config.retry_base_delay,
+            max_delay: config.retry_max_delay,
+            retry_on: config.retry_on.clone(),
+        }
+    }
+
+    /// Policy with the settings provided by the request `options` replacing the
+    /// settings of this policy
+    pub fn with_options(self, options: &RetryOptions) -> RetryPolicy {
+        let delay = |value: Option<u32>| value.map(|value| Duration::from_millis(value.into()));
+
+        RetryPolicy {
+            max_attempts: options.max_attempts.unwrap_or(self.max_attempts).max(1),
+            base_delay: delay(options.base_delay_ms).unwrap_or(self.base_delay),
+            max_delay: delay(options.max_delay_ms).unwrap_or(self.max_delay),
+            retry_on: options.retry_on.clone().unwrap_or(self.retry_on),
+        }
+    }
+
+    /// Policy of the current conversion, or the configured policy when not
+    /// running within [with_retry_policy]
+    pub fn current() -> RetryPolicy {
+        REQUEST_RETRY_POLICY
+            .try_with(RetryPolicy::clone)
+            .unwrap_or_else(|_| RetryPolicy::from_env())
+    }
+
+    /// Whether a failure of the `class` on the `attempt` (starting at 1) is retried
+    pub fn should_retry(&self, class: RetryClass, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.retry_on.contains(&class)
+    }
+
+    /// Delay before retrying after the `attempt` (starting at 1), full jitter
+    /// chooses the delay at random up to the exponential backoff
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(self.max_delay);
+
+        backoff.mul_f64(fastrand::f64())
+    }
+
+    /// Run the `operation`, retrying while `classify` reports a retryable class for
+    /// its output. The output of the last attempt is returned once retries are exhausted
+    pub async fn run<T, F, Fut>(
+        &self,
+        name: &str,
+        mut classify: impl FnMut(&T) -> Option<RetryClass>,
+        mut operation: F,
+    ) -> T
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            let output = operation().await;
+
+            let Some(class) = classify(&output) else {
+                return output;
+            };
+
+            if !self.should_retry(class, attempt) {
+                if attempt > 1 {
+                    tracing::error!(operation = %name, ?class, attempt, "retries exhausted");
+                }
+
+                return output;
+            }
+
+            let delay = self.delay(attempt);
+            tracing::warn!(operation = %name, ?class, attempt, ?delay, "operation failed, retrying");
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Run the `future` using the retry `policy` of a request for the operations within it
+pub async fn with_retry_policy<F: Future>(policy: RetryPolicy, future: F) -> F::Output {
+    REQUEST_RETRY_POLICY.scope(policy, future).await
+}
@@ -0,0 +1,738 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "kms")]
+use aws_sdk_kms::{
+    primitives::Blob,
+    types::{KeySpec, MessageType, SigningAlgorithmSpec},
+};
+use chrono::{DateTime, Utc};
+use cms::{
+    cert::{CertificateChoices, IssuerAndSerialNumber},
+    content_info::{CmsVersion, ContentInfo},
+    signed_data::{
+        CertificateSet, EncapsulatedContentInfo, SignedAttributes, SignedData, SignerIdentifier,
+        SignerInfo, SignerInfos, UnsignedAttributes,
+    },
+};
+use der::{
+    Any, Encode, EncodeValue, Sequence, Tagged,
+    asn1::{ObjectIdentifier, OctetString, SetOfVec},
+};
+use lopdf::{Dictionary, IncrementalDocument, Object, StringFormat, dictionary};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use x509_cert::{Certificate, attr::Attribute, spki::AlgorithmIdentifierOwned};
+
+#[cfg(feature = "kms")]
+use crate::aws::aws_config;
+#[cfg(not(feature = "kms"))]
+use crate::config_check::feature_disabled_error;
+use crate::{
+    config::config_var,
+    error::ConvertError,
+    timestamp::{is_timestamping_enabled, request_timestamp},
+};
+
+/// Environment variable for the ID or ARN of the asymmetric KMS key outputs are
+/// signed with, signing is only available when this is set
+pub const SIGNING_KEY_ID_ENV: &str = "SIGNING_KMS_KEY_ID";
+
+/// Environment variable for the path to the PEM certificate chain of the signing
+/// key (i.e issued by ACM Private CA), starting with the certificate of the key
+const SIGNING_CERTIFICATE_PATH_ENV: &str = "SIGNING_CERTIFICATE_PATH";
+
+/// Space reserved within the PDF for the encoded signature in bytes, leaving room
+/// for the certificate chains of the signer and time stamp authority
+const SIGNATURE_SIZE: usize = 32 * 1024;
+
+/// Value written for each byte range offset before the offsets are known, wide
+/// enough that the actual offsets always fit in its place
+const BYTE_RANGE_PLACEHOLDER: i64 = 9_999_999_999;
+
+/// Maximum length of the signature reason, location and contact info
+const MAX_FIELD_LENGTH: usize = 256;
+
+const ID_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.1");
+const ID_SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+const ID_CONTENT_TYPE: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.3");
+const ID_MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+const ID_SIGNING_CERTIFICATE_V2: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.2.47");
+const ID_SIGNATURE_TIME_STAMP_TOKEN: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.2.14");
+const ID_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+const ID_RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const ID_ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+
+/// Signer loaded on first use
+static SIGNER: OnceCell<Signer> = OnceCell::const_new();
+
+/// Client for the signing key, cached across warm invocations
+#[cfg(feature = "kms")]
+static KMS_CLIENT: OnceCell<aws_sdk_kms::Client> = OnceCell::const_new();
+
+/// Whether signing outputs is available
+pub fn is_signing_enabled() -> bool {
+    config_var(SIGNING_KEY_ID_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+/// Details recorded within the signature of PDF outputs
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SignOptions {
+    /// Reason for signing (i.e "Generated by the reporting service")
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Location the document was signed at
+    #[serde(default)]
+    pub location: Option<String>,
+    /// Contact details of the signer
+    #[serde(default)]
+    pub contact_info: Option<String>,
+    /// Embed a trusted timestamp from the configured time stamp authority within
+    /// the signature, for long-term validation of the signature
+    #[serde(default)]
+    pub timestamp: bool,
+}
+
+impl SignOptions {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        for (field, value) in [
+            ("sign.reason", &self.reason),
+            ("sign.location", &self.location),
+            ("sign.contact_info", &self.contact_info),
+        ] {
+            if let Some(value) = value
+                && (value.len() > MAX_FIELD_LENGTH || value.chars().any(char::is_control))
+            {
+                return Err(ConvertError {
+                    reason: Some("INVALID_REQUEST"),
+                    x2t_code: None,
+                    message: format!("{field}: invalid value"),
+                });
+            }
+        }
+
+        if self.timestamp && !is_timestamping_enabled() {
+            return Err(ConvertError {
+                reason: Some("SIGNING_UNAVAILABLE"),
+                x2t_code: None,
+                message: "sign.timestamp: timestamping is not configured".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Algorithm of the KMS signing key
+#[derive(Clone, Copy)]
+#[cfg_attr(not(feature = "kms"), allow(dead_code))]
+enum KeyAlgorithm {
+    Rsa,
+    /// ECDSA on the P-256 curve
+    Ecdsa,
+}
+
+impl KeyAlgorithm {
+    #[cfg(feature = "kms")]
+    fn from_key_spec(key_spec: &KeySpec) -> Option<KeyAlgorithm> {
+        match key_spec {
+            KeySpec::Rsa2048 | KeySpec::Rsa3072 | KeySpec::Rsa4096 => Some(KeyAlgorithm::Rsa),
+            KeySpec::EccNistP256 => Some(KeyAlgorithm::Ecdsa),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "kms")]
+    fn signing_algorithm(self) -> SigningAlgorithmSpec {
+        match self {
+            KeyAlgorithm::Rsa => SigningAlgorithmSpec::RsassaPkcs1V15Sha256,
+            KeyAlgorithm::Ecdsa => SigningAlgorithmSpec::EcdsaSha256,
+        }
+    }
+
+    /// Signature algorithm identifier of the CMS signer info
+    fn identifier(self) -> AlgorithmIdentifierOwned {
+        match self {
+            KeyAlgorithm::Rsa => AlgorithmIdentifierOwned {
+                oid: ID_RSA_ENCRYPTION,
+                parameters: Some(Any::null()),
+            },
+            KeyAlgorithm::Ecdsa => AlgorithmIdentifierOwned {
+                oid: ID_ECDSA_WITH_SHA256,
+                parameters: None,
+            },
+        }
+    }
+}
+
+/// KMS key and certificate chain outputs are signed with
+struct Signer {
+    key_id: String,
+    algorithm: KeyAlgorithm,
+    /// Certificate chain starting with the certificate of the key
+    certificates: Vec<Certificate>,
+}
+
+/// `ESSCertIDv2` from RFC 5035 using the default SHA-256 hash algorithm
+#[derive(Sequence)]
+struct EssCertIdV2 {
+    cert_hash: OctetString,
+}
+
+/// `SigningCertificateV2` from RFC 5035, required by PAdES to bind the signature
+/// to the signing certificate
+#[derive(Sequence)]
+struct SigningCertificateV2 {
+    certs: Vec<EssCertIdV2>,
+}
+
+/// Load the signing key and certificate chain, the certificate must match the
+/// public key of the KMS key
+async fn load_signer() -> Result<Signer, ConvertError> {
+    let key_id = config_var(SIGNING_KEY_ID_ENV).map_err(|_| signing_unavailable())?;
+    let certificate_path =
+        config_var(SIGNING_CERTIFICATE_PATH_ENV).map_err(|_| signing_unavailable())?;
+
+    let pem = tokio::fs::read(&certificate_path).await.map_err(|err| {
+        tracing::error!(?err, "failed to read signing certificate");
+        signer_error()
+    })?;
+
+    let certificates = Certificate::load_pem_chain(&pem).map_err(|err| {
+        tracing::error!(?err, "failed to parse signing certificate");
+        signer_error()
+    })?;
+
+    let certificate = certificates.first().ok_or_else(|| {
+        tracing::error!("signing certificate chain is empty");
+        signer_error()
+    })?;
+
+    let (algorithm, public_key) = get_public_key(&key_id).await?;
+
+    let certificate_key = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|err| {
+            tracing::error!(?err, "failed to encode certificate public key");
+            signer_error()
+        })?;
+
+    if public_key != certificate_key {
+        tracing::error!("signing certificate does not match the kms key");
+        return Err(signer_error());
+    }
+
+    Ok(Signer {
+        key_id,
+        algorithm,
+        certificates,
+    })
+}
+
+impl Signer {
+    /// Create the detached CMS signature of the content with the SHA-256 `digest`,
+    /// including a time stamp token over the signature when `timestamp` is set
+    async fn sign(&self, digest: &[u8], timestamp: bool) -> Result<Vec<u8>, ConvertError> {
+        let signed_attrs = signed_attributes(&self.certificates[0], digest)?;
+
+        // The signature covers the DER encoding of the signed attributes
+        let signed_attrs_digest = Sha256::digest(signed_attrs.to_der().map_err(encode_error)?);
+
+        let signature = sign_digest(&self.key_id, self.algorithm, &signed_attrs_digest).await?;
+
+        let unsigned_attrs = match timestamp {
+            true => {
+                let token = request_timestamp(&signature).await?;
+                let attribute = attribute(ID_SIGNATURE_TIME_STAMP_TOKEN, &token)?;
+                Some(SetOfVec::try_from(vec![attribute]).map_err(encode_error)?)
+            }
+            false => None,
+        };
+
+        encode_signed_data(
+            &self.certificates,
+            self.algorithm,
+            signed_attrs,
+            &signature,
+            unsigned_attrs,
+        )
+    }
+}
+
+#[cfg(feature = "kms")]
+async fn kms_client() -> &'static aws_sdk_kms::Client {
+    KMS_CLIENT
+        .get_or_init(|| async { aws_sdk_kms::Client::new(&aws_config().await) })
+        .await
+}
+
+/// Get the algorithm and DER encoded public key of the KMS key with the `key_id`
+#[cfg(feature = "kms")]
+async fn get_public_key(key_id: &str) -> Result<(KeyAlgorithm, Vec<u8>), ConvertError> {
+    let public_key = kms_client()
+        .await
+        .get_public_key()
+        .key_id(key_id)
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to get signing public key");
+            signer_error()
+        })?;
+
+    let algorithm = public_key
+        .key_spec()
+        .and_then(KeyAlgorithm::from_key_spec)
+        .ok_or_else(|| {
+            tracing::error!(key_spec = ?public_key.key_spec(), "unsupported signing key spec");
+            signer_error()
+        })?;
+
+    let public_key = public_key
+        .public_key()
+        .map(|value| value.as_ref().to_vec())
+        .unwrap_or_default();
+
+    Ok((algorithm, public_key))
+}
+
+#[cfg(not(feature = "kms"))]
+async fn get_public_key(_key_id: &str) -> Result<(KeyAlgorithm, Vec<u8>), ConvertError> {
+    Err(feature_disabled_error(SIGNING_KEY_ID_ENV, "kms"))
+}
+
+/// Sign the SHA-256 `digest` using the KMS key with the `key_id`
+#[cfg(feature = "kms")]
+async fn sign_digest(
+    key_id: &str,
+    algorithm: KeyAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<u8>, ConvertError> {
+    let response = kms_client()
+        .await
+        .sign()
+        .key_id(key_id)
+        .message(Blob::new(digest))
+        .message_type(MessageType::Digest)
+        .signing_algorithm(algorithm.signing_algorithm())
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to sign with kms key");
+            sign_error()
+        })?;
+
+    let signature = response.signature.ok_or_else(|| {
+        tracing::error!("kms sign response has no signature");
+        sign_error()
+    })?;
+
+    Ok(signature.into_inner())
+}
+
+#[cfg(not(feature = "kms"))]
+async fn sign_digest(
+    _key_id: &str,
+    _algorithm: KeyAlgorithm,
+    _digest: &[u8],
+) -> Result<Vec<u8>, ConvertError> {
+    Err(feature_disabled_error(SIGNING_KEY_ID_ENV, "kms"))
+}
+
+/// Signed attributes of a PAdES baseline signature of the content with the
+/// SHA-256 `digest`, the signing time is recorded by the PDF signature dictionary
+fn signed_attributes(
+    certificate: &Certificate,
+    digest: &[u8],
+) -> Result<SignedAttributes, ConvertError> {
+    let certificate_hash = Sha256::digest(certificate.to_der().map_err(encode_error)?);
+    let signing_certificate = SigningCertificateV2 {
+        certs: vec![EssCertIdV2 {
+            cert_hash: OctetString::new(certificate_hash.to_vec()).map_err(encode_error)?,
+        }],
+    };
+
+    SetOfVec::try_from(vec![
+        attribute(ID_CONTENT_TYPE, &ID_DATA)?,
+        attribute(
+            ID_MESSAGE_DIGEST,
+            &OctetString::new(digest).map_err(encode_error)?,
+        )?,
+        attribute(ID_SIGNING_CERTIFICATE_V2, &signing_certificate)?,
+    ])
+    .map_err(encode_error)
+}
+
+/// Encode the CMS `SignedData` of a detached signature by the first of the
+/// `certificates` over the `signed_attrs`
+fn encode_signed_data(
+    certificates: &[Certificate],
+    algorithm: KeyAlgorithm,
+    signed_attrs: SignedAttributes,
+    signature: &[u8],
+    unsigned_attrs: Option<UnsignedAttributes>,
+) -> Result<Vec<u8>, ConvertError> {
+    let certificate = &certificates[0];
+
+    let sha256 = AlgorithmIdentifierOwned {
+        oid: ID_SHA256,
+        parameters: None,
+    };
+
+    let signer_info = SignerInfo {
+        version: CmsVersion::V1,
+        sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+            issuer: certificate.tbs_certificate.issuer.clone(),
+            serial_number: certificate.tbs_certificate.serial_number.clone(),
+        }),
+        digest_alg: sha256.clone(),
+        signed_attrs: Some(signed_attrs),
+        signature_algorithm: algorithm.identifier(),
+        signature: OctetString::new(signature).map_err(encode_error)?,
+        unsigned_attrs,
+    };
+
+    let certificates = certificates
+        .iter()
+        .cloned()
+        .map(CertificateChoices::Certificate)
+        .collect::<Vec<_>>();
+
+    let signed_data = SignedData {
+        version: CmsVersion::V1,
+        digest_algorithms: SetOfVec::try_from(vec![sha256]).map_err(encode_error)?,
+        encap_content_info: EncapsulatedContentInfo {
+            econtent_type: ID_DATA,
+            econtent: None,
+        },
+        certificates: Some(CertificateSet(
+            SetOfVec::try_from(certificates).map_err(encode_error)?,
+        )),
+        crls: None,
+        signer_infos: SignerInfos(SetOfVec::try_from(vec![signer_info]).map_err(encode_error)?),
+    };
+
+    ContentInfo {
+        content_type: ID_SIGNED_DATA,
+        content: Any::encode_from(&signed_data).map_err(encode_error)?,
+    }
+    .to_der()
+    .map_err(encode_error)
+}
+
+/// PDF with a signature placeholder appended as an incremental update
+struct PreparedDocument {
+    bytes: Vec<u8>,
+    /// Offsets of the signature contents hex string (Including the delimiters)
+    contents_start: usize,
+    contents_end: usize,
+}
+
+/// Sign the PDF at `output_path` in place with a PAdES baseline signature using the
+/// configured KMS key. The signature is appended as an incremental update so the
+/// existing content (And its linearization) is unchanged. The signed file is
+/// written to `signed_path` before replacing the output
+pub async fn sign_pdf(
+    output_path: &Path,
+    signed_path: &Path,
+    options: &SignOptions,
+) -> Result<(), ConvertError> {
+    let signer = SIGNER.get_or_try_init(load_signer).await?;
+
+    let prepare_path = output_path.to_path_buf();
+    let prepare_options = options.clone();
+    let signing_time = Utc::now();
+
+    let mut prepared = tokio::task::spawn_blocking(move || {
+        prepare_document(&prepare_path, &prepare_options, signing_time)
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "prepare signature task failed");
+        sign_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to prepare pdf for signing");
+        sign_error()
+    })?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&prepared.bytes[..prepared.contents_start]);
+    hasher.update(&prepared.bytes[prepared.contents_end..]);
+    let digest = hasher.finalize();
+
+    let signature = signer.sign(&digest, options.timestamp).await?;
+
+    // The hex encoded signature is written within the delimiters of the placeholder
+    if signature.len() > SIGNATURE_SIZE {
+        tracing::error!(
+            size = signature.len(),
+            "signature exceeds the reserved space"
+        );
+        return Err(sign_error());
+    }
+
+    let contents_start = prepared.contents_start + 1;
+    let signature = hex::encode_upper(signature);
+    prepared.bytes[contents_start..contents_start + signature.len()]
+        .copy_from_slice(signature.as_bytes());
+
+    write_signed(output_path, signed_path, prepared.bytes).await
+}
+
+/// Append the signature field and a placeholder signature to the document
+fn prepare_document(
+    output_path: &Path,
+    options: &SignOptions,
+    signing_time: DateTime<Utc>,
+) -> lopdf::Result<PreparedDocument> {
+    let mut document = IncrementalDocument::load(output_path)?;
+    let previous_size = document.get_prev_documents_bytes().len();
+
+    let previous = document.get_prev_documents();
+    let catalog_id = previous.trailer.get(b"Root")?.as_reference()?;
+    let (_, page_id) = previous
+        .get_pages()
+        .into_iter()
+        .next()
+        .ok_or(lopdf::Error::PageNumberNotFound(1))?;
+    let version = previous.version.clone();
+
+    let new_document = &mut document.new_document;
+    new_document.version = version;
+
+    let mut signature = dictionary! {
+        "Type" => "Sig",
+        "Filter" => "Adobe.PPKLite",
+        "SubFilter" => "ETSI.CAdES.detached",
+        "ByteRange" => vec![
+            Object::Integer(0),
+            Object::Integer(BYTE_RANGE_PLACEHOLDER),
+            Object::Integer(BYTE_RANGE_PLACEHOLDER),
+            Object::Integer(BYTE_RANGE_PLACEHOLDER),
+        ],
+        "Contents" => Object::String(vec![0; SIGNATURE_SIZE], StringFormat::Hexadecimal),
+        "M" => Object::string_literal(signing_time.format("D:%Y%m%d%H%M%SZ").to_string()),
+    };
+
+    for (key, value) in [
+        ("Reason", &options.reason),
+        ("Location", &options.location),
+        ("ContactInfo", &options.contact_info),
+    ] {
+        if let Some(value) = value {
+            signature.set(key, lopdf::text_string(value));
+        }
+    }
+
+    let signature_id = new_document.add_object(signature);
+
+    // Invisible widget on the first page for the signature field
+    let field_id = new_document.add_object(dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "Widget",
+        "FT" => "Sig",
+        "T" => Object::string_literal(format!("Signature{}", signature_id.0)),
+        "V" => signature_id,
+        "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+        // Print and locked
+        "F" => 132,
+        "P" => page_id,
+    });
+
+    document.opt_clone_object_to_new_document(page_id)?;
+    push_to_array(&mut document, page_id, b"Annots", field_id.into())?;
+
+    document.opt_clone_object_to_new_document(catalog_id)?;
+    let form_id = match document
+        .new_document
+        .get_dictionary(catalog_id)?
+        .get(b"AcroForm")
+    {
+        Ok(Object::Reference(form_id)) => {
+            let form_id = *form_id;
+            document.opt_clone_object_to_new_document(form_id)?;
+            form_id
+        }
+        Ok(Object::Dictionary(form)) => {
+            let form = form.clone();
+            document.new_document.add_object(form)
+        }
+        _ => document.new_document.add_object(Dictionary::new()),
+    };
+
+    push_to_array(&mut document, form_id, b"Fields", field_id.into())?;
+
+    // Signatures exist and the document must only be appended to
+    document
+        .new_document
+        .get_dictionary_mut(form_id)?
+        .set("SigFlags", 3);
+    document
+        .new_document
+        .get_dictionary_mut(catalog_id)?
+        .set("AcroForm", form_id);
+
+    let mut bytes = Vec::new();
+    document.save_to(&mut bytes)?;
+
+    let (contents_start, contents_end) = find_contents(&bytes, previous_size)
+        .ok_or_else(|| lopdf::Error::Invalid("missing signature contents".to_string()))?;
+
+    write_byte_range(&mut bytes, previous_size, contents_start, contents_end)?;
+
+    Ok(PreparedDocument {
+        bytes,
+        contents_start,
+        contents_end,
+    })
+}
+
+/// Push a `value` onto the array at `key` of the dictionary object `id` within the
+/// update, the array is created when missing and cloned into the update when it
+/// is referenced
+fn push_to_array(
+    document: &mut IncrementalDocument,
+    id: lopdf::ObjectId,
+    key: &[u8],
+    value: Object,
+) -> lopdf::Result<()> {
+    let existing = document.new_document.get_dictionary(id)?.get(key).cloned();
+
+    match existing {
+        Ok(Object::Reference(array_id)) => {
+            document.opt_clone_object_to_new_document(array_id)?;
+            document
+                .new_document
+                .get_object_mut(array_id)?
+                .as_array_mut()?
+                .push(value);
+        }
+        Ok(Object::Array(mut array)) => {
+            array.push(value);
+            document
+                .new_document
+                .get_dictionary_mut(id)?
+                .set(key, array);
+        }
+        _ => {
+            document
+                .new_document
+                .get_dictionary_mut(id)?
+                .set(key, vec![value]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the offsets of the placeholder signature contents within the appended update
+fn find_contents(bytes: &[u8], update_start: usize) -> Option<(usize, usize)> {
+    let mut placeholder = Vec::with_capacity(SIGNATURE_SIZE * 2 + 2);
+    placeholder.push(b'<');
+    placeholder.resize(SIGNATURE_SIZE * 2 + 1, b'0');
+    placeholder.push(b'>');
+
+    let start = update_start
+        + bytes[update_start..]
+            .windows(placeholder.len())
+            .position(|window| window == placeholder.as_slice())?;
+
+    Some((start, start + placeholder.len()))
+}
+
+/// Replace the placeholder byte range with the ranges around the signature contents,
+/// the values are padded with spaces to keep the offsets of the document unchanged
+fn write_byte_range(
+    bytes: &mut [u8],
+    update_start: usize,
+    contents_start: usize,
+    contents_end: usize,
+) -> lopdf::Result<()> {
+    let placeholder =
+        format!("[0 {BYTE_RANGE_PLACEHOLDER} {BYTE_RANGE_PLACEHOLDER} {BYTE_RANGE_PLACEHOLDER}]");
+
+    let start = update_start
+        + bytes[update_start..]
+            .windows(placeholder.len())
+            .position(|window| window == placeholder.as_bytes())
+            .ok_or_else(|| lopdf::Error::Invalid("missing byte range".to_string()))?;
+
+    let byte_range = format!(
+        "[0 {contents_start} {contents_end} {}]",
+        bytes.len() - contents_end
+    );
+    let byte_range = format!("{byte_range:<width$}", width = placeholder.len());
+
+    bytes[start..start + placeholder.len()].copy_from_slice(byte_range.as_bytes());
+    Ok(())
+}
+
+async fn write_signed(
+    output_path: &Path,
+    signed_path: &Path,
+    bytes: Vec<u8>,
+) -> Result<(), ConvertError> {
+    let output_path: PathBuf = output_path.to_path_buf();
+    let signed_path: PathBuf = signed_path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        std::fs::write(&signed_path, bytes)?;
+        std::fs::File::open(&signed_path)?.sync_all()?;
+        std::fs::rename(&signed_path, &output_path)
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "write signed pdf task failed");
+        sign_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to write signed pdf");
+        sign_error()
+    })
+}
+
+/// Create a signed attribute with a single value
+fn attribute(
+    oid: ObjectIdentifier,
+    value: &(impl EncodeValue + Tagged),
+) -> Result<Attribute, ConvertError> {
+    Ok(Attribute {
+        oid,
+        values: SetOfVec::try_from(vec![Any::encode_from(value).map_err(encode_error)?])
+            .map_err(encode_error)?,
+    })
+}
+
+fn encode_error(err: der::Error) -> ConvertError {
+    tracing::error!(?err, "failed to encode signature");
+    sign_error()
+}
+
+fn signing_unavailable() -> ConvertError {
+    ConvertError {
+        reason: Some("SIGNING_UNAVAILABLE"),
+        x2t_code: None,
+        message: "signing is not configured".to_string(),
+    }
+}
+
+fn signer_error() -> ConvertError {
+    ConvertError {
+        reason: Some("SIGNING_KEY"),
+        x2t_code: None,
+        message: "failed to load signing key".to_string(),
+    }
+}
+
+fn sign_error() -> ConvertError {
+    ConvertError {
+        reason: Some("SIGN_OUTPUT"),
+        x2t_code: None,
+        message: "failed to sign pdf output".to_string(),
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    admission::{DiskReservation, reserve_disk_space},
+    error::ConvertError,
+};
+
+/// Number of leading bytes of the source file captured for detecting the file condition
+pub const SOURCE_HEADER_SIZE: usize = 1024 * 32;
+
+/// Minimum size of a source file that could be a document, smaller files are
+/// too small to contain the signature of any supported format
+const MIN_SOURCE_SIZE: u64 = 4;
+
+/// Details about the source file collected while it was streamed to disk
+pub struct SourceFile {
+    /// Total size of the source file in bytes
+    pub size: u64,
+    /// Hex encoded SHA-256 checksum of the source file
+    pub sha256: String,
+    /// Leading bytes of the file (Up to [SOURCE_HEADER_SIZE]) used for
+    /// detecting the file condition
+    pub header: Vec<u8>,
+    /// Disk space reserved for the conversion of the file
+    pub disk_reservation: Option<DiskReservation>,
+    /// Entity tag of the source object, [None] for sources not loaded from storage
+    pub etag: Option<String>,
+}
+
+impl SourceFile {
+    /// Reject empty and implausibly small source files before they are converted
+    pub fn check_size(&self) -> Result<(), ConvertError> {
+        if self.size < MIN_SOURCE_SIZE {
+            tracing::warn!(size = self.size, "source file is empty");
+
+            return Err(ConvertError {
+                reason: Some("INPUT_EMPTY"),
+                x2t_code: None,
+                message: format!("source file is empty or too small ({} bytes)", self.size),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the source file to disk computing the checksum and capturing
+/// the file header as the chunks are written
+pub struct SourceFileWriter {
+    file: tokio::fs::File,
+    hasher: Sha256,
+    header: Vec<u8>,
+    size: u64,
+    disk_reservation: Option<DiskReservation>,
+}
+
+impl SourceFileWriter {
+    /// Create the source file, when the `expected_size` of the file is known the
+    /// disk space required for the conversion is reserved before any data is written
+    pub async fn create(
+        file_path: &Path,
+        expected_size: Option<u64>,
+    ) -> Result<SourceFileWriter, ConvertError> {
+        let disk_reservation = match (expected_size, file_path.parent()) {
+            (Some(expected_size), Some(temp_path)) => {
+                Some(reserve_disk_space(temp_path, expected_size)?)
+            }
+            _ => None,
+        };
+
+        let file = tokio::fs::File::create(file_path).await.map_err(|err| {
+            tracing::error!(?err, "failed to create source file");
+            ConvertError {
+                reason: Some("GET_OBJECT"),
+                x2t_code: None,
+                message: err.to_string(),
+            }
+        })?;
+
+        Ok(SourceFileWriter {
+            file,
+            hasher: Sha256::new(),
+            header: Vec::with_capacity(SOURCE_HEADER_SIZE),
+            size: 0,
+            disk_reservation,
+        })
+    }
+
+    /// Total number of bytes written so far
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub async fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), ConvertError> {
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+
+        // Capture the leading bytes of the file
+        if self.header.len() < SOURCE_HEADER_SIZE {
+            let remaining = SOURCE_HEADER_SIZE - self.header.len();
+            self.header
+                .extend_from_slice(&chunk[..remaining.min(chunk.len())]);
+        }
+
+        self.file.write_all(chunk).await.map_err(|err| {
+            tracing::error!(?err, "failed to write object chunk");
+            ConvertError {
+                reason: Some("WRITE_OBJECT_CHUNK"),
+                x2t_code: None,
+                message: "failed to write chunk".to_string(),
+            }
+        })
+    }
+
+    pub async fn finish(mut self) -> Result<SourceFile, ConvertError> {
+        self.file.flush().await.map_err(|err| {
+            tracing::error!(?err, "failed to flush object");
+            ConvertError {
+                reason: Some("FLUSH_OBJECT"),
+                x2t_code: None,
+                message: "failed to flush object".to_string(),
+            }
+        })?;
+
+        Ok(SourceFile {
+            size: self.size,
+            sha256: format!("{:x}", self.hasher.finalize()),
+            header: self.header,
+            disk_reservation: self.disk_reservation,
+            etag: None,
+        })
+    }
+}
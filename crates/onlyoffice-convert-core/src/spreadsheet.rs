@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::ConvertError;
+
+/// Limits x2t applies when opening spreadsheet sources, large workbooks convert
+/// (taking longer) rather than failing with the x2t limit errors when raised
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpreadsheetLimits {
+    /// Maximum total uncompressed size of the worksheet XML in bytes, the number
+    /// of rows and cells that can be opened grows with the size
+    pub max_uncompressed_size: u64,
+}
+
+impl SpreadsheetLimits {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        if self.max_uncompressed_size == 0 {
+            return Err(ConvertError {
+                reason: Some("INVALID_REQUEST"),
+                x2t_code: None,
+                message: "spreadsheet_limits.max_uncompressed_size: must be greater than 0"
+                    .to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Smallest print scale supported by spreadsheets as a percentage
+const MIN_SCALE: u32 = 10;
+
+/// Largest print scale supported by spreadsheets as a percentage
+const MAX_SCALE: u32 = 400;
+
+/// Largest page margin in millimetres
+const MAX_MARGIN: f64 = 500.0;
+
+/// Page layout for spreadsheets rendered to PDF, the layout stored within the
+/// workbook is used for the options that are not provided
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SpreadsheetPrintOptions {
+    /// Page margins in millimetres
+    #[serde(default)]
+    pub margins: Option<PageMargins>,
+    /// Scale of the printed sheets as a percentage (10 to 400)
+    #[serde(default)]
+    pub scale: Option<u32>,
+    /// Number of pages the width of each sheet is fitted to
+    #[serde(default)]
+    pub fit_to_width: Option<u32>,
+    /// Number of pages the height of each sheet is fitted to, 0 allows
+    /// as many pages as needed
+    #[serde(default)]
+    pub fit_to_height: Option<u32>,
+    #[serde(default)]
+    pub orientation: Option<PageOrientation>,
+    /// Whether the cell gridlines are printed
+    #[serde(default)]
+    pub gridlines: Option<bool>,
+    /// Whether the row and column headings are printed
+    #[serde(default)]
+    pub headings: Option<bool>,
+}
+
+/// Margins of a printed page in millimetres
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct PageMargins {
+    pub left: f64,
+    pub right: f64,
+    pub top: f64,
+    pub bottom: f64,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageOrientation {
+    Portrait,
+    Landscape,
+}
+
+impl SpreadsheetPrintOptions {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        if let Some(margins) = &self.margins
+            && [margins.left, margins.right, margins.top, margins.bottom]
+                .iter()
+                .any(|margin| !(0.0..=MAX_MARGIN).contains(margin))
+        {
+            return Err(invalid_print_options(&format!(
+                "margins: must be between 0 and {MAX_MARGIN} millimetres"
+            )));
+        }
+
+        if let Some(scale) = self.scale {
+            if !(MIN_SCALE..=MAX_SCALE).contains(&scale) {
+                return Err(invalid_print_options(&format!(
+                    "scale: must be between {MIN_SCALE} and {MAX_SCALE}"
+                )));
+            }
+
+            // Fitting to pages determines the scale
+            if self.fit_to_width.is_some() || self.fit_to_height.is_some() {
+                return Err(invalid_print_options(
+                    "scale: cannot be combined with fit_to_width or fit_to_height",
+                ));
+            }
+        }
+
+        if self.fit_to_width == Some(0) {
+            return Err(invalid_print_options(
+                "fit_to_width: must be greater than 0",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// JSON params passed to x2t for the spreadsheet renderer
+    pub fn json_params(&self) -> String {
+        let mut layout = json!({});
+
+        if let Some(margins) = &self.margins {
+            layout["margins"] = json!({
+                "left": margins.left,
+                "right": margins.right,
+                "top": margins.top,
+                "bottom": margins.bottom,
+            });
+        }
+
+        if let Some(scale) = self.scale {
+            layout["scale"] = json!(scale);
+        }
+
+        if self.fit_to_width.is_some() || self.fit_to_height.is_some() {
+            layout["fitToWidth"] = json!(self.fit_to_width.unwrap_or(0));
+            layout["fitToHeight"] = json!(self.fit_to_height.unwrap_or(0));
+        }
+
+        if let Some(orientation) = self.orientation {
+            layout["orientation"] = json!(orientation);
+        }
+
+        if let Some(gridlines) = self.gridlines {
+            layout["gridLines"] = json!(gridlines);
+        }
+
+        if let Some(headings) = self.headings {
+            layout["headings"] = json!(headings);
+        }
+
+        json!({ "spreadsheetLayout": layout }).to_string()
+    }
+}
+
+fn invalid_print_options(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("INVALID_REQUEST"),
+        x2t_code: None,
+        message: format!("spreadsheet.{message}"),
+    }
+}
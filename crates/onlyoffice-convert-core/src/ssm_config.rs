@@ -0,0 +1,126 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::time::MissedTickBehavior;
+
+use crate::{
+    aws::aws_config,
+    config::{CONFIG_SSM_PATH_ENV, set_config_overrides},
+    error::ConvertError,
+};
+
+/// Environment variable for the number of seconds between refreshes of the SSM
+/// configuration, 0 disables refreshing
+const CONFIG_SSM_REFRESH_INTERVAL_ENV: &str = "CONFIG_SSM_REFRESH_INTERVAL";
+
+const DEFAULT_CONFIG_SSM_REFRESH_INTERVAL: u64 = 300;
+
+fn ssm_config_path() -> Option<String> {
+    std::env::var(CONFIG_SSM_PATH_ENV)
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+fn ssm_refresh_interval() -> Option<Duration> {
+    let seconds = std::env::var(CONFIG_SSM_REFRESH_INTERVAL_ENV)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_CONFIG_SSM_REFRESH_INTERVAL);
+
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Load the configuration from SSM Parameter Store and start refreshing it in the
+/// background, called once at cold start before any requests are handled (When enabled)
+///
+/// Settings that are only read at start (i.e the x2t concurrency and the synced
+/// fonts) only change on the next cold start
+pub async fn load_ssm_config() -> Result<(), ConvertError> {
+    let Some(path) = ssm_config_path() else {
+        // SSM configuration is not enabled
+        return Ok(());
+    };
+
+    let aws_config = aws_config().await;
+    let ssm_client = aws_sdk_ssm::Client::new(&aws_config);
+
+    let values = get_ssm_parameters(&ssm_client, &path).await?;
+
+    tracing::debug!(count = values.len(), %path, "loaded ssm configuration");
+
+    set_config_overrides(values)?;
+
+    if let Some(interval) = ssm_refresh_interval() {
+        tokio::spawn(refresh_ssm_config(ssm_client, path, interval));
+    }
+
+    Ok(())
+}
+
+/// Reload the configuration every `interval`, failed refreshes keep the
+/// current configuration
+async fn refresh_ssm_config(ssm_client: aws_sdk_ssm::Client, path: String, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+
+    // The function is frozen between invocations, skip the missed refreshes
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    // The first tick completes immediately
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let values = match get_ssm_parameters(&ssm_client, &path).await {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::warn!(message = %err.message, "failed to refresh ssm configuration");
+                continue;
+            }
+        };
+
+        if let Err(err) = set_config_overrides(values) {
+            tracing::warn!(message = %err.message, "refreshed ssm configuration is invalid");
+        }
+    }
+}
+
+/// Get the parameters directly under the `path` keyed by their name within the path,
+/// secure string parameters are decrypted
+async fn get_ssm_parameters(
+    ssm_client: &aws_sdk_ssm::Client,
+    path: &str,
+) -> Result<HashMap<String, String>, ConvertError> {
+    let mut pages = ssm_client
+        .get_parameters_by_path()
+        .path(path)
+        .with_decryption(true)
+        .into_paginator()
+        .send();
+
+    let mut values = HashMap::new();
+
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            tracing::error!(?err, %path, "failed to get ssm parameters");
+            ConvertError {
+                reason: Some("SSM_CONFIG"),
+                x2t_code: None,
+                message: "failed to load configuration from ssm".to_string(),
+            }
+        })?;
+
+        for parameter in page.parameters() {
+            let (Some(name), Some(value)) = (parameter.name(), parameter.value()) else {
+                continue;
+            };
+
+            let name = name
+                .strip_prefix(path)
+                .unwrap_or(name)
+                .trim_start_matches('/');
+            values.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    Ok(values)
+}
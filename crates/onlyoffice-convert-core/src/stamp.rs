@@ -0,0 +1,501 @@
+use std::path::Path;
+
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ConvertError;
+
+/// Maximum length of a header or footer line
+const MAX_LINE_LENGTH: usize = 256;
+
+/// Size of the stamped text in points
+const FONT_SIZE: f32 = 8.0;
+
+/// Distance of the stamped text from the edges of the page in points
+const MARGIN: f32 = 18.0;
+
+/// Name of the stamp font within the page resources
+const FONT_NAME: &str = "OOConvertStamp";
+
+/// Text of the page number stamp
+const PAGE_NUMBER_TEXT: &str = "Page {page} of {pages}";
+
+/// Widths of the printable ASCII characters (32 to 126) in Helvetica, in
+/// thousandths of the font size
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Width used for characters outside of the ASCII range
+const DEFAULT_WIDTH: u16 = 556;
+
+/// Header, footer and page numbers stamped onto PDF outputs
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct StampOptions {
+    /// Line of text centered at the top of each page
+    #[serde(default)]
+    pub header: Option<String>,
+    /// Line of text centered at the bottom of each page
+    #[serde(default)]
+    pub footer: Option<String>,
+    /// Stamp "Page N of M" at the bottom right of each page
+    #[serde(default)]
+    pub page_numbers: bool,
+}
+
+/// Values substituted into the `{name}` placeholders of the stamped text
+pub struct StampValues<'a> {
+    pub request_id: &'a str,
+    /// Time the output was generated in the RFC 3339 form
+    pub timestamp: &'a str,
+}
+
+impl StampOptions {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        if self.header.is_none() && self.footer.is_none() && !self.page_numbers {
+            return Err(invalid_request(
+                "stamp: header, footer or page_numbers is required",
+            ));
+        }
+
+        for (field, line) in [
+            ("stamp.header", &self.header),
+            ("stamp.footer", &self.footer),
+        ] {
+            if let Some(line) = line
+                && (line.len() > MAX_LINE_LENGTH || line.chars().any(char::is_control))
+            {
+                return Err(invalid_request(&format!("{field}: invalid line")));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the stamped text differs between requests for the same source
+    pub fn is_per_request(&self) -> bool {
+        [&self.header, &self.footer]
+            .into_iter()
+            .flatten()
+            .any(|line| line.contains("{request_id}") || line.contains("{timestamp}"))
+    }
+}
+
+/// Stamp the header, footer and page numbers onto each page of the PDF at `output_path`
+/// in place, replacing the `{page}`, `{pages}`, `{request_id}` and `{timestamp}`
+/// placeholders of the text. The stamped file is written to `stamped_path` before
+/// replacing the output
+pub async fn stamp_pdf(
+    output_path: &Path,
+    stamped_path: &Path,
+    options: &StampOptions,
+    values: StampValues<'_>,
+) -> Result<(), ConvertError> {
+    let output_path = output_path.to_path_buf();
+    let stamped_path = stamped_path.to_path_buf();
+    let options = options.clone();
+    let request_id = values.request_id.to_string();
+    let timestamp = values.timestamp.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let values = StampValues {
+            request_id: &request_id,
+            timestamp: &timestamp,
+        };
+
+        let mut document = Document::load(&output_path)?;
+        stamp_document(&mut document, &options, &values)?;
+        document.save(&stamped_path)?.sync_all()?;
+        std::fs::rename(&stamped_path, &output_path)?;
+        Ok::<_, lopdf::Error>(())
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "stamp task failed");
+        stamp_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to stamp pdf");
+        stamp_error()
+    })
+}
+
+fn stamp_document(
+    document: &mut Document,
+    options: &StampOptions,
+    values: &StampValues<'_>,
+) -> lopdf::Result<()> {
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+
+    let pages = document.get_pages();
+    let page_count = pages.len();
+
+    for (page_number, page_id) in pages {
+        let fill = |text: &str| {
+            text.replace("{page}", &page_number.to_string())
+                .replace("{pages}", &page_count.to_string())
+                .replace("{request_id}", values.request_id)
+                .replace("{timestamp}", values.timestamp)
+        };
+
+        let [left, bottom, right, top] = page_box(document, page_id)?;
+        let center = (left + right) / 2.0;
+
+        let mut content = String::new();
+
+        if let Some(header) = &options.header {
+            let text = encode_text(&fill(header));
+            let x = center - text_width(&text) / 2.0;
+            push_text(&mut content, x, top - MARGIN - FONT_SIZE, &text);
+        }
+
+        if let Some(footer) = &options.footer {
+            let text = encode_text(&fill(footer));
+            let x = center - text_width(&text) / 2.0;
+            push_text(&mut content, x, bottom + MARGIN, &text);
+        }
+
+        if options.page_numbers {
+            let text = encode_text(&fill(PAGE_NUMBER_TEXT));
+            let x = right - MARGIN - text_width(&text);
+            push_text(&mut content, x, bottom + MARGIN, &text);
+        }
+
+        add_page_font(document, page_id, font_id)?;
+
+        // The existing content is wrapped in a saved graphics state so the stamp
+        // is drawn with the default state, the stamp starts with a new line as the
+        // existing content may not end with whitespace
+        let save_id = document.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+        let stamp_id = document.add_object(Stream::new(
+            Dictionary::new(),
+            format!("\nQ\n{content}").into_bytes(),
+        ));
+
+        let mut contents: Vec<Object> = vec![save_id.into()];
+        contents.extend(
+            document
+                .get_page_contents(page_id)
+                .into_iter()
+                .map(Object::from),
+        );
+        contents.push(stamp_id.into());
+
+        document
+            .get_dictionary_mut(page_id)?
+            .set("Contents", contents);
+    }
+
+    Ok(())
+}
+
+/// Visible area of the page (The crop box or media box), inherited from the
+/// parent pages when not set on the page
+pub fn page_box(document: &Document, page_id: ObjectId) -> lopdf::Result<[f32; 4]> {
+    let value = inherited(document, page_id, b"CropBox")?
+        .or(inherited(document, page_id, b"MediaBox")?)
+        .ok_or(lopdf::Error::DictKey)?;
+
+    let values = document.dereference(value)?.1.as_array()?;
+    let mut output = [0.0; 4];
+
+    for (output, value) in output.iter_mut().zip(values) {
+        *output = document.dereference(value)?.1.as_float()?;
+    }
+
+    // Normalize boxes defined from the top right
+    let [x1, y1, x2, y2] = output;
+    Ok([x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)])
+}
+
+/// Get a page attribute that can be inherited from the parent pages
+pub fn inherited<'a>(
+    document: &'a Document,
+    page_id: ObjectId,
+    key: &[u8],
+) -> lopdf::Result<Option<&'a Object>> {
+    let mut node = document.get_dictionary(page_id)?;
+
+    // Bounded to protect against reference cycles
+    for _ in 0..64 {
+        if let Ok(value) = node.get(key) {
+            return Ok(Some(value));
+        }
+
+        match node.get(b"Parent").and_then(Object::as_reference) {
+            Ok(parent_id) => node = document.get_dictionary(parent_id)?,
+            Err(_) => return Ok(None),
+        }
+    }
+
+    Err(lopdf::Error::ReferenceCycle)
+}
+
+/// Add the stamp font to the resources of the page, the (possibly inherited)
+/// resources are copied onto the page so other pages are unaffected
+fn add_page_font(
+    document: &mut Document,
+    page_id: ObjectId,
+    font_id: ObjectId,
+) -> lopdf::Result<()> {
+    let mut resources = match inherited(document, page_id, b"Resources")? {
+        Some(value) => document.dereference(value)?.1.as_dict()?.clone(),
+        None => Dictionary::new(),
+    };
+
+    let mut fonts = match resources.get(b"Font") {
+        Ok(value) => document.dereference(value)?.1.as_dict()?.clone(),
+        Err(_) => Dictionary::new(),
+    };
+
+    fonts.set(FONT_NAME, font_id);
+    resources.set("Font", fonts);
+
+    document
+        .get_dictionary_mut(page_id)?
+        .set("Resources", resources);
+
+    Ok(())
+}
+
+/// Append the operators drawing a line of encoded `text` at `x` and `y`
+fn push_text(content: &mut String, x: f32, y: f32, text: &[u8]) {
+    let mut literal = String::with_capacity(text.len());
+
+    for &byte in text {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            0x20..=0x7e => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{byte:03o}")),
+        }
+    }
+
+    content.push_str(&format!(
+        "BT\n/{FONT_NAME} {FONT_SIZE} Tf\n{x:.2} {y:.2} Td\n({literal}) Tj\nET\n"
+    ));
+}
+
+/// Encode text using the WinAnsi encoding of the stamp font, characters outside
+/// of the Latin-1 range are replaced with `?`
+fn encode_text(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|char| match u32::from(char) {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => code as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Width of the encoded `text` in points
+fn text_width(text: &[u8]) -> f32 {
+    let width: u32 = text
+        .iter()
+        .map(|&byte| {
+            let width = match byte {
+                0x20..=0x7e => HELVETICA_WIDTHS[usize::from(byte - 0x20)],
+                _ => DEFAULT_WIDTH,
+            };
+            u32::from(width)
+        })
+        .sum();
+
+    width as f32 * FONT_SIZE / 1000.0
+}
+
+fn invalid_request(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("INVALID_REQUEST"),
+        x2t_code: None,
+        message: message.to_string(),
+    }
+}
+
+fn stamp_error() -> ConvertError {
+    ConvertError {
+        reason: Some("STAMP_OUTPUT"),
+        x2t_code: None,
+        message: "failed to stamp pdf output".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{Document, Object, ObjectId, Stream, dictionary};
+
+    use super::{
+        FONT_NAME, MAX_LINE_LENGTH, StampOptions, StampValues, encode_text, page_box, push_text,
+        stamp_document, text_width,
+    };
+
+    /// Create a document with `count` pages that inherit their media box and
+    /// resources from the parent pages, returns the document and the page IDs
+    fn document(count: usize) -> (Document, Vec<ObjectId>) {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+
+        let page_ids: Vec<ObjectId> = (0..count)
+            .map(|_| {
+                let content_id =
+                    document.add_object(Stream::new(dictionary! {}, b"0 0 m 10 10 l S".to_vec()));
+                document.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                })
+            })
+            .collect();
+
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => page_ids.iter().map(|id| Object::from(*id)).collect::<Vec<_>>(),
+                "Count" => count as i64,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => dictionary! {} } },
+            }),
+        );
+
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+
+        (document, page_ids)
+    }
+
+    #[test]
+    fn test_validate() {
+        let options = StampOptions {
+            footer: Some("Confidential".to_string()),
+            ..Default::default()
+        };
+        options.validate().unwrap();
+
+        for options in [
+            StampOptions::default(),
+            StampOptions {
+                header: Some("a".repeat(MAX_LINE_LENGTH + 1)),
+                ..Default::default()
+            },
+            StampOptions {
+                footer: Some("line\nbreak".to_string()),
+                ..Default::default()
+            },
+        ] {
+            let err = options.validate().unwrap_err();
+            assert_eq!(err.reason, Some("INVALID_REQUEST"), "{options:?}");
+        }
+    }
+
+    #[test]
+    fn test_is_per_request() {
+        let options = StampOptions {
+            header: Some("Page {page} of {pages}".to_string()),
+            page_numbers: true,
+            ..Default::default()
+        };
+        assert!(!options.is_per_request());
+
+        let options = StampOptions {
+            footer: Some("Generated {timestamp}".to_string()),
+            ..Default::default()
+        };
+        assert!(options.is_per_request());
+    }
+
+    #[test]
+    fn test_encode_text() {
+        assert_eq!(encode_text("Caf\u{e9} \u{20ac}\t"), b"Caf\xE9 ??");
+    }
+
+    #[test]
+    fn test_text_width() {
+        // Helvetica "A" is 667 thousandths of the 8pt font size
+        assert!((text_width(b"A") - 5.336).abs() < 1e-4);
+        assert!((text_width(b"\xE9") - 4.448).abs() < 1e-4);
+        assert_eq!(text_width(b""), 0.0);
+    }
+
+    #[test]
+    fn test_push_text() {
+        let mut content = String::new();
+        push_text(&mut content, 10.0, 20.5, b"(a)\\\xE9");
+
+        assert_eq!(
+            content,
+            format!("BT\n/{FONT_NAME} 8 Tf\n10.00 20.50 Td\n(\\(a\\)\\\\\\351) Tj\nET\n")
+        );
+    }
+
+    #[test]
+    fn test_page_box() {
+        let (mut document, pages) = document(2);
+
+        // Inherited from the parent pages
+        assert_eq!(
+            page_box(&document, pages[0]).unwrap(),
+            [0.0, 0.0, 612.0, 792.0]
+        );
+
+        // The crop box takes precedence and is normalized
+        document.get_dictionary_mut(pages[1]).unwrap().set(
+            "CropBox",
+            vec![600.into(), 780.into(), 12.into(), 12.into()],
+        );
+        assert_eq!(
+            page_box(&document, pages[1]).unwrap(),
+            [12.0, 12.0, 600.0, 780.0]
+        );
+    }
+
+    #[test]
+    fn test_stamp_document() {
+        let (mut document, pages) = document(2);
+        let options = StampOptions {
+            header: Some("{request_id} {page}/{pages}".to_string()),
+            footer: None,
+            page_numbers: true,
+        };
+        let values = StampValues {
+            request_id: "request",
+            timestamp: "2026-01-01T00:00:00Z",
+        };
+
+        stamp_document(&mut document, &options, &values).unwrap();
+
+        for (index, page_id) in pages.into_iter().enumerate() {
+            let number = index + 1;
+            let content = String::from_utf8(document.get_page_content(page_id).unwrap()).unwrap();
+
+            // The existing content is kept within a saved graphics state
+            assert!(content.starts_with("q\n0 0 m 10 10 l S\nQ\n"), "{content}");
+            assert!(content.contains(&format!("(request {number}/2) Tj")));
+            assert!(content.contains(&format!("553.08 18.00 Td\n(Page {number} of 2) Tj")));
+
+            // The inherited fonts are copied onto the page along with the stamp font
+            let fonts = document
+                .get_dictionary(page_id)
+                .and_then(|page| page.get(b"Resources"))
+                .and_then(Object::as_dict)
+                .and_then(|resources| resources.get(b"Font"))
+                .and_then(Object::as_dict)
+                .unwrap();
+            assert!(fonts.has(b"F1"));
+            assert!(fonts.has(FONT_NAME.as_bytes()));
+        }
+    }
+}
@@ -0,0 +1,1253 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    future::Future,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use aws_sdk_s3::{
+    config::{Region, http::HttpResponse, retry::RetryConfig},
+    error::{ProvideErrorMetadata, SdkError},
+    presigning::PresigningConfig,
+    primitives::{ByteStream, Length},
+    types::{
+        ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart, ObjectCannedAcl, RequestPayer,
+        StorageClass,
+    },
+};
+use bytes::Bytes;
+use futures::{
+    FutureExt, StreamExt, TryStreamExt,
+    future::BoxFuture,
+    stream::{self, BoxStream},
+};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+use url::form_urlencoded;
+
+use crate::{
+    aws::{AwsPartition, aws_config},
+    config::{app_config, config_var},
+    rate_limit::{download_limiter, limit_body, limit_stream, upload_limiter},
+    retry::{RetryClass, RetryPolicy},
+};
+
+/// Environment variable for a comma separated list of buckets with S3 Transfer
+/// Acceleration enabled, requests for these buckets are sent to the accelerate
+/// endpoint. Buckets without acceleration enabled reject accelerated requests
+const S3_ACCELERATE_BUCKETS_ENV: &str = "S3_ACCELERATE_BUCKETS";
+
+/// Parts uploaded concurrently when there is enough time for the upload
+const BASE_MULTIPART_CONCURRENCY: usize = 4;
+
+/// Parts each concurrent upload is given, so a slow part does not hold up the
+/// upload while the other uploads are idle
+const PARTS_PER_UPLOAD: u64 = 4;
+
+/// Estimated throughput of a single upload in bytes per second, used to choose the
+/// concurrency needed to finish within the remaining time
+const ESTIMATED_UPLOAD_THROUGHPUT: u64 = 16 * 1024 * 1024;
+
+/// Smallest part size used, larger than the S3 minimum of 5MB to limit the number
+/// of requests for smaller uploads
+const MIN_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Largest part size accepted by S3
+const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Maximum number of parts of a multipart upload accepted by S3
+const MAX_PARTS: u64 = 10_000;
+
+/// Part sizes are rounded up to a multiple of this size
+const PART_SIZE_ALIGNMENT: u64 = 1024 * 1024;
+
+/// S3 error code for requests for a version that does not exist
+const NO_SUCH_VERSION_CODE: &str = "NoSuchVersion";
+
+/// S3 error code for requests where the If-Match precondition failed
+const PRECONDITION_FAILED_CODE: &str = "PreconditionFailed";
+
+/// S3 error code for conditional writes that conflicted with a concurrent write
+const CONDITIONAL_REQUEST_CONFLICT_CODE: &str = "ConditionalRequestConflict";
+
+/// S3 error code for requests rejected due to the request rate
+const SLOW_DOWN_CODE: &str = "SlowDown";
+
+/// Header S3 responds with identifying the region of a bucket, included in the
+/// redirect responses for requests sent to the wrong region
+const BUCKET_REGION_HEADER: &str = "x-amz-bucket-region";
+
+/// Regions of the buckets that have been resolved, cached across warm invocations
+static BUCKET_REGIONS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+tokio::task_local! {
+    /// Number of requests throttled by S3 while running [count_throttled]
+    static THROTTLED_REQUESTS: Cell<u64>;
+
+    /// Time uploads must finish by while running [with_upload_deadline]
+    static UPLOAD_DEADLINE: Instant;
+}
+
+/// Object storage operations used for the conversion source and outputs,
+/// implemented over S3 by [S3Storage]
+pub trait Storage: Send + Sync {
+    /// Get an object, the body is streamed as it is read
+    fn get_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<StorageObject, StorageError>>;
+
+    /// Get the metadata of an object without its body
+    fn head_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<ObjectHead, StorageError>>;
+
+    /// Store an object
+    fn put_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        body: PutBody<'a>,
+        options: PutOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Copy an object within storage, the metadata of the source object is kept
+    fn copy_object<'a>(
+        &'a self,
+        source_bucket: &'a str,
+        source_key: &'a str,
+        dest_bucket: &'a str,
+        dest_key: &'a str,
+        options: WriteOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Get the user defined metadata and tags of an object
+    fn get_object_metadata<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<ObjectMetadata, StorageError>>;
+
+    /// Delete an object, or a specific version of the object
+    fn delete_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: DeleteOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+}
+
+/// Options for retrieving an object
+#[derive(Default, Clone, Copy)]
+pub struct GetOptions<'a> {
+    /// Specific version of the object to retrieve, the latest version is used when not set
+    pub version_id: Option<&'a str>,
+    /// Entity tag the object must match, fails with [StorageError::PreconditionFailed]
+    /// when the object has a different entity tag
+    pub if_match: Option<&'a str>,
+    /// Acknowledge the request is charged to the requester, required for
+    /// objects within requester pays buckets
+    pub requester_pays: bool,
+}
+
+/// Options for deleting an object
+#[derive(Default, Clone, Copy)]
+pub struct DeleteOptions<'a> {
+    /// Specific version of the object to permanently delete, the latest version is
+    /// deleted (or replaced by a delete marker in versioned buckets) when not set
+    pub version_id: Option<&'a str>,
+    /// Entity tag the object must match, fails with [StorageError::PreconditionFailed]
+    /// when the object has a different entity tag
+    pub if_match: Option<&'a str>,
+    /// Acknowledge the request is charged to the requester, required for
+    /// objects within requester pays buckets
+    pub requester_pays: bool,
+}
+
+/// Object retrieved from storage
+pub struct StorageObject {
+    /// Size of the object in bytes when known
+    pub content_length: Option<u64>,
+    /// Entity tag identifying the object contents
+    pub etag: Option<String>,
+    /// Stream of the object body chunks
+    pub body: BoxStream<'static, Result<Bytes, StorageError>>,
+}
+
+/// Metadata of an object
+pub struct ObjectHead {
+    /// Size of the object in bytes when known
+    pub content_length: Option<u64>,
+    /// Entity tag identifying the object contents
+    pub etag: Option<String>,
+}
+
+/// User defined metadata and tags of an object
+#[derive(Debug, Default, Clone)]
+pub struct ObjectMetadata {
+    /// User defined metadata (`x-amz-meta-*`) without the prefix
+    pub metadata: HashMap<String, String>,
+    /// Tags of the object as key value pairs
+    pub tags: Vec<(String, String)>,
+}
+
+/// Body of an object being stored
+pub enum PutBody<'a> {
+    /// Stream the body from a file
+    File(&'a Path),
+    /// Body is already in memory
+    Bytes(Vec<u8>),
+}
+
+/// Options for a stored object
+#[derive(Default)]
+pub struct PutOptions<'a> {
+    pub content_type: Option<&'a str>,
+    pub content_encoding: Option<&'a str>,
+    /// User defined metadata and tags to store with the object
+    pub metadata: Option<&'a ObjectMetadata>,
+    /// Entity tag the existing object must match, fails with [StorageError::PreconditionFailed]
+    /// when the object has been replaced since it was read
+    pub if_match: Option<&'a str>,
+    pub write: WriteOptions<'a>,
+}
+
+/// Ownership and storage options for objects written by a put or copy
+#[derive(Default, Clone, Copy)]
+pub struct WriteOptions<'a> {
+    /// Storage class of the written object, the bucket default is used when not set
+    pub storage_class: Option<ObjectStorageClass>,
+    /// Canned ACL to apply to the written object
+    pub acl: Option<ObjectAcl>,
+    /// Account ID that must own the destination bucket, the write fails when
+    /// the bucket is owned by another account
+    pub expected_bucket_owner: Option<&'a str>,
+    /// Acknowledge the request is charged to the requester, required for
+    /// writing into (or copying from) requester pays buckets
+    pub requester_pays: bool,
+    /// Checksum S3 verifies the written object against, stored with the object
+    /// so it can be read later through `GetObjectAttributes`
+    pub checksum_algorithm: Option<ObjectChecksumAlgorithm>,
+}
+
+/// Canned ACL applied to a written object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObjectAcl {
+    Private,
+    PublicRead,
+    PublicReadWrite,
+    AuthenticatedRead,
+    AwsExecRead,
+    BucketOwnerRead,
+    /// Grants the bucket owner full control over objects written into buckets
+    /// owned by other accounts
+    BucketOwnerFullControl,
+}
+
+/// Storage class of a written object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ObjectStorageClass {
+    Standard,
+    StandardIa,
+    OnezoneIa,
+    IntelligentTiering,
+    GlacierIr,
+    Glacier,
+    DeepArchive,
+}
+
+/// Additional checksum algorithm used to verify a written object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ObjectChecksumAlgorithm {
+    #[serde(rename = "CRC32C")]
+    Crc32c,
+    #[serde(rename = "SHA256")]
+    Sha256,
+}
+
+impl ObjectStorageClass {
+    /// Whether objects of the class must be restored before they can be read
+    pub fn is_archival(&self) -> bool {
+        matches!(
+            self,
+            ObjectStorageClass::Glacier | ObjectStorageClass::DeepArchive
+        )
+    }
+}
+
+impl From<ObjectStorageClass> for StorageClass {
+    fn from(value: ObjectStorageClass) -> Self {
+        match value {
+            ObjectStorageClass::Standard => StorageClass::Standard,
+            ObjectStorageClass::StandardIa => StorageClass::StandardIa,
+            ObjectStorageClass::OnezoneIa => StorageClass::OnezoneIa,
+            ObjectStorageClass::IntelligentTiering => StorageClass::IntelligentTiering,
+            ObjectStorageClass::GlacierIr => StorageClass::GlacierIr,
+            ObjectStorageClass::Glacier => StorageClass::Glacier,
+            ObjectStorageClass::DeepArchive => StorageClass::DeepArchive,
+        }
+    }
+}
+
+impl From<ObjectChecksumAlgorithm> for ChecksumAlgorithm {
+    fn from(value: ObjectChecksumAlgorithm) -> Self {
+        match value {
+            ObjectChecksumAlgorithm::Crc32c => ChecksumAlgorithm::Crc32C,
+            ObjectChecksumAlgorithm::Sha256 => ChecksumAlgorithm::Sha256,
+        }
+    }
+}
+
+impl From<ObjectAcl> for ObjectCannedAcl {
+    fn from(value: ObjectAcl) -> Self {
+        match value {
+            ObjectAcl::Private => ObjectCannedAcl::Private,
+            ObjectAcl::PublicRead => ObjectCannedAcl::PublicRead,
+            ObjectAcl::PublicReadWrite => ObjectCannedAcl::PublicReadWrite,
+            ObjectAcl::AuthenticatedRead => ObjectCannedAcl::AuthenticatedRead,
+            ObjectAcl::AwsExecRead => ObjectCannedAcl::AwsExecRead,
+            ObjectAcl::BucketOwnerRead => ObjectCannedAcl::BucketOwnerRead,
+            ObjectAcl::BucketOwnerFullControl => ObjectCannedAcl::BucketOwnerFullControl,
+        }
+    }
+}
+
+/// Errors from storage operations
+#[derive(Debug)]
+pub enum StorageError {
+    /// Requested object does not exist
+    NoSuchKey,
+    /// Requested version of the object does not exist
+    NoSuchVersion,
+    /// Object did not match the requested entity tag
+    PreconditionFailed,
+    /// Failed to read the local file for the body
+    ReadBody(std::io::Error),
+    /// Request was throttled by the storage service (SlowDown) and retrying
+    /// did not succeed
+    Throttled,
+    /// Request failed from a network error or server error (5xx) and retrying
+    /// did not succeed
+    Unavailable(String),
+    /// Request to the storage service failed
+    Request(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::NoSuchKey => f.write_str("object does not exist"),
+            StorageError::NoSuchVersion => f.write_str("object version does not exist"),
+            StorageError::PreconditionFailed => f.write_str("object entity tag did not match"),
+            StorageError::ReadBody(err) => write!(f, "failed to read body: {err}"),
+            StorageError::Throttled => f.write_str("request was throttled"),
+            StorageError::Unavailable(message) => f.write_str(message),
+            StorageError::Request(message) => f.write_str(message),
+        }
+    }
+}
+
+/// [Storage] backed by S3, requests are sent to the region of each bucket
+pub struct S3Storage {
+    /// Client for the configured region
+    client: aws_sdk_s3::Client,
+}
+
+impl S3Storage {
+    /// Create the storage using the `client`, requests are retried by the retry policy
+    /// of the conversion so the client should not retry requests itself
+    pub fn new(client: aws_sdk_s3::Client) -> S3Storage {
+        S3Storage { client }
+    }
+
+    /// Create the storage using the AWS config from the environment, the retries of the
+    /// SDK are disabled as they would multiply the attempts of the retry policy
+    pub async fn from_env() -> S3Storage {
+        let aws_config = aws_config().await;
+        let config = aws_sdk_s3::config::Builder::from(&aws_config)
+            .retry_config(RetryConfig::disabled())
+            .build();
+
+        S3Storage::new(aws_sdk_s3::Client::from_conf(config))
+    }
+
+    /// Create a presigned URL for getting an object, the URL is valid for `expires_in`
+    pub async fn presign_get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        let config = PresigningConfig::expires_in(expires_in)
+            .map_err(|err| StorageError::Request(err.to_string()))?;
+
+        let request = self
+            .bucket_client(bucket)
+            .await
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .presigned(config)
+            .await
+            .map_err(|err| StorageError::Request(err.to_string()))?;
+
+        Ok(request.uri().to_string())
+    }
+
+    /// Client for requests to the `bucket`, buckets in other regions are sent to
+    /// their region rather than failing with a redirect. The configured client
+    /// is used when the region cannot be resolved. Requests for accelerated
+    /// buckets use the accelerate endpoint
+    async fn bucket_client(&self, bucket: &str) -> aws_sdk_s3::Client {
+        let client = self.region_client(bucket).await;
+
+        if !is_accelerated_bucket(bucket) {
+            return client;
+        }
+
+        aws_sdk_s3::Client::from_conf(client.config().to_builder().accelerate(true).build())
+    }
+
+    /// Client for the region of the `bucket`
+    async fn region_client(&self, bucket: &str) -> aws_sdk_s3::Client {
+        let regions = BUCKET_REGIONS.get_or_init(Default::default);
+        let cached = regions
+            .lock()
+            .ok()
+            .and_then(|regions| regions.get(bucket).cloned());
+
+        let region = match cached {
+            Some(value) => value,
+            None => match self.resolve_bucket_region(bucket).await {
+                Some(value) => {
+                    if let Ok(mut regions) = regions.lock() {
+                        regions.insert(bucket.to_string(), value.clone());
+                    }
+                    value
+                }
+                None => return self.client.clone(),
+            },
+        };
+
+        let config = self.client.config();
+        let client_region = config.region().map(Region::as_ref).unwrap_or_default();
+        if client_region == region {
+            return self.client.clone();
+        }
+
+        // Requests cannot be sent across partitions, the client region is kept so the
+        // request fails within the partition rather than being sent to another partition
+        if AwsPartition::from_region(client_region) != AwsPartition::from_region(&region) {
+            tracing::warn!(%bucket, %region, %client_region, "bucket region is within another partition");
+            return self.client.clone();
+        }
+
+        aws_sdk_s3::Client::from_conf(config.to_builder().region(Region::new(region)).build())
+    }
+
+    /// Resolve the region of a bucket using HeadBucket, the region is reported
+    /// by successful responses and by the redirect and access denied errors
+    async fn resolve_bucket_region(&self, bucket: &str) -> Option<String> {
+        let region = match self.client.head_bucket().bucket(bucket).send().await {
+            Ok(response) => response.bucket_region,
+            Err(err) => err
+                .raw_response()
+                .and_then(|response| response.headers().get(BUCKET_REGION_HEADER))
+                .map(str::to_string),
+        };
+
+        if region.is_none() {
+            tracing::warn!(%bucket, "failed to resolve bucket region");
+        }
+
+        region
+    }
+}
+
+/// Upload the file at the `path` as a multipart upload using the `plan`, the
+/// upload is aborted when a part fails so the uploaded parts are not stored
+async fn put_multipart(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: &str,
+    path: &Path,
+    plan: MultipartPlan,
+    options: &PutOptions<'_>,
+    tagging: Option<String>,
+) -> Result<(), StorageError> {
+    tracing::debug!(
+        %bucket,
+        %key,
+        size = plan.size,
+        part_size = plan.part_size,
+        part_count = plan.part_count,
+        concurrency = plan.concurrency,
+        "starting multipart upload"
+    );
+
+    let write = options.write;
+
+    let upload = retry_request(bucket, key, || async {
+        client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .set_content_type(options.content_type.map(str::to_string))
+            .set_content_encoding(options.content_encoding.map(str::to_string))
+            .set_metadata(options.metadata.map(|metadata| metadata.metadata.clone()))
+            .set_tagging(tagging.clone())
+            .set_storage_class(write.storage_class.map(StorageClass::from))
+            .set_acl(write.acl.map(ObjectCannedAcl::from))
+            .set_checksum_algorithm(write.checksum_algorithm.map(ChecksumAlgorithm::from))
+            .set_expected_bucket_owner(write.expected_bucket_owner.map(str::to_string))
+            .set_request_payer(request_payer(write.requester_pays))
+            .send()
+            .await
+            .map_err(request_error)
+    })
+    .await?;
+
+    let Some(upload_id) = upload.upload_id else {
+        return Err(StorageError::Request(
+            "multipart upload was created without an upload ID".to_string(),
+        ));
+    };
+
+    // Aborts the upload when the upload is dropped before finishing (i.e by a timeout)
+    let abort_guard = MultipartAbortGuard {
+        client: client.clone(),
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        upload_id: upload_id.clone(),
+        expected_bucket_owner: write.expected_bucket_owner.map(str::to_string),
+        requester_pays: write.requester_pays,
+        armed: true,
+    };
+
+    let parts = stream::iter(1..=plan.part_count)
+        .map(|part_number| {
+            let offset = (part_number - 1) * plan.part_size;
+            let length = plan.part_size.min(plan.size - offset);
+            let upload_id = upload_id.as_str();
+
+            // The body is created for each attempt as it is consumed by the request
+            retry_request(bucket, key, move || async move {
+                let body = ByteStream::read_from()
+                    .path(path)
+                    .offset(offset)
+                    .length(Length::Exact(length))
+                    .build()
+                    .await
+                    .map_err(|err| StorageError::ReadBody(std::io::Error::other(err)))?;
+                let body = limit_upload(body);
+
+                let response = client
+                    .upload_part()
+                    .bucket(bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(part_number as i32)
+                    .content_length(length as i64)
+                    .body(body)
+                    .set_checksum_algorithm(write.checksum_algorithm.map(ChecksumAlgorithm::from))
+                    .set_expected_bucket_owner(write.expected_bucket_owner.map(str::to_string))
+                    .set_request_payer(request_payer(write.requester_pays))
+                    .send()
+                    .await
+                    .map_err(request_error)?;
+
+                Ok(CompletedPart::builder()
+                    .part_number(part_number as i32)
+                    .set_e_tag(response.e_tag)
+                    .set_checksum_crc32_c(response.checksum_crc32_c)
+                    .set_checksum_sha256(response.checksum_sha256)
+                    .build())
+            })
+        })
+        .buffer_unordered(plan.concurrency)
+        .try_collect::<Vec<CompletedPart>>()
+        .await;
+
+    let mut parts = match parts {
+        Ok(parts) => parts,
+        Err(err) => {
+            abort_guard.disarm();
+            abort_multipart(client, bucket, key, &upload_id, write).await;
+            return Err(err);
+        }
+    };
+
+    // Parts finish out of order but must be completed in order
+    parts.sort_by_key(|part| part.part_number);
+
+    let result = retry_request(bucket, key, || async {
+        client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts.clone()))
+                    .build(),
+            )
+            .set_expected_bucket_owner(write.expected_bucket_owner.map(str::to_string))
+            .set_request_payer(request_payer(write.requester_pays))
+            .set_if_match(options.if_match.map(str::to_string))
+            .send()
+            .await
+            .map_err(put_error)
+    })
+    .await;
+
+    abort_guard.disarm();
+
+    if let Err(err) = result {
+        abort_multipart(client, bucket, key, &upload_id, write).await;
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Aborts a multipart upload in the background when dropped while armed, so the
+/// parts of uploads that are dropped before finishing are not kept (and billed)
+struct MultipartAbortGuard {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    expected_bucket_owner: Option<String>,
+    requester_pays: bool,
+    armed: bool,
+}
+
+impl MultipartAbortGuard {
+    /// Disarm the guard once the upload has finished or is aborted by the caller
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for MultipartAbortGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let client = self.client.clone();
+        let bucket = std::mem::take(&mut self.bucket);
+        let key = std::mem::take(&mut self.key);
+        let upload_id = std::mem::take(&mut self.upload_id);
+        let expected_bucket_owner = self.expected_bucket_owner.take();
+        let requester_pays = self.requester_pays;
+
+        tracing::warn!(%bucket, %key, %upload_id, "multipart upload dropped, aborting upload");
+
+        tokio::spawn(async move {
+            let write = WriteOptions {
+                expected_bucket_owner: expected_bucket_owner.as_deref(),
+                requester_pays,
+                ..Default::default()
+            };
+            abort_multipart(&client, &bucket, &key, &upload_id, write).await;
+        });
+    }
+}
+
+/// Limit the rate the upload `body` is sent at when an upload rate limit is configured
+fn limit_upload(body: ByteStream) -> ByteStream {
+    match upload_limiter() {
+        Some(limiter) => ByteStream::new(limit_body(body.into_inner(), limiter)),
+        None => body,
+    }
+}
+
+/// Abort a failed multipart upload so its uploaded parts are not kept (and billed)
+async fn abort_multipart(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    write: WriteOptions<'_>,
+) {
+    let result = retry_request(bucket, key, || async {
+        client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .set_expected_bucket_owner(write.expected_bucket_owner.map(str::to_string))
+            .set_request_payer(request_payer(write.requester_pays))
+            .send()
+            .await
+            .map_err(request_error)
+    })
+    .await;
+
+    if let Err(err) = result {
+        tracing::warn!(?err, %bucket, %key, %upload_id, "failed to abort multipart upload");
+    }
+}
+
+/// Part size and concurrency of a multipart upload
+#[derive(Debug, Clone, Copy)]
+struct MultipartPlan {
+    /// Size of the uploaded file in bytes
+    size: u64,
+    /// Size of each part in bytes, the last part may be smaller
+    part_size: u64,
+    part_count: u64,
+    /// Number of parts uploaded concurrently
+    concurrency: usize,
+}
+
+impl MultipartPlan {
+    /// Plan the upload of a file of `size` bytes. The concurrency is increased from
+    /// the base concurrency when the upload would not finish within the `remaining`
+    /// time at the estimated throughput, up to `max_concurrency`. Parts are sized so
+    /// each concurrent upload is given a few parts within the S3 part limits
+    fn new(size: u64, remaining: Option<Duration>, max_concurrency: usize) -> MultipartPlan {
+        let mut concurrency = BASE_MULTIPART_CONCURRENCY;
+
+        if let Some(remaining) = remaining {
+            let throughput = ESTIMATED_UPLOAD_THROUGHPUT as f64 * remaining.as_secs_f64();
+            let needed = (size as f64 / throughput.max(1.0)).ceil() as usize;
+            concurrency = concurrency.max(needed);
+        }
+
+        let concurrency = concurrency.min(max_concurrency).max(1);
+
+        let part_size = size
+            .div_ceil(concurrency as u64 * PARTS_PER_UPLOAD)
+            .max(size.div_ceil(MAX_PARTS))
+            .clamp(MIN_PART_SIZE, MAX_PART_SIZE)
+            .next_multiple_of(PART_SIZE_ALIGNMENT);
+
+        let part_count = size.div_ceil(part_size).max(1);
+
+        MultipartPlan {
+            size,
+            part_size,
+            part_count,
+            concurrency: concurrency.min(part_count as usize),
+        }
+    }
+}
+
+/// Time remaining for uploads when running within [with_upload_deadline]
+fn upload_remaining() -> Option<Duration> {
+    UPLOAD_DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// Run the `future` with the `deadline` uploads within it must finish by (i.e the
+/// Lambda deadline), used to choose the concurrency of multipart uploads
+pub async fn with_upload_deadline<F: Future>(deadline: Instant, future: F) -> F::Output {
+    UPLOAD_DEADLINE.scope(deadline, future).await
+}
+
+/// Whether requests for the `bucket` use S3 Transfer Acceleration
+fn is_accelerated_bucket(bucket: &str) -> bool {
+    config_var(S3_ACCELERATE_BUCKETS_ENV).is_ok_and(|value| {
+        value
+            .split(',')
+            .any(|accelerated| accelerated.trim() == bucket)
+    })
+}
+
+impl Storage for S3Storage {
+    fn get_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<StorageObject, StorageError>> {
+        async move {
+            let client = self.bucket_client(bucket).await;
+
+            let response = retry_request(bucket, key, || async {
+                client
+                    .get_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .set_version_id(options.version_id.map(str::to_string))
+                    .set_if_match(options.if_match.map(str::to_string))
+                    .set_request_payer(request_payer(options.requester_pays))
+                    .send()
+                    .await
+                    .map_err(|err| {
+                        if err
+                            .as_service_error()
+                            .is_some_and(|value| value.is_no_such_key())
+                        {
+                            return StorageError::NoSuchKey;
+                        }
+
+                        match err.as_service_error().and_then(|value| value.code()) {
+                            Some(NO_SUCH_VERSION_CODE) => return StorageError::NoSuchVersion,
+                            Some(PRECONDITION_FAILED_CODE) => {
+                                return StorageError::PreconditionFailed;
+                            }
+                            _ => {}
+                        }
+
+                        request_error(err)
+                    })
+            })
+            .await?;
+
+            let content_length = response
+                .content_length()
+                .and_then(|value| u64::try_from(value).ok());
+
+            let body = stream::unfold(response.body, |mut body| async move {
+                let chunk = body.next().await?;
+                let chunk = chunk.map_err(|err| StorageError::Request(err.to_string()));
+                Some((chunk, body))
+            });
+
+            let body = match download_limiter() {
+                Some(limiter) => limit_stream(body, limiter),
+                None => Box::pin(body),
+            };
+
+            Ok(StorageObject {
+                content_length,
+                etag: response.e_tag,
+                body,
+            })
+        }
+        .boxed()
+    }
+
+    fn head_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<ObjectHead, StorageError>> {
+        async move {
+            let client = self.bucket_client(bucket).await;
+
+            let response = retry_request(bucket, key, || async {
+                client
+                    .head_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .set_version_id(options.version_id.map(str::to_string))
+                    .set_if_match(options.if_match.map(str::to_string))
+                    .set_request_payer(request_payer(options.requester_pays))
+                    .send()
+                    .await
+                    .map_err(|err| {
+                        if err
+                            .as_service_error()
+                            .is_some_and(|value| value.is_not_found())
+                        {
+                            return StorageError::NoSuchKey;
+                        }
+
+                        // HEAD responses have no body so the error is identified by its status
+                        if err
+                            .raw_response()
+                            .is_some_and(|response| response.status().as_u16() == 412)
+                        {
+                            return StorageError::PreconditionFailed;
+                        }
+
+                        request_error(err)
+                    })
+            })
+            .await?;
+
+            Ok(ObjectHead {
+                content_length: response
+                    .content_length()
+                    .and_then(|value| u64::try_from(value).ok()),
+                etag: response.e_tag,
+            })
+        }
+        .boxed()
+    }
+
+    fn put_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        body: PutBody<'a>,
+        options: PutOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            let client = self.bucket_client(bucket).await;
+
+            let tagging = options
+                .metadata
+                .filter(|metadata| !metadata.tags.is_empty())
+                .map(|metadata| {
+                    form_urlencoded::Serializer::new(String::new())
+                        .extend_pairs(&metadata.tags)
+                        .finish()
+                });
+
+            if let PutBody::File(path) = &body {
+                let size = tokio::fs::metadata(path)
+                    .await
+                    .map_err(StorageError::ReadBody)?
+                    .len();
+
+                let config = app_config();
+                if size >= config.s3_multipart_threshold {
+                    let plan = MultipartPlan::new(
+                        size,
+                        upload_remaining(),
+                        config.s3_multipart_max_concurrency,
+                    );
+                    return put_multipart(&client, bucket, key, path, plan, &options, tagging)
+                        .await;
+                }
+            }
+
+            // The body is created for each attempt as it is consumed by the request
+            retry_request(bucket, key, || async {
+                let body = match &body {
+                    PutBody::File(path) => ByteStream::from_path(path)
+                        .await
+                        .map_err(|err| StorageError::ReadBody(std::io::Error::other(err)))?,
+                    PutBody::Bytes(bytes) => ByteStream::from(bytes.clone()),
+                };
+                let body = limit_upload(body);
+
+                client
+                    .put_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .body(body)
+                    .set_content_type(options.content_type.map(str::to_string))
+                    .set_content_encoding(options.content_encoding.map(str::to_string))
+                    .set_metadata(options.metadata.map(|metadata| metadata.metadata.clone()))
+                    .set_tagging(tagging.clone())
+                    .set_storage_class(options.write.storage_class.map(StorageClass::from))
+                    .set_acl(options.write.acl.map(ObjectCannedAcl::from))
+                    .set_checksum_algorithm(
+                        options
+                            .write
+                            .checksum_algorithm
+                            .map(ChecksumAlgorithm::from),
+                    )
+                    .set_expected_bucket_owner(
+                        options.write.expected_bucket_owner.map(str::to_string),
+                    )
+                    .set_request_payer(request_payer(options.write.requester_pays))
+                    .set_if_match(options.if_match.map(str::to_string))
+                    .send()
+                    .await
+                    .map_err(put_error)?;
+
+                Ok(())
+            })
+            .await
+        }
+        .boxed()
+    }
+
+    fn copy_object<'a>(
+        &'a self,
+        source_bucket: &'a str,
+        source_key: &'a str,
+        dest_bucket: &'a str,
+        dest_key: &'a str,
+        options: WriteOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            let client = self.bucket_client(dest_bucket).await;
+
+            retry_request(dest_bucket, dest_key, || async {
+                client
+                    .copy_object()
+                    .copy_source(format!(
+                        "{source_bucket}/{}",
+                        encode_copy_source_key(source_key)
+                    ))
+                    .bucket(dest_bucket)
+                    .key(dest_key)
+                    .set_storage_class(options.storage_class.map(StorageClass::from))
+                    .set_acl(options.acl.map(ObjectCannedAcl::from))
+                    .set_checksum_algorithm(options.checksum_algorithm.map(ChecksumAlgorithm::from))
+                    .set_expected_bucket_owner(options.expected_bucket_owner.map(str::to_string))
+                    .set_request_payer(request_payer(options.requester_pays))
+                    .send()
+                    .await
+                    .map_err(request_error)?;
+
+                Ok(())
+            })
+            .await
+        }
+        .boxed()
+    }
+
+    fn get_object_metadata<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<ObjectMetadata, StorageError>> {
+        async move {
+            let client = self.bucket_client(bucket).await;
+
+            let head = retry_request(bucket, key, || async {
+                client
+                    .head_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .set_version_id(options.version_id.map(str::to_string))
+                    .set_if_match(options.if_match.map(str::to_string))
+                    .set_request_payer(request_payer(options.requester_pays))
+                    .send()
+                    .await
+                    .map_err(|err| {
+                        if err
+                            .as_service_error()
+                            .is_some_and(|value| value.is_not_found())
+                        {
+                            return StorageError::NoSuchKey;
+                        }
+
+                        // HEAD responses have no body so the error is identified by its status
+                        if err
+                            .raw_response()
+                            .is_some_and(|response| response.status().as_u16() == 412)
+                        {
+                            return StorageError::PreconditionFailed;
+                        }
+
+                        request_error(err)
+                    })
+            })
+            .await?;
+
+            let tagging = retry_request(bucket, key, || async {
+                client
+                    .get_object_tagging()
+                    .bucket(bucket)
+                    .key(key)
+                    .set_version_id(options.version_id.map(str::to_string))
+                    .set_request_payer(request_payer(options.requester_pays))
+                    .send()
+                    .await
+                    .map_err(request_error)
+            })
+            .await?;
+
+            Ok(ObjectMetadata {
+                metadata: head.metadata.unwrap_or_default(),
+                tags: tagging
+                    .tag_set()
+                    .iter()
+                    .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+                    .collect(),
+            })
+        }
+        .boxed()
+    }
+
+    fn delete_object<'a>(
+        &'a self,
+        bucket: &'a str,
+        key: &'a str,
+        options: DeleteOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            let client = self.bucket_client(bucket).await;
+
+            retry_request(bucket, key, || async {
+                client
+                    .delete_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .set_version_id(options.version_id.map(str::to_string))
+                    .set_if_match(options.if_match.map(str::to_string))
+                    .set_request_payer(request_payer(options.requester_pays))
+                    .send()
+                    .await
+                    .map_err(|err| {
+                        match err.as_service_error().and_then(|value| value.code()) {
+                            Some(NO_SUCH_VERSION_CODE) => return StorageError::NoSuchVersion,
+                            Some(PRECONDITION_FAILED_CODE) => {
+                                return StorageError::PreconditionFailed;
+                            }
+                            _ => {}
+                        }
+
+                        request_error(err)
+                    })
+            })
+            .await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Storage error for a failed S3 request, requests rejected with `SlowDown` (or
+/// a 503 for HEAD requests which have no error body) are reported as throttled.
+/// Network failures and other server errors are reported as unavailable
+fn request_error<E>(err: SdkError<E, HttpResponse>) -> StorageError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+{
+    let status = err
+        .raw_response()
+        .map(|response| response.status().as_u16());
+
+    if err.code() == Some(SLOW_DOWN_CODE) || status == Some(503) {
+        return StorageError::Throttled;
+    }
+
+    let unavailable = matches!(
+        err,
+        SdkError::DispatchFailure(_) | SdkError::TimeoutError(_) | SdkError::ResponseError(_)
+    ) || status.is_some_and(|status| status >= 500);
+
+    if unavailable {
+        return StorageError::Unavailable(err.to_string());
+    }
+
+    StorageError::Request(err.to_string())
+}
+
+/// Map the error of a write, conditional writes fail when the object no longer
+/// matches or a concurrent conditional write to the object is in progress
+fn put_error<E>(err: SdkError<E, HttpResponse>) -> StorageError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+{
+    match err.code() {
+        Some(PRECONDITION_FAILED_CODE | CONDITIONAL_REQUEST_CONFLICT_CODE) => {
+            StorageError::PreconditionFailed
+        }
+        _ => request_error(err),
+    }
+}
+
+/// Run the storage `request`, retrying throttled and unavailable requests using the
+/// retry policy of the conversion
+async fn retry_request<T, F, Fut>(bucket: &str, key: &str, request: F) -> Result<T, StorageError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, StorageError>>,
+{
+    let classify = |result: &Result<T, StorageError>| match result {
+        Err(StorageError::Throttled) => {
+            // Counted for the conversion when running within [count_throttled]
+            _ = THROTTLED_REQUESTS.try_with(|count| count.set(count.get() + 1));
+            Some(RetryClass::Throttled)
+        }
+        Err(StorageError::Unavailable(_)) => Some(RetryClass::Transient),
+        _ => None,
+    };
+
+    RetryPolicy::current()
+        .run("s3 request", classify, request)
+        .instrument(tracing::debug_span!("s3_request", %bucket, %key))
+        .await
+}
+
+/// Run the `future` counting the storage requests throttled by S3 within it,
+/// including throttled requests that were retried successfully
+pub async fn count_throttled<F: Future>(future: F) -> (F::Output, u64) {
+    THROTTLED_REQUESTS
+        .scope(Cell::new(0), async move {
+            let output = future.await;
+            (output, THROTTLED_REQUESTS.with(Cell::get))
+        })
+        .await
+}
+
+/// Request payer for requests that are charged to the requester
+fn request_payer(requester_pays: bool) -> Option<RequestPayer> {
+    requester_pays.then_some(RequestPayer::Requester)
+}
+
+/// URL encode an object key for use within a copy source, the `/`
+/// separators are left as is
+fn encode_copy_source_key(key: &str) -> String {
+    let mut output = String::with_capacity(key.len());
+
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                output.push(byte as char)
+            }
+            _ => output.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        BASE_MULTIPART_CONCURRENCY, MAX_PARTS, MIN_PART_SIZE, MultipartPlan, PART_SIZE_ALIGNMENT,
+    };
+
+    const MB: u64 = 1024 * 1024;
+
+    /// Check the parts of the `plan` cover the file within the S3 part limits
+    fn assert_valid_plan(plan: &MultipartPlan) {
+        assert!(plan.part_size >= MIN_PART_SIZE);
+        assert_eq!(plan.part_size % PART_SIZE_ALIGNMENT, 0);
+        assert!(plan.part_count <= MAX_PARTS);
+        assert!(plan.part_size * plan.part_count >= plan.size);
+        assert!(plan.part_size * (plan.part_count - 1) < plan.size);
+        assert!(plan.concurrency >= 1 && plan.concurrency as u64 <= plan.part_count);
+    }
+
+    #[test]
+    fn test_multipart_plan_small() {
+        let plan = MultipartPlan::new(10 * MB, None, 16);
+        assert_valid_plan(&plan);
+        assert_eq!(plan.part_size, MIN_PART_SIZE);
+        assert_eq!(plan.part_count, 2);
+        assert_eq!(plan.concurrency, 2);
+    }
+
+    #[test]
+    fn test_multipart_plan_large() {
+        let plan = MultipartPlan::new(200 * 1024 * MB + 1, None, 16);
+        assert_valid_plan(&plan);
+        assert_eq!(plan.concurrency, BASE_MULTIPART_CONCURRENCY);
+    }
+
+    #[test]
+    fn test_multipart_plan_deadline() {
+        // 1 GB at the estimated throughput needs 7 concurrent uploads to finish within 10s
+        let plan = MultipartPlan::new(1024 * MB, Some(Duration::from_secs(10)), 16);
+        assert_valid_plan(&plan);
+        assert_eq!(plan.concurrency, 7);
+
+        let plan = MultipartPlan::new(1024 * MB, Some(Duration::from_secs(10)), 5);
+        assert_valid_plan(&plan);
+        assert_eq!(plan.concurrency, 5);
+
+        // Plenty of time remaining keeps the base concurrency
+        let plan = MultipartPlan::new(1024 * MB, Some(Duration::from_secs(600)), 16);
+        assert_eq!(plan.concurrency, BASE_MULTIPART_CONCURRENCY);
+    }
+}
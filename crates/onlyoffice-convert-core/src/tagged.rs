@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use lopdf::{Document, Object};
+
+use crate::error::ConvertError;
+
+/// Check the PDF at `output_path` is tagged (has a structure tree and is marked as
+/// tagged), fails with `TAGGED_PDF_UNSUPPORTED` when the x2t install did not produce
+/// a tagged output so untagged outputs are never delivered as accessible
+pub async fn check_tagged_pdf(output_path: &Path) -> Result<(), ConvertError> {
+    let output_path = output_path.to_path_buf();
+
+    let tagged = tokio::task::spawn_blocking(move || {
+        let document = Document::load(&output_path)?;
+        Ok::<_, lopdf::Error>(is_tagged(&document))
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "tagged pdf check task failed");
+        check_error()
+    })?
+    .map_err(|err| {
+        tracing::error!(?err, "failed to read pdf output");
+        check_error()
+    })?;
+
+    if !tagged {
+        tracing::warn!("x2t did not produce a tagged pdf");
+
+        return Err(ConvertError {
+            reason: Some("TAGGED_PDF_UNSUPPORTED"),
+            x2t_code: None,
+            message: "tagged pdf output is not supported by the converter for this source"
+                .to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+fn is_tagged(document: &Document) -> bool {
+    let Ok(catalog) = document.catalog() else {
+        return false;
+    };
+
+    let marked = catalog
+        .get(b"MarkInfo")
+        .and_then(|value| document.dereference(value))
+        .and_then(|(_, value)| value.as_dict())
+        .and_then(|mark_info| mark_info.get(b"Marked"))
+        .and_then(Object::as_bool)
+        .unwrap_or(false);
+
+    marked && catalog.has(b"StructTreeRoot")
+}
+
+fn check_error() -> ConvertError {
+    ConvertError {
+        reason: Some("TAGGED_PDF_CHECK"),
+        x2t_code: None,
+        message: "failed to check pdf output is tagged".to_string(),
+    }
+}
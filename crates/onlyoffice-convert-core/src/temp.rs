@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use crate::config::app_config;
+
+/// Name of the directory conversion files are stored within
+const TEMP_DIR_NAME: &str = "onlyoffice-convert-server";
+
+/// Select the temporary directory to store the conversion files within, small sources
+/// are stored within the memory backed temp directory when one is configured
+pub fn select_temp_path(source_size: Option<u64>) -> PathBuf {
+    let config = app_config();
+    let disk_path = config.temp_dir.join(TEMP_DIR_NAME);
+
+    let Some(memory_dir) = &config.memory_temp_dir else {
+        return disk_path;
+    };
+
+    // Only sources with a known small size are stored in memory
+    if source_size.is_none_or(|size| size > config.memory_temp_max_size) {
+        return disk_path;
+    }
+
+    if !memory_dir.is_dir() {
+        tracing::warn!(path = %memory_dir.display(), "memory temp directory does not exist");
+        return disk_path;
+    }
+
+    memory_dir.join(TEMP_DIR_NAME)
+}
+
+/// Whether the memory backed temp directory is enabled
+pub fn is_memory_temp_enabled() -> bool {
+    app_config().memory_temp_dir.is_some()
+}
@@ -0,0 +1,436 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use serde_json::{Map, Value};
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{error::ConvertError, validate::escape_xml};
+
+/// Part of a DOCX that must exist for the source to be filled
+const DOCUMENT_PART: &str = "word/document.xml";
+
+const PLACEHOLDER_OPEN: &str = "{{";
+const PLACEHOLDER_CLOSE: &str = "}}";
+
+/// Tag closing a paragraph, placeholders cannot span paragraphs
+const PARAGRAPH_CLOSE: &str = "</w:p>";
+
+const TEXT_OPEN: &str = "<w:t";
+const TEXT_CLOSE: &str = "</w:t>";
+
+/// Replace the `{{key}}` placeholders within the DOCX at `input_path` using the
+/// template `data`, nested values are referenced by their dotted path (i.e
+/// `{{customer.name}}`). Placeholders without a matching value are left as is.
+/// The filled document is written to `filled_path` before replacing the input
+pub async fn fill_template(
+    input_path: &Path,
+    filled_path: &Path,
+    data: &Map<String, Value>,
+) -> Result<(), ConvertError> {
+    let input_path = input_path.to_path_buf();
+    let filled_path = filled_path.to_path_buf();
+    let data = data.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let file = File::open(&input_path).map_err(|err| {
+            tracing::error!(?err, "failed to open template source");
+            template_error()
+        })?;
+
+        let mut archive = ZipArchive::new(file)
+            .ok()
+            .filter(|archive| archive.index_for_name(DOCUMENT_PART).is_some())
+            .ok_or_else(|| ConvertError {
+                reason: Some("TEMPLATE_INVALID_SOURCE"),
+                x2t_code: None,
+                message: "template_data is only supported for DOCX sources".to_string(),
+            })?;
+
+        write_filled(&mut archive, &filled_path, &data).map_err(|err| {
+            tracing::error!(?err, "failed to fill template");
+            template_error()
+        })?;
+
+        std::fs::rename(&filled_path, &input_path).map_err(|err| {
+            tracing::error!(?err, "failed to replace source with filled template");
+            template_error()
+        })
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "template task failed");
+        template_error()
+    })?
+}
+
+/// Copy the `archive` to the `filled_path` filling the placeholders of the text parts
+fn write_filled(
+    archive: &mut ZipArchive<File>,
+    filled_path: &Path,
+    data: &Map<String, Value>,
+) -> zip::result::ZipResult<()> {
+    let mut writer = ZipWriter::new(File::create(filled_path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+
+        if !is_text_part(file.name()) {
+            writer.raw_copy_file(file)?;
+            continue;
+        }
+
+        let name = file.name().to_string();
+        let mut xml = String::new();
+        file.read_to_string(&mut xml)?;
+
+        writer.start_file(name, options)?;
+        writer.write_all(fill_part(&xml, data).as_bytes())?;
+    }
+
+    writer.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Whether the DOCX part contains document text that placeholders are filled within
+fn is_text_part(name: &str) -> bool {
+    name == DOCUMENT_PART
+        || name == "word/footnotes.xml"
+        || name == "word/endnotes.xml"
+        || ((name.starts_with("word/header") || name.starts_with("word/footer"))
+            && name.ends_with(".xml"))
+}
+
+/// Fill the placeholders within each paragraph of a part
+fn fill_part(xml: &str, data: &Map<String, Value>) -> String {
+    let mut output = String::with_capacity(xml.len());
+    let mut rest = xml;
+
+    while let Some(index) = rest.find(PARAGRAPH_CLOSE) {
+        let (paragraph, after) = rest.split_at(index + PARAGRAPH_CLOSE.len());
+        output.push_str(&fill_paragraph(paragraph, data));
+        rest = after;
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Location of a `<w:t>` text element within a paragraph
+struct TextNode {
+    start: usize,
+    content_start: usize,
+    content_end: usize,
+    end: usize,
+}
+
+/// Fill the placeholders of a paragraph, Word commonly splits the text of a single
+/// placeholder across multiple runs so placeholders are found within the combined
+/// text of the paragraph and the value is written to the run the placeholder starts in
+fn fill_paragraph(xml: &str, data: &Map<String, Value>) -> String {
+    let nodes = find_text_nodes(xml);
+
+    // Combined text of the paragraph and the node that owns each byte of it
+    let mut text = String::new();
+    let mut owners = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        let value = unescape_xml(&xml[node.content_start..node.content_end]);
+        owners.extend(std::iter::repeat_n(index, value.len()));
+        text.push_str(&value);
+    }
+
+    let placeholders = find_placeholders(&text, data);
+    if placeholders.is_empty() {
+        return xml.to_string();
+    }
+
+    let mut node_texts = vec![String::new(); nodes.len()];
+    let mut position = 0;
+
+    for (start, end, value) in placeholders {
+        push_owned(&text, &owners, position, start, &mut node_texts);
+        node_texts[owners[start]].push_str(&value);
+        position = end;
+    }
+
+    push_owned(&text, &owners, position, text.len(), &mut node_texts);
+
+    let mut output = String::with_capacity(xml.len());
+    let mut last = 0;
+
+    for (node, node_text) in nodes.iter().zip(node_texts) {
+        output.push_str(&xml[last..node.start]);
+        output.push_str(r#"<w:t xml:space="preserve">"#);
+        output.push_str(&escape_xml(&node_text));
+        output.push_str(TEXT_CLOSE);
+        last = node.end;
+    }
+
+    output.push_str(&xml[last..]);
+    output
+}
+
+/// Push the text between `start` and `end` to the nodes that own it
+fn push_owned(text: &str, owners: &[usize], start: usize, end: usize, node_texts: &mut [String]) {
+    for (offset, char) in text[start..end].char_indices() {
+        node_texts[owners[start + offset]].push(char);
+    }
+}
+
+/// Find the `<w:t>` elements within the XML
+fn find_text_nodes(xml: &str) -> Vec<TextNode> {
+    let mut nodes = Vec::new();
+    let mut position = 0;
+
+    while let Some(offset) = xml[position..].find(TEXT_OPEN) {
+        let start = position + offset;
+        let after_name = start + TEXT_OPEN.len();
+        position = after_name;
+
+        // Skip other elements sharing the prefix (i.e <w:tab/> or <w:tbl>)
+        if !matches!(xml.as_bytes().get(after_name), Some(b'>' | b' ')) {
+            continue;
+        }
+
+        let Some(tag_end) = xml[after_name..].find('>').map(|index| after_name + index) else {
+            break;
+        };
+
+        // Self closing elements have no text
+        if xml.as_bytes()[tag_end - 1] == b'/' {
+            continue;
+        }
+
+        let content_start = tag_end + 1;
+        let Some(content_end) = xml[content_start..]
+            .find(TEXT_CLOSE)
+            .map(|index| content_start + index)
+        else {
+            break;
+        };
+
+        let end = content_end + TEXT_CLOSE.len();
+        nodes.push(TextNode {
+            start,
+            content_start,
+            content_end,
+            end,
+        });
+        position = end;
+    }
+
+    nodes
+}
+
+/// Find the placeholders with a value in the `data`, returns the start and end
+/// of each placeholder within the `text` along with its value
+fn find_placeholders(text: &str, data: &Map<String, Value>) -> Vec<(usize, usize, String)> {
+    let mut placeholders = Vec::new();
+    let mut position = 0;
+
+    while let Some(offset) = text[position..].find(PLACEHOLDER_OPEN) {
+        let start = position + offset;
+        let key_start = start + PLACEHOLDER_OPEN.len();
+
+        let Some(key_end) = text[key_start..]
+            .find(PLACEHOLDER_CLOSE)
+            .map(|index| key_start + index)
+        else {
+            break;
+        };
+
+        let end = key_end + PLACEHOLDER_CLOSE.len();
+
+        match lookup_value(data, text[key_start..key_end].trim()) {
+            Some(value) => {
+                placeholders.push((start, end, value));
+                position = end;
+            }
+            None => position = key_start,
+        }
+    }
+
+    placeholders
+}
+
+/// Get the text of the value at the dotted `key` path, arrays and objects have no text
+fn lookup_value(data: &Map<String, Value>, key: &str) -> Option<String> {
+    let mut parts = key.split('.');
+    let mut value = data.get(parts.next()?)?;
+
+    for part in parts {
+        value = value.as_object()?.get(part)?;
+    }
+
+    match value {
+        Value::Null => Some(String::new()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::String(value) => Some(value.clone()),
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+/// Unescape the text content of an XML element
+pub fn unescape_xml(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(index) = rest.find('&') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let decoded = entity.and_then(|(name, end)| {
+            let char = match name {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = match name.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => name.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((char, end))
+        });
+
+        match decoded {
+            Some((char, end)) => {
+                output.push(char);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn template_error() -> ConvertError {
+    ConvertError {
+        reason: Some("FILL_TEMPLATE"),
+        x2t_code: None,
+        message: "failed to fill template".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Map, Value, json};
+
+    use super::{fill_part, lookup_value, unescape_xml};
+
+    fn data(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn test_lookup_value() {
+        let data = data(json!({
+            "name": "Name",
+            "customer": { "address": { "city": "City" }, "id": 42, "active": true },
+            "empty": null,
+            "items": [1, 2]
+        }));
+
+        assert_eq!(lookup_value(&data, "name").as_deref(), Some("Name"));
+        assert_eq!(
+            lookup_value(&data, "customer.address.city").as_deref(),
+            Some("City")
+        );
+        assert_eq!(lookup_value(&data, "customer.id").as_deref(), Some("42"));
+        assert_eq!(
+            lookup_value(&data, "customer.active").as_deref(),
+            Some("true")
+        );
+        assert_eq!(lookup_value(&data, "empty").as_deref(), Some(""));
+
+        // Missing paths, objects and arrays have no text
+        assert_eq!(lookup_value(&data, "customer.missing"), None);
+        assert_eq!(lookup_value(&data, "name.length"), None);
+        assert_eq!(lookup_value(&data, "customer.address"), None);
+        assert_eq!(lookup_value(&data, "items"), None);
+    }
+
+    #[test]
+    fn test_fill_placeholder() {
+        let data = data(json!({ "customer": { "name": "Name" } }));
+        let xml = "<w:p><w:r><w:t>Hello {{ customer.name }}!</w:t></w:r></w:p>";
+
+        assert_eq!(
+            fill_part(xml, &data),
+            r#"<w:p><w:r><w:t xml:space="preserve">Hello Name!</w:t></w:r></w:p>"#
+        );
+    }
+
+    #[test]
+    fn test_fill_placeholder_split_across_runs() {
+        let data = data(json!({ "customer": { "name": "Name" } }));
+        let xml = concat!(
+            "<w:p><w:r><w:t>Hello {{cust</w:t></w:r>",
+            "<w:r><w:rPr><w:b/></w:rPr><w:t>omer.</w:t></w:r>",
+            "<w:r><w:tab/><w:t xml:space=\"preserve\">name}}!</w:t></w:r></w:p>"
+        );
+
+        // The value is written to the run the placeholder starts in
+        assert_eq!(
+            fill_part(xml, &data),
+            concat!(
+                "<w:p><w:r><w:t xml:space=\"preserve\">Hello Name</w:t></w:r>",
+                "<w:r><w:rPr><w:b/></w:rPr><w:t xml:space=\"preserve\"></w:t></w:r>",
+                "<w:r><w:tab/><w:t xml:space=\"preserve\">!</w:t></w:r></w:p>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_fill_placeholder_escaped() {
+        let data = data(json!({ "company": "A & B <C>" }));
+        let xml = "<w:p><w:r><w:t>{{company}} &amp; &#233;</w:t></w:r></w:p>";
+
+        assert_eq!(
+            fill_part(xml, &data),
+            r#"<w:p><w:r><w:t xml:space="preserve">A &amp; B &lt;C&gt; &amp; é</w:t></w:r></w:p>"#
+        );
+    }
+
+    #[test]
+    fn test_fill_placeholder_unmatched() {
+        let data = data(json!({ "name": "Name", "items": [1] }));
+
+        // Placeholders without a value are left as is
+        for xml in [
+            "<w:p><w:r><w:t>{{missing}} {{items}}</w:t></w:r></w:p>",
+            "<w:p><w:r><w:t>{{name</w:t></w:r></w:p>",
+            // Placeholders cannot span paragraphs
+            "<w:p><w:r><w:t>{{na</w:t></w:r></w:p><w:p><w:r><w:t>me}}</w:t></w:r></w:p>",
+        ] {
+            assert_eq!(fill_part(xml, &data), xml);
+        }
+    }
+
+    #[test]
+    fn test_unescape_xml() {
+        assert_eq!(unescape_xml("&lt;a&gt; &amp; &quot;b&apos;"), "<a> & \"b'");
+        assert_eq!(unescape_xml("&#65;&#x42;"), "AB");
+
+        // Unknown and unterminated entities are kept
+        assert_eq!(unescape_xml("&unknown; & &#xZZ;"), "&unknown; & &#xZZ;");
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "dynamodb")]
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Deserialize;
+#[cfg(feature = "http-client")]
+use serde::Serialize;
+#[cfg(feature = "dynamodb")]
+use tokio::sync::OnceCell;
+
+#[cfg(feature = "dynamodb")]
+use crate::aws::aws_config;
+#[cfg(not(all(feature = "dynamodb", feature = "http-client")))]
+use crate::config_check::feature_disabled_error;
+use crate::{config::config_var, error::ConvertError, format::OutputFormat, quota::TenantQuota};
+#[cfg(feature = "http-client")]
+use crate::{
+    proxy::http_client_builder,
+    retry::{RetryClass, RetryPolicy},
+};
+
+/// Environment variable for the settings of each tenant as a JSON object keyed
+/// by the tenant ID, typically provided through the SSM configuration
+const TENANTS_ENV: &str = "TENANTS";
+
+/// Environment variable for the DynamoDB table tenant settings are loaded from when
+/// the tenant is not within [TENANTS_ENV]. The table must have a string `tenant_id`
+/// partition key, with the settings stored as a JSON string `settings` attribute
+pub const TENANTS_TABLE_ENV: &str = "TENANTS_TABLE";
+
+/// Environment variable mapping IAM caller ARNs to the tenant their HTTP requests are
+/// made on behalf of as a JSON object, used when JWT authorization is not enabled
+const TENANT_CALLERS_ENV: &str = "TENANT_CALLERS";
+
+/// Duration tenant settings loaded from the table are reused for
+const TENANT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Time allowed for the tenant callback endpoint to respond
+#[cfg(feature = "http-client")]
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client for the tenants table, cached across warm invocations
+#[cfg(feature = "dynamodb")]
+static DYNAMODB_CLIENT: OnceCell<aws_sdk_dynamodb::Client> = OnceCell::const_new();
+
+/// Settings of a tenant along with the time they were loaded
+type CachedTenant = (Instant, Arc<TenantSettings>);
+
+/// Tenant settings loaded from the table keyed by tenant ID, cached across warm invocations
+static TENANT_CACHE: OnceLock<Mutex<HashMap<String, CachedTenant>>> = OnceLock::new();
+
+/// Whether requests are required to specify a tenant
+pub fn is_tenancy_enabled() -> bool {
+    [TENANTS_ENV, TENANTS_TABLE_ENV]
+        .into_iter()
+        .any(|key| config_var(key).is_ok_and(|value| !value.is_empty()))
+}
+
+/// Settings restricting the requests of a tenant, restrictions that are
+/// not provided allow any value
+#[derive(Debug, Default, Deserialize)]
+pub struct TenantSettings {
+    /// Buckets the tenant may read sources from and write outputs to
+    #[serde(default)]
+    pub allowed_buckets: Option<Vec<String>>,
+    /// Formats the tenant may convert into
+    #[serde(default)]
+    pub allowed_formats: Option<Vec<OutputFormat>>,
+    /// Font profiles the tenant may render with
+    #[serde(default)]
+    pub font_profiles: Option<Vec<String>>,
+    /// Maximum size of the source file in bytes
+    #[serde(default)]
+    pub max_source_size: Option<u64>,
+    /// Conversions and source bytes allowed for the tenant within each period
+    #[serde(default)]
+    pub quota: Option<TenantQuota>,
+    /// Endpoint notified with the outcome of each conversion of the tenant
+    #[serde(default)]
+    pub callback_url: Option<String>,
+}
+
+impl TenantSettings {
+    /// Check the buckets, formats and font profile of the request are allowed for the tenant
+    pub fn check_request<'a>(
+        &self,
+        buckets: impl IntoIterator<Item = (&'static str, &'a str)>,
+        formats: &[OutputFormat],
+        font_profile: Option<&str>,
+    ) -> Result<(), ConvertError> {
+        if let Some(allowed_buckets) = &self.allowed_buckets {
+            for (field, bucket) in buckets {
+                if !allowed_buckets.iter().any(|value| value == bucket) {
+                    return Err(not_allowed_error(field));
+                }
+            }
+        }
+
+        if let Some(allowed_formats) = &self.allowed_formats
+            && formats
+                .iter()
+                .any(|format| !allowed_formats.contains(format))
+        {
+            return Err(not_allowed_error("output_format"));
+        }
+
+        if let (Some(font_profiles), Some(font_profile)) = (&self.font_profiles, font_profile)
+            && !font_profiles.iter().any(|value| value == font_profile)
+        {
+            return Err(not_allowed_error("font_profile"));
+        }
+
+        Ok(())
+    }
+
+    /// Check the size of the source file is within the tenant limit
+    pub fn check_source_size(&self, size: u64) -> Result<(), ConvertError> {
+        match self.max_source_size {
+            Some(max_source_size) if size > max_source_size => {
+                tracing::warn!(size, max_source_size, "source exceeds tenant size limit");
+
+                Err(ConvertError {
+                    reason: Some("SOURCE_TOO_LARGE"),
+                    x2t_code: None,
+                    message: format!(
+                        "source file exceeds the maximum size of {max_source_size} bytes"
+                    ),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Tenant the IAM `caller` is mapped to within [TENANT_CALLERS_ENV], [None] when
+/// the caller is not mapped to a tenant
+pub fn caller_tenant(caller: &str) -> Result<Option<String>, ConvertError> {
+    let callers = match config_var(TENANT_CALLERS_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        _ => return Ok(None),
+    };
+
+    let mut callers: HashMap<String, String> = serde_json::from_str(&callers).map_err(|err| {
+        tracing::error!(?err, "invalid tenant callers configuration");
+        tenant_error()
+    })?;
+
+    Ok(callers.remove(caller))
+}
+
+/// Load the settings of the tenant with the `tenant_id`, fails with `UNKNOWN_TENANT`
+/// when the tenant is not configured
+pub async fn load_tenant(tenant_id: &str) -> Result<Arc<TenantSettings>, ConvertError> {
+    if let Ok(tenants) = config_var(TENANTS_ENV)
+        && !tenants.is_empty()
+    {
+        let mut tenants: HashMap<String, TenantSettings> =
+            serde_json::from_str(&tenants).map_err(|err| {
+                tracing::error!(?err, "invalid tenants configuration");
+                tenant_error()
+            })?;
+
+        if let Some(settings) = tenants.remove(tenant_id) {
+            check_settings_features(&settings)?;
+            return Ok(Arc::new(settings));
+        }
+    }
+
+    let table = match config_var(TENANTS_TABLE_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        _ => return Err(unknown_tenant_error()),
+    };
+
+    let cache = TENANT_CACHE.get_or_init(Default::default);
+
+    if let Some((loaded_at, settings)) = cache
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(tenant_id)
+        && loaded_at.elapsed() < TENANT_CACHE_TTL
+    {
+        return Ok(settings.clone());
+    }
+
+    let settings = get_tenant_item(&table, tenant_id).await?;
+    check_settings_features(&settings)?;
+    let settings = Arc::new(settings);
+
+    cache
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(tenant_id.to_string(), (Instant::now(), settings.clone()));
+
+    Ok(settings)
+}
+
+/// Check the integrations the tenant settings rely on are compiled in, as the
+/// quota would otherwise fail every conversion and callbacks would never be sent
+fn check_settings_features(settings: &TenantSettings) -> Result<(), ConvertError> {
+    #[cfg(not(feature = "dynamodb"))]
+    if settings.quota.is_some() {
+        return Err(feature_disabled_error("quota", "dynamodb"));
+    }
+
+    #[cfg(not(feature = "http-client"))]
+    if settings.callback_url.is_some() {
+        return Err(feature_disabled_error("callback_url", "http-client"));
+    }
+
+    _ = settings;
+    Ok(())
+}
+
+/// Read the settings of a tenant from the tenants table
+#[cfg(feature = "dynamodb")]
+async fn get_tenant_item(table: &str, tenant_id: &str) -> Result<TenantSettings, ConvertError> {
+    let response = DYNAMODB_CLIENT
+        .get_or_init(|| async { aws_sdk_dynamodb::Client::new(&aws_config().await) })
+        .await
+        .get_item()
+        .table_name(table)
+        .key("tenant_id", AttributeValue::S(tenant_id.to_string()))
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %tenant_id, "failed to get tenant");
+            tenant_error()
+        })?;
+
+    let item = response.item.ok_or_else(unknown_tenant_error)?;
+
+    match item.get("settings") {
+        Some(AttributeValue::S(settings)) => serde_json::from_str(settings).map_err(|err| {
+            tracing::error!(?err, %tenant_id, "invalid tenant settings");
+            tenant_error()
+        }),
+        // Tenants without settings are unrestricted
+        _ => Ok(TenantSettings::default()),
+    }
+}
+
+#[cfg(not(feature = "dynamodb"))]
+async fn get_tenant_item(_table: &str, _tenant_id: &str) -> Result<TenantSettings, ConvertError> {
+    Err(feature_disabled_error(TENANTS_TABLE_ENV, "dynamodb"))
+}
+
+/// Body of the tenant callback
+#[cfg(feature = "http-client")]
+#[derive(Serialize)]
+struct TenantCallback<'a> {
+    request_id: &'a str,
+    tenant: &'a str,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a ConvertError>,
+}
+
+/// Notify the tenant callback endpoint of the outcome of a conversion, throttled and
+/// failed deliveries are retried using the retry policy of the conversion. Failures
+/// are logged as the conversion has already completed
+#[cfg(feature = "http-client")]
+pub async fn send_tenant_callback<T>(
+    callback_url: &str,
+    request_id: &str,
+    tenant: &str,
+    result: &Result<T, ConvertError>,
+) {
+    let body = TenantCallback {
+        request_id,
+        tenant,
+        success: result.is_ok(),
+        error: result.as_ref().err(),
+    };
+
+    let body = match serde_json::to_vec(&body) {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "failed to serialize tenant callback");
+            return;
+        }
+    };
+
+    let client = match http_client_builder().timeout(CALLBACK_TIMEOUT).build() {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "failed to create tenant callback client");
+            return;
+        }
+    };
+
+    let classify = |response: &Result<reqwest::Response, reqwest::Error>| match response {
+        Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            Some(RetryClass::Throttled)
+        }
+        Ok(response) if response.status().is_server_error() => Some(RetryClass::Transient),
+        Ok(_) => None,
+        Err(_) => Some(RetryClass::Transient),
+    };
+
+    let response = RetryPolicy::current()
+        .run("tenant callback", classify, || {
+            client
+                .post(callback_url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+        })
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => {}
+        Ok(response) => {
+            tracing::warn!(status = %response.status(), %tenant, "tenant callback failed");
+        }
+        Err(err) => tracing::warn!(?err, %tenant, "failed to send tenant callback"),
+    }
+}
+
+#[cfg(not(feature = "http-client"))]
+pub async fn send_tenant_callback<T>(
+    _callback_url: &str,
+    _request_id: &str,
+    tenant: &str,
+    _result: &Result<T, ConvertError>,
+) {
+    tracing::error!(%tenant, "tenant callbacks require the http-client feature");
+}
+
+fn not_allowed_error(field: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("TENANT_NOT_ALLOWED"),
+        x2t_code: None,
+        message: format!("{field}: not allowed for tenant"),
+    }
+}
+
+fn unknown_tenant_error() -> ConvertError {
+    ConvertError {
+        reason: Some("UNKNOWN_TENANT"),
+        x2t_code: None,
+        message: "tenant: unknown tenant".to_string(),
+    }
+}
+
+fn tenant_error() -> ConvertError {
+    ConvertError {
+        reason: Some("TENANT_CONFIG"),
+        x2t_code: None,
+        message: "failed to load tenant".to_string(),
+    }
+}
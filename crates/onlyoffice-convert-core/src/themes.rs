@@ -0,0 +1,183 @@
+use std::{path::PathBuf, sync::OnceLock};
+
+use futures::{StreamExt, TryStreamExt, stream};
+
+use crate::{
+    aws::aws_config,
+    config::{app_config, config_var},
+    error::ConvertError,
+};
+
+/// Environment variable for the bucket containing the presentation themes bundle,
+/// the themes are only synced when this is set
+const THEMES_BUCKET_ENV: &str = "THEMES_BUCKET";
+
+/// Environment variable for the key prefix the themes bundle is stored under
+const THEMES_PREFIX_ENV: &str = "THEMES_PREFIX";
+
+const DEFAULT_THEMES_PREFIX: &str = "themes/";
+
+/// Name of the directory the themes are synced into
+const SYNCED_THEMES_DIR_NAME: &str = "onlyoffice-themes";
+
+/// Number of theme files downloaded at once
+const THEME_DOWNLOAD_CONCURRENCY: usize = 8;
+
+/// Directory containing the synced themes, set once the themes are synced
+static SYNCED_THEMES_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Directory x2t should load presentation themes from, the synced themes directory
+/// when a themes bundle is configured otherwise the x2t themes directory. [None]
+/// when neither is available, x2t then renders presentations without their themes
+pub fn themes_path() -> Option<PathBuf> {
+    if let Some(path) = SYNCED_THEMES_PATH.get() {
+        return Some(path.clone());
+    }
+
+    let path = &app_config().x2t_themes_path;
+
+    path.is_dir().then(|| path.clone())
+}
+
+/// Download the themes bundle from the configured S3 prefix into a temporary
+/// directory, called once at cold start before any requests are handled (When enabled)
+pub async fn sync_themes() -> Result<(), ConvertError> {
+    let bucket = match config_var(THEMES_BUCKET_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        // Themes bundle is not enabled
+        _ => return Ok(()),
+    };
+
+    let prefix =
+        config_var(THEMES_PREFIX_ENV).unwrap_or_else(|_| DEFAULT_THEMES_PREFIX.to_string());
+
+    let themes_path = app_config().temp_dir.join(SYNCED_THEMES_DIR_NAME);
+
+    if themes_path.exists() {
+        tokio::fs::remove_dir_all(&themes_path)
+            .await
+            .map_err(sync_error)?;
+    }
+
+    tokio::fs::create_dir_all(&themes_path)
+        .await
+        .map_err(sync_error)?;
+
+    let aws_config = aws_config().await;
+    let s3_client = aws_sdk_s3::Client::new(&aws_config);
+
+    let mut pages = s3_client
+        .list_objects_v2()
+        .bucket(&bucket)
+        .prefix(&prefix)
+        .into_paginator()
+        .send();
+
+    let mut keys: Vec<String> = Vec::new();
+
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            tracing::error!(?err, "failed to list themes");
+            ConvertError {
+                reason: Some("SYNC_THEMES"),
+                x2t_code: None,
+                message: "failed to list themes".to_string(),
+            }
+        })?;
+
+        keys.extend(
+            page.contents()
+                .iter()
+                .filter_map(|object| object.key.clone()),
+        );
+    }
+
+    // Theme paths within the themes directory
+    let themes: Vec<(String, PathBuf)> = keys
+        .into_iter()
+        .filter_map(|key| {
+            let path = themes_path.join(theme_relative_path(&key, &prefix)?);
+            Some((key, path))
+        })
+        .collect();
+
+    tracing::debug!(count = themes.len(), %bucket, %prefix, "syncing themes");
+
+    stream::iter(themes)
+        .map(|(key, path)| download_theme(&s3_client, &bucket, key, path))
+        .buffer_unordered(THEME_DOWNLOAD_CONCURRENCY)
+        .try_collect::<Vec<()>>()
+        .await?;
+
+    _ = SYNCED_THEMES_PATH.set(themes_path);
+
+    Ok(())
+}
+
+/// Get the path of a theme file relative to the themes prefix, [None] for
+/// directory markers and keys that would escape the themes directory
+fn theme_relative_path<'a>(key: &'a str, prefix: &str) -> Option<&'a str> {
+    let relative = key.strip_prefix(prefix)?.trim_start_matches('/');
+
+    if relative.is_empty()
+        || relative.ends_with('/')
+        || relative
+            .split(['/', '\\'])
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return None;
+    }
+
+    Some(relative)
+}
+
+/// Download a single theme file to disk
+async fn download_theme(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: String,
+    path: PathBuf,
+) -> Result<(), ConvertError> {
+    let response = s3_client
+        .get_object()
+        .bucket(bucket)
+        .key(&key)
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %key, "failed to download theme");
+            ConvertError {
+                reason: Some("SYNC_THEMES"),
+                x2t_code: None,
+                message: "failed to download theme".to_string(),
+            }
+        })?;
+
+    let bytes = response.body.collect().await.map_err(|err| {
+        tracing::error!(?err, %key, "failed to read theme");
+        ConvertError {
+            reason: Some("SYNC_THEMES"),
+            x2t_code: None,
+            message: "failed to download theme".to_string(),
+        }
+    })?;
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(sync_error)?;
+    }
+
+    tokio::fs::write(&path, bytes.into_bytes())
+        .await
+        .map_err(sync_error)
+}
+
+fn sync_error(err: std::io::Error) -> ConvertError {
+    tracing::error!(?err, "failed to setup themes directory");
+    ConvertError {
+        reason: Some("SYNC_THEMES"),
+        x2t_code: None,
+        message: "failed to setup themes directory".to_string(),
+    }
+}
@@ -0,0 +1,225 @@
+#[cfg(feature = "http-client")]
+use std::time::Duration;
+
+use cms::{content_info::ContentInfo, signed_data::SignedData};
+use der::{
+    Any, Decode, Encode, Reader, Sequence, SliceReader, Tag, Tagged,
+    asn1::{BitString, GeneralizedTime, Int, ObjectIdentifier, OctetString, Uint},
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use x509_cert::spki::AlgorithmIdentifierOwned;
+
+#[cfg(not(feature = "http-client"))]
+use crate::config_check::feature_disabled_error;
+#[cfg(feature = "http-client")]
+use crate::proxy::http_client_builder;
+use crate::{config::config_var, error::ConvertError};
+
+/// Environment variable for the URL of the RFC 3161 time stamp authority (TSA)
+/// signatures are timestamped by, timestamping is only available when this is set
+pub const TSA_URL_ENV: &str = "SIGNING_TSA_URL";
+
+/// Time allowed for the time stamp authority to respond
+#[cfg(feature = "http-client")]
+const TSA_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[cfg(feature = "http-client")]
+const TIMESTAMP_QUERY_CONTENT_TYPE: &str = "application/timestamp-query";
+
+const ID_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+const ID_TST_INFO: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+
+/// PKI status values of a response that include a token
+const STATUS_GRANTED: u8 = 0;
+const STATUS_GRANTED_WITH_MODS: u8 = 1;
+
+/// Whether timestamping signatures is available
+pub fn is_timestamping_enabled() -> bool {
+    config_var(TSA_URL_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+/// `MessageImprint` from RFC 3161
+#[derive(Sequence)]
+struct MessageImprint {
+    hash_algorithm: AlgorithmIdentifierOwned,
+    hashed_message: OctetString,
+}
+
+/// `TimeStampReq` from RFC 3161, the policy of the TSA is used
+#[derive(Sequence)]
+struct TimeStampReq {
+    version: u8,
+    message_imprint: MessageImprint,
+    nonce: Uint,
+    /// Request the TSA certificate is included so the token can be validated
+    cert_req: bool,
+}
+
+/// `TimeStampResp` from RFC 3161
+#[derive(Sequence)]
+struct TimeStampResp {
+    status: PkiStatusInfo,
+    #[asn1(optional = "true")]
+    time_stamp_token: Option<ContentInfo>,
+}
+
+/// `PKIStatusInfo` from RFC 3161
+#[derive(Sequence)]
+struct PkiStatusInfo {
+    status: u8,
+    #[asn1(optional = "true")]
+    status_string: Option<Vec<String>>,
+    #[asn1(optional = "true")]
+    fail_info: Option<BitString>,
+}
+
+/// Request a time stamp token over the `signature` value from the configured
+/// time stamp authority, returns the token (A CMS `ContentInfo`) to embed as the
+/// signature time stamp of the signer
+pub async fn request_timestamp(signature: &[u8]) -> Result<Any, ConvertError> {
+    let tsa_url = config_var(TSA_URL_ENV).map_err(|_| {
+        tracing::error!("time stamp authority is not configured");
+        timestamp_error()
+    })?;
+
+    let hashed_message = Sha256::digest(signature);
+    let nonce = Uint::new(&Uuid::new_v4().as_bytes()[..8]).map_err(encode_error)?;
+
+    let request = TimeStampReq {
+        version: 1,
+        message_imprint: MessageImprint {
+            hash_algorithm: AlgorithmIdentifierOwned {
+                oid: ID_SHA256,
+                parameters: None,
+            },
+            hashed_message: OctetString::new(hashed_message.to_vec()).map_err(encode_error)?,
+        },
+        nonce: nonce.clone(),
+        cert_req: true,
+    }
+    .to_der()
+    .map_err(encode_error)?;
+
+    let body = post_timestamp_query(&tsa_url, request).await?;
+
+    let response = TimeStampResp::from_der(&body).map_err(|err| {
+        tracing::error!(?err, "failed to parse timestamp response");
+        timestamp_error()
+    })?;
+
+    let status = response.status;
+    let token = match response.time_stamp_token {
+        Some(token)
+            if status.status == STATUS_GRANTED || status.status == STATUS_GRANTED_WITH_MODS =>
+        {
+            token
+        }
+        _ => {
+            tracing::error!(
+                status = status.status,
+                status_string = ?status.status_string,
+                fail_info = ?status.fail_info,
+                "timestamp request was rejected"
+            );
+            return Err(timestamp_error());
+        }
+    };
+
+    // The token must be for this request, protecting against replayed responses
+    if !is_token_for(&token, &hashed_message, &nonce).unwrap_or(false) {
+        tracing::error!("timestamp token does not match the request");
+        return Err(timestamp_error());
+    }
+
+    Any::encode_from(&token).map_err(encode_error)
+}
+
+/// Send the DER encoded time stamp `request` to the time stamp authority at the
+/// `tsa_url`, returns the DER encoded response
+#[cfg(feature = "http-client")]
+async fn post_timestamp_query(tsa_url: &str, request: Vec<u8>) -> Result<Vec<u8>, ConvertError> {
+    let client = http_client_builder()
+        .timeout(TSA_TIMEOUT)
+        .build()
+        .map_err(|err| {
+            tracing::error!(?err, "failed to create tsa client");
+            timestamp_error()
+        })?;
+
+    let response = client
+        .post(tsa_url)
+        .header(reqwest::header::CONTENT_TYPE, TIMESTAMP_QUERY_CONTENT_TYPE)
+        .body(request)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| {
+            tracing::error!(?err, "failed to request timestamp");
+            timestamp_error()
+        })?;
+
+    let body = response.bytes().await.map_err(|err| {
+        tracing::error!(?err, "failed to read timestamp response");
+        timestamp_error()
+    })?;
+
+    Ok(body.to_vec())
+}
+
+#[cfg(not(feature = "http-client"))]
+async fn post_timestamp_query(_tsa_url: &str, _request: Vec<u8>) -> Result<Vec<u8>, ConvertError> {
+    Err(feature_disabled_error(TSA_URL_ENV, "http-client"))
+}
+
+/// Check the `TSTInfo` of a time stamp token has the `hashed_message` and `nonce`
+/// of the request
+fn is_token_for(token: &ContentInfo, hashed_message: &[u8], nonce: &Uint) -> der::Result<bool> {
+    let signed_data: SignedData = token.content.decode_as()?;
+    let content = &signed_data.encap_content_info;
+
+    let Some(tst_info) = content
+        .econtent
+        .as_ref()
+        .filter(|_| content.econtent_type == ID_TST_INFO)
+    else {
+        return Ok(false);
+    };
+
+    let tst_info = tst_info.decode_as::<OctetString>()?;
+    let mut reader = SliceReader::new(tst_info.as_bytes())?;
+
+    reader.sequence(|reader| {
+        let _version: u8 = reader.decode()?;
+        let _policy: ObjectIdentifier = reader.decode()?;
+        let message_imprint: MessageImprint = reader.decode()?;
+        let _serial_number: Int = reader.decode()?;
+        let _gen_time: GeneralizedTime = reader.decode()?;
+
+        // The nonce is the only integer among the optional fields that follow
+        let mut nonce_matches = false;
+        while !reader.is_finished() {
+            let field: Any = reader.decode()?;
+            if field.tag() == Tag::Integer {
+                nonce_matches = field.decode_as::<Uint>()? == *nonce;
+            }
+        }
+
+        Ok(message_imprint.hash_algorithm.oid == ID_SHA256
+            && message_imprint.hashed_message.as_bytes() == hashed_message
+            && nonce_matches)
+    })
+}
+
+fn encode_error(err: der::Error) -> ConvertError {
+    tracing::error!(?err, "failed to encode timestamp request");
+    timestamp_error()
+}
+
+fn timestamp_error() -> ConvertError {
+    ConvertError {
+        reason: Some("TIMESTAMP_SIGNATURE"),
+        x2t_code: None,
+        message: "failed to timestamp signature".to_string(),
+    }
+}
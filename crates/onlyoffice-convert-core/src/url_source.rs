@@ -0,0 +1,536 @@
+#[cfg(feature = "http-client")]
+use std::time::Duration;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+};
+
+#[cfg(feature = "http-client")]
+use reqwest::{header::LOCATION, redirect::Policy};
+use url::{Host, Url};
+
+#[cfg(not(feature = "http-client"))]
+use crate::config_check::feature_disabled_error;
+use crate::{
+    config::{app_config, config_var},
+    error::ConvertError,
+    proxy::proxy_url,
+    source::SourceFile,
+};
+#[cfg(feature = "http-client")]
+use crate::{proxy::http_client_builder, source::SourceFileWriter};
+
+/// Environment variable for the comma separated list of hosts URL sources can be loaded
+/// from. Entries can be an exact host, a `*.example.com` wildcard or `*` for any public
+/// host. URL sources are disabled when this is not set
+pub const ALLOWED_HOSTS_ENV: &str = "URL_SOURCE_ALLOWED_HOSTS";
+
+/// Environment variable for the comma separated list of allowed URL schemes
+const ALLOWED_SCHEMES_ENV: &str = "URL_SOURCE_ALLOWED_SCHEMES";
+
+const DEFAULT_ALLOWED_SCHEMES: &str = "https";
+
+/// Time allowed to connect to the URL source host
+#[cfg(feature = "http-client")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time allowed between reads of the URL source response, a host that stops
+/// sending the body fails the download rather than holding the invocation
+#[cfg(feature = "http-client")]
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Restrictions applied to URL sources to prevent them from being used to
+/// probe the internal network
+struct UrlSourcePolicy {
+    allowed_hosts: Vec<String>,
+    allowed_schemes: Vec<String>,
+    max_redirects: usize,
+    max_size: u64,
+}
+
+impl UrlSourcePolicy {
+    fn from_env() -> UrlSourcePolicy {
+        UrlSourcePolicy {
+            allowed_hosts: env_list(ALLOWED_HOSTS_ENV).unwrap_or_default(),
+            allowed_schemes: env_list(ALLOWED_SCHEMES_ENV)
+                .unwrap_or_else(|| vec![DEFAULT_ALLOWED_SCHEMES.to_string()]),
+            max_redirects: app_config().url_source_max_redirects,
+            max_size: app_config().url_source_max_size,
+        }
+    }
+
+    /// Policy for presigned S3 URLs provided by AWS, any public host is allowed
+    /// but redirects are not followed
+    fn presigned() -> UrlSourcePolicy {
+        UrlSourcePolicy {
+            allowed_hosts: vec!["*".to_string()],
+            allowed_schemes: vec![DEFAULT_ALLOWED_SCHEMES.to_string()],
+            max_redirects: 0,
+            ..UrlSourcePolicy::from_env()
+        }
+    }
+
+    fn is_host_allowed(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+
+        self.allowed_hosts.iter().any(|allowed| {
+            if allowed == "*" {
+                return true;
+            }
+
+            match allowed.strip_prefix("*.") {
+                Some(domain) => host.ends_with(&format!(".{domain}")),
+                None => host == *allowed,
+            }
+        })
+    }
+
+    /// Whether the host is allowed by an exact entry rather than a wildcard
+    fn is_host_explicitly_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts.contains(&host.to_ascii_lowercase())
+    }
+
+    /// Check the URL is allowed by the policy, resolving the host to an address
+    /// that is safe to connect to. [None] when the request is sent through the proxy
+    /// as the proxy connects to the host, the resolved addresses are checked first.
+    /// Hosts that only resolve through the proxy cannot be checked so they must be
+    /// explicitly allowed, wildcard entries do not allow them
+    async fn check_url(&self, url: &Url) -> Result<Option<SocketAddr>, ConvertError> {
+        if !self
+            .allowed_schemes
+            .iter()
+            .any(|scheme| scheme == url.scheme())
+        {
+            return Err(not_allowed("url scheme is not allowed"));
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| not_allowed("url must contain a host"))?;
+
+        if !self.is_host_allowed(host) {
+            return Err(not_allowed("url host is not allowed"));
+        }
+
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| not_allowed("url must contain a port"))?;
+
+        let proxied = proxy_url(url).is_some();
+
+        let addresses: Vec<SocketAddr> = match url.host() {
+            Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+            Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+            _ => match tokio::net::lookup_host((host, port)).await {
+                Ok(addresses) => addresses.collect(),
+                // Hosts may only be resolvable by the proxy (i.e within a VPC without DNS egress)
+                Err(err) if proxied => {
+                    if !self.is_host_explicitly_allowed(host) {
+                        tracing::warn!(?err, %host, "proxied url host could not be resolved");
+                        return Err(not_allowed(
+                            "url host only resolves through the proxy and must be explicitly allowed",
+                        ));
+                    }
+
+                    return Ok(None);
+                }
+                Err(err) => {
+                    tracing::error!(?err, "failed to resolve url source host");
+                    return Err(ConvertError {
+                        reason: Some("URL_SOURCE_REQUEST"),
+                        x2t_code: None,
+                        message: "failed to resolve url host".to_string(),
+                    });
+                }
+            },
+        };
+
+        // Every resolved address must be public, otherwise the host could be
+        // used to reach internal services
+        if addresses.is_empty() || !addresses.iter().all(|address| is_public_ip(address.ip())) {
+            return Err(not_allowed("url host resolves to a non-public address"));
+        }
+
+        if proxied {
+            return Ok(None);
+        }
+
+        Ok(Some(addresses[0]))
+    }
+}
+
+/// Download a URL source to disk, only following redirects that also satisfy
+/// the URL source policy. `presigned` URLs from AWS are not subject to the
+/// allowed hosts of the policy
+pub async fn stream_url_source(
+    source_url: &str,
+    file_path: &Path,
+    presigned: bool,
+) -> Result<SourceFile, ConvertError> {
+    let policy = if presigned {
+        UrlSourcePolicy::presigned()
+    } else {
+        UrlSourcePolicy::from_env()
+    };
+
+    let mut url = Url::parse(source_url).map_err(|err| {
+        tracing::error!(?err, "invalid source url");
+        ConvertError {
+            reason: Some("INVALID_REQUEST"),
+            x2t_code: None,
+            message: "source_url: invalid url".to_string(),
+        }
+    })?;
+
+    for _ in 0..=policy.max_redirects {
+        let address = policy.check_url(&url).await?;
+
+        match fetch_url(&url, address, policy.max_size, file_path).await? {
+            UrlResponse::Redirect(location) => url = location,
+            UrlResponse::Complete(source) => return Ok(source),
+        }
+    }
+
+    Err(ConvertError {
+        reason: Some("URL_SOURCE_TOO_MANY_REDIRECTS"),
+        x2t_code: None,
+        message: "url source exceeded the maximum number of redirects".to_string(),
+    })
+}
+
+/// Outcome of requesting a single URL of a URL source
+#[cfg_attr(not(feature = "http-client"), allow(dead_code))]
+enum UrlResponse {
+    /// The URL redirected to another URL, which must be checked before it is requested
+    Redirect(Url),
+    /// The URL source was downloaded to disk
+    Complete(SourceFile),
+}
+
+/// Request the `url` without following redirects, connecting to the checked `address`
+/// when one is provided and streaming a successful response to the `file_path`
+#[cfg(feature = "http-client")]
+async fn fetch_url(
+    url: &Url,
+    address: Option<SocketAddr>,
+    max_size: u64,
+    file_path: &Path,
+) -> Result<UrlResponse, ConvertError> {
+    let host = url.host_str().unwrap_or_default().to_string();
+
+    let mut client = http_client_builder()
+        .redirect(Policy::none())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT);
+
+    // Pin the connection to the checked address so the host can't
+    // be re-resolved to a different address
+    if let Some(address) = address {
+        client = client.resolve(&host, address);
+    }
+
+    let client = client.build().map_err(request_error)?;
+
+    let mut response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(request_error)?;
+
+    let status = response.status();
+
+    if status.is_redirection() {
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| ConvertError {
+                reason: Some("URL_SOURCE_STATUS"),
+                x2t_code: None,
+                message: "redirect response missing location".to_string(),
+            })?;
+
+        let location = url.join(location).map_err(|err| {
+            tracing::error!(?err, "invalid redirect location");
+            ConvertError {
+                reason: Some("URL_SOURCE_STATUS"),
+                x2t_code: None,
+                message: "invalid redirect location".to_string(),
+            }
+        })?;
+
+        return Ok(UrlResponse::Redirect(location));
+    }
+
+    if !status.is_success() {
+        return Err(ConvertError {
+            reason: Some("URL_SOURCE_STATUS"),
+            x2t_code: None,
+            message: format!("url source responded with status {status}"),
+        });
+    }
+
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_size)
+    {
+        return Err(too_large(max_size));
+    }
+
+    let mut writer = SourceFileWriter::create(file_path, response.content_length()).await?;
+
+    while let Some(chunk) = response.chunk().await.map_err(request_error)? {
+        // Content length may be missing or incorrect, enforce the limit while streaming
+        if writer.size() + chunk.len() as u64 > max_size {
+            return Err(too_large(max_size));
+        }
+
+        writer.write_chunk(&chunk).await?;
+    }
+
+    writer.finish().await.map(UrlResponse::Complete)
+}
+
+#[cfg(not(feature = "http-client"))]
+async fn fetch_url(
+    _url: &Url,
+    _address: Option<SocketAddr>,
+    _max_size: u64,
+    _file_path: &Path,
+) -> Result<UrlResponse, ConvertError> {
+    Err(feature_disabled_error("source_url", "http-client"))
+}
+
+/// Check if an address is publicly routable, denies loopback, private, link-local
+/// (Including the 169.254.169.254 metadata endpoint) and other reserved ranges
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+/// IPv4 address an IPv6 address is translated to, these reach the IPv4 address
+/// so they are only public when the IPv4 address is
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let ipv4 = |high: u16, low: u16| Ipv4Addr::from(((high as u32) << 16) | low as u32);
+
+    match ip.segments() {
+        // IPv4-mapped ::ffff:a.b.c.d
+        [0, 0, 0, 0, 0, 0xffff, high, low]
+        // IPv4-compatible ::a.b.c.d (Including :: and ::1 which map to 0.0.0.0/8)
+        | [0, 0, 0, 0, 0, 0, high, low]
+        // NAT64 64:ff9b::/96
+        | [0x64, 0xff9b, 0, 0, 0, 0, high, low]
+        // 6to4 2002::/16
+        | [0x2002, high, low, ..] => Some(ipv4(high, low)),
+        _ => None,
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network" 0.0.0.0/8
+        || a == 0
+        // Shared address space 100.64.0.0/10
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // IETF protocol assignments 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18)
+        // Reserved 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link local fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+/// Read a comma separated list from an environment variable
+fn env_list(key: &str) -> Option<Vec<String>> {
+    let value = config_var(key).ok()?;
+
+    Some(
+        value
+            .split(',')
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|value| !value.is_empty())
+            .collect(),
+    )
+}
+
+fn not_allowed(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("URL_SOURCE_NOT_ALLOWED"),
+        x2t_code: None,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(feature = "http-client")]
+fn too_large(max_size: u64) -> ConvertError {
+    ConvertError {
+        reason: Some("URL_SOURCE_TOO_LARGE"),
+        x2t_code: None,
+        message: format!("url source exceeds the maximum size of {max_size} bytes"),
+    }
+}
+
+#[cfg(feature = "http-client")]
+fn request_error(err: reqwest::Error) -> ConvertError {
+    tracing::error!(?err, "failed to download url source");
+    ConvertError {
+        reason: Some("URL_SOURCE_REQUEST"),
+        x2t_code: None,
+        message: "failed to download url source".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use url::Url;
+
+    use super::{UrlSourcePolicy, is_public_ip};
+
+    fn policy(allowed_hosts: &[&str]) -> UrlSourcePolicy {
+        UrlSourcePolicy {
+            allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+            allowed_schemes: vec!["https".to_string()],
+            max_redirects: 0,
+            max_size: 1024,
+        }
+    }
+
+    async fn check_url(policy: &UrlSourcePolicy, url: &str) -> Result<(), &'static str> {
+        let url = Url::parse(url).unwrap();
+        match policy.check_url(&url).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.reason.unwrap_or_default()),
+        }
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        for ip in [
+            "93.184.215.14",
+            "8.8.8.8",
+            "2606:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert!(is_public_ip(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_is_public_ip_rejected() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "192.0.0.1",
+            "198.18.0.1",
+            "240.0.0.1",
+            "255.255.255.255",
+            "224.0.0.1",
+            "::",
+            "::1",
+            "fc00::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "2001:db8::1",
+            "ff02::1",
+            // IPv6 addresses reaching a private IPv4 address
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "::10.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:a9fe:a9fe::",
+            "2002:7f00:1::1",
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert!(!is_public_ip(ip), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        check_url(&policy(&["*"]), "https://8.8.8.8/file.docx")
+            .await
+            .unwrap();
+        check_url(&policy(&["8.8.8.8"]), "https://8.8.8.8:8443/file.docx")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_url_rejected() {
+        let not_allowed = Err("URL_SOURCE_NOT_ALLOWED");
+
+        // Scheme is not allowed
+        assert_eq!(
+            check_url(&policy(&["*"]), "http://8.8.8.8/file.docx").await,
+            not_allowed
+        );
+        assert_eq!(
+            check_url(&policy(&["*"]), "file:///etc/passwd").await,
+            not_allowed
+        );
+
+        // Host is not allowed
+        assert_eq!(
+            check_url(&policy(&[]), "https://8.8.8.8/file.docx").await,
+            not_allowed
+        );
+        assert_eq!(
+            check_url(&policy(&["*.example.com"]), "https://example.com/file.docx").await,
+            not_allowed
+        );
+        assert_eq!(
+            check_url(
+                &policy(&["*.example.com"]),
+                "https://example.com.attacker.test/file.docx"
+            )
+            .await,
+            not_allowed
+        );
+
+        // Host resolves to a non-public address
+        for url in [
+            "https://127.0.0.1/file.docx",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/file.docx",
+            "https://[::ffff:10.0.0.1]/file.docx",
+            "https://[64:ff9b::a9fe:a9fe]/file.docx",
+        ] {
+            assert_eq!(check_url(&policy(&["*"]), url).await, not_allowed, "{url}");
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+#[cfg(feature = "firehose")]
+use aws_sdk_firehose::{primitives::Blob, types::Record};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+#[cfg(feature = "firehose")]
+use tokio::sync::OnceCell;
+
+#[cfg(feature = "firehose")]
+use crate::aws::aws_config;
+use crate::{
+    config::config_var,
+    error::ConvertError,
+    format::OutputFormat,
+    storage::{PutBody, PutOptions, S3Storage, Storage},
+};
+
+/// Environment variable for where usage records are written, either `s3` or
+/// `firehose`. Usage is only recorded when this is set
+const USAGE_LOG_ENV: &str = "USAGE_LOG";
+
+/// Environment variable for the bucket usage records are stored within when
+/// using the `s3` usage log
+const USAGE_BUCKET_ENV: &str = "USAGE_BUCKET";
+
+/// Environment variable for the key prefix usage records are stored under
+const USAGE_PREFIX_ENV: &str = "USAGE_PREFIX";
+
+/// Environment variable for the Firehose delivery stream usage records are
+/// sent to when using the `firehose` usage log
+pub const USAGE_DELIVERY_STREAM_ENV: &str = "USAGE_DELIVERY_STREAM";
+
+/// Environment variable set by Lambda to the memory of the function in MB
+const LAMBDA_MEMORY_SIZE_ENV: &str = "AWS_LAMBDA_FUNCTION_MEMORY_SIZE";
+
+const DEFAULT_USAGE_PREFIX: &str = "usage/";
+
+/// Client for the usage delivery stream, cached across warm invocations
+#[cfg(feature = "firehose")]
+static FIREHOSE_CLIENT: OnceCell<aws_sdk_firehose::Client> = OnceCell::const_new();
+
+/// Destination usage records are written to
+enum UsageLog {
+    S3 { bucket: String, prefix: String },
+    Firehose { delivery_stream: String },
+}
+
+fn usage_log() -> Option<UsageLog> {
+    match config_var(USAGE_LOG_ENV).ok()?.as_str() {
+        "s3" => {
+            let Some(bucket) = config_var(USAGE_BUCKET_ENV)
+                .ok()
+                .filter(|value| !value.is_empty())
+            else {
+                tracing::warn!("s3 usage log is missing the usage bucket");
+                return None;
+            };
+
+            let prefix =
+                config_var(USAGE_PREFIX_ENV).unwrap_or_else(|_| DEFAULT_USAGE_PREFIX.to_string());
+
+            Some(UsageLog::S3 { bucket, prefix })
+        }
+        "firehose" => {
+            let Some(delivery_stream) = config_var(USAGE_DELIVERY_STREAM_ENV)
+                .ok()
+                .filter(|value| !value.is_empty())
+            else {
+                tracing::warn!("firehose usage log is missing the delivery stream");
+                return None;
+            };
+
+            Some(UsageLog::Firehose { delivery_stream })
+        }
+        value => {
+            tracing::warn!(%value, "unknown usage log");
+            None
+        }
+    }
+}
+
+/// Whether usage is recorded
+pub fn is_usage_enabled() -> bool {
+    config_var(USAGE_LOG_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+/// Resources used by a conversion, collected while converting
+#[derive(Default)]
+pub struct UsageMetrics {
+    /// Size of the source file in bytes
+    pub source_bytes: u64,
+    /// Total size of the output files in bytes, before compression
+    pub output_bytes: u64,
+    /// Number of storage requests throttled by S3, including those that
+    /// succeeded once retried
+    pub throttled_requests: u64,
+}
+
+/// Record of the resources used by a single conversion for chargeback,
+/// written as a line of JSON
+#[derive(Serialize)]
+pub struct UsageRecord {
+    pub request_id: String,
+    /// Time the conversion started
+    pub started_at: DateTime<Utc>,
+    /// Tenant the conversion was requested on behalf of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Extension of the source file (i.e `docx`) when it has one
+    pub source_format: Option<String>,
+    pub output_formats: Vec<OutputFormat>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+    pub source_bytes: u64,
+    pub output_bytes: u64,
+    pub throttled_requests: u64,
+    pub duration_ms: u64,
+    /// Memory of the function in MB, [None] when not running on Lambda
+    pub memory_mb: Option<u64>,
+    /// Estimated compute charged for the conversion, the memory of the function for
+    /// the duration of the conversion. Conversions sharing an invocation (i.e batches)
+    /// are each estimated at the full memory of the function
+    pub gb_seconds: Option<f64>,
+}
+
+impl UsageRecord {
+    /// Set the outcome and resources used by the conversion
+    pub fn finish<T>(
+        &mut self,
+        result: &Result<T, ConvertError>,
+        metrics: &UsageMetrics,
+        duration: Duration,
+    ) {
+        self.duration_ms = duration.as_millis() as u64;
+        self.success = result.is_ok();
+        self.reason = result.as_ref().err().and_then(|error| error.reason);
+        self.source_bytes = metrics.source_bytes;
+        self.output_bytes = metrics.output_bytes;
+        self.throttled_requests = metrics.throttled_requests;
+        self.memory_mb = std::env::var(LAMBDA_MEMORY_SIZE_ENV)
+            .ok()
+            .and_then(|value| value.parse().ok());
+        self.gb_seconds = self
+            .memory_mb
+            .map(|memory_mb| memory_mb as f64 / 1024.0 * duration.as_secs_f64());
+    }
+}
+
+/// Write the `record` to the usage log, failing to write the record is
+/// logged but does not fail the conversion
+pub async fn write_usage_record(record: &UsageRecord) {
+    let Some(log) = usage_log() else {
+        return;
+    };
+
+    let mut line = match serde_json::to_vec(record) {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "failed to serialize usage record");
+            return;
+        }
+    };
+    line.push(b'\n');
+
+    match log {
+        UsageLog::S3 { bucket, prefix } => {
+            let key = format!(
+                "{prefix}{}/{}.jsonl",
+                record.started_at.format("%Y/%m/%d"),
+                record.request_id
+            );
+
+            let result = S3Storage::from_env()
+                .await
+                .put_object(
+                    &bucket,
+                    &key,
+                    PutBody::Bytes(line),
+                    PutOptions {
+                        content_type: Some("application/x-ndjson"),
+                        ..Default::default()
+                    },
+                )
+                .await;
+
+            if let Err(err) = result {
+                tracing::error!(?err, %key, "failed to store usage record");
+            }
+        }
+        UsageLog::Firehose { delivery_stream } => {
+            put_firehose_record(&delivery_stream, line).await;
+        }
+    }
+}
+
+/// Send the usage record `line` to the Firehose `delivery_stream`
+#[cfg(feature = "firehose")]
+async fn put_firehose_record(delivery_stream: &str, line: Vec<u8>) {
+    let record = match Record::builder().data(Blob::new(line)).build() {
+        Ok(value) => value,
+        Err(err) => {
+            tracing::error!(?err, "failed to build usage record");
+            return;
+        }
+    };
+
+    let result = FIREHOSE_CLIENT
+        .get_or_init(|| async { aws_sdk_firehose::Client::new(&aws_config().await) })
+        .await
+        .put_record()
+        .delivery_stream_name(delivery_stream)
+        .record(record)
+        .send()
+        .await;
+
+    if let Err(err) = result {
+        tracing::error!(?err, %delivery_stream, "failed to send usage record");
+    }
+}
+
+#[cfg(not(feature = "firehose"))]
+async fn put_firehose_record(delivery_stream: &str, _line: Vec<u8>) {
+    tracing::error!(%delivery_stream, "firehose usage log requires the firehose feature");
+}
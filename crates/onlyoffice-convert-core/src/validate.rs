@@ -0,0 +1,261 @@
+use crate::error::ConvertError;
+
+/// Maximum length of an S3 object key in bytes
+const MAX_KEY_LENGTH: usize = 1024;
+
+/// Maximum length of a caller provided name
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Maximum length of an S3 object version ID in bytes
+const MAX_VERSION_ID_LENGTH: usize = 1024;
+
+/// Maximum length of an object entity tag in bytes
+const MAX_ETAG_LENGTH: usize = 256;
+
+/// Validate a caller provided bucket name follows the S3 bucket naming rules
+pub fn validate_bucket(field: &str, bucket: &str) -> Result<(), ConvertError> {
+    let valid_length = (3..=63).contains(&bucket.len());
+    let valid_chars = bucket.bytes().all(|byte| {
+        byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'.' || byte == b'-'
+    });
+    let valid_edges = bucket
+        .bytes()
+        .next()
+        .zip(bucket.bytes().last())
+        .is_some_and(|(first, last)| first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric());
+
+    if !valid_length || !valid_chars || !valid_edges || bucket.contains("..") {
+        return Err(invalid_request(field, "invalid bucket name"));
+    }
+
+    Ok(())
+}
+
+/// Validate a caller provided object key, rejects keys containing control
+/// characters, relative path segments or a leading path separator
+pub fn validate_key(field: &str, key: &str) -> Result<(), ConvertError> {
+    if key.is_empty() {
+        return Err(invalid_request(field, "key must not be empty"));
+    }
+
+    if key.len() > MAX_KEY_LENGTH {
+        return Err(invalid_request(field, "key is too long"));
+    }
+
+    if key.chars().any(char::is_control) {
+        return Err(invalid_request(
+            field,
+            "key must not contain control characters",
+        ));
+    }
+
+    if key.starts_with('/') || key.starts_with('\\') {
+        return Err(invalid_request(field, "key must not be an absolute path"));
+    }
+
+    if key
+        .split(['/', '\\'])
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return Err(invalid_request(
+            field,
+            "key must not contain relative path segments",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate a caller provided name (i.e a font profile) that is used within object
+/// keys and paths, names are limited to ASCII letters, digits, `-` and `_`
+pub fn validate_name(field: &str, name: &str) -> Result<(), ConvertError> {
+    let valid_length = (1..=MAX_NAME_LENGTH).contains(&name.len());
+    let valid_chars = name
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_');
+
+    if !valid_length || !valid_chars {
+        return Err(invalid_request(field, "invalid name"));
+    }
+
+    Ok(())
+}
+
+/// Validate a caller provided AWS account ID, account IDs are 12 digits
+pub fn validate_account_id(field: &str, account_id: &str) -> Result<(), ConvertError> {
+    if account_id.len() != 12 || !account_id.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid_request(field, "invalid account ID"));
+    }
+
+    Ok(())
+}
+
+/// Validate a caller provided object version ID, version IDs are opaque
+/// strings of up to 1024 bytes
+pub fn validate_version_id(field: &str, version_id: &str) -> Result<(), ConvertError> {
+    validate_opaque(field, "version ID", version_id, MAX_VERSION_ID_LENGTH)
+}
+
+/// Validate a caller provided entity tag
+pub fn validate_etag(field: &str, etag: &str) -> Result<(), ConvertError> {
+    validate_opaque(field, "entity tag", etag, MAX_ETAG_LENGTH)
+}
+
+/// Validate an opaque value that is passed through to S3
+fn validate_opaque(
+    field: &str,
+    name: &str,
+    value: &str,
+    max_length: usize,
+) -> Result<(), ConvertError> {
+    if value.is_empty() {
+        return Err(invalid_request(field, &format!("{name} must not be empty")));
+    }
+
+    if value.len() > max_length {
+        return Err(invalid_request(field, &format!("{name} is too long")));
+    }
+
+    if value.chars().any(char::is_control) {
+        return Err(invalid_request(
+            field,
+            &format!("{name} must not contain control characters"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate a caller provided number is within the `min` and `max` (inclusive)
+pub fn validate_range(field: &str, value: u32, min: u32, max: u32) -> Result<(), ConvertError> {
+    if value < min || value > max {
+        return Err(invalid_request(
+            field,
+            &format!("must be between {min} and {max}"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Parse a caller provided color in the `#rrggbb` hex form
+pub fn parse_hex_color(field: &str, value: &str) -> Result<[u8; 3], ConvertError> {
+    let invalid = || invalid_request(field, "color must be in the #rrggbb form");
+
+    let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+    if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16);
+
+    Ok([
+        channel(0).map_err(|_| invalid())?,
+        channel(2).map_err(|_| invalid())?,
+        channel(4).map_err(|_| invalid())?,
+    ])
+}
+
+fn invalid_request(field: &str, message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("INVALID_REQUEST"),
+        x2t_code: None,
+        message: format!("{field}: {message}"),
+    }
+}
+
+/// Escape a value for use within the text content of the x2t XML config
+pub fn escape_xml(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+
+    for char in value.chars() {
+        match char {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&apos;"),
+            _ => output.push(char),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_KEY_LENGTH, validate_key};
+
+    #[test]
+    fn test_validate_key() {
+        let max_length = "a".repeat(MAX_KEY_LENGTH);
+
+        for key in [
+            "input.docx",
+            "tenant/input.docx",
+            "a/b/c/input.docx",
+            "dir/.hidden",
+            "dir/..name/file",
+            "file..docx",
+            "nested\\dir/input.docx",
+            "emoji-\u{1F600}.docx",
+            &max_length,
+        ] {
+            assert!(validate_key("key", key).is_ok(), "{key:?}");
+        }
+    }
+
+    #[test]
+    fn test_validate_key_rejected() {
+        let too_long = "a".repeat(MAX_KEY_LENGTH + 1);
+        // Multi-byte characters count towards the length in bytes
+        let too_long_bytes = "\u{e9}".repeat(MAX_KEY_LENGTH / 2 + 1);
+
+        for (key, message) in [
+            ("", "key: key must not be empty"),
+            (&too_long, "key: key is too long"),
+            (&too_long_bytes, "key: key is too long"),
+            (
+                "input\0.docx",
+                "key: key must not contain control characters",
+            ),
+            (
+                "input\n.docx",
+                "key: key must not contain control characters",
+            ),
+            (
+                "dir/\u{7f}/input.docx",
+                "key: key must not contain control characters",
+            ),
+            ("/input.docx", "key: key must not be an absolute path"),
+            ("\\input.docx", "key: key must not be an absolute path"),
+            ("..", "key: key must not contain relative path segments"),
+            (
+                "../input.docx",
+                "key: key must not contain relative path segments",
+            ),
+            (
+                "dir/../input.docx",
+                "key: key must not contain relative path segments",
+            ),
+            (
+                "dir\\..\\input.docx",
+                "key: key must not contain relative path segments",
+            ),
+            ("dir/..", "key: key must not contain relative path segments"),
+            (".", "key: key must not contain relative path segments"),
+            (
+                "./input.docx",
+                "key: key must not contain relative path segments",
+            ),
+            (
+                "dir/./input.docx",
+                "key: key must not contain relative path segments",
+            ),
+        ] {
+            let err = validate_key("key", key).unwrap_err();
+            assert_eq!(err.reason, Some("INVALID_REQUEST"), "{key:?}");
+            assert_eq!(err.message, message, "{key:?}");
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::{
+    aws::aws_config,
+    config::{app_config, config_var},
+    error::ConvertError,
+    pipeline::{OutputContext, Pipeline, PostProcessor},
+};
+
+/// Environment variable for the bucket containing the WASM plugins, plugins are
+/// only loaded when this is set
+const WASM_PLUGINS_BUCKET_ENV: &str = "WASM_PLUGINS_BUCKET";
+
+/// Environment variable for the key prefix the WASM plugins are stored under
+const WASM_PLUGINS_PREFIX_ENV: &str = "WASM_PLUGINS_PREFIX";
+
+const DEFAULT_WASM_PLUGINS_PREFIX: &str = "plugins/";
+
+/// Extension of the plugin modules within the plugins prefix
+const WASM_PLUGIN_EXTENSION: &str = ".wasm";
+
+/// Post-processor running a WASM module over each output.
+///
+/// Plugins export their `memory` along with:
+/// - `alloc(len: i32) -> i32` allocating `len` bytes, returning the offset
+/// - `transform(input: i32, input_len: i32, format: i32, format_len: i32) -> i64`
+///   transforming the output file (i.e a PDF) given the output format extension (i.e `pdf`),
+///   returning the offset of the transformed file in the upper 32 bits and its length in
+///   the lower 32 bits. A zero length fails the conversion
+///
+/// Each output is transformed by a new instance so no state is kept between outputs
+#[derive(Clone)]
+pub struct WasmPlugin {
+    /// Key of the plugin module along with the SHA-256 hash of the module, replacing
+    /// the module under the same key changes the name (and the result cache key)
+    name: String,
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    max_memory: usize,
+}
+
+/// Data of the store a plugin is instantiated within
+struct PluginState {
+    limits: StoreLimits,
+}
+
+impl WasmPlugin {
+    /// Transform the `input` file of the `format`, returning the transformed file
+    fn transform(&self, input: &[u8], format: &str) -> Result<Vec<u8>, wasmi::Error> {
+        let mut store = Store::new(
+            &self.engine,
+            PluginState {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.max_memory)
+                    .instances(1)
+                    .build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.fuel)?;
+
+        // Plugins are not given any imports
+        let instance = Linker::new(&self.engine).instantiate_and_start(&mut store, &self.module)?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| wasmi::Error::new("plugin does not export its memory"))?;
+        let alloc = instance.get_typed_func::<u32, u32>(&store, "alloc")?;
+        let transform =
+            instance.get_typed_func::<(u32, u32, u32, u32), u64>(&store, "transform")?;
+
+        let input_len = u32::try_from(input.len())
+            .map_err(|_| wasmi::Error::new("output is too large for the plugin"))?;
+        let input_ptr = alloc.call(&mut store, input_len)?;
+        memory.write(&mut store, input_ptr as usize, input)?;
+
+        let format_len = format.len() as u32;
+        let format_ptr = alloc.call(&mut store, format_len)?;
+        memory.write(&mut store, format_ptr as usize, format.as_bytes())?;
+
+        let result = transform.call(&mut store, (input_ptr, input_len, format_ptr, format_len))?;
+        let output_ptr = (result >> 32) as usize;
+        let output_len = (result & 0xffff_ffff) as usize;
+
+        if output_len == 0 {
+            return Err(wasmi::Error::new("plugin failed to transform the output"));
+        }
+
+        // Checked before allocating so the plugin cannot request more than its memory
+        if output_ptr.saturating_add(output_len) > memory.data_size(&store) {
+            return Err(wasmi::Error::new("plugin output is out of bounds"));
+        }
+
+        let mut output = vec![0; output_len];
+        memory.read(&store, output_ptr, &mut output)?;
+
+        Ok(output)
+    }
+}
+
+impl PostProcessor for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process<'a>(
+        &'a self,
+        context: &'a OutputContext<'a>,
+    ) -> BoxFuture<'a, Result<(), ConvertError>> {
+        Box::pin(async move {
+            let input = tokio::fs::read(context.output_path).await.map_err(|err| {
+                tracing::error!(?err, "failed to read output for plugin");
+                plugin_error(&self.name)
+            })?;
+
+            let plugin = self.clone();
+            let format = context.format.extension();
+
+            let output = tokio::task::spawn_blocking(move || plugin.transform(&input, format))
+                .await
+                .map_err(|err| {
+                    tracing::error!(?err, "plugin task failed");
+                    plugin_error(&self.name)
+                })?
+                .map_err(|err| {
+                    tracing::error!(?err, plugin = %self.name, "plugin failed to transform output");
+                    plugin_error(&self.name)
+                })?;
+
+            tokio::fs::write(context.processed_path, &output)
+                .await
+                .map_err(|err| {
+                    tracing::error!(?err, "failed to write plugin output");
+                    plugin_error(&self.name)
+                })?;
+
+            tokio::fs::rename(context.processed_path, context.output_path)
+                .await
+                .map_err(|err| {
+                    tracing::error!(?err, "failed to replace output with plugin output");
+                    plugin_error(&self.name)
+                })
+        })
+    }
+}
+
+/// Load the WASM plugins from the configured S3 prefix adding them to the `pipeline`
+/// as post-processors, run in the order of their keys (i.e `01-watermark.wasm`). Called
+/// once at cold start before any requests are handled (When enabled)
+pub async fn load_wasm_plugins(mut pipeline: Pipeline) -> Result<Pipeline, ConvertError> {
+    let bucket = match config_var(WASM_PLUGINS_BUCKET_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        // Plugins are not enabled
+        _ => return Ok(pipeline),
+    };
+
+    let prefix = config_var(WASM_PLUGINS_PREFIX_ENV)
+        .unwrap_or_else(|_| DEFAULT_WASM_PLUGINS_PREFIX.to_string());
+
+    let config = app_config();
+    let fuel = config.wasm_plugin_fuel;
+    let max_memory = config.wasm_plugin_max_memory;
+
+    let aws_config = aws_config().await;
+    let s3_client = aws_sdk_s3::Client::new(&aws_config);
+
+    let mut pages = s3_client
+        .list_objects_v2()
+        .bucket(&bucket)
+        .prefix(&prefix)
+        .into_paginator()
+        .send();
+
+    let mut keys: Vec<String> = Vec::new();
+
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            tracing::error!(?err, "failed to list plugins");
+            load_error("failed to list plugins")
+        })?;
+
+        keys.extend(
+            page.contents()
+                .iter()
+                .filter_map(|object| object.key.clone())
+                .filter(|key| key.ends_with(WASM_PLUGIN_EXTENSION)),
+        );
+    }
+
+    keys.sort();
+
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+
+    for key in keys {
+        let response = s3_client
+            .get_object()
+            .bucket(&bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|err| {
+                tracing::error!(?err, %key, "failed to download plugin");
+                load_error("failed to download plugin")
+            })?;
+
+        let bytes = response.body.collect().await.map_err(|err| {
+            tracing::error!(?err, %key, "failed to read plugin");
+            load_error("failed to download plugin")
+        })?;
+
+        let bytes = bytes.into_bytes();
+        let hash = Sha256::digest(&bytes);
+
+        let module = Module::new(&engine, bytes).map_err(|err| {
+            tracing::error!(?err, %key, "plugin is not a valid module");
+            load_error("plugin is not a valid module")
+        })?;
+
+        let name = format!("{key}@sha256:{hash:x}");
+        tracing::debug!(%name, "loaded plugin");
+
+        pipeline = pipeline.with_post_processor(WasmPlugin {
+            name,
+            engine: engine.clone(),
+            module,
+            fuel,
+            max_memory,
+        });
+    }
+
+    Ok(pipeline)
+}
+
+fn load_error(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("LOAD_PLUGINS"),
+        x2t_code: None,
+        message: message.to_string(),
+    }
+}
+
+fn plugin_error(name: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("PLUGIN_FAILED"),
+        x2t_code: None,
+        message: format!("plugin {name} failed to transform the output"),
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use calamine::{Data, Range, Reader, Xlsx, open_workbook};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::error::ConvertError;
+
+/// Maximum length of a worksheet name
+const MAX_SHEET_NAME_LENGTH: usize = 255;
+
+/// Worksheet selected by its 0-based index or its name
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SheetSelector {
+    Index(usize),
+    Name(String),
+}
+
+/// Options for CSV outputs
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CsvOptions {
+    /// Worksheet to export, x2t exports the first worksheet when not provided
+    #[serde(default)]
+    pub sheet: Option<SheetSelector>,
+    /// Export every worksheet as a separate CSV file
+    #[serde(default)]
+    pub all_sheets: bool,
+}
+
+impl CsvOptions {
+    pub fn validate(&self) -> Result<(), ConvertError> {
+        if self.sheet.is_some() && self.all_sheets {
+            return Err(invalid_request(
+                "csv: sheet and all_sheets cannot be used together",
+            ));
+        }
+
+        if let Some(SheetSelector::Name(name)) = &self.sheet
+            && (name.is_empty() || name.len() > MAX_SHEET_NAME_LENGTH)
+        {
+            return Err(invalid_request("csv.sheet: invalid sheet name"));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the CSV must be exported from the workbook rather than by x2t
+    pub fn requires_workbook(&self) -> bool {
+        self.sheet.is_some() || self.all_sheets
+    }
+}
+
+/// Output written from an XLSX workbook produced by x2t rather than by x2t itself
+pub enum WorkbookExport<'a> {
+    /// Selected worksheets as CSV
+    Csv(&'a CsvOptions),
+    /// Cells of every worksheet as JSON
+    Json,
+}
+
+impl WorkbookExport<'_> {
+    /// Write the export from the XLSX workbook at `workbook_path`, see [export_csv_sheets]
+    /// and [export_json]. Returns the paths of the written files
+    pub async fn export(
+        &self,
+        workbook_path: &Path,
+        output_path: &Path,
+        sheets_dir: &Path,
+    ) -> Result<Vec<PathBuf>, ConvertError> {
+        let workbook_path = workbook_path.to_path_buf();
+        let output_path = output_path.to_path_buf();
+
+        match self {
+            WorkbookExport::Csv(options) => {
+                let options = (*options).clone();
+                let sheets_dir = sheets_dir.to_path_buf();
+
+                tokio::task::spawn_blocking(move || {
+                    export_csv_sheets(&workbook_path, &options, output_path, &sheets_dir)
+                })
+                .await
+            }
+            WorkbookExport::Json => {
+                tokio::task::spawn_blocking(move || {
+                    export_json(&workbook_path, &output_path)?;
+                    Ok(vec![output_path])
+                })
+                .await
+            }
+        }
+        .map_err(|err| {
+            tracing::error!(?err, "workbook export task failed");
+            export_error()
+        })?
+    }
+}
+
+fn open_xlsx(workbook_path: &Path) -> Result<Xlsx<BufReader<File>>, ConvertError> {
+    open_workbook(workbook_path).map_err(|err| {
+        tracing::error!(?err, "failed to open workbook");
+        export_error()
+    })
+}
+
+/// Write the CSV files selected by the `options`. A selected sheet is written to
+/// `output_path`, when exporting all sheets each sheet is written within the
+/// `sheets_dir`. Returns the paths of the written files in sheet order
+fn export_csv_sheets(
+    workbook_path: &Path,
+    options: &CsvOptions,
+    output_path: PathBuf,
+    sheets_dir: &Path,
+) -> Result<Vec<PathBuf>, ConvertError> {
+    let mut workbook = open_xlsx(workbook_path)?;
+
+    if !options.all_sheets {
+        let range = match &options.sheet {
+            Some(SheetSelector::Index(index)) => workbook.worksheet_range_at(*index),
+            Some(SheetSelector::Name(name)) => {
+                // Names are checked first as missing sheets are reported as errors
+                if workbook.sheet_names().contains(name) {
+                    Some(workbook.worksheet_range(name))
+                } else {
+                    None
+                }
+            }
+            None => workbook.worksheet_range_at(0),
+        };
+
+        let range = range.ok_or_else(sheet_not_found)?.map_err(|err| {
+            tracing::error!(?err, "failed to read worksheet");
+            export_error()
+        })?;
+
+        write_csv(&range, &output_path)?;
+        return Ok(vec![output_path]);
+    }
+
+    std::fs::create_dir_all(sheets_dir).map_err(|err| {
+        tracing::error!(?err, "failed to create worksheet directory");
+        export_error()
+    })?;
+
+    workbook
+        .worksheets()
+        .iter()
+        .enumerate()
+        .map(|(index, (_, range))| {
+            let path = sheets_dir.join(format!("sheet_{index}.csv"));
+            write_csv(range, &path)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Structure of the JSON export
+#[derive(Serialize)]
+struct JsonWorkbook<'a> {
+    sheets: Vec<JsonSheet<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonSheet<'a> {
+    name: &'a str,
+    /// Rows of cells, serialized as they are written
+    #[serde(serialize_with = "serialize_rows")]
+    rows: &'a Range<Data>,
+}
+
+/// Write the cells of every worksheet to a JSON file at `output_path`
+fn export_json(workbook_path: &Path, output_path: &Path) -> Result<(), ConvertError> {
+    let mut workbook = open_xlsx(workbook_path)?;
+    let worksheets = workbook.worksheets();
+
+    let output = JsonWorkbook {
+        sheets: worksheets
+            .iter()
+            .map(|(name, range)| JsonSheet { name, rows: range })
+            .collect(),
+    };
+
+    let write = || -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        serde_json::to_writer(&mut writer, &output)?;
+        writer.into_inner()?.sync_all()
+    };
+
+    write().map_err(|err| {
+        tracing::error!(?err, "failed to write workbook json");
+        export_error()
+    })
+}
+
+fn serialize_rows<S: Serializer>(range: &&Range<Data>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        range
+            .rows()
+            .map(|row| row.iter().map(JsonCell).collect::<Vec<_>>()),
+    )
+}
+
+/// Cell serialized as its JSON value, empty cells are null
+struct JsonCell<'a>(&'a Data);
+
+impl Serialize for JsonCell<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Data::Empty => serializer.serialize_none(),
+            Data::Int(value) => serializer.serialize_i64(*value),
+            Data::Float(value) if value.is_finite() => serializer.serialize_f64(*value),
+            Data::Bool(value) => serializer.serialize_bool(*value),
+            cell => serializer.serialize_str(&cell_text(cell)),
+        }
+    }
+}
+
+/// Write the cells of a worksheet to a CSV file at `path`
+fn write_csv(range: &Range<Data>, path: &Path) -> Result<(), ConvertError> {
+    let write = || -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        for row in range.rows() {
+            for (index, cell) in row.iter().enumerate() {
+                if index > 0 {
+                    writer.write_all(b",")?;
+                }
+
+                write_csv_field(&mut writer, &cell_text(cell))?;
+            }
+
+            writer.write_all(b"\n")?;
+        }
+
+        writer.into_inner()?.sync_all()
+    };
+
+    write().map_err(|err| {
+        tracing::error!(?err, "failed to write worksheet csv");
+        export_error()
+    })
+}
+
+/// Write a CSV field, quoting fields that contain separators, quotes or new lines
+fn write_csv_field(writer: &mut impl Write, value: &str) -> std::io::Result<()> {
+    if !value.contains([',', '"', '\n', '\r']) {
+        return writer.write_all(value.as_bytes());
+    }
+
+    writer.write_all(b"\"")?;
+    writer.write_all(value.replace('"', "\"\"").as_bytes())?;
+    writer.write_all(b"\"")
+}
+
+/// Text representation of a cell, dates are written in the ISO 8601 form
+pub fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(value) | Data::DateTimeIso(value) | Data::DurationIso(value) => value.clone(),
+        Data::DateTime(value) => match value.as_datetime() {
+            Some(datetime) => format!("{}T{}", datetime.date(), datetime.time()),
+            None => value.to_string(),
+        },
+        cell => cell.to_string(),
+    }
+}
+
+fn invalid_request(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("INVALID_REQUEST"),
+        x2t_code: None,
+        message: message.to_string(),
+    }
+}
+
+fn sheet_not_found() -> ConvertError {
+    ConvertError {
+        reason: Some("SHEET_NOT_FOUND"),
+        x2t_code: None,
+        message: "requested sheet does not exist in the source".to_string(),
+    }
+}
+
+fn export_error() -> ConvertError {
+    ConvertError {
+        reason: Some("EXPORT_WORKSHEET"),
+        x2t_code: None,
+        message: "failed to export worksheet".to_string(),
+    }
+}
@@ -0,0 +1,222 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+
+use crate::{
+    config::app_config, config_check::configuration_error, error::ConvertError,
+    x2t_bundle::bundled_x2t_path,
+};
+
+#[cfg(not(windows))]
+pub const X2T_BIN: &str = "x2t";
+#[cfg(windows)]
+pub const X2T_BIN: &str = "x2t.exe";
+
+/// Environment variables passed through to the x2t process, all other variables
+/// (Including the AWS credentials) are stripped from its environment
+const X2T_INHERITED_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "TMPDIR",
+    "LANG",
+    "LANGUAGE",
+    "LC_ALL",
+    "FONTCONFIG_PATH",
+    "FONTCONFIG_FILE",
+    "ICU_DATA",
+];
+
+/// Prefix of the ICU data files (i.e `icudt58l.dat`)
+const ICU_DATA_FILE_PREFIX: &str = "icudt";
+
+/// Extension of the ICU data files
+const ICU_DATA_FILE_EXTENSION: &str = "dat";
+
+/// x2t error code for failing to load the ICU data
+const ICU_ERROR_CODE: i32 = 0x005c;
+
+const DEFAULT_X2T_PATH: &str = "/var/www/onlyoffice/documentserver/server/FileConverter/bin";
+
+/// Standard locations of x2t within Lambda layers (Layers are extracted into /opt),
+/// checked in order when x2t is not at the default install path
+const LAYER_X2T_PATHS: &[&str] = &[
+    "/opt/onlyoffice/documentserver/server/FileConverter/bin",
+    "/opt/documentserver/server/FileConverter/bin",
+    "/opt/server/FileConverter/bin",
+    "/opt/FileConverter/bin",
+    "/opt/x2t",
+    "/opt/bin",
+];
+
+/// Directory of the installed x2t, either the `X2T_PATH`, the default install path
+/// or one of the Lambda layer paths when it exists. [None] when x2t is not installed
+pub fn installed_x2t_path() -> Option<PathBuf> {
+    if let Some(path) = &app_config().x2t_path {
+        return Some(path.clone());
+    }
+
+    let default_path = Path::new(DEFAULT_X2T_PATH);
+    if default_path.is_dir() {
+        return Some(default_path.to_path_buf());
+    }
+
+    let layer_path = LAYER_X2T_PATHS
+        .iter()
+        .map(Path::new)
+        .find(|path| path.join(X2T_BIN).is_file())?;
+
+    tracing::debug!(path = %layer_path.display(), "using x2t from lambda layer");
+
+    Some(layer_path.to_path_buf())
+}
+
+/// Version of x2t used by requests that do not select a version
+pub fn default_x2t_version() -> Option<String> {
+    app_config().x2t_default_version.clone()
+}
+
+/// Path to the x2t installation for the `version`, fails when the version
+/// is not one of the configured versions
+pub fn x2t_version_path(version: &str) -> Result<PathBuf, ConvertError> {
+    app_config()
+        .x2t_version_path(version)
+        .map(Path::to_path_buf)
+        .ok_or_else(|| ConvertError {
+            reason: Some("UNKNOWN_X2T_VERSION"),
+            x2t_code: None,
+            message: format!("unknown x2t version \"{version}\""),
+        })
+}
+
+/// Directory of the x2t used to convert with the selected `version`, otherwise the
+/// installed x2t or the x2t bundle. [None] when x2t is not available
+pub fn resolve_x2t_path(version: Option<&str>) -> Result<Option<PathBuf>, ConvertError> {
+    // Selected x2t versions use their own installation in place of the default
+    if let Some(version) = version {
+        return x2t_version_path(version).map(Some);
+    }
+
+    // Try the installed x2t, then the x2t bundle downloaded at cold start
+    Ok(installed_x2t_path().or_else(bundled_x2t_path))
+}
+
+/// Create a command for x2t or one of the other OnlyOffice tools within the `x2t_path`
+/// directory, the environment is restricted to the [X2T_INHERITED_ENV] variables
+pub fn x2t_command(program: &Path, x2t_path: &Path) -> Command {
+    // Update the library path to include the x2t bin directory, fixes a bug where some of the requires
+    // .so libraries aren't loaded when they need to be
+    let ld_library_path = std::env::var("LD_LIBRARY_PATH").unwrap_or_default();
+    let ld_library_path = format!("{}:{}", x2t_path.display(), ld_library_path);
+
+    let mut command = Command::new(program);
+    command
+        // Build the environment explicitly so x2t doesn't inherit the AWS credentials
+        .env_clear()
+        .envs(
+            X2T_INHERITED_ENV
+                .iter()
+                .filter_map(|key| std::env::var_os(key).map(|value| (key, value))),
+        )
+        .env("LD_LIBRARY_PATH", &ld_library_path);
+
+    // The configured ICU data takes precedence over the inherited location
+    if let Some(icu_data_path) = icu_data_path(x2t_path) {
+        command.env("ICU_DATA", icu_data_path);
+    }
+
+    command
+}
+
+/// Directory of the ICU data used by the x2t within `x2t_path` (When configured)
+pub fn icu_data_path(x2t_path: &Path) -> Option<PathBuf> {
+    // Joining an absolute path replaces the x2t path
+    app_config()
+        .x2t_icu_data_path
+        .as_ref()
+        .map(|path| x2t_path.join(path))
+}
+
+/// ICU data file within the directory at `path`, [None] when there is no data file
+pub fn find_icu_data_file(path: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == ICU_DATA_FILE_EXTENSION)
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(ICU_DATA_FILE_PREFIX))
+        })
+}
+
+/// Check the configured ICU data directory contains ICU data for the default and each
+/// selectable x2t installation, called once at cold start so a misconfigured path fails
+/// before any requests are handled rather than failing each conversion (When configured)
+pub fn check_icu_data() -> Result<(), ConvertError> {
+    let config = app_config();
+    if config.x2t_icu_data_path.is_none() {
+        return Ok(());
+    }
+
+    // x2t not being installed is reported when converting
+    let x2t_paths = resolve_x2t_path(default_x2t_version().as_deref())?
+        .into_iter()
+        .chain(config.x2t_versions.iter().map(|(_, path)| path.clone()));
+
+    for x2t_path in x2t_paths {
+        let Some(path) = icu_data_path(&x2t_path) else {
+            continue;
+        };
+
+        if find_icu_data_file(&path).is_none() {
+            tracing::error!(path = %path.display(), "icu data not found");
+            return Err(configuration_error(&format!(
+                "ICU data (icudt*.dat) not found within {}",
+                path.display()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the x2t error `code` is one of the errors for sources exceeding
+/// the x2t limits (size, rows or cells)
+pub fn is_limits_error_code(code: i32) -> bool {
+    matches!(code, 0x005d | 0x005e | 0x0060)
+}
+
+/// Whether the x2t error `code` is the error for failing to load the ICU data
+pub fn is_icu_error_code(code: i32) -> bool {
+    code == ICU_ERROR_CODE
+}
+
+/// Translate a x2t error code to the common x2t error messages
+pub fn get_error_code_message(code: i32) -> Option<&'static str> {
+    Some(match code {
+        0x0001 => "AVS_FILEUTILS_ERROR_UNKNOWN",
+        0x0050 => "AVS_FILEUTILS_ERROR_CONVERT",
+        0x0051 => "AVS_FILEUTILS_ERROR_CONVERT_DOWNLOAD",
+        0x0052 => "AVS_FILEUTILS_ERROR_CONVERT_UNKNOWN_FORMAT",
+        0x0053 => "AVS_FILEUTILS_ERROR_CONVERT_TIMEOUT",
+        0x0054 => "AVS_FILEUTILS_ERROR_CONVERT_READ_FILE",
+        0x0055 => "AVS_FILEUTILS_ERROR_CONVERT_DRM_UNSUPPORTED",
+        0x0056 => "AVS_FILEUTILS_ERROR_CONVERT_CORRUPTED",
+        0x0057 => "AVS_FILEUTILS_ERROR_CONVERT_LIBREOFFICE",
+        0x0058 => "AVS_FILEUTILS_ERROR_CONVERT_PARAMS",
+        0x0059 => "AVS_FILEUTILS_ERROR_CONVERT_NEED_PARAMS",
+        0x005a => "AVS_FILEUTILS_ERROR_CONVERT_DRM",
+        0x005b => "AVS_FILEUTILS_ERROR_CONVERT_PASSWORD",
+        0x005c => "AVS_FILEUTILS_ERROR_CONVERT_ICU",
+        0x005d => "AVS_FILEUTILS_ERROR_CONVERT_LIMITS",
+        0x005e => "AVS_FILEUTILS_ERROR_CONVERT_ROWLIMITS",
+        0x005f => "AVS_FILEUTILS_ERROR_CONVERT_DETECT",
+        0x0060 => "AVS_FILEUTILS_ERROR_CONVERT_CELLLIMITS",
+        _ => return None,
+    })
+}
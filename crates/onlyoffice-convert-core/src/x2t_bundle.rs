@@ -0,0 +1,238 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+use zip::ZipArchive;
+
+use crate::{
+    config::{app_config, config_var},
+    error::ConvertError,
+    storage::{GetOptions, S3Storage, Storage},
+    x2t::{X2T_BIN, installed_x2t_path},
+};
+
+/// Environment variable for the bucket containing the x2t bundle, a ZIP archive of
+/// the x2t installation that is downloaded at cold start when x2t is not installed
+const X2T_BUNDLE_BUCKET_ENV: &str = "X2T_BUNDLE_BUCKET";
+
+/// Environment variable for the key of the x2t bundle within the bucket
+const X2T_BUNDLE_KEY_ENV: &str = "X2T_BUNDLE_KEY";
+
+const DEFAULT_X2T_BUNDLE_KEY: &str = "x2t.zip";
+
+/// Name of the directory the bundle is extracted into
+const BUNDLE_DIR_NAME: &str = "onlyoffice-x2t";
+
+/// Name of the file the bundle is downloaded to before it is extracted
+const BUNDLE_FILE_NAME: &str = "onlyoffice-x2t.zip";
+
+/// Name of the file within the bundle directory containing the entity tag of the
+/// extracted bundle, written once the bundle is fully extracted
+const BUNDLE_ETAG_FILE_NAME: &str = ".bundle-etag";
+
+/// Directory containing x2t within the extracted bundle, set once the bundle is extracted
+static BUNDLED_X2T_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Directory containing x2t within the extracted bundle, [None] when no
+/// bundle was extracted
+pub fn bundled_x2t_path() -> Option<PathBuf> {
+    BUNDLED_X2T_PATH.get().cloned()
+}
+
+/// Download and extract the x2t bundle from the configured S3 location when x2t is
+/// not installed, called once at cold start before any requests are handled (When enabled).
+///
+/// The temporary directory outlives the process within an execution environment so
+/// an extracted bundle is reused when the bundle has not changed
+pub async fn bootstrap_x2t() -> Result<(), ConvertError> {
+    let bucket = match config_var(X2T_BUNDLE_BUCKET_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        // x2t bundle is not enabled
+        _ => return Ok(()),
+    };
+
+    if installed_x2t_path().is_some() {
+        tracing::debug!("x2t is installed, skipping x2t bundle");
+        return Ok(());
+    }
+
+    let key = config_var(X2T_BUNDLE_KEY_ENV).unwrap_or_else(|_| DEFAULT_X2T_BUNDLE_KEY.to_string());
+
+    let bundle_path = app_config().temp_dir.join(BUNDLE_DIR_NAME);
+    let etag_path = bundle_path.join(BUNDLE_ETAG_FILE_NAME);
+
+    let storage = S3Storage::from_env().await;
+
+    let head = storage
+        .head_object(&bucket, &key, GetOptions::default())
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %bucket, %key, "failed to get x2t bundle");
+            bundle_error()
+        })?;
+
+    let extracted_etag = tokio::fs::read_to_string(&etag_path).await.ok();
+
+    if head.etag.is_some() && extracted_etag == head.etag {
+        tracing::debug!(path = %bundle_path.display(), "using existing x2t bundle");
+    } else {
+        download_bundle(&storage, &bucket, &key, head.etag.as_deref(), &bundle_path).await?;
+    }
+
+    let x2t_path = find_x2t_dir(bundle_path).await?;
+
+    tracing::debug!(path = %x2t_path.display(), "using x2t from bundle");
+
+    _ = BUNDLED_X2T_PATH.set(x2t_path);
+
+    Ok(())
+}
+
+/// Download the bundle and extract it into the `bundle_path`, replacing any
+/// previously extracted bundle
+async fn download_bundle(
+    storage: &S3Storage,
+    bucket: &str,
+    key: &str,
+    etag: Option<&str>,
+    bundle_path: &Path,
+) -> Result<(), ConvertError> {
+    if bundle_path.exists() {
+        tokio::fs::remove_dir_all(bundle_path)
+            .await
+            .map_err(io_error)?;
+    }
+
+    tokio::fs::create_dir_all(bundle_path)
+        .await
+        .map_err(io_error)?;
+
+    tracing::debug!(%bucket, %key, "downloading x2t bundle");
+
+    // Require the bundle to match the head so the stored entity tag is accurate
+    let mut object = storage
+        .get_object(
+            bucket,
+            key,
+            GetOptions {
+                if_match: etag,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, %bucket, %key, "failed to download x2t bundle");
+            bundle_error()
+        })?;
+
+    let file_path = app_config().temp_dir.join(BUNDLE_FILE_NAME);
+    let mut file = tokio::fs::File::create(&file_path)
+        .await
+        .map_err(io_error)?;
+
+    while let Some(chunk) = object.body.next().await {
+        let chunk = chunk.map_err(|err| {
+            tracing::error!(?err, "failed to read x2t bundle");
+            bundle_error()
+        })?;
+
+        file.write_all(&chunk).await.map_err(io_error)?;
+    }
+
+    file.flush().await.map_err(io_error)?;
+    drop(file);
+
+    let extract_path = bundle_path.to_path_buf();
+    let archive_path = file_path.clone();
+
+    tokio::task::spawn_blocking(move || extract_bundle(&archive_path, &extract_path))
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "x2t bundle extract task failed");
+            bundle_error()
+        })??;
+
+    // The archive is no longer needed, free the space for conversions
+    if let Err(err) = tokio::fs::remove_file(&file_path).await {
+        tracing::warn!(?err, "failed to remove x2t bundle archive");
+    }
+
+    if let Some(etag) = etag {
+        tokio::fs::write(bundle_path.join(BUNDLE_ETAG_FILE_NAME), etag)
+            .await
+            .map_err(io_error)?;
+    }
+
+    Ok(())
+}
+
+/// Extract the bundle archive, the unix permissions of the entries are kept
+/// so the x2t binaries remain executable
+fn extract_bundle(archive_path: &Path, bundle_path: &Path) -> Result<(), ConvertError> {
+    let file = File::open(archive_path).map_err(io_error)?;
+
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|err| {
+        tracing::error!(?err, "x2t bundle is not a valid ZIP archive");
+        bundle_error()
+    })?;
+
+    archive.extract(bundle_path).map_err(|err| {
+        tracing::error!(?err, "failed to extract x2t bundle");
+        bundle_error()
+    })
+}
+
+/// Find the shallowest directory within the `bundle_path` that contains the x2t binary
+async fn find_x2t_dir(bundle_path: PathBuf) -> Result<PathBuf, ConvertError> {
+    let x2t_dir = tokio::task::spawn_blocking(move || {
+        let mut pending = VecDeque::from([bundle_path]);
+
+        while let Some(path) = pending.pop_front() {
+            if path.join(X2T_BIN).is_file() {
+                return Some(path);
+            }
+
+            let Ok(read_dir) = std::fs::read_dir(&path) else {
+                continue;
+            };
+
+            pending.extend(
+                read_dir
+                    .flatten()
+                    .filter(|entry| entry.file_type().is_ok_and(|value| value.is_dir()))
+                    .map(|entry| entry.path()),
+            );
+        }
+
+        None
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(?err, "x2t bundle search task failed");
+        bundle_error()
+    })?;
+
+    x2t_dir.ok_or_else(|| {
+        tracing::error!("x2t bundle does not contain x2t");
+        bundle_error()
+    })
+}
+
+fn io_error(err: std::io::Error) -> ConvertError {
+    tracing::error!(?err, "failed to setup x2t bundle directory");
+    bundle_error()
+}
+
+fn bundle_error() -> ConvertError {
+    ConvertError {
+        reason: Some("X2T_BUNDLE"),
+        x2t_code: None,
+        message: "failed to setup x2t bundle".to_string(),
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use crate::{
+    format::{InputFormat, OutputFormat},
+    validate::escape_xml,
+};
+
+/// x2t thumbnail format code for PNG images
+const THUMBNAIL_FORMAT_PNG: u32 = 4;
+
+/// Thumbnail aspect mode that keeps the aspect ratio of the page
+const THUMBNAIL_ASPECT_KEEP: u32 = 1;
+
+/// Thumbnail aspect mode that renders at the size of the page
+const THUMBNAIL_ASPECT_PAGE: u32 = 2;
+
+/// Source formats the spreadsheet limit applies to
+const SPREADSHEET_LIMIT_TYPES: &str = "xlsx;xltx;xlsm;xltm;xlsb;ods;ots";
+
+/// Size thumbnails are rendered at
+#[derive(Debug, Clone, Copy)]
+pub enum ThumbnailSize {
+    /// Fit within the dimensions, keeping the aspect ratio of the page
+    Fit { width: u32, height: u32 },
+    /// Size of the page at 96 DPI
+    Page,
+}
+
+/// Configuration for a single x2t conversion
+pub struct X2tConfig<'a> {
+    /// Path to the source file
+    pub file_from: &'a Path,
+    /// Path the output should be written to
+    pub file_to: &'a Path,
+    /// Directory containing the fonts available to x2t
+    pub font_dir: &'a Path,
+    /// Directory containing the presentation themes, x2t renders
+    /// presentations without their built-in themes when not provided
+    pub theme_dir: Option<&'a Path>,
+    /// Directory x2t can use for its temporary files
+    pub temp_dir: &'a Path,
+    /// Path to the AllFonts.js font cache, x2t uses the cache bundled
+    /// alongside it when not provided
+    pub all_fonts_path: Option<&'a Path>,
+    /// Whether fonts should be embedded within the output, x2t uses
+    /// its default for the output format when not provided
+    pub embedded_fonts: Option<bool>,
+    /// JSON params passed through to the x2t document renderer
+    pub json_params: Option<&'a str>,
+    /// Size thumbnails are rendered at, x2t uses its default size
+    /// when not provided
+    pub thumbnail_size: Option<ThumbnailSize>,
+    /// Password to open an encrypted source with
+    pub password: Option<&'a str>,
+    /// Maximum uncompressed size of the worksheet XML of spreadsheet sources in
+    /// bytes, x2t uses its own limits when not provided
+    pub spreadsheet_limit: Option<u64>,
+    /// Format of the source, x2t detects the format from the source
+    /// contents when not provided
+    pub format_from: Option<InputFormat>,
+    /// Format to convert the source into
+    pub format: OutputFormat,
+}
+
+impl X2tConfig<'_> {
+    /// Create the XML config file contents (TaskQueueDataConvert) for x2t
+    pub fn to_xml(&self) -> String {
+        let thumbnail = if self.format == OutputFormat::Thumbnail {
+            let (aspect, size) = match self.thumbnail_size {
+                Some(ThumbnailSize::Fit { width, height }) => (
+                    THUMBNAIL_ASPECT_KEEP,
+                    format!(
+                        r#"
+            <width>{width}</width>
+            <height>{height}</height>"#
+                    ),
+                ),
+                Some(ThumbnailSize::Page) => (THUMBNAIL_ASPECT_PAGE, String::new()),
+                None => (THUMBNAIL_ASPECT_KEEP, String::new()),
+            };
+
+            // Only the first page is rendered for thumbnails
+            format!(
+                r#"
+          <m_oThumbnail>
+            <format>{THUMBNAIL_FORMAT_PNG}</format>
+            <aspect>{aspect}</aspect>
+            <first>true</first>{size}
+          </m_oThumbnail>"#
+            )
+        } else {
+            String::new()
+        };
+
+        let all_fonts = match self.all_fonts_path {
+            Some(path) => format!(
+                r#"
+          <m_sAllFontsPath>{}</m_sAllFontsPath>"#,
+                escape_path(path)
+            ),
+            None => String::new(),
+        };
+
+        let theme_dir = match self.theme_dir {
+            Some(path) => format!(
+                r#"
+          <m_sThemeDir>{}</m_sThemeDir>"#,
+                escape_path(path)
+            ),
+            None => String::new(),
+        };
+
+        let json_params = match self.json_params {
+            Some(value) => format!(
+                r#"
+          <m_sJsonParams>{}</m_sJsonParams>"#,
+                escape_xml(value)
+            ),
+            None => String::new(),
+        };
+
+        let password = match self.password {
+            Some(value) => format!(
+                r#"
+          <m_sPassword>{}</m_sPassword>"#,
+                escape_xml(value)
+            ),
+            None => String::new(),
+        };
+
+        let input_limits = match self.spreadsheet_limit {
+            Some(value) => format!(
+                r#"
+          <m_oInputLimits>
+            <m_oInputLimit type="{SPREADSHEET_LIMIT_TYPES}">
+              <m_oZip uncompressed="{value}" template="*.xml"/>
+            </m_oInputLimit>
+          </m_oInputLimits>"#
+            ),
+            None => String::new(),
+        };
+
+        let format_from = match self.format_from {
+            Some(format) => format!(
+                r#"
+          <m_nFormatFrom>{}</m_nFormatFrom>"#,
+                format.x2t_code()
+            ),
+            None => String::new(),
+        };
+
+        let embedded_fonts = match self.embedded_fonts {
+            Some(value) => format!(
+                r#"
+          <m_bEmbeddedFonts>{value}</m_bEmbeddedFonts>"#
+            ),
+            None => String::new(),
+        };
+
+        format!(
+            r#"
+        <?xml version="1.0" encoding="utf-8"?>
+        <TaskQueueDataConvert xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+                              xmlns:xsd="http://www.w3.org/2001/XMLSchema">
+          <m_sFileFrom>{}</m_sFileFrom>
+          <m_sFileTo>{}</m_sFileTo>{format_from}{password}
+          <m_sFontDir>{}</m_sFontDir>{all_fonts}{theme_dir}
+          <m_sTempDir>{}</m_sTempDir>
+          <m_nFormatTo>{}</m_nFormatTo>{embedded_fonts}{json_params}{thumbnail}{input_limits}
+        </TaskQueueDataConvert>
+        "#,
+            escape_path(self.file_from),
+            escape_path(self.file_to),
+            escape_path(self.font_dir),
+            escape_path(self.temp_dir),
+            self.format.x2t_code(),
+        )
+    }
+}
+
+fn escape_path(path: &Path) -> String {
+    escape_xml(&path.display().to_string())
+}
@@ -0,0 +1,216 @@
+//! Golden tests for the generated x2t config, config regressions otherwise only
+//! show up as x2t exit codes. Run with `UPDATE_GOLDEN=1` to regenerate the
+//! golden files after an intended change.
+
+use std::path::{Path, PathBuf};
+
+use onlyoffice_convert_core::{
+    format::{InputFormat, OutputFormat},
+    x2t_config::{ThumbnailSize, X2tConfig},
+};
+
+/// Names of every output format
+const FORMAT_NAMES: &[&str] = &[
+    "pdf",
+    "pdfa",
+    "docx",
+    "odt",
+    "rtf",
+    "txt",
+    "html",
+    "epub",
+    "md",
+    "xlsx",
+    "ods",
+    "csv",
+    "pptx",
+    "odp",
+    "thumbnail",
+    "json",
+];
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden/x2t_config")
+        .join(format!("{name}.xml"))
+}
+
+/// Compare `actual` against the golden file, writing the golden file instead
+/// when `UPDATE_GOLDEN` is set
+fn assert_golden(name: &str, actual: &str) {
+    let path = golden_path(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!(
+            "failed to read golden file {} ({err}), run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+
+    assert_eq!(
+        actual,
+        expected,
+        "x2t config does not match golden file {}, run with UPDATE_GOLDEN=1 if the change is intended",
+        path.display()
+    );
+}
+
+fn base_config(format: OutputFormat) -> X2tConfig<'static> {
+    X2tConfig {
+        file_from: Path::new("/tmp/convert/input"),
+        file_to: Path::new("/tmp/convert/output"),
+        font_dir: Path::new("/opt/fonts"),
+        temp_dir: Path::new("/tmp/convert/temp"),
+        all_fonts_path: None,
+        theme_dir: None,
+        embedded_fonts: None,
+        json_params: None,
+        thumbnail_size: None,
+        password: None,
+        spreadsheet_limit: None,
+        format_from: None,
+        format,
+    }
+}
+
+#[test]
+fn test_format_codes() {
+    for name in FORMAT_NAMES {
+        let format = OutputFormat::from_name(name).unwrap();
+        assert_golden(&format!("format_{name}"), &base_config(format).to_xml());
+    }
+}
+
+#[test]
+fn test_format_from() {
+    for (name, format_from) in [
+        ("rtf", InputFormat::Rtf),
+        ("html", InputFormat::Html),
+        ("mht", InputFormat::Mht),
+        ("word_xml", InputFormat::WordXml),
+        ("flat_docx", InputFormat::FlatDocx),
+        ("flat_odt", InputFormat::FlatOdt),
+        ("md", InputFormat::Markdown),
+        ("vsdx", InputFormat::Vsdx),
+    ] {
+        let config = X2tConfig {
+            format_from: Some(format_from),
+            ..base_config(OutputFormat::Pdf)
+        };
+
+        assert_golden(&format!("format_from_{name}"), &config.to_xml());
+    }
+}
+
+#[test]
+fn test_all_fonts_path() {
+    let config = X2tConfig {
+        all_fonts_path: Some(Path::new("/tmp/font-cache/AllFonts.js")),
+        ..base_config(OutputFormat::Pdf)
+    };
+
+    assert_golden("all_fonts_path", &config.to_xml());
+}
+
+#[test]
+fn test_theme_dir() {
+    let config = X2tConfig {
+        theme_dir: Some(Path::new("/opt/themes")),
+        ..base_config(OutputFormat::Pdf)
+    };
+
+    assert_golden("theme_dir", &config.to_xml());
+}
+
+#[test]
+fn test_password() {
+    let config = X2tConfig {
+        password: Some("p&ss<word>"),
+        ..base_config(OutputFormat::Pdf)
+    };
+
+    assert_golden("password", &config.to_xml());
+}
+
+#[test]
+fn test_spreadsheet_limit() {
+    let config = X2tConfig {
+        spreadsheet_limit: Some(1_073_741_824),
+        ..base_config(OutputFormat::Pdf)
+    };
+
+    assert_golden("spreadsheet_limit", &config.to_xml());
+}
+
+#[test]
+fn test_embedded_fonts() {
+    for embedded_fonts in [true, false] {
+        let config = X2tConfig {
+            embedded_fonts: Some(embedded_fonts),
+            ..base_config(OutputFormat::Pdf)
+        };
+
+        assert_golden(
+            &format!("embedded_fonts_{embedded_fonts}"),
+            &config.to_xml(),
+        );
+    }
+}
+
+#[test]
+fn test_escaped_paths() {
+    let config = X2tConfig {
+        file_from: Path::new("/tmp/convert/<input> & \"quotes\""),
+        file_to: Path::new("/tmp/convert/'output'"),
+        ..base_config(OutputFormat::Docx)
+    };
+
+    assert_golden("escaped_paths", &config.to_xml());
+}
+
+#[test]
+fn test_thumbnail_size() {
+    let fit = X2tConfig {
+        thumbnail_size: Some(ThumbnailSize::Fit {
+            width: 320,
+            height: 240,
+        }),
+        ..base_config(OutputFormat::Thumbnail)
+    };
+
+    assert_golden("thumbnail_size_fit", &fit.to_xml());
+
+    let page = X2tConfig {
+        thumbnail_size: Some(ThumbnailSize::Page),
+        ..base_config(OutputFormat::Thumbnail)
+    };
+
+    assert_golden("thumbnail_size_page", &page.to_xml());
+}
+
+#[test]
+fn test_json_params() {
+    let config = X2tConfig {
+        json_params: Some(r#"{"printOptions":{"pages":"1-3,5","printNotes":true}}"#),
+        ..base_config(OutputFormat::Pdf)
+    };
+
+    assert_golden("json_params", &config.to_xml());
+}
+
+#[test]
+fn test_all_options() {
+    let config = X2tConfig {
+        all_fonts_path: Some(Path::new("/tmp/font-cache/AllFonts.js")),
+        embedded_fonts: Some(false),
+        ..base_config(OutputFormat::Thumbnail)
+    };
+
+    assert_golden("all_options", &config.to_xml());
+}
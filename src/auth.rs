@@ -0,0 +1,678 @@
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac, digest::KeyInit};
+use onlyoffice_convert_core::{
+    aws::aws_config, config::config_var, error::ConvertError, tenant::caller_tenant,
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Map, Value};
+use sha2::{Sha256, Sha384, Sha512};
+use tokio::sync::OnceCell;
+
+use crate::http::{HttpRequest, TENANT_HEADER};
+
+/// Environment variable for the Secrets Manager secret ID (Name or ARN) containing the
+/// shared HMAC secret, HTTP requests are only required to be signed when this is set
+const HMAC_SECRET_ID_ENV: &str = "HMAC_SECRET_ID";
+
+/// Header containing the hex encoded HMAC-SHA256 signature of the canonical request,
+/// optionally prefixed with "sha256=". The canonical request is each of the following
+/// joined by a newline:
+///
+/// - Request method (i.e `POST`)
+/// - Request path (i.e `/convert/pdf`)
+/// - Query parameters sorted by name as `name=value` pairs joined by `&`, names and
+///   values are percent encoded leaving only the RFC 3986 unreserved characters as is
+/// - Value of the `x-tenant-id` header, empty when not provided
+/// - Value of the `x-signature-timestamp` header
+/// - Request body
+const SIGNATURE_HEADER: &str = "x-signature";
+
+/// Header containing the time the request was signed in seconds since the unix epoch
+const SIGNATURE_TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+
+/// Maximum difference in seconds between the signature timestamp and the current
+/// time, signatures outside of this window are rejected so they cannot be replayed
+const MAX_SIGNATURE_AGE_SECS: u64 = 5 * 60;
+
+/// Shared secret loaded from Secrets Manager, cached across warm invocations
+static HMAC_SECRET: OnceCell<Vec<u8>> = OnceCell::const_new();
+
+/// Verify the HMAC signature of an HTTP request when HMAC authentication is enabled,
+/// the request is marked as signed once verified (See [HttpRequest::signed])
+pub async fn verify_request_signature(request: &mut HttpRequest) -> Result<(), ConvertError> {
+    let secret_id = match config_var(HMAC_SECRET_ID_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        // HMAC authentication is not enabled
+        _ => return Ok(()),
+    };
+
+    let secret = HMAC_SECRET
+        .get_or_try_init(|| load_hmac_secret(&secret_id))
+        .await?;
+
+    verify_signature(secret, request, unix_now())?;
+    request.signed = true;
+    Ok(())
+}
+
+/// Verify the signature of the `request` was created using the `secret` within
+/// [MAX_SIGNATURE_AGE_SECS] of `now`
+fn verify_signature(secret: &[u8], request: &HttpRequest, now: u64) -> Result<(), ConvertError> {
+    let signature = request
+        .header(SIGNATURE_HEADER)
+        .ok_or_else(|| unauthorized("missing request signature"))?;
+    let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+    let signature =
+        hex::decode(signature.trim()).map_err(|_| unauthorized("malformed request signature"))?;
+
+    let timestamp = request
+        .header(SIGNATURE_TIMESTAMP_HEADER)
+        .ok_or_else(|| unauthorized("missing signature timestamp"))?;
+    let signed_at: u64 = timestamp
+        .trim()
+        .parse()
+        .map_err(|_| unauthorized("malformed signature timestamp"))?;
+
+    if now.abs_diff(signed_at) > MAX_SIGNATURE_AGE_SECS {
+        return Err(unauthorized("request signature has expired"));
+    }
+
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(secret).map_err(|err| {
+        tracing::error!(?err, "invalid hmac secret");
+        ConvertError {
+            reason: Some("HMAC_SECRET"),
+            x2t_code: None,
+            message: "failed to load hmac secret".to_string(),
+        }
+    })?;
+    mac.update(&canonical_request(request, timestamp.trim()));
+
+    // Constant time comparison of the signature
+    mac.verify_slice(&signature)
+        .map_err(|_| unauthorized("invalid request signature"))
+}
+
+/// Canonical form of the `request` covered by its signature, see [SIGNATURE_HEADER]
+fn canonical_request(request: &HttpRequest, timestamp: &str) -> Vec<u8> {
+    let mut query: Vec<(String, String)> = request
+        .query
+        .iter()
+        .map(|(name, value)| (encode_component(name), encode_component(value)))
+        .collect();
+    query.sort();
+
+    let query = query
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut canonical = format!(
+        "{}\n{}\n{query}\n{}\n{timestamp}\n",
+        request.method,
+        request.path,
+        request.header(TENANT_HEADER).unwrap_or_default(),
+    )
+    .into_bytes();
+    canonical.extend_from_slice(&request.body);
+    canonical
+}
+
+/// Percent encode a query parameter name or value, only the RFC 3986
+/// unreserved characters are left as is
+fn encode_component(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                output.push(byte as char)
+            }
+            _ => _ = write!(output, "%{byte:02X}"),
+        }
+    }
+
+    output
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
+
+/// Load the shared HMAC secret from Secrets Manager
+async fn load_hmac_secret(secret_id: &str) -> Result<Vec<u8>, ConvertError> {
+    let aws_config = aws_config().await;
+    let client = aws_sdk_secretsmanager::Client::new(&aws_config);
+
+    let response = client
+        .get_secret_value()
+        .secret_id(secret_id)
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, "failed to load hmac secret");
+            ConvertError {
+                reason: Some("HMAC_SECRET"),
+                x2t_code: None,
+                message: "failed to load hmac secret".to_string(),
+            }
+        })?;
+
+    if let Some(secret) = response.secret_string() {
+        return Ok(secret.as_bytes().to_vec());
+    }
+
+    if let Some(secret) = response.secret_binary() {
+        return Ok(secret.as_ref().to_vec());
+    }
+
+    tracing::error!("hmac secret has no value");
+
+    Err(ConvertError {
+        reason: Some("HMAC_SECRET"),
+        x2t_code: None,
+        message: "failed to load hmac secret".to_string(),
+    })
+}
+
+fn unauthorized(message: &str) -> ConvertError {
+    ConvertError {
+        reason: Some("UNAUTHORIZED"),
+        x2t_code: None,
+        message: message.to_string(),
+    }
+}
+
+/// Environment variable for the JWT secret, uses the same name and convention as
+/// the OnlyOffice Document Server so existing integrations can share the secret.
+/// JWT authorization is only required when this is set
+const JWT_SECRET_ENV: &str = "JWT_SECRET";
+
+/// Environment variable for the header containing the JWT, matches the
+/// OnlyOffice Document Server `JWT_HEADER` option
+const JWT_HEADER_ENV: &str = "JWT_HEADER";
+
+const DEFAULT_JWT_HEADER: &str = "authorization";
+
+/// Registered claims that are not part of the convert request
+const REGISTERED_CLAIMS: &[&str] = &["exp", "nbf", "iat", "iss", "aud", "sub", "jti"];
+
+/// Prefix of tokens within the authorization header, matched case-insensitively
+const BEARER_PREFIX: &str = "bearer ";
+
+/// Get the JWT from the configured request header (When present)
+pub fn request_jwt(request: &HttpRequest) -> Option<String> {
+    let header = config_var(JWT_HEADER_ENV)
+        .map(|value| value.to_ascii_lowercase())
+        .unwrap_or_else(|_| DEFAULT_JWT_HEADER.to_string());
+
+    let value = request.header(&header)?.trim();
+    let token = match value.get(..BEARER_PREFIX.len()) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(BEARER_PREFIX) => &value[BEARER_PREFIX.len()..],
+        _ => value,
+    };
+
+    Some(token.trim().to_string())
+}
+
+/// Verify the request JWT when JWT authorization is enabled, following the OnlyOffice
+/// Document Server convention the token is either provided in the header (`header_token`)
+/// or as a `token` field in the request body
+///
+/// The request is built only from the claims of the token, the `payload` claim (header
+/// tokens) or the top level claims (body tokens). Fields of the `request` outside of the
+/// token are rejected unless the token contains the same value
+pub fn verify_request_jwt(
+    header_token: Option<String>,
+    request: Value,
+) -> Result<Value, ConvertError> {
+    match config_var(JWT_SECRET_ENV) {
+        Ok(secret) if !secret.is_empty() => {
+            verify_jwt_request(secret.as_bytes(), header_token, request, unix_now())
+        }
+        // JWT authorization is not enabled
+        _ => Ok(request),
+    }
+}
+
+/// Build the request from the claims of the token verified using the `secret`, see
+/// [verify_request_jwt]
+fn verify_jwt_request(
+    secret: &[u8],
+    header_token: Option<String>,
+    request: Value,
+    now: u64,
+) -> Result<Value, ConvertError> {
+    let Value::Object(mut request) = request else {
+        return Err(unauthorized("missing token"));
+    };
+
+    let body_token = match request.remove("token") {
+        Some(Value::String(value)) => Some(value),
+        _ => None,
+    };
+
+    let token = header_token
+        .or(body_token)
+        .ok_or_else(|| unauthorized("missing token"))?;
+
+    let mut claims = decode_jwt(&token, secret, now)?;
+
+    let mut claims = match claims.remove("payload") {
+        Some(Value::Object(payload)) => payload,
+        _ => claims,
+    };
+
+    claims.retain(|key, _| !REGISTERED_CLAIMS.contains(&key.as_str()));
+
+    // Unsigned fields could otherwise redirect the conversion (i.e to another bucket)
+    if let Some((key, _)) = request
+        .iter()
+        .find(|(key, value)| claims.get(key.as_str()) != Some(value))
+    {
+        tracing::warn!(%key, "request field is not covered by the token");
+        return Err(unauthorized(&format!(
+            "{key}: field is not covered by the token"
+        )));
+    }
+
+    Ok(Value::Object(claims))
+}
+
+/// Authorize the tenant of a HTTP convert request against the authenticated caller.
+/// With JWT authorization the tenant is only taken from the token claims (See
+/// [verify_request_jwt]). The header and body tenant of `signed` requests are covered
+/// by the HMAC signature and are trusted as is. Otherwise the tenant is the one the IAM
+/// `caller` is mapped to and a header or body tenant that does not match it is rejected
+pub fn authorize_request_tenant(
+    request: Value,
+    caller: Option<&str>,
+    signed: bool,
+) -> Result<Value, ConvertError> {
+    if config_var(JWT_SECRET_ENV).is_ok_and(|secret| !secret.is_empty()) || signed {
+        return Ok(request);
+    }
+
+    let tenant = match caller {
+        Some(caller) => caller_tenant(caller)?,
+        None => None,
+    };
+
+    authorize_tenant(request, tenant)
+}
+
+/// Set the tenant of the `request` to the authenticated `tenant`, see [authorize_request_tenant]
+fn authorize_tenant(request: Value, tenant: Option<String>) -> Result<Value, ConvertError> {
+    let Value::Object(mut request) = request else {
+        return Ok(request);
+    };
+
+    let requested = match request.remove("tenant") {
+        Some(Value::String(value)) => Some(value),
+        Some(_) => return Err(tenant_not_allowed()),
+        None => None,
+    };
+
+    if requested.is_some() && requested != tenant {
+        tracing::warn!(
+            ?requested,
+            ?tenant,
+            "request tenant does not match the caller"
+        );
+        return Err(tenant_not_allowed());
+    }
+
+    if let Some(tenant) = tenant {
+        request.insert("tenant".to_string(), Value::String(tenant));
+    }
+
+    Ok(Value::Object(request))
+}
+
+fn tenant_not_allowed() -> ConvertError {
+    ConvertError {
+        reason: Some("TENANT_NOT_ALLOWED"),
+        x2t_code: None,
+        message: "tenant: not allowed for caller".to_string(),
+    }
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+/// Decode and verify a HMAC signed JWT returning its claims, tokens must expire
+/// (`exp`) so a leaked token cannot be used indefinitely
+fn decode_jwt(token: &str, secret: &[u8], now: u64) -> Result<Map<String, Value>, ConvertError> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(claims), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(unauthorized("malformed token"));
+    };
+
+    // Signature covers the encoded "header.claims" portion of the token
+    let signed = &token[..header.len() + 1 + claims.len()];
+
+    let header: JwtHeader = decode_jwt_part(header)?;
+    let signature = BASE64_URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| unauthorized("malformed token"))?;
+
+    let valid = match header.alg.as_str() {
+        "HS256" => verify_hmac::<Hmac<Sha256>>(secret, signed, &signature),
+        "HS384" => verify_hmac::<Hmac<Sha384>>(secret, signed, &signature),
+        "HS512" => verify_hmac::<Hmac<Sha512>>(secret, signed, &signature),
+        _ => return Err(unauthorized("unsupported token algorithm")),
+    };
+
+    if !valid {
+        return Err(unauthorized("invalid token signature"));
+    }
+
+    let claims: Map<String, Value> = decode_jwt_part(claims)?;
+
+    let exp = claims
+        .get("exp")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| unauthorized("token has no expiry"))?;
+
+    if now >= exp {
+        return Err(unauthorized("token has expired"));
+    }
+
+    if claims
+        .get("nbf")
+        .and_then(Value::as_u64)
+        .is_some_and(|nbf| now < nbf)
+    {
+        return Err(unauthorized("token is not yet valid"));
+    }
+
+    Ok(claims)
+}
+
+fn decode_jwt_part<T: DeserializeOwned>(part: &str) -> Result<T, ConvertError> {
+    let bytes = BASE64_URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| unauthorized("malformed token"))?;
+
+    serde_json::from_slice(&bytes).map_err(|_| unauthorized("malformed token"))
+}
+
+fn verify_hmac<M: Mac + KeyInit>(secret: &[u8], signed: &str, signature: &[u8]) -> bool {
+    let Ok(mut mac) = <M as KeyInit>::new_from_slice(secret) else {
+        return false;
+    };
+
+    mac.update(signed.as_bytes());
+    mac.verify_slice(signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+    use hmac::{Hmac, Mac, digest::KeyInit};
+    use serde_json::{Value, json};
+    use sha2::Sha256;
+
+    use super::{
+        SIGNATURE_HEADER, SIGNATURE_TIMESTAMP_HEADER, authorize_request_tenant, authorize_tenant,
+        canonical_request, decode_jwt, verify_jwt_request, verify_signature,
+    };
+    use crate::http::HttpRequest;
+
+    const SECRET: &[u8] = b"secret";
+    const NOW: u64 = 1_700_000_000;
+
+    /// Create a HS256 token with the `claims` signed using the `secret`
+    fn jwt(claims: Value, secret: &[u8]) -> String {
+        let header = BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        let claims = BASE64_URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signed = format!("{header}.{claims}");
+
+        let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(secret).unwrap();
+        mac.update(signed.as_bytes());
+        let signature = BASE64_URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+        format!("{signed}.{signature}")
+    }
+
+    fn http_request(headers: &[(&str, &str)]) -> HttpRequest {
+        let body = br#"{"source_key":"input.docx"}"#.to_vec();
+
+        HttpRequest {
+            method: "POST".to_string(),
+            body_size: body.len(),
+            body,
+            path: "/convert/pdf".to_string(),
+            query: HashMap::from([("name".to_string(), "a b".to_string())]),
+            path_parameters: HashMap::new(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            caller: None,
+            signed: false,
+        }
+    }
+
+    /// Sign the `request` using the `secret` at the `timestamp`
+    fn sign_request(request: &mut HttpRequest, secret: &[u8], timestamp: u64) {
+        let timestamp = timestamp.to_string();
+        request
+            .headers
+            .insert(SIGNATURE_TIMESTAMP_HEADER.to_string(), timestamp.clone());
+
+        let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(secret).unwrap();
+        mac.update(&canonical_request(request, &timestamp));
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        request
+            .headers
+            .insert(SIGNATURE_HEADER.to_string(), format!("sha256={signature}"));
+    }
+
+    #[test]
+    fn test_canonical_request() {
+        let request = http_request(&[("x-tenant-id", "tenant")]);
+        let canonical = canonical_request(&request, "100");
+
+        assert_eq!(
+            canonical,
+            b"POST\n/convert/pdf\nname=a%20b\ntenant\n100\n{\"source_key\":\"input.docx\"}"
+        );
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let mut request = http_request(&[]);
+        sign_request(&mut request, SECRET, NOW);
+        verify_signature(SECRET, &request, NOW).unwrap();
+
+        // Within the allowed clock skew
+        verify_signature(SECRET, &request, NOW + 60).unwrap();
+    }
+
+    #[test]
+    fn test_verify_signature_rejected() {
+        let err = verify_signature(SECRET, &http_request(&[]), NOW).unwrap_err();
+        assert_eq!(err.reason, Some("UNAUTHORIZED"));
+
+        // Signed with another secret
+        let mut request = http_request(&[]);
+        sign_request(&mut request, b"other", NOW);
+        let err = verify_signature(SECRET, &request, NOW).unwrap_err();
+        assert_eq!(err.message, "invalid request signature");
+
+        // Expired
+        let mut request = http_request(&[]);
+        sign_request(&mut request, SECRET, NOW);
+        let err = verify_signature(SECRET, &request, NOW + 60 * 60).unwrap_err();
+        assert_eq!(err.message, "request signature has expired");
+
+        // Modified after signing
+        let mut request = http_request(&[]);
+        sign_request(&mut request, SECRET, NOW);
+        request.body = br#"{"source_key":"other.docx"}"#.to_vec();
+        let err = verify_signature(SECRET, &request, NOW).unwrap_err();
+        assert_eq!(err.message, "invalid request signature");
+
+        // Tenant header added after signing
+        let mut request = http_request(&[]);
+        sign_request(&mut request, SECRET, NOW);
+        request
+            .headers
+            .insert("x-tenant-id".to_string(), "tenant".to_string());
+        let err = verify_signature(SECRET, &request, NOW).unwrap_err();
+        assert_eq!(err.message, "invalid request signature");
+    }
+
+    #[test]
+    fn test_decode_jwt() {
+        let token = jwt(
+            json!({ "exp": NOW + 60, "source_key": "input.docx" }),
+            SECRET,
+        );
+        let claims = decode_jwt(&token, SECRET, NOW).unwrap();
+        assert_eq!(claims.get("source_key"), Some(&json!("input.docx")));
+    }
+
+    #[test]
+    fn test_decode_jwt_rejected() {
+        // Signed with another secret
+        let token = jwt(json!({ "exp": NOW + 60 }), b"other");
+        let err = decode_jwt(&token, SECRET, NOW).unwrap_err();
+        assert_eq!(err.message, "invalid token signature");
+
+        let token = jwt(json!({}), SECRET);
+        let err = decode_jwt(&token, SECRET, NOW).unwrap_err();
+        assert_eq!(err.message, "token has no expiry");
+
+        let token = jwt(json!({ "exp": NOW }), SECRET);
+        let err = decode_jwt(&token, SECRET, NOW).unwrap_err();
+        assert_eq!(err.message, "token has expired");
+
+        let token = jwt(json!({ "exp": NOW + 60, "nbf": NOW + 30 }), SECRET);
+        let err = decode_jwt(&token, SECRET, NOW).unwrap_err();
+        assert_eq!(err.message, "token is not yet valid");
+
+        // Unsigned tokens
+        let header = BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#);
+        let claims = BASE64_URL_SAFE_NO_PAD.encode(json!({ "exp": NOW + 60 }).to_string());
+        let err = decode_jwt(&format!("{header}.{claims}."), SECRET, NOW).unwrap_err();
+        assert_eq!(err.message, "unsupported token algorithm");
+
+        let err = decode_jwt("malformed", SECRET, NOW).unwrap_err();
+        assert_eq!(err.message, "malformed token");
+    }
+
+    #[test]
+    fn test_verify_jwt_request() {
+        // Header token with a payload claim
+        let token = jwt(
+            json!({ "exp": NOW + 60, "payload": { "source_key": "input.docx" } }),
+            SECRET,
+        );
+        let request = verify_jwt_request(SECRET, Some(token), json!({}), NOW).unwrap();
+        assert_eq!(request, json!({ "source_key": "input.docx" }));
+
+        // Body token, fields matching the claims are allowed
+        let token = jwt(
+            json!({ "exp": NOW + 60, "source_key": "input.docx" }),
+            SECRET,
+        );
+        let request = verify_jwt_request(
+            SECRET,
+            None,
+            json!({ "token": token, "source_key": "input.docx" }),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(request, json!({ "source_key": "input.docx" }));
+    }
+
+    #[test]
+    fn test_verify_jwt_request_rejected() {
+        let err = verify_jwt_request(SECRET, None, json!({}), NOW).unwrap_err();
+        assert_eq!(err.message, "missing token");
+
+        // Fields outside of the token
+        let token = jwt(
+            json!({ "exp": NOW + 60, "source_key": "input.docx" }),
+            SECRET,
+        );
+        let err = verify_jwt_request(SECRET, Some(token), json!({ "dest_bucket": "other" }), NOW)
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "dest_bucket: field is not covered by the token"
+        );
+
+        // Fields that differ from the token
+        let token = jwt(
+            json!({ "exp": NOW + 60, "source_key": "input.docx" }),
+            SECRET,
+        );
+        let err = verify_jwt_request(
+            SECRET,
+            Some(token),
+            json!({ "source_key": "other.docx" }),
+            NOW,
+        )
+        .unwrap_err();
+        assert_eq!(err.message, "source_key: field is not covered by the token");
+    }
+
+    #[test]
+    fn test_authorize_tenant() {
+        let request = authorize_tenant(json!({}), Some("tenant".to_string())).unwrap();
+        assert_eq!(request, json!({ "tenant": "tenant" }));
+
+        let request =
+            authorize_tenant(json!({ "tenant": "tenant" }), Some("tenant".to_string())).unwrap();
+        assert_eq!(request, json!({ "tenant": "tenant" }));
+
+        let request = authorize_tenant(json!({}), None).unwrap();
+        assert_eq!(request, json!({}));
+    }
+
+    #[test]
+    fn test_authorize_tenant_mismatch() {
+        let err =
+            authorize_tenant(json!({ "tenant": "other" }), Some("tenant".to_string())).unwrap_err();
+        assert_eq!(err.reason, Some("TENANT_NOT_ALLOWED"));
+
+        let err = authorize_tenant(json!({ "tenant": "other" }), None).unwrap_err();
+        assert_eq!(err.reason, Some("TENANT_NOT_ALLOWED"));
+    }
+
+    #[test]
+    fn test_authorize_signed_request_tenant() {
+        let mut request = http_request(&[("x-tenant-id", "tenant")]);
+        sign_request(&mut request, SECRET, NOW);
+        verify_signature(SECRET, &request, NOW).unwrap();
+
+        // The signed header tenant is kept without an IAM caller
+        let value = request.into_request_value().unwrap();
+        let value = authorize_request_tenant(value, None, true).unwrap();
+        assert_eq!(value.get("tenant"), Some(&json!("tenant")));
+
+        // Unsigned requests without a caller can't pick a tenant
+        let request = http_request(&[("x-tenant-id", "tenant")]);
+        let value = request.into_request_value().unwrap();
+        let err = authorize_request_tenant(value, None, false).unwrap_err();
+        assert_eq!(err.reason, Some("TENANT_NOT_ALLOWED"));
+    }
+}
@@ -0,0 +1,256 @@
+use std::{path::PathBuf, sync::Arc};
+
+use bytes::Bytes;
+use futures::{FutureExt, future::BoxFuture, stream};
+use lambda_runtime::Error;
+use onlyoffice_convert_core::{
+    convert::{ConvertOptions, ConvertOutput, ConvertRequest, convert_with_options},
+    format::OutputFormat,
+    storage::{
+        DeleteOptions, GetOptions, ObjectHead, ObjectMetadata, PutBody, PutOptions, Storage,
+        StorageError, StorageObject, WriteOptions,
+    },
+};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Command (first argument) that runs a single conversion from the command line
+/// instead of starting the Lambda runtime
+pub const CLI_COMMAND: &str = "convert";
+
+/// Bucket the piped source is presented as to the conversion
+const PIPE_BUCKET: &str = "stdin";
+
+/// Key the piped source is presented as when no name is provided
+const DEFAULT_PIPE_KEY: &str = "input";
+
+/// Path meaning stdin (for the input) or stdout (for the output)
+const PIPE_PATH: &str = "-";
+
+const USAGE: &str = "usage: convert --to <format> [--from <format>] [--input <path>] \
+[--output <path>] [--name <file name>] [--options <json>]
+
+Reads the source from stdin and writes the output to stdout unless paths are provided";
+
+/// Arguments of the convert command
+struct CliArgs {
+    /// Format to convert to
+    to: OutputFormat,
+    /// Format of the source, detected from the contents or name when not provided
+    from: Option<String>,
+    /// Path to read the source from, stdin when not provided
+    input: Option<PathBuf>,
+    /// Path to write the output to, stdout when not provided
+    output: Option<PathBuf>,
+    /// File name of the source used to detect its format (i.e `notes.md`)
+    name: Option<String>,
+    /// Additional request options as a JSON object (i.e `{"linearize":true}`)
+    options: Option<String>,
+}
+
+impl CliArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<CliArgs, String> {
+        let mut to = None;
+        let mut from = None;
+        let mut input = None;
+        let mut output = None;
+        let mut name = None;
+        let mut options = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {arg}"))
+            };
+
+            match arg.as_str() {
+                "--to" => {
+                    let format = value()?;
+                    to = Some(
+                        OutputFormat::from_name(&format)
+                            .ok_or_else(|| format!("unknown output format: {format}"))?,
+                    );
+                }
+                "--from" => from = Some(value()?),
+                "--input" => input = Some(value()?).filter(|path| path != PIPE_PATH),
+                "--output" => output = Some(value()?).filter(|path| path != PIPE_PATH),
+                "--name" => name = Some(value()?),
+                "--options" => options = Some(value()?),
+                _ => return Err(format!("unknown argument: {arg}")),
+            }
+        }
+
+        Ok(CliArgs {
+            to: to.ok_or("--to is required")?,
+            from,
+            input: input.map(PathBuf::from),
+            output: output.map(PathBuf::from),
+            name,
+            options,
+        })
+    }
+
+    /// Convert request for the source, the output is returned inline as the
+    /// request has no destination
+    fn request(&self, key: &str) -> Result<ConvertRequest, String> {
+        let mut request: Map<String, Value> = match &self.options {
+            Some(options) => serde_json::from_str(options)
+                .map_err(|err| format!("--options is not a JSON object: {err}"))?,
+            None => Map::new(),
+        };
+
+        request.insert("source_bucket".to_string(), PIPE_BUCKET.into());
+        request.insert("source_key".to_string(), key.into());
+        request.insert(
+            "output_format".to_string(),
+            serde_json::to_value(self.to).map_err(|err| err.to_string())?,
+        );
+
+        if let Some(from) = &self.from {
+            request.insert("source_format".to_string(), from.as_str().into());
+        }
+
+        serde_json::from_value(Value::Object(request))
+            .map_err(|err| format!("invalid request: {err}"))
+    }
+}
+
+/// Run the convert command with the `args` following the command, piping the
+/// source from stdin and the output to stdout so the converter can be used
+/// within shell pipelines
+pub async fn run(args: impl Iterator<Item = String>) -> Result<(), Error> {
+    let args = CliArgs::parse(args).map_err(|err| Error::from(format!("{err}\n\n{USAGE}")))?;
+
+    let source = match &args.input {
+        Some(path) => tokio::fs::read(path).await?,
+        None => {
+            let mut source = Vec::new();
+            tokio::io::stdin().read_to_end(&mut source).await?;
+            source
+        }
+    };
+
+    // The source name is used to detect formats that cannot be detected from their contents
+    let key = args
+        .name
+        .clone()
+        .or_else(|| {
+            let path = args.input.as_ref()?;
+            Some(path.file_name()?.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| DEFAULT_PIPE_KEY.to_string());
+
+    let request = args.request(&key).map_err(Error::from)?;
+    let options = ConvertOptions {
+        storage: Some(Arc::new(PipeStorage {
+            source: Bytes::from(source),
+        })),
+        ..Default::default()
+    };
+
+    let request_id = format!("cli-{}", std::process::id());
+    let result = convert_with_options(&request_id, request, options)
+        .await
+        .map_err(|error| match serde_json::to_string(&error) {
+            Ok(error_json) => Error::from(error_json),
+            Err(err) => Error::from(err),
+        })?;
+
+    if !result.substituted_fonts.is_empty() {
+        tracing::warn!(substituted_fonts = ?result.substituted_fonts, "fonts were substituted");
+    }
+
+    let ConvertOutput::Inline { bytes, .. } = result.output else {
+        return Err(Error::from("output was not returned inline"));
+    };
+
+    match &args.output {
+        Some(path) => tokio::fs::write(path, &bytes).await?,
+        None => {
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(&bytes).await?;
+            stdout.flush().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Storage serving the piped source, outputs are returned inline so nothing is stored
+struct PipeStorage {
+    source: Bytes,
+}
+
+impl Storage for PipeStorage {
+    fn get_object<'a>(
+        &'a self,
+        _bucket: &'a str,
+        _key: &'a str,
+        _options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<StorageObject, StorageError>> {
+        let object = StorageObject {
+            content_length: Some(self.source.len() as u64),
+            etag: None,
+            body: Box::pin(stream::iter([Ok(self.source.clone())])),
+        };
+
+        async move { Ok(object) }.boxed()
+    }
+
+    fn head_object<'a>(
+        &'a self,
+        _bucket: &'a str,
+        _key: &'a str,
+        _options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<ObjectHead, StorageError>> {
+        let head = ObjectHead {
+            content_length: Some(self.source.len() as u64),
+            etag: None,
+        };
+
+        async move { Ok(head) }.boxed()
+    }
+
+    fn put_object<'a>(
+        &'a self,
+        _bucket: &'a str,
+        _key: &'a str,
+        _body: PutBody<'a>,
+        _options: PutOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move { Err(unsupported()) }.boxed()
+    }
+
+    fn copy_object<'a>(
+        &'a self,
+        _source_bucket: &'a str,
+        _source_key: &'a str,
+        _dest_bucket: &'a str,
+        _dest_key: &'a str,
+        _options: WriteOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move { Err(unsupported()) }.boxed()
+    }
+
+    fn get_object_metadata<'a>(
+        &'a self,
+        _bucket: &'a str,
+        _key: &'a str,
+        _options: GetOptions<'a>,
+    ) -> BoxFuture<'a, Result<ObjectMetadata, StorageError>> {
+        async move { Ok(ObjectMetadata::default()) }.boxed()
+    }
+
+    fn delete_object<'a>(
+        &'a self,
+        _bucket: &'a str,
+        _key: &'a str,
+        _options: DeleteOptions<'a>,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move { Err(unsupported()) }.boxed()
+    }
+}
+
+fn unsupported() -> StorageError {
+    StorageError::Request("piped conversions do not store objects".to_string())
+}
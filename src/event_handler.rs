@@ -1,566 +1,504 @@
-use std::{
-    env::temp_dir,
-    path::{Path, PathBuf, absolute},
+use futures::{StreamExt, stream};
+use lambda_runtime::{
+    LambdaEvent,
+    tower::{ServiceBuilder, ServiceExt},
+};
+use onlyoffice_convert_core::{
+    cancel::CancelSignal,
+    concurrency::x2t_concurrency,
+    config_check::check_init_error,
+    convert::{ConvertOptions, ConvertOutput, ConvertRequest, ConvertResult, convert_with_options},
+    diagnostics::{environment_report, is_diagnostics_enabled},
+    error::ConvertError,
+    progress::with_progress_listener,
 };
-
-use aws_config::{BehaviorVersion, SdkConfig, meta::region::RegionProviderChain};
-use aws_sdk_s3::primitives::ByteStream;
-use lambda_runtime::LambdaEvent;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    process::Command,
+use serde_json::{Map, Value};
+
+use crate::{
+    auth::{authorize_request_tenant, request_jwt, verify_request_jwt},
+    fan_out::{
+        BatchChunk, ChunkGuard, cancel_job, complete_chunk, fail_chunk, fan_out_batch, job_status,
+        start_batch_job, watch_job,
+    },
+    http::{EventPayload, HttpRequest, HttpResponse, JobRoute, TENANT_HEADER},
+    job_store::JobOutput,
+    middleware::{
+        AuthLayer, Invocation, LoggingLayer, SizeLimitLayer, TimeoutLayer, error_response,
+    },
+    object_lambda::handle_object_lambda_event,
 };
-use uuid::Uuid;
-
-use crate::encrypted::{FileCondition, get_file_condition};
 
-const DEFAULT_X2T_PATH: &str = "/var/www/onlyoffice/documentserver/server/FileConverter/bin";
-const DEFAULT_FONTS_PATH: &str = "/var/www/onlyoffice/documentserver/fonts";
-
-#[cfg(not(windows))]
-const X2T_BIN: &str = "x2t";
-#[cfg(windows)]
-const X2T_BIN: &str = "x2t.exe";
+/// Header listing the substituted fonts for inline output responses
+const SUBSTITUTED_FONTS_HEADER: &str = "x-substituted-fonts";
 
 #[derive(Serialize)]
 pub struct Output {
     success: bool,
+    /// Fonts referenced by the source that were not available and have
+    /// been substituted in the output
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    substituted_fonts: Vec<String>,
 }
 
-pub(crate) async fn function_handler(
-    event: LambdaEvent<Value>,
-) -> Result<Output, lambda_runtime::Error> {
-    if let Err(error) = handle_request(event).await {
-        let error_json = serde_json::to_string(&error)?;
-        return Err(lambda_runtime::Error::from(error_json));
-    }
-
-    Ok(Output { success: true })
+/// Output for a batch of conversions
+#[derive(Serialize)]
+struct BatchOutput {
+    /// Whether every conversion in the batch succeeded
+    success: bool,
+    /// Result for each request in the batch, in the same order
+    results: Vec<BatchItemOutput>,
 }
 
-async fn handle_request(event: LambdaEvent<Value>) -> Result<(), LambdaError> {
-    let request: ConvertRequest = serde_json::from_value(event.payload).map_err(|err| {
-        tracing::error!(?err, "failed to parse request");
-
-        LambdaError {
-            reason: Some("PARSE_REQUEST"),
-            x2t_code: None,
-            message: "failed to parse convert request".to_string(),
-        }
-    })?;
-
-    let aws_config = aws_config().await;
-    let s3_client = aws_sdk_s3::Client::new(&aws_config);
-
-    let mut x2t_path: Option<PathBuf> = None;
-    let mut fonts_path: Option<PathBuf> = None;
-
-    // Try loading paths from environment variables
-    if x2t_path.is_none()
-        && let Ok(path) = std::env::var("X2T_PATH")
-    {
-        x2t_path = Some(PathBuf::from(&path));
-    }
+#[derive(Serialize)]
+struct BatchItemOutput {
+    success: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    substituted_fonts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ConvertError>,
+}
 
-    if fonts_path.is_none()
-        && let Ok(path) = std::env::var("X2T_FONTS_PATH")
-    {
-        fonts_path = Some(PathBuf::from(&path));
-    }
+/// Request from a direct invocation, either a single conversion, a batch, a
+/// chunk of a fanned out batch or the status query / cancellation of a fanned
+/// out batch
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DirectRequest {
+    JobStatus {
+        job_status: String,
+        /// Tenant the job was created by
+        #[serde(default)]
+        tenant: Option<String>,
+    },
+    CancelJob {
+        cancel_job: String,
+        #[serde(default)]
+        tenant: Option<String>,
+    },
+    BatchChunk {
+        batch_chunk: BatchChunk,
+    },
+    Batch {
+        batch: Vec<ConvertRequest>,
+    },
+    Single(Box<ConvertRequest>),
+}
 
-    // Try determine default path
-    if x2t_path.is_none() {
-        let default_path = Path::new(DEFAULT_X2T_PATH);
+pub(crate) async fn function_handler(
+    event: LambdaEvent<Value>,
+) -> Result<Value, lambda_runtime::Error> {
+    let (payload, context) = event.into_parts();
 
-        if default_path.is_dir() {
-            x2t_path = Some(default_path.to_path_buf());
+    let payload = match EventPayload::from_value(payload) {
+        Ok(value) => value,
+        Err(error) => {
+            let error_json = serde_json::to_string(&error)?;
+            return Err(lambda_runtime::Error::from(error_json));
         }
-    }
+    };
 
-    if fonts_path.is_none() {
-        let default_path = Path::new(DEFAULT_FONTS_PATH);
-        fonts_path = Some(default_path.to_path_buf());
+    // Functions that failed to initialize report the configuration error to each invocation
+    if let Err(error) = check_init_error() {
+        return error_response(matches!(payload, EventPayload::Http(_)), error);
     }
 
-    // Check a path was provided
-    let x2t_path = match x2t_path {
-        Some(value) => absolute(value).map_err(|err| {
-            tracing::error!(?err, "failed to make x2t path absolute");
+    // Cross-cutting policies are applied by the layers before the invocation is handled
+    ServiceBuilder::new()
+        .layer(LoggingLayer)
+        .layer(TimeoutLayer)
+        .layer(SizeLimitLayer)
+        .layer(AuthLayer)
+        .service_fn(handle_invocation)
+        .oneshot(Invocation { context, payload })
+        .await
+}
 
-            LambdaError {
-                reason: Some("X2T_PATH_ABSOLUTE"),
-                x2t_code: None,
-                message: "failed to make x2t path absolute".to_string(),
+/// Handle an invocation once it has passed through the middleware layers
+async fn handle_invocation(invocation: Invocation) -> Result<Value, lambda_runtime::Error> {
+    let Invocation { context, payload } = invocation;
+
+    match payload {
+        EventPayload::Direct(payload) => {
+            // Batches too large for a single invocation are split across invocations
+            if let Some(batch) = fan_out_batch(&payload) {
+                // Malformed requests are rejected before any chunk is invoked
+                let result =
+                    match parse_request(Vec::<ConvertRequest>::deserialize(&payload["batch"]))
+                        .and_then(|requests| {
+                            let tenant = batch_tenant(&requests)?;
+                            Ok((requests, tenant))
+                        }) {
+                        Ok((requests, tenant)) => {
+                            let outputs = requests
+                                .iter()
+                                .map(|request| {
+                                    let (bucket, keys) = request.output_locations()?;
+                                    Some(JobOutput {
+                                        bucket: bucket.to_string(),
+                                        keys,
+                                    })
+                                })
+                                .collect();
+
+                            start_batch_job(&context.request_id, tenant, batch, outputs).await
+                        }
+                        Err(error) => Err(error),
+                    };
+
+                return match result {
+                    Ok(output) => Ok(serde_json::to_value(output)?),
+                    Err(error) => {
+                        let error_json = serde_json::to_string(&error)?;
+                        Err(lambda_runtime::Error::from(error_json))
+                    }
+                };
             }
-        })?,
-        None => {
-            tracing::error!("no x2t install path provided, cannot start server");
-            panic!();
-        }
-    };
-
-    let fonts_path = match fonts_path {
-        Some(value) => absolute(value).map_err(|err| {
-            tracing::error!(?err, "failed to make fonts path absolute");
 
-            LambdaError {
-                reason: Some("X2T_FONTS_PATH_ABSOLUTE"),
-                x2t_code: None,
-                message: "failed to make x2t fonts path absolute".to_string(),
+            let result = match parse_request(serde_json::from_value(payload)) {
+                Ok(DirectRequest::JobStatus {
+                    job_status: job_id,
+                    tenant,
+                }) => {
+                    return match job_status(&job_id, tenant.as_deref()).await {
+                        Ok(output) => Ok(serde_json::to_value(output)?),
+                        Err(error) => {
+                            let error_json = serde_json::to_string(&error)?;
+                            Err(lambda_runtime::Error::from(error_json))
+                        }
+                    };
+                }
+                Ok(DirectRequest::CancelJob {
+                    cancel_job: job_id,
+                    tenant,
+                }) => {
+                    return match cancel_job(&job_id, tenant.as_deref()).await {
+                        Ok(job) => Ok(serde_json::to_value(job)?),
+                        Err(error) => {
+                            let error_json = serde_json::to_string(&error)?;
+                            Err(lambda_runtime::Error::from(error_json))
+                        }
+                    };
+                }
+                Ok(DirectRequest::BatchChunk { batch_chunk }) => {
+                    let output = handle_batch_chunk(&context.request_id, batch_chunk).await?;
+                    return Ok(serde_json::to_value(output)?);
+                }
+                Ok(DirectRequest::Batch { batch }) => {
+                    let output = handle_batch(&context.request_id, batch, None).await;
+                    return Ok(serde_json::to_value(output)?);
+                }
+                Ok(DirectRequest::Single(request)) => {
+                    handle_direct_request(&context.request_id, *request, None).await
+                }
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(output) => Ok(serde_json::to_value(output)?),
+                Err(error) => {
+                    let error_json = serde_json::to_string(&error)?;
+                    Err(lambda_runtime::Error::from(error_json))
+                }
             }
-        })?,
-        None => {
-            tracing::error!("no fonts path provided, cannot start server");
-            panic!();
         }
-    };
-
-    let temp_path = temp_dir().join("onlyoffice-convert-server");
 
-    // Ensure temporary path exists
-    if !temp_path.exists() {
-        tokio::fs::create_dir_all(&temp_path).await.map_err(|err| {
-            tracing::error!(?err, "failed to create temporary directory");
-
-            LambdaError {
-                reason: Some("SETUP_TEMP_DIR_FAILED"),
-                x2t_code: None,
-                message: "failed to create temporary directory".to_string(),
+        EventPayload::Http(http_request) => {
+            if http_request.is_diagnostics_route() {
+                let response = handle_diagnostics_request(&http_request).await?;
+                return Ok(response.into_event_value());
             }
-        })?;
-    }
-
-    // Create temporary path
-    let paths = create_convert_temp_paths(&temp_path).map_err(|err| {
-        tracing::error!(?err, "failed to setup temporary paths");
-        LambdaError {
-            reason: Some("SETUP_TEMP_FAILED"),
-            x2t_code: None,
-            message: "failed to setup temporary file paths".to_string(),
-        }
-    })?;
-
-    // Generate the convert config
-    let config = format!(
-        r#"
-        <?xml version="1.0" encoding="utf-8"?>
-        <TaskQueueDataConvert xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
-                              xmlns:xsd="http://www.w3.org/2001/XMLSchema">
-          <m_sFileFrom>{}</m_sFileFrom>
-          <m_sFileTo>{}</m_sFileTo>
-          <m_sFontDir>{}</m_sFontDir>
-          <m_sTempDir>{}</m_sTempDir>
-          <m_nFormatTo>513</m_nFormatTo>
-        </TaskQueueDataConvert>
-        "#,
-        paths.input_path.display(),
-        paths.output_path.display(),
-        fonts_path.display(),
-        paths.temp_path.display(),
-    );
-
-    let result = x2t(X2tInput {
-        s3_client: &s3_client,
-        paths: &paths,
-        request,
-        config_bytes: config.as_bytes(),
-        x2t_path: &x2t_path,
-    })
-    .await;
-
-    // Spawn a cleanup task
-    tokio::spawn(async move {
-        if paths.input_path.exists()
-            && let Err(err) = tokio::fs::remove_file(paths.input_path).await
-        {
-            tracing::error!(?err, "failed to delete config file");
-        }
 
-        if paths.config_path.exists()
-            && let Err(err) = tokio::fs::remove_file(paths.config_path).await
-        {
-            tracing::error!(?err, "failed to delete config file");
-        }
+            if let Some(route) = http_request.job_route() {
+                let response = handle_job_request(*http_request, route).await?;
+                return Ok(response.into_event_value());
+            }
 
-        if paths.output_path.exists()
-            && let Err(err) = tokio::fs::remove_file(paths.output_path).await
-        {
-            tracing::error!(?err, "failed to delete config file");
+            let caller = http_request.caller.clone();
+            let response = handle_http_request(*http_request, |request| {
+                let options = ConvertOptions {
+                    caller,
+                    ..Default::default()
+                };
+                convert_with_options(&context.request_id, request, options)
+            })
+            .await?;
+            Ok(response.into_event_value())
         }
 
-        if paths.temp_path.exists()
-            && let Err(err) = tokio::fs::remove_dir_all(paths.temp_path).await
-        {
-            tracing::error!(?err, "failed to remove converter temporary files");
+        EventPayload::ObjectLambda(event) => {
+            handle_object_lambda_event(&context.request_id, *event).await
         }
-    });
-
-    result?;
-
-    Ok(())
-}
-
-struct X2tInput<'a> {
-    s3_client: &'a aws_sdk_s3::Client,
-    paths: &'a ConvertTempPaths,
-    request: ConvertRequest,
-    config_bytes: &'a [u8],
-    x2t_path: &'a Path,
+    }
 }
 
-async fn x2t(input: X2tInput<'_>) -> Result<(), LambdaError> {
-    tracing::debug!("writing config file");
+/// Handle a convert request made over HTTP using `convert` to run the conversion,
+/// the output is returned in the response when the request does not specify a destination.
+/// The HMAC signature must already have been verified (See [AuthLayer])
+pub async fn handle_http_request<F, Fut>(
+    http_request: HttpRequest,
+    convert: F,
+) -> Result<HttpResponse, serde_json::Error>
+where
+    F: FnOnce(ConvertRequest) -> Fut,
+    Fut: Future<Output = Result<ConvertResult, ConvertError>>,
+{
+    let jwt = request_jwt(&http_request);
+    let caller = http_request.caller.clone();
+    let signed = http_request.signed;
+    let result = match http_request
+        .into_request_value()
+        .and_then(|value| verify_request_jwt(jwt, value))
+        .and_then(|value| authorize_request_tenant(value, caller.as_deref(), signed))
+        .and_then(|value| parse_request(serde_json::from_value(value)))
+    {
+        Ok(request) => convert(request).await,
+        Err(error) => Err(error),
+    };
 
-    // Write the config file to disk
-    tokio::fs::write(&input.paths.config_path, input.config_bytes)
-        .await
-        .map_err(|err| {
-            tracing::error!(?err, "failed to write config file");
-            LambdaError {
-                reason: Some("WRITE_CONFIG_FILE"),
-                x2t_code: None,
-                message: "failed to write config file".to_string(),
+    let response = match result {
+        Ok(ConvertResult {
+            output: ConvertOutput::Uploaded,
+            substituted_fonts,
+        }) => HttpResponse::json(
+            200,
+            &Output {
+                success: true,
+                substituted_fonts,
+            },
+        )?,
+        Ok(ConvertResult {
+            output:
+                ConvertOutput::Inline {
+                    bytes,
+                    content_type,
+                },
+            substituted_fonts,
+        }) => {
+            let mut response = HttpResponse::binary(200, content_type, bytes);
+            if !substituted_fonts.is_empty() {
+                response
+                    .headers
+                    .push((SUBSTITUTED_FONTS_HEADER, substituted_fonts.join(",")));
             }
-        })?;
-
-    tracing::debug!("streaming source file");
-
-    // Stream the input file to disk
-    stream_source_file(
-        input.s3_client,
-        input.request.source_bucket,
-        input.request.source_key,
-        &input.paths.input_path,
-    )
-    .await?;
-
-    let x2t = input.x2t_path.join(X2T_BIN);
-    let x2t = x2t.to_string_lossy();
-
-    // Update the library path to include the x2t bin directory, fixes a bug where some of the requires
-    // .so libraries aren't loaded when they need to be
-    let ld_library_path = std::env::var("LD_LIBRARY_PATH").unwrap_or_default();
-    let ld_library_path = format!("{}:{}", input.x2t_path.display(), ld_library_path);
-
-    tracing::debug!("running x2t");
+            response
+        }
+        Err(error) => HttpResponse::json(error.status_code(), &error)?,
+    };
 
-    let output = Command::new(x2t.as_ref())
-        .arg(input.paths.config_path.display().to_string())
-        .env("LD_LIBRARY_PATH", &ld_library_path)
-        .output()
-        .await
-        .map_err(|err| {
-            tracing::error!(?err, "failed to run x2t");
-            LambdaError {
-                reason: Some("RUN_X2T"),
-                x2t_code: None,
-                message: "failed to run x2t".to_string(),
-            }
-        })?;
+    Ok(response)
+}
 
-    tracing::debug!("x2t complete");
+/// Handle a request to query or cancel a fanned out batch made over HTTP, responds
+/// with the status of the job or the cancelled job
+async fn handle_job_request(
+    http_request: HttpRequest,
+    route: JobRoute,
+) -> Result<HttpResponse, serde_json::Error> {
+    // Jobs are only available to the tenant of the authenticated caller, the tenant
+    // header of signed requests is covered by the signature
+    let jwt = request_jwt(&http_request);
+    let mut request = Map::new();
+    if let (true, Some(tenant)) = (http_request.signed, http_request.header(TENANT_HEADER)) {
+        request.insert("tenant".to_string(), Value::String(tenant.to_string()));
+    }
 
-    if !output.status.success() {
-        let error_code = output.status.code();
-        let message = error_code
-            .and_then(get_error_code_message)
-            .unwrap_or("unknown error occurred");
+    let tenant = match verify_request_jwt(jwt, Value::Object(request)).and_then(|value| {
+        authorize_request_tenant(value, http_request.caller.as_deref(), http_request.signed)
+    }) {
+        Ok(value) => value
+            .get("tenant")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        Err(error) => return HttpResponse::json(error.status_code(), &error),
+    };
+    let tenant = tenant.as_deref();
+
+    match route {
+        JobRoute::Status(job_id) => match job_status(&job_id, tenant).await {
+            Ok(output) => HttpResponse::json(200, &output),
+            Err(error) => HttpResponse::json(error.status_code(), &error),
+        },
+        JobRoute::Cancel(job_id) => match cancel_job(&job_id, tenant).await {
+            Ok(job) => HttpResponse::json(200, &job),
+            Err(error) => HttpResponse::json(error.status_code(), &error),
+        },
+    }
+}
 
-        let stderr = String::from_utf8_lossy(&output.stderr);
+/// Handle a request for the environment diagnostics made over HTTP, responds with
+/// the report when diagnostics are enabled
+pub async fn handle_diagnostics_request(
+    http_request: &HttpRequest,
+) -> Result<HttpResponse, serde_json::Error> {
+    let jwt = request_jwt(http_request);
+    if let Err(error) = verify_request_jwt(jwt, Value::Object(Map::new())) {
+        return HttpResponse::json(error.status_code(), &error);
+    }
 
-        tracing::debug!("reading file integrity");
+    if !is_diagnostics_enabled() {
+        let error = ConvertError {
+            reason: Some("DIAGNOSTICS_DISABLED"),
+            x2t_code: None,
+            message: "diagnostics are not enabled".to_string(),
+        };
+        return HttpResponse::json(error.status_code(), &error);
+    }
 
-        let mut file = tokio::fs::File::open(&input.paths.input_path)
-            .await
-            .map_err(|err| {
-                tracing::error!(?err, "failed to open input file for integrity check");
+    match environment_report().await {
+        Ok(report) => HttpResponse::json(200, &report),
+        Err(error) => HttpResponse::json(error.status_code(), &error),
+    }
+}
 
-                LambdaError {
-                    reason: Some("OPEN_FILE_INTEGRITY"),
-                    x2t_code: None,
-                    message: "failed to open input file for integrity check".to_string(),
-                }
-            })?;
-        let mut file_bytes = [0u8; 1024 * 32];
-        let mut file_size: usize = 0;
-
-        loop {
-            // Read a chunk into the buffer
-            let n = file
-                .read(&mut file_bytes[file_size..])
-                .await
-                .map_err(|err| {
-                    tracing::error!(?err, "failed to read input file for integrity check");
-
-                    LambdaError {
-                        reason: Some("READ_FILE_INTEGRITY"),
-                        x2t_code: None,
-                        message: "failed to read input file for integrity check".to_string(),
-                    }
-                })?;
-            if n == 0 {
-                break;
-            }
+/// Handle the result of parsing a request
+fn parse_request<T>(result: Result<T, serde_json::Error>) -> Result<T, ConvertError> {
+    result.map_err(|err| {
+        tracing::error!(?err, "failed to parse request");
 
-            file_size += n;
+        ConvertError {
+            reason: Some("PARSE_REQUEST"),
+            x2t_code: None,
+            message: "failed to parse convert request".to_string(),
         }
+    })
+}
 
-        file_size = file_size.min(file_bytes.len());
-
-        tracing::debug!("finished reading file integrity, checking integrity");
+/// Handle a request from a direct invocation, these must specify a
+/// destination as inline output is only supported for HTTP events
+async fn handle_direct_request(
+    request_id: &str,
+    request: ConvertRequest,
+    cancel: Option<CancelSignal>,
+) -> Result<Output, ConvertError> {
+    if request.destination().is_none() {
+        return Err(ConvertError {
+            reason: Some("MISSING_DESTINATION"),
+            x2t_code: None,
+            message: "dest_bucket and dest_key are required".to_string(),
+        });
+    }
 
-        let file_condition = get_file_condition(&file_bytes[0..file_size]);
+    let options = ConvertOptions {
+        cancel,
+        ..Default::default()
+    };
+    let result = convert_with_options(request_id, request, options).await?;
 
-        tracing::error!(
-            "error processing file (stderr = {stderr}, exit code = {error_code:?}, file_condition = {file_condition:?})"
-        );
+    Ok(Output {
+        success: true,
+        substituted_fonts: result.substituted_fonts,
+    })
+}
 
-        // Assume encryption for out of range crashes
-        if stderr.contains("std::out_of_range") {
-            return Err(LambdaError {
-                reason: Some("FILE_LIKELY_ENCRYPTED"),
-                x2t_code: error_code,
-                message: "file is encrypted".to_string(),
-            });
-        }
+/// Tenant of a batch that is fanned out into a job, the requests of the batch
+/// must share the tenant that owns the job
+fn batch_tenant(requests: &[ConvertRequest]) -> Result<Option<String>, ConvertError> {
+    let tenant = requests.first().and_then(ConvertRequest::tenant);
 
-        return Err(match file_condition {
-            FileCondition::LikelyCorrupted => LambdaError {
-                reason: Some("FILE_LIKELY_CORRUPTED"),
-                x2t_code: error_code,
-                message: "file is corrupted".to_string(),
-            },
-            FileCondition::LikelyEncrypted => LambdaError {
-                reason: Some("FILE_LIKELY_ENCRYPTED"),
-                x2t_code: error_code,
-                message: "file is encrypted".to_string(),
-            },
-            _ => LambdaError {
-                reason: None,
-                x2t_code: error_code,
-                message: message.to_string(),
-            },
+    if requests.iter().any(|request| request.tenant() != tenant) {
+        return Err(ConvertError {
+            reason: Some("INVALID_REQUEST"),
+            x2t_code: None,
+            message: "batch: requests must have the same tenant".to_string(),
         });
     }
 
-    stream_output_file(
-        input.s3_client,
-        input.request.dest_bucket,
-        input.request.dest_key,
-        &input.paths.output_path,
-    )
-    .await?;
-
-    Ok(())
+    Ok(tenant.map(str::to_string))
 }
 
-#[derive(Deserialize)]
-struct ConvertRequest {
-    /// Bucket the input source file is within
-    source_bucket: String,
-    /// Key within the source bucket for the source file
-    source_key: String,
-
-    /// Bucket to store the output file
-    dest_bucket: String,
-    /// Key within the `dest_bucket` for the output file
-    dest_key: String,
-}
+/// Handle a batch of requests, up to [x2t_concurrency] requests are processed
+/// concurrently so the sources of waiting requests are not streamed to disk
+/// before an x2t process is available
+async fn handle_batch(
+    request_id: &str,
+    requests: Vec<ConvertRequest>,
+    cancel: Option<CancelSignal>,
+) -> BatchOutput {
+    let results: Vec<Result<Output, ConvertError>> =
+        stream::iter(requests.into_iter().enumerate().map(|(index, request)| {
+            let request_id = format!("{request_id}-{index}");
+            let cancel = cancel.clone();
+            async move { handle_direct_request(&request_id, request, cancel).await }
+        }))
+        .buffered(x2t_concurrency())
+        .collect()
+        .await;
+
+    let results: Vec<BatchItemOutput> = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(output) => BatchItemOutput {
+                success: true,
+                substituted_fonts: output.substituted_fonts,
+                error: None,
+            },
+            Err(error) => BatchItemOutput {
+                success: false,
+                substituted_fonts: Vec::new(),
+                error: Some(error),
+            },
+        })
+        .collect();
 
-struct ConvertTempPaths {
-    config_path: PathBuf,
-    input_path: PathBuf,
-    temp_path: PathBuf,
-    output_path: PathBuf,
+    BatchOutput {
+        success: results.iter().all(|result| result.success),
+        results,
+    }
 }
 
-/// Stream a file from S3 to disk
-async fn stream_source_file(
-    s3_client: &aws_sdk_s3::Client,
-    source_bucket: String,
-    source_key: String,
-    file_path: &Path,
-) -> Result<(), LambdaError> {
-    let response = match s3_client
-        .get_object()
-        .bucket(source_bucket)
-        .key(source_key)
-        .send()
-        .await
-    {
+/// Handle a chunk of a fanned out batch, the results are stored within the job
+/// store to be aggregated into the job
+async fn handle_batch_chunk(
+    request_id: &str,
+    chunk: BatchChunk,
+) -> Result<BatchOutput, lambda_runtime::Error> {
+    let requests = match parse_request(serde_json::from_value(Value::Array(chunk.batch))) {
         Ok(value) => value,
-        Err(err) => {
-            tracing::error!(?err, "error streaming source file");
-
-            if err
-                .as_service_error()
-                .is_some_and(|value| value.is_no_such_key())
-            {
-                return Err(LambdaError {
-                    reason: Some("NO_SUCH_KEY"),
-                    x2t_code: None,
-                    message: "key not found in source bucket".to_string(),
-                });
+        Err(error) => {
+            if let Err(err) = fail_chunk(&chunk.job_id, &error).await {
+                tracing::error!(?err, "failed to fail batch chunk job");
             }
-
-            return Err(LambdaError {
-                reason: Some("GET_OBJECT"),
-                x2t_code: None,
-                message: err.to_string(),
-            });
+            return Err(lambda_runtime::Error::from(serde_json::to_string(&error)?));
         }
     };
 
-    let mut body = response.body;
-
-    let mut file = tokio::fs::File::create(file_path).await.map_err(|err| {
-        tracing::error!(?err, "failed to create source file");
-        LambdaError {
-            reason: Some("GET_OBJECT"),
-            x2t_code: None,
-            message: err.to_string(),
-        }
-    })?;
-
-    while let Some(chunk_result) = body.next().await {
-        let chunk = chunk_result.map_err(|err| {
-            tracing::error!(?err, "failed to read object chunk");
-            LambdaError {
-                reason: Some("READ_OBJECT_CHUNK"),
-                x2t_code: None,
-                message: "failed to read chunk".to_string(),
-            }
-        })?;
-
-        file.write_all(&chunk).await.map_err(|err| {
-            tracing::error!(?err, "failed to write object chunk");
-            LambdaError {
-                reason: Some("WRITE_OBJECT_CHUNK"),
-                x2t_code: None,
-                message: "failed to write chunk".to_string(),
-            }
-        })?;
-    }
-
-    file.flush().await.map_err(|err| {
-        tracing::error!(?err, "failed to flush object");
-        LambdaError {
-            reason: Some("FLUSH_OBJECT"),
-            x2t_code: None,
-            message: "failed to flush object".to_string(),
+    // Chunks of cancelled or failed jobs are skipped, the conversions of the chunk
+    // are cancelled if the job stops running while converting
+    let watch = match watch_job(&chunk.job_id, chunk.index).await {
+        Ok(Some(value)) => value,
+        Ok(None) => {
+            tracing::info!(job_id = %chunk.job_id, "skipping chunk of job that is not running");
+            return Ok(BatchOutput {
+                success: false,
+                results: Vec::new(),
+            });
         }
-    })?;
+        Err(error) => return Err(lambda_runtime::Error::from(serde_json::to_string(&error)?)),
+    };
 
-    Ok(())
-}
+    // The job is failed when the chunk is dropped by the invocation timing out
+    let guard = ChunkGuard::new(&chunk.job_id);
 
-/// Stream a file upload from disk to S3
-async fn stream_output_file(
-    s3_client: &aws_sdk_s3::Client,
-    dest_bucket: String,
-    dest_key: String,
-    file_path: &Path,
-) -> Result<(), LambdaError> {
-    let byte_stream = ByteStream::from_path(file_path).await.map_err(|err| {
-        tracing::error!(?err, "failed to create output stream");
-        LambdaError {
-            reason: Some("CREATE_OUTPUT_STREAM"),
-            x2t_code: None,
-            message: "failed to create output stream".to_string(),
-        }
-    })?;
-
-    s3_client
-        .put_object()
-        .bucket(dest_bucket)
-        .key(dest_key)
-        .body(byte_stream)
-        .send()
-        .await
-        .map_err(|err| {
-            tracing::error!(?err, "failed to upload output");
-            LambdaError {
-                reason: Some("UPLOAD_OUTPUT_STREAM"),
-                x2t_code: None,
-                message: "failed to upload output stream".to_string(),
-            }
-        })?;
-
-    Ok(())
-}
+    let output = with_progress_listener(
+        watch.listener.clone(),
+        handle_batch(request_id, requests, Some(watch.signal.clone())),
+    )
+    .await;
+    drop(watch);
 
-fn create_convert_temp_paths(temp_dir: &Path) -> std::io::Result<ConvertTempPaths> {
-    // Generate random unique ID
-    let random_id = Uuid::new_v4().simple();
-
-    // Create paths in temp directory
-    let config_path = temp_dir.join(format!("tmp_native_config_{random_id}.xml"));
-    let input_path = temp_dir.join(format!("tmp_native_input_{random_id}"));
-    let output_path = temp_dir.join(format!("tmp_native_output_{random_id}.pdf"));
-    let temp_path = temp_dir.join(format!("tmp_native_temp_{random_id}"));
-
-    // Make paths absolute
-    let config_path = absolute(config_path)
-        .inspect_err(|err| tracing::error!(?err, "failed to make file path absolute (config)"))?;
-    let input_path = absolute(input_path)
-        .inspect_err(|err| tracing::error!(?err, "failed to make file path absolute (input)"))?;
-    let output_path = absolute(output_path)
-        .inspect_err(|err| tracing::error!(?err, "failed to make file path absolute (output)"))?;
-    let temp_path = absolute(temp_path)
-        .inspect_err(|err| tracing::error!(?err, "failed to make file path absolute (temp)"))?;
-
-    Ok(ConvertTempPaths {
-        config_path,
-        input_path,
-        output_path,
-        temp_path,
-    })
-}
+    let results = output
+        .results
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?;
 
-/// Create the AWS production configuration
-pub async fn aws_config() -> SdkConfig {
-    let region_provider = RegionProviderChain::default_provider()
-        // Fallback to our desired region
-        .or_else("ap-southeast-2");
-
-    // Load the configuration from env variables (See https://docs.aws.amazon.com/sdkref/latest/guide/settings-reference.html#EVarSettings)
-    aws_config::defaults(BehaviorVersion::v2025_08_07())
-        // Setup the region provider
-        .region(region_provider)
-        .load()
-        .await
-}
+    let completed = complete_chunk(&chunk.job_id, chunk.index, &results).await;
+    guard.disarm();
 
-#[derive(Serialize, Debug)]
-pub struct LambdaError {
-    pub reason: Option<&'static str>,
-    pub x2t_code: Option<i32>,
-    pub message: String,
-}
+    if let Err(error) = completed {
+        return Err(lambda_runtime::Error::from(serde_json::to_string(&error)?));
+    }
 
-/// Translate a x2t error code to the common x2t error messages
-fn get_error_code_message(code: i32) -> Option<&'static str> {
-    Some(match code {
-        0x0001 => "AVS_FILEUTILS_ERROR_UNKNOWN",
-        0x0050 => "AVS_FILEUTILS_ERROR_CONVERT",
-        0x0051 => "AVS_FILEUTILS_ERROR_CONVERT_DOWNLOAD",
-        0x0052 => "AVS_FILEUTILS_ERROR_CONVERT_UNKNOWN_FORMAT",
-        0x0053 => "AVS_FILEUTILS_ERROR_CONVERT_TIMEOUT",
-        0x0054 => "AVS_FILEUTILS_ERROR_CONVERT_READ_FILE",
-        0x0055 => "AVS_FILEUTILS_ERROR_CONVERT_DRM_UNSUPPORTED",
-        0x0056 => "AVS_FILEUTILS_ERROR_CONVERT_CORRUPTED",
-        0x0057 => "AVS_FILEUTILS_ERROR_CONVERT_LIBREOFFICE",
-        0x0058 => "AVS_FILEUTILS_ERROR_CONVERT_PARAMS",
-        0x0059 => "AVS_FILEUTILS_ERROR_CONVERT_NEED_PARAMS",
-        0x005a => "AVS_FILEUTILS_ERROR_CONVERT_DRM",
-        0x005b => "AVS_FILEUTILS_ERROR_CONVERT_PASSWORD",
-        0x005c => "AVS_FILEUTILS_ERROR_CONVERT_ICU",
-        0x005d => "AVS_FILEUTILS_ERROR_CONVERT_LIMITS",
-        0x005e => "AVS_FILEUTILS_ERROR_CONVERT_ROWLIMITS",
-        0x005f => "AVS_FILEUTILS_ERROR_CONVERT_DETECT",
-        0x0060 => "AVS_FILEUTILS_ERROR_CONVERT_CELLLIMITS",
-        _ => return None,
-    })
+    Ok(output)
 }
@@ -0,0 +1,529 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "fan-out")]
+use aws_sdk_lambda::{primitives::Blob, types::InvocationType};
+#[cfg(feature = "fan-out")]
+use onlyoffice_convert_core::aws::aws_config;
+use onlyoffice_convert_core::{
+    cancel::{CancelSignal, cancel_signal},
+    config::app_config,
+    error::ConvertError,
+    progress::{ConvertStage, ProgressListener, progress_channel},
+    storage::S3Storage,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+#[cfg(feature = "fan-out")]
+use tokio::sync::OnceCell;
+
+use crate::job_store::{
+    Job, JobOutput, JobStatus, JobStore, is_job_store_enabled, validate_job_id,
+};
+
+/// Environment variable set by Lambda to the name of the running function
+const FUNCTION_NAME_ENV: &str = "AWS_LAMBDA_FUNCTION_NAME";
+
+/// Time between checks of whether the job of a running chunk has been cancelled,
+/// progress reported since the previous check is recorded in the job
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Maximum time between the progress recorded for a running chunk
+const CHUNK_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Client used to invoke the chunks, cached across warm invocations
+#[cfg(feature = "fan-out")]
+static LAMBDA_CLIENT: OnceCell<aws_sdk_lambda::Client> = OnceCell::const_new();
+
+/// Chunk of a fanned out batch, converted by an asynchronous invocation of
+/// the function
+#[derive(Serialize, Deserialize)]
+pub struct BatchChunk {
+    pub job_id: String,
+    /// Index of the chunk within the job
+    pub index: usize,
+    pub batch: Vec<Value>,
+}
+
+/// Payload of a chunk invocation
+#[cfg(feature = "fan-out")]
+#[derive(Serialize)]
+struct BatchChunkPayload<'a> {
+    batch_chunk: &'a BatchChunk,
+}
+
+/// Output for a batch that has been fanned out, the results are aggregated
+/// into the job once every chunk has completed
+#[derive(Serialize)]
+pub struct BatchJobOutput {
+    success: bool,
+    job_id: String,
+    status: JobStatus,
+    items: usize,
+    chunks: usize,
+}
+
+/// Status of a job for clients polling for its completion
+#[derive(Serialize)]
+pub struct JobStatusOutput {
+    #[serde(flatten)]
+    job: Job,
+    /// Time the job took in milliseconds, set once the job is no longer running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    /// Presigned URLs for the outputs of each request in the batch in the same order,
+    /// set once completed. Failed requests and requests with inline outputs have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_urls: Option<Vec<Vec<String>>>,
+}
+
+/// Get the batch of a direct invocation payload when the batch is too large for
+/// a single invocation and should be fanned out, requires the job store and must
+/// be running within Lambda with the `fan-out` feature
+pub fn fan_out_batch(payload: &Value) -> Option<&Vec<Value>> {
+    let batch = payload.get("batch")?.as_array()?;
+
+    if !cfg!(feature = "fan-out")
+        || batch.len() <= app_config().batch_chunk_size
+        || !is_job_store_enabled()
+        || std::env::var(FUNCTION_NAME_ENV).is_err()
+    {
+        return None;
+    }
+
+    Some(batch)
+}
+
+/// Create a job for the `batch` and asynchronously invoke the function for each
+/// chunk of the batch, returns once every chunk has been invoked. The `outputs`
+/// are the output locations of each request in the batch, the job is owned by
+/// the `tenant` of the requests
+pub async fn start_batch_job(
+    job_id: &str,
+    tenant: Option<String>,
+    batch: &[Value],
+    outputs: Vec<Option<JobOutput>>,
+) -> Result<BatchJobOutput, ConvertError> {
+    let store = JobStore::from_env().await.ok_or_else(fan_out_error)?;
+    let function_name = std::env::var(FUNCTION_NAME_ENV).map_err(|_| fan_out_error())?;
+
+    let chunks: Vec<Vec<Value>> = batch
+        .chunks(app_config().batch_chunk_size)
+        .map(<[Value]>::to_vec)
+        .collect();
+
+    let job = Job::new(
+        job_id.to_string(),
+        tenant,
+        batch.len(),
+        chunks.len(),
+        outputs,
+    );
+    store.put_job(&job).await?;
+
+    for (index, batch) in chunks.into_iter().enumerate() {
+        let chunk = BatchChunk {
+            job_id: job_id.to_string(),
+            index,
+            batch,
+        };
+
+        if let Err(error) = invoke_chunk(&function_name, &chunk).await {
+            // Chunks that were already invoked still run but the job is not completed
+            store
+                .update_job(job_id, |job| fail_running(job, &error))
+                .await?;
+            return Err(error);
+        }
+    }
+
+    Ok(BatchJobOutput {
+        success: true,
+        job_id: job.job_id,
+        status: job.status,
+        items: job.items,
+        chunks: job.chunks,
+    })
+}
+
+/// Asynchronously invoke the function with the `function_name` to convert the `chunk`
+#[cfg(feature = "fan-out")]
+async fn invoke_chunk(function_name: &str, chunk: &BatchChunk) -> Result<(), ConvertError> {
+    let client = LAMBDA_CLIENT
+        .get_or_init(|| async { aws_sdk_lambda::Client::new(&aws_config().await) })
+        .await;
+
+    let payload = serde_json::to_vec(&BatchChunkPayload { batch_chunk: chunk }).map_err(|err| {
+        tracing::error!(?err, "failed to serialize batch chunk");
+        fan_out_error()
+    })?;
+
+    client
+        .invoke()
+        .function_name(function_name)
+        .invocation_type(InvocationType::Event)
+        .payload(Blob::new(payload))
+        .send()
+        .await
+        .map_err(|err| {
+            tracing::error!(?err, index = chunk.index, "failed to invoke batch chunk");
+            fan_out_error()
+        })?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "fan-out"))]
+async fn invoke_chunk(_function_name: &str, chunk: &BatchChunk) -> Result<(), ConvertError> {
+    tracing::error!(
+        index = chunk.index,
+        "invoking batch chunks requires the fan-out feature"
+    );
+    Err(fan_out_error())
+}
+
+/// Store the `results` of a chunk, the job is completed with the results of every
+/// chunk in order once the last chunk has been stored
+pub async fn complete_chunk(
+    job_id: &str,
+    index: usize,
+    results: &[Value],
+) -> Result<(), ConvertError> {
+    let store = JobStore::from_env().await.ok_or_else(fan_out_error)?;
+    store.put_chunk_results(job_id, index, results).await?;
+
+    let job = store
+        .update_job(job_id, |job| {
+            if job.status != JobStatus::Running {
+                return false;
+            }
+
+            job.complete_chunk(index);
+            true
+        })
+        .await?;
+
+    let Some(job) = job else {
+        tracing::warn!(%job_id, "batch chunk job does not exist");
+        return Ok(());
+    };
+
+    if job.status != JobStatus::Running {
+        return Ok(());
+    }
+
+    let mut aggregated = Vec::with_capacity(job.items);
+    for index in 0..job.chunks {
+        match store.get_chunk_results(job_id, index).await? {
+            Some(results) => aggregated.extend(results),
+            // Remaining chunks complete the job once they finish
+            None => return Ok(()),
+        }
+    }
+
+    // The last chunks can finish together, completing the job more than once
+    // stores the same results. A job cancelled or failed meanwhile is kept
+    store
+        .update_job(job_id, |job| {
+            if job.status != JobStatus::Running {
+                return false;
+            }
+
+            job.complete(aggregated.clone());
+            true
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Watch the job of the running chunk at `index`, the returned signal is cancelled once
+/// the job is no longer running (i.e cancelled, failed or expired). Progress reported to
+/// the returned listener is recorded in the job. [None] when the job is not running
+pub async fn watch_job(job_id: &str, index: usize) -> Result<Option<JobWatch>, ConvertError> {
+    let store = JobStore::from_env().await.ok_or_else(fan_out_error)?;
+
+    if !report_chunk(&store, job_id, index, None)
+        .await?
+        .is_some_and(|job| job.status == JobStatus::Running)
+    {
+        return Ok(None);
+    }
+
+    let (handle, signal) = cancel_signal();
+    let (listener, mut receiver) = progress_channel();
+    let job_id = job_id.to_string();
+
+    let task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CANCEL_POLL_INTERVAL);
+        let mut reported_at = Instant::now();
+
+        loop {
+            interval.tick().await;
+
+            // Chunks without new reports (i.e waiting on x2t) are still recorded as
+            // alive so they are not expired
+            let stage = receiver.take_report();
+            let job = if stage.is_some() || reported_at.elapsed() >= CHUNK_HEARTBEAT_INTERVAL {
+                reported_at = Instant::now();
+                report_chunk(&store, &job_id, index, stage).await
+            } else {
+                store.get_job(&job_id).await
+            };
+
+            // Chunks that are still running expire the job of a chunk that stopped
+            let job = match job {
+                Ok(Some(job)) => expire_stale(&store, job).await.map(Some),
+                result => result,
+            };
+
+            match job {
+                Ok(Some(job)) if job.status == JobStatus::Running => {}
+                // Missing jobs have been removed from the store
+                Ok(_) => {
+                    tracing::info!(%job_id, "batch job is no longer running");
+                    handle.cancel();
+                    return;
+                }
+                Err(err) => tracing::warn!(?err, "failed to check batch job status"),
+            }
+        }
+    });
+
+    Ok(Some(JobWatch {
+        signal,
+        listener,
+        task,
+    }))
+}
+
+/// Record a progress report of the chunk at `index` in its job unless the job is no
+/// longer running. Returns the job, [None] when the job doesn't exist
+async fn report_chunk(
+    store: &JobStore,
+    job_id: &str,
+    index: usize,
+    stage: Option<ConvertStage>,
+) -> Result<Option<Job>, ConvertError> {
+    store
+        .update_job(job_id, |job| {
+            if job.status != JobStatus::Running {
+                return false;
+            }
+
+            job.report_chunk(index, stage);
+            true
+        })
+        .await
+}
+
+/// Expire the `job` when one of its chunks has stopped reporting progress (See
+/// [Job::is_stale]). Returns the latest job
+async fn expire_stale(store: &JobStore, job: Job) -> Result<Job, ConvertError> {
+    let config = app_config();
+    let (timeout, start_timeout) = (config.job_stale_timeout, config.job_start_timeout);
+    if !job.is_stale(timeout, start_timeout) {
+        return Ok(job);
+    }
+
+    tracing::warn!(job_id = %job.job_id, "batch chunk stopped reporting progress or never started, expiring job");
+
+    let updated = store
+        .update_job(&job.job_id, |job| {
+            if !job.is_stale(timeout, start_timeout) {
+                return false;
+            }
+
+            job.expire();
+            true
+        })
+        .await?;
+
+    Ok(updated.unwrap_or(job))
+}
+
+/// Cancellation and progress of a chunk whose job is being watched, see [watch_job]
+pub struct JobWatch {
+    pub signal: CancelSignal,
+    /// Listener for the conversions of the chunk, see
+    /// [onlyoffice_convert_core::progress::with_progress_listener]
+    pub listener: ProgressListener,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for JobWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Get the status of a job of the `tenant`, completed jobs include presigned URLs
+/// for the outputs of the requests that succeeded. Running jobs with a chunk that
+/// stopped reporting progress are expired
+pub async fn job_status(
+    job_id: &str,
+    tenant: Option<&str>,
+) -> Result<JobStatusOutput, ConvertError> {
+    validate_job_id(job_id)?;
+
+    let store = JobStore::from_env().await.ok_or_else(fan_out_error)?;
+    let job = store
+        .get_job(job_id)
+        .await?
+        .filter(|job| job.tenant.as_deref() == tenant)
+        .ok_or_else(job_not_found_error)?;
+    let job = expire_stale(&store, job).await?;
+
+    let duration_ms = job
+        .completed_at
+        .map(|completed_at| completed_at.saturating_sub(job.created_at));
+
+    let output_urls = match (&job.status, &job.results) {
+        (JobStatus::Completed, Some(results)) => {
+            Some(presign_job_outputs(&job.outputs, results).await?)
+        }
+        _ => None,
+    };
+
+    Ok(JobStatusOutput {
+        job,
+        duration_ms,
+        output_urls,
+    })
+}
+
+/// Presign the outputs of each request that succeeded
+async fn presign_job_outputs(
+    outputs: &[Option<JobOutput>],
+    results: &[Value],
+) -> Result<Vec<Vec<String>>, ConvertError> {
+    let storage = S3Storage::from_env().await;
+    let expires_in = app_config().job_output_url_expiry;
+    let mut output_urls = Vec::with_capacity(results.len());
+
+    for (index, result) in results.iter().enumerate() {
+        let succeeded = result.get("success") == Some(&Value::Bool(true));
+        let mut urls = Vec::new();
+
+        if let (true, Some(Some(output))) = (succeeded, outputs.get(index)) {
+            for key in &output.keys {
+                let url = storage
+                    .presign_get_object(&output.bucket, key, expires_in)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(?err, %key, "failed to presign job output");
+                        ConvertError {
+                            reason: Some("PRESIGN_OUTPUT"),
+                            x2t_code: None,
+                            message: "failed to create output url".to_string(),
+                        }
+                    })?;
+                urls.push(url);
+            }
+        }
+
+        output_urls.push(urls);
+    }
+
+    Ok(output_urls)
+}
+
+/// Cancel a running job of the `tenant`, chunks that are converting stop at their
+/// next check of the job. Returns the updated job
+pub async fn cancel_job(job_id: &str, tenant: Option<&str>) -> Result<Job, ConvertError> {
+    validate_job_id(job_id)?;
+
+    let store = JobStore::from_env().await.ok_or_else(fan_out_error)?;
+
+    store
+        .update_job(job_id, |job| {
+            if job.status != JobStatus::Running || job.tenant.as_deref() != tenant {
+                return false;
+            }
+
+            job.cancel();
+            true
+        })
+        .await?
+        // Jobs of other tenants are reported as missing
+        .filter(|job| job.tenant.as_deref() == tenant)
+        .ok_or_else(job_not_found_error)
+}
+
+/// Fails the job of a chunk that is dropped before its results are stored (i.e by
+/// the invocation timing out) so the job does not wait on the chunk
+pub struct ChunkGuard {
+    job_id: Option<String>,
+}
+
+impl ChunkGuard {
+    pub fn new(job_id: &str) -> ChunkGuard {
+        ChunkGuard {
+            job_id: Some(job_id.to_string()),
+        }
+    }
+
+    /// Disarm the guard once the chunk has been completed or failed
+    pub fn disarm(mut self) {
+        self.job_id = None;
+    }
+}
+
+impl Drop for ChunkGuard {
+    fn drop(&mut self) {
+        let Some(job_id) = self.job_id.take() else {
+            return;
+        };
+
+        tracing::warn!(%job_id, "batch chunk dropped before completing, failing job");
+
+        tokio::spawn(async move {
+            let error = ConvertError {
+                reason: Some("REQUEST_TIMEOUT"),
+                x2t_code: None,
+                message: "batch chunk timed out".to_string(),
+            };
+
+            if let Err(err) = fail_chunk(&job_id, &error).await {
+                tracing::error!(?err, "failed to fail batch chunk job");
+            }
+        });
+    }
+}
+
+/// Fail the job of a chunk that could not be converted
+pub async fn fail_chunk(job_id: &str, error: &ConvertError) -> Result<(), ConvertError> {
+    let store = JobStore::from_env().await.ok_or_else(fan_out_error)?;
+
+    store
+        .update_job(job_id, |job| fail_running(job, error))
+        .await?;
+
+    Ok(())
+}
+
+/// Fail the `job` with the `error` unless it has already completed, failed
+/// or been cancelled. Returns whether the job was failed
+fn fail_running(job: &mut Job, error: &ConvertError) -> bool {
+    if job.status != JobStatus::Running {
+        return false;
+    }
+
+    job.fail(error);
+    true
+}
+
+fn job_not_found_error() -> ConvertError {
+    ConvertError {
+        reason: Some("JOB_NOT_FOUND"),
+        x2t_code: None,
+        message: "job does not exist".to_string(),
+    }
+}
+
+fn fan_out_error() -> ConvertError {
+    ConvertError {
+        reason: Some("FAN_OUT_BATCH"),
+        x2t_code: None,
+        message: "failed to fan out batch".to_string(),
+    }
+}
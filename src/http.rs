@@ -0,0 +1,455 @@
+use std::{collections::HashMap, num::NonZeroUsize};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use onlyoffice_convert_core::{config::app_config, error::ConvertError, format::OutputFormat};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::object_lambda::{ObjectLambdaEvent, is_object_lambda_event};
+
+/// Header containing the tenant the request is made on behalf of, a `tenant` within
+/// the body takes precedence. Must match the tenant of the authenticated caller
+/// (See [crate::auth::authorize_request_tenant])
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// Payload of an invocation, either a plain JSON convert request from a direct
+/// Lambda invoke, an HTTP event from API Gateway / a Function URL or a GET made
+/// through an S3 Object Lambda access point
+pub enum EventPayload {
+    Direct(Value),
+    Http(Box<HttpRequest>),
+    ObjectLambda(Box<ObjectLambdaEvent>),
+}
+
+/// HTTP request extracted from an API Gateway / Function URL event
+pub struct HttpRequest {
+    /// Request method (i.e POST)
+    pub method: String,
+    /// Decoded request body, left empty when the body is larger than the maximum
+    /// request body size
+    pub body: Vec<u8>,
+    /// Size of the decoded request body in bytes
+    pub body_size: usize,
+    /// Request path (i.e /convert/pdf)
+    pub path: String,
+    /// Query string parameters
+    pub query: HashMap<String, String>,
+    /// Path parameters extracted by API Gateway routes (i.e /convert/{format})
+    pub path_parameters: HashMap<String, String>,
+    /// Request headers, names are lowercase
+    pub headers: HashMap<String, String>,
+    /// Identity of the caller reported by API Gateway, the IAM user ARN for IAM
+    /// authorized requests otherwise the source IP
+    pub caller: Option<String>,
+    /// Whether the HMAC signature of the request has been verified (See
+    /// [crate::auth::verify_request_signature])
+    pub signed: bool,
+}
+
+/// Routes for fanned out batch jobs
+pub enum JobRoute {
+    /// `GET /jobs/{job_id}` to get the status of a job
+    Status(String),
+    /// `POST /jobs/{job_id}/cancel` to cancel a running job
+    Cancel(String),
+}
+
+/// Subset of the API Gateway (v1 and v2) and Function URL event structure
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpEvent {
+    /// Request method for API Gateway v1 events
+    #[serde(default)]
+    http_method: Option<String>,
+    #[serde(default)]
+    request_context: Option<HttpRequestContext>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    is_base64_encoded: bool,
+    /// Request path for API Gateway v2 and Function URL events
+    #[serde(default)]
+    raw_path: Option<String>,
+    /// Request path for API Gateway v1 events
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    query_string_parameters: Option<HashMap<String, String>>,
+    #[serde(default)]
+    path_parameters: Option<HashMap<String, String>>,
+    #[serde(default)]
+    headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct HttpRequestContext {
+    /// Request details for API Gateway v2 and Function URL events
+    #[serde(default)]
+    http: Option<HttpRequestDetails>,
+    /// Caller identity for API Gateway v1 events
+    #[serde(default)]
+    identity: Option<HttpRequestIdentity>,
+    /// Authorizer details for API Gateway v2 and Function URL events
+    #[serde(default)]
+    authorizer: Option<HttpRequestAuthorizer>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpRequestDetails {
+    method: String,
+    #[serde(default)]
+    source_ip: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpRequestIdentity {
+    #[serde(default)]
+    source_ip: Option<String>,
+    #[serde(default)]
+    user_arn: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HttpRequestAuthorizer {
+    #[serde(default)]
+    iam: Option<HttpRequestIamAuthorizer>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpRequestIamAuthorizer {
+    #[serde(default)]
+    user_arn: Option<String>,
+}
+
+impl HttpRequestContext {
+    /// Identity of the caller, preferring the IAM user over the source IP
+    fn caller(self) -> Option<String> {
+        let (v1_user, v1_ip) = match self.identity {
+            Some(identity) => (identity.user_arn, identity.source_ip),
+            None => (None, None),
+        };
+
+        self.authorizer
+            .and_then(|authorizer| authorizer.iam)
+            .and_then(|iam| iam.user_arn)
+            .or(v1_user)
+            .or_else(|| self.http.and_then(|http| http.source_ip))
+            .or(v1_ip)
+    }
+}
+
+impl EventPayload {
+    /// Determine the kind of payload provided, HTTP events are detected by the
+    /// presence of the `requestContext` alongside the HTTP method (v1) or raw path (v2)
+    pub fn from_value(payload: Value) -> Result<EventPayload, ConvertError> {
+        if is_object_lambda_event(&payload) {
+            let event = serde_json::from_value(payload).map_err(|err| {
+                tracing::error!(?err, "failed to parse object lambda event");
+
+                ConvertError {
+                    reason: Some("PARSE_OBJECT_LAMBDA_EVENT"),
+                    x2t_code: None,
+                    message: "failed to parse object lambda event".to_string(),
+                }
+            })?;
+
+            return Ok(EventPayload::ObjectLambda(Box::new(event)));
+        }
+
+        if !is_http_event(&payload) {
+            return Ok(EventPayload::Direct(payload));
+        }
+
+        let event: HttpEvent = serde_json::from_value(payload).map_err(|err| {
+            tracing::error!(?err, "failed to parse http event");
+
+            ConvertError {
+                reason: Some("PARSE_HTTP_EVENT"),
+                x2t_code: None,
+                message: "failed to parse http event".to_string(),
+            }
+        })?;
+
+        let body = event.body.unwrap_or_default();
+        let body_size = if event.is_base64_encoded {
+            base64_decoded_size(&body)
+        } else {
+            body.len()
+        };
+
+        // Oversized bodies are rejected by the size limit layer without being decoded
+        let max_size = app_config().max_request_body_size.map(NonZeroUsize::get);
+        let body = if max_size.is_some_and(|max_size| body_size > max_size) {
+            Vec::new()
+        } else if event.is_base64_encoded {
+            BASE64_STANDARD.decode(body).map_err(|err| {
+                tracing::error!(?err, "failed to decode base64 http body");
+
+                ConvertError {
+                    reason: Some("PARSE_HTTP_EVENT"),
+                    x2t_code: None,
+                    message: "failed to decode base64 request body".to_string(),
+                }
+            })?
+        } else {
+            body.into_bytes()
+        };
+
+        let method = event
+            .http_method
+            .or_else(|| {
+                let http = event.request_context.as_ref()?.http.as_ref()?;
+                Some(http.method.clone())
+            })
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+
+        let caller = event.request_context.and_then(HttpRequestContext::caller);
+
+        Ok(EventPayload::Http(Box::new(HttpRequest {
+            method,
+            body,
+            body_size,
+            path: event.raw_path.or(event.path).unwrap_or_default(),
+            query: event.query_string_parameters.unwrap_or_default(),
+            path_parameters: event.path_parameters.unwrap_or_default(),
+            headers: event
+                .headers
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, value)| (name.to_ascii_lowercase(), value))
+                .collect(),
+            caller,
+            signed: false,
+        })))
+    }
+}
+
+impl HttpRequest {
+    /// Get a header value by its lowercase name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// Route of a request to query or cancel a fanned out batch job, [None]
+    /// for convert requests
+    pub fn job_route(&self) -> Option<JobRoute> {
+        let mut segments = self.path.split('/').filter(|segment| !segment.is_empty());
+
+        let route = match (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) {
+            (Some("jobs"), Some(job_id), None, None) if self.method == "GET" => {
+                JobRoute::Status(job_id.to_string())
+            }
+            (Some("jobs"), Some(job_id), Some("cancel"), None) if self.method == "POST" => {
+                JobRoute::Cancel(job_id.to_string())
+            }
+            _ => return None,
+        };
+
+        Some(route)
+    }
+
+    /// Whether the request is for the `GET /diagnostics` environment diagnostics
+    pub fn is_diagnostics_route(&self) -> bool {
+        self.method == "GET" && self.path.trim_end_matches('/') == "/diagnostics"
+    }
+
+    /// Create the JSON value for the convert request, fields missing from the JSON
+    /// body are filled using the query and path parameters:
+    ///
+    /// - `source_bucket`, `source_key`, `source_version_id`, `expected_etag`, `source_url`,
+    ///   `dest_bucket`, `dest_key` query parameters
+    /// - `source` and `dest` query parameters in the `bucket/key` or `s3://bucket/key` form
+    /// - `format` query or path parameter, or the path in the `/convert/{format}` form
+    /// - `tenant` from the `x-tenant-id` header
+    pub fn into_request_value(self) -> Result<Value, ConvertError> {
+        let mut request = if self.body.iter().all(u8::is_ascii_whitespace) {
+            Map::new()
+        } else {
+            serde_json::from_slice::<Map<String, Value>>(&self.body).map_err(|err| {
+                tracing::error!(?err, "failed to parse request body");
+
+                ConvertError {
+                    reason: Some("PARSE_REQUEST"),
+                    x2t_code: None,
+                    message: "failed to parse convert request".to_string(),
+                }
+            })?
+        };
+
+        for field in [
+            "source_bucket",
+            "source_key",
+            "source_version_id",
+            "expected_etag",
+            "source_url",
+            "dest_bucket",
+            "dest_key",
+        ] {
+            if let Some(value) = self.query.get(field) {
+                insert_missing(&mut request, field, value);
+            }
+        }
+
+        if let Some((bucket, key)) = self
+            .query
+            .get("source")
+            .and_then(|value| split_location(value))
+        {
+            insert_missing(&mut request, "source_bucket", bucket);
+            insert_missing(&mut request, "source_key", key);
+        }
+
+        if let Some((bucket, key)) = self
+            .query
+            .get("dest")
+            .and_then(|value| split_location(value))
+        {
+            insert_missing(&mut request, "dest_bucket", bucket);
+            insert_missing(&mut request, "dest_key", key);
+        }
+
+        if let Some(tenant) = self.header(TENANT_HEADER) {
+            insert_missing(&mut request, "tenant", tenant);
+        }
+
+        let format = self
+            .path_parameters
+            .get("format")
+            .or_else(|| self.query.get("format"))
+            .map(String::as_str)
+            .or_else(|| path_format(&self.path));
+
+        if let Some(format) = format {
+            let format = OutputFormat::from_name(format).ok_or_else(|| ConvertError {
+                reason: Some("UNKNOWN_OUTPUT_FORMAT"),
+                x2t_code: None,
+                message: format!("unknown output format \"{format}\""),
+            })?;
+
+            if !request.contains_key("output_format") {
+                request.insert(
+                    "output_format".to_string(),
+                    serde_json::to_value(format).unwrap_or_default(),
+                );
+            }
+        }
+
+        Ok(Value::Object(request))
+    }
+}
+
+/// Insert a string value into the request if the field is not already present
+fn insert_missing(request: &mut Map<String, Value>, field: &str, value: &str) {
+    if !request.contains_key(field) {
+        request.insert(field.to_string(), Value::String(value.to_string()));
+    }
+}
+
+/// Split a `bucket/key` or `s3://bucket/key` location into its bucket and key
+fn split_location(value: &str) -> Option<(&str, &str)> {
+    let value = value.strip_prefix("s3://").unwrap_or(value);
+    let (bucket, key) = value.split_once('/')?;
+
+    if bucket.is_empty() || key.is_empty() {
+        return None;
+    }
+
+    Some((bucket, key))
+}
+
+/// Extract the format from a `/convert/{format}` path
+fn path_format(path: &str) -> Option<&str> {
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+
+    segments.find(|segment| *segment == "convert")?;
+    segments.next()
+}
+
+/// Size of the bytes decoded from the base64 `body`
+fn base64_decoded_size(body: &str) -> usize {
+    let padding = body.bytes().rev().take_while(|byte| *byte == b'=').count();
+    (body.len() / 4 * 3).saturating_sub(padding)
+}
+
+fn is_http_event(payload: &Value) -> bool {
+    payload.get("requestContext").is_some()
+        && (payload.get("httpMethod").is_some() || payload.get("rawPath").is_some())
+}
+
+/// Response to an HTTP request
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub content_type: &'static str,
+    /// Additional response headers
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Create a response with a JSON body
+    pub fn json<T: Serialize>(
+        status_code: u16,
+        body: &T,
+    ) -> Result<HttpResponse, serde_json::Error> {
+        Ok(HttpResponse {
+            status_code,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: serde_json::to_vec(body)?,
+        })
+    }
+
+    /// Create a response with a binary body
+    pub fn binary(status_code: u16, content_type: &'static str, body: Vec<u8>) -> HttpResponse {
+        HttpResponse {
+            status_code,
+            content_type,
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    /// Create an API Gateway / Function URL compatible response, binary bodies are base64
+    /// encoded so API Gateway binary media handling can decode them
+    pub fn into_event_value(self) -> Value {
+        let mut headers = Map::new();
+        headers.insert(
+            "content-type".to_string(),
+            Value::String(self.content_type.to_string()),
+        );
+
+        for (name, value) in self.headers {
+            headers.insert(name.to_string(), Value::String(value));
+        }
+
+        if self.content_type == "application/json" {
+            return json!({
+                "statusCode": self.status_code,
+                "headers": headers,
+                "body": String::from_utf8_lossy(&self.body),
+                "isBase64Encoded": false
+            });
+        }
+
+        headers.insert(
+            "content-length".to_string(),
+            Value::String(self.body.len().to_string()),
+        );
+
+        json!({
+            "statusCode": self.status_code,
+            "headers": headers,
+            "body": BASE64_STANDARD.encode(&self.body),
+            "isBase64Encoded": true
+        })
+    }
+}
@@ -0,0 +1,509 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+use onlyoffice_convert_core::{
+    config::config_var,
+    error::ConvertError,
+    progress::ConvertStage,
+    storage::{GetOptions, PutBody, PutOptions, S3Storage, Storage, StorageError},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+/// Environment variable for the bucket jobs are stored within, jobs (and the
+/// batch fan-out that depends on them) are only available when this is set
+const JOB_STORE_BUCKET_ENV: &str = "JOB_STORE_BUCKET";
+
+/// Environment variable for the key prefix jobs are stored under
+const JOB_STORE_PREFIX_ENV: &str = "JOB_STORE_PREFIX";
+
+const DEFAULT_JOB_STORE_PREFIX: &str = "jobs/";
+
+/// Name of the object containing the job record within the job prefix
+const JOB_FILE_NAME: &str = "job.json";
+
+/// Maximum size of a stored job object that will be read
+const MAX_JOB_OBJECT_SIZE: usize = 32 * 1024 * 1024;
+
+/// Maximum length of a job ID
+const MAX_JOB_ID_LENGTH: usize = 64;
+
+/// Attempts made to update a job that is concurrently being updated
+const MAX_JOB_UPDATE_ATTEMPTS: usize = 5;
+
+/// Whether the job store is configured
+pub fn is_job_store_enabled() -> bool {
+    config_var(JOB_STORE_BUCKET_ENV).is_ok_and(|value| !value.is_empty())
+}
+
+/// Current state of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+    /// A chunk stopped reporting progress (i.e the invocation converting it crashed)
+    Expired,
+}
+
+/// Location the outputs of a request within a job are uploaded to
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JobOutput {
+    pub bucket: String,
+    /// Keys of the outputs in the same order as the output formats of the request
+    pub keys: Vec<String>,
+}
+
+/// Progress of a chunk of a job, reported by the invocation converting the chunk
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ChunkProgress {
+    /// Stage of the latest conversion reported by the chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<ConvertStage>,
+    /// Whether the results of the chunk have been stored
+    #[serde(default)]
+    pub completed: bool,
+    /// Time of the latest report in milliseconds since the unix epoch, [None] until
+    /// the chunk has started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+}
+
+/// Record of a batch job that is converted across multiple invocations
+#[derive(Serialize, Deserialize)]
+pub struct Job {
+    pub job_id: String,
+    pub status: JobStatus,
+    /// Tenant the batch was converted on behalf of, only the tenant can
+    /// query or cancel the job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Number of requests within the batch
+    pub items: usize,
+    /// Number of chunks the batch was split into
+    pub chunks: usize,
+    /// Time the job was created in milliseconds since the unix epoch
+    pub created_at: u64,
+    /// Time the job completed, failed or was cancelled in milliseconds since the unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<u64>,
+    /// Whether every conversion in the batch succeeded, set once completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+    /// Result for each request in the batch in the same order, set once completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<Value>>,
+    /// Error that caused the job to fail
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+    /// Output location for each request in the batch in the same order, [None]
+    /// for requests with outputs returned inline
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<Option<JobOutput>>,
+    /// Progress of each chunk of the job in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunk_progress: Vec<ChunkProgress>,
+}
+
+impl Job {
+    pub fn new(
+        job_id: String,
+        tenant: Option<String>,
+        items: usize,
+        chunks: usize,
+        outputs: Vec<Option<JobOutput>>,
+    ) -> Job {
+        Job {
+            job_id,
+            status: JobStatus::Running,
+            tenant,
+            items,
+            chunks,
+            created_at: now_millis(),
+            completed_at: None,
+            success: None,
+            results: None,
+            error: None,
+            outputs,
+            chunk_progress: vec![ChunkProgress::default(); chunks],
+        }
+    }
+
+    /// Record a progress report of the chunk at `index`, the `stage` is kept
+    /// from the previous report when [None]
+    pub fn report_chunk(&mut self, index: usize, stage: Option<ConvertStage>) {
+        if let Some(progress) = self.chunk_progress.get_mut(index) {
+            progress.stage = stage.or(progress.stage);
+            progress.updated_at = Some(now_millis());
+        }
+    }
+
+    /// Record the results of the chunk at `index` as stored
+    pub fn complete_chunk(&mut self, index: usize) {
+        if let Some(progress) = self.chunk_progress.get_mut(index) {
+            progress.completed = true;
+            progress.updated_at = Some(now_millis());
+        }
+    }
+
+    /// Whether the job is running with a chunk that has not reported progress within
+    /// the `timeout`. Chunks that have not started may be queued behind other
+    /// invocations and are instead timed from the job creation using the `start_timeout`
+    pub fn is_stale(&self, timeout: Duration, start_timeout: Duration) -> bool {
+        let now = now_millis();
+
+        self.status == JobStatus::Running
+            && self.chunk_progress.iter().any(|progress| {
+                let (since, timeout) = match progress.updated_at {
+                    Some(updated_at) => (updated_at, timeout),
+                    None => (self.created_at, start_timeout),
+                };
+
+                !progress.completed && now.saturating_sub(since) > timeout.as_millis() as u64
+            })
+    }
+
+    /// Mark the job as completed with the aggregated `results`
+    pub fn complete(&mut self, results: Vec<Value>) {
+        self.status = JobStatus::Completed;
+        self.completed_at = Some(now_millis());
+        self.success = Some(
+            results
+                .iter()
+                .all(|result| result.get("success") == Some(&Value::Bool(true))),
+        );
+        self.results = Some(results);
+    }
+
+    /// Mark the job as failed with the `error`
+    pub fn fail(&mut self, error: &ConvertError) {
+        self.status = JobStatus::Failed;
+        self.completed_at = Some(now_millis());
+        self.success = Some(false);
+        self.error = serde_json::to_value(error).ok();
+    }
+
+    /// Mark the job as cancelled, remaining conversions are skipped and their
+    /// outputs are not stored
+    pub fn cancel(&mut self) {
+        self.status = JobStatus::Cancelled;
+        self.completed_at = Some(now_millis());
+        self.success = Some(false);
+    }
+
+    /// Mark the job as expired, chunks that are still converting stop at their
+    /// next check of the job
+    pub fn expire(&mut self) {
+        self.status = JobStatus::Expired;
+        self.completed_at = Some(now_millis());
+        self.success = Some(false);
+        self.error = serde_json::to_value(ConvertError {
+            reason: Some("JOB_EXPIRED"),
+            x2t_code: None,
+            message: "batch chunk stopped reporting progress".to_string(),
+        })
+        .ok();
+    }
+}
+
+/// Jobs stored as JSON objects within S3, each job is stored under its own prefix
+/// alongside the results of its chunks
+pub struct JobStore {
+    storage: S3Storage,
+    bucket: String,
+    prefix: String,
+}
+
+impl JobStore {
+    /// Create the job store from the environment, [None] when not configured
+    pub async fn from_env() -> Option<JobStore> {
+        let bucket = config_var(JOB_STORE_BUCKET_ENV)
+            .ok()
+            .filter(|value| !value.is_empty())?;
+        let prefix = config_var(JOB_STORE_PREFIX_ENV)
+            .unwrap_or_else(|_| DEFAULT_JOB_STORE_PREFIX.to_string());
+
+        Some(JobStore {
+            storage: S3Storage::from_env().await,
+            bucket,
+            prefix,
+        })
+    }
+
+    fn job_key(&self, job_id: &str) -> String {
+        format!("{}{job_id}/{JOB_FILE_NAME}", self.prefix)
+    }
+
+    fn chunk_key(&self, job_id: &str, index: usize) -> String {
+        format!("{}{job_id}/chunks/{index}.json", self.prefix)
+    }
+
+    pub async fn put_job(&self, job: &Job) -> Result<(), ConvertError> {
+        let key = self.job_key(&job.job_id);
+        self.put_json(&key, job, None).await.map_err(|err| {
+            tracing::error!(?err, %key, "failed to store job object");
+            job_store_error()
+        })
+    }
+
+    /// Get a job, [None] when the job doesn't exist
+    pub async fn get_job(&self, job_id: &str) -> Result<Option<Job>, ConvertError> {
+        Ok(self
+            .get_json(&self.job_key(job_id))
+            .await?
+            .map(|(job, _)| job))
+    }
+
+    /// Update a job using `update` which returns whether the job was modified. The job
+    /// is only written when it has not changed since it was read, the update is applied
+    /// to the latest job again when it has. Returns the job, [None] when the job doesn't exist
+    pub async fn update_job<F>(
+        &self,
+        job_id: &str,
+        mut update: F,
+    ) -> Result<Option<Job>, ConvertError>
+    where
+        F: FnMut(&mut Job) -> bool,
+    {
+        let key = self.job_key(job_id);
+
+        for _ in 0..MAX_JOB_UPDATE_ATTEMPTS {
+            let Some((mut job, etag)) = self.get_json::<Job>(&key).await? else {
+                return Ok(None);
+            };
+
+            if !update(&mut job) {
+                return Ok(Some(job));
+            }
+
+            match self.put_json(&key, &job, etag.as_deref()).await {
+                Ok(()) => return Ok(Some(job)),
+                Err(StorageError::PreconditionFailed) => {
+                    tracing::debug!(%job_id, "job changed while updating, retrying update");
+                }
+                Err(err) => {
+                    tracing::error!(?err, %key, "failed to store job object");
+                    return Err(job_store_error());
+                }
+            }
+        }
+
+        tracing::error!(%job_id, "job kept changing while updating");
+        Err(job_store_error())
+    }
+
+    /// Store the results of a chunk of the job
+    pub async fn put_chunk_results(
+        &self,
+        job_id: &str,
+        index: usize,
+        results: &[Value],
+    ) -> Result<(), ConvertError> {
+        let key = self.chunk_key(job_id, index);
+        self.put_json(&key, &results, None).await.map_err(|err| {
+            tracing::error!(?err, %key, "failed to store job object");
+            job_store_error()
+        })
+    }
+
+    /// Get the results of a chunk of the job, [None] when the chunk has not completed
+    pub async fn get_chunk_results(
+        &self,
+        job_id: &str,
+        index: usize,
+    ) -> Result<Option<Vec<Value>>, ConvertError> {
+        Ok(self
+            .get_json(&self.chunk_key(job_id, index))
+            .await?
+            .map(|(results, _)| results))
+    }
+
+    /// Store the `value` at the `key`, only replacing the object when it
+    /// matches the `if_match` entity tag (When provided)
+    async fn put_json<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        if_match: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let body = serde_json::to_vec(value)
+            .map_err(|err| StorageError::Request(format!("failed to serialize object: {err}")))?;
+
+        self.storage
+            .put_object(
+                &self.bucket,
+                key,
+                PutBody::Bytes(body),
+                PutOptions {
+                    content_type: Some("application/json"),
+                    if_match,
+                    ..Default::default()
+                },
+            )
+            .await
+    }
+
+    /// Get the value stored at the `key` along with the entity tag of the object
+    async fn get_json<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<(T, Option<String>)>, ConvertError> {
+        let object = match self
+            .storage
+            .get_object(&self.bucket, key, GetOptions::default())
+            .await
+        {
+            Ok(value) => value,
+            Err(StorageError::NoSuchKey) => return Ok(None),
+            Err(err) => {
+                tracing::error!(?err, %key, "failed to get job object");
+                return Err(job_store_error());
+            }
+        };
+
+        let etag = object.etag;
+        let mut body = object.body;
+        let mut bytes = Vec::new();
+
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|err| {
+                tracing::error!(?err, %key, "failed to read job object");
+                job_store_error()
+            })?;
+            bytes.extend_from_slice(&chunk);
+
+            if bytes.len() > MAX_JOB_OBJECT_SIZE {
+                tracing::error!(%key, "job object is too large");
+                return Err(job_store_error());
+            }
+        }
+
+        let value = serde_json::from_slice(&bytes).map_err(|err| {
+            tracing::error!(?err, %key, "failed to parse job object");
+            job_store_error()
+        })?;
+
+        Ok(Some((value, etag)))
+    }
+}
+
+/// Validate a caller provided job ID, job IDs are the request IDs of the
+/// invocations that created them
+pub fn validate_job_id(job_id: &str) -> Result<(), ConvertError> {
+    let valid_length = (1..=MAX_JOB_ID_LENGTH).contains(&job_id.len());
+    let valid_chars = job_id
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-');
+
+    if !valid_length || !valid_chars {
+        return Err(ConvertError {
+            reason: Some("INVALID_REQUEST"),
+            x2t_code: None,
+            message: "job_id: invalid job ID".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn job_store_error() -> ConvertError {
+    ConvertError {
+        reason: Some("JOB_STORE"),
+        x2t_code: None,
+        message: "failed to access job store".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Job, JobStatus, MAX_JOB_ID_LENGTH, validate_job_id};
+
+    fn test_job(created_at: u64) -> Job {
+        let mut job = Job::new("job".to_string(), None, 4, 2, Vec::new());
+        job.created_at = created_at;
+        job
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+    const START_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn test_job_not_stale() {
+        let job = test_job(super::now_millis());
+        assert!(!job.is_stale(TIMEOUT, START_TIMEOUT));
+    }
+
+    #[test]
+    fn test_job_stale_chunk_not_started() {
+        let mut job = test_job(0);
+        job.report_chunk(0, None);
+        assert!(job.is_stale(TIMEOUT, START_TIMEOUT));
+    }
+
+    #[test]
+    fn test_job_stale_chunk_stopped_reporting() {
+        let mut job = test_job(super::now_millis());
+        job.report_chunk(0, None);
+        job.chunk_progress[0].updated_at = Some(super::now_millis() - 2 * 60 * 1000);
+        assert!(job.is_stale(TIMEOUT, START_TIMEOUT));
+    }
+
+    #[test]
+    fn test_job_not_stale_chunk_queued() {
+        // Queued for longer than the progress timeout but within the start timeout
+        let mut job = test_job(super::now_millis() - 30 * 60 * 1000);
+        job.report_chunk(0, None);
+        assert!(!job.is_stale(TIMEOUT, START_TIMEOUT));
+    }
+
+    #[test]
+    fn test_job_not_stale_chunks_reported() {
+        let mut job = test_job(0);
+        job.report_chunk(0, None);
+        job.complete_chunk(1);
+        assert!(!job.is_stale(TIMEOUT, START_TIMEOUT));
+    }
+
+    #[test]
+    fn test_job_not_stale_once_finished() {
+        let mut job = test_job(0);
+        job.cancel();
+        assert!(!job.is_stale(TIMEOUT, START_TIMEOUT));
+
+        job.status = JobStatus::Running;
+        job.expire();
+        assert_eq!(job.status, JobStatus::Expired);
+        assert!(!job.is_stale(TIMEOUT, START_TIMEOUT));
+    }
+
+    #[test]
+    fn test_validate_job_id() {
+        validate_job_id("8f14e45f-ceea-467f-a8e4-8d2f1a2b3c4d").unwrap();
+        validate_job_id(&"a".repeat(MAX_JOB_ID_LENGTH)).unwrap();
+    }
+
+    #[test]
+    fn test_validate_job_id_rejected() {
+        for job_id in [
+            String::new(),
+            "a".repeat(MAX_JOB_ID_LENGTH + 1),
+            "../jobs".to_string(),
+            "jobs/other".to_string(),
+            "job id".to_string(),
+        ] {
+            let err = validate_job_id(&job_id).unwrap_err();
+            assert_eq!(err.reason, Some("INVALID_REQUEST"));
+        }
+    }
+}
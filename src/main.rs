@@ -1,13 +1,119 @@
 use lambda_runtime::{Error, run, service_fn, tracing};
+#[cfg(feature = "server")]
+use onlyoffice_convert_core::config::config_var;
+#[cfg(feature = "ssm")]
+use onlyoffice_convert_core::ssm_config::load_ssm_config;
+use onlyoffice_convert_core::{
+    aws::check_aws_region,
+    config::{check_features, load_app_config},
+    config_check::set_init_error,
+    error::ConvertError,
+    fonts::sync_fonts,
+    themes::sync_themes,
+    x2t::check_icu_data,
+    x2t_bundle::bootstrap_x2t,
+};
+#[cfg(feature = "wasm-plugins")]
+use onlyoffice_convert_core::{
+    pipeline::{Pipeline, register_pipeline},
+    wasm_plugin::load_wasm_plugins,
+};
 mod event_handler;
 use event_handler::function_handler;
-mod encrypted;
+mod auth;
+#[cfg(feature = "cli")]
+mod cli;
+mod fan_out;
+mod http;
+mod job_store;
+mod middleware;
+mod object_lambda;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "sqs")]
+mod sqs;
+#[cfg(feature = "server")]
+mod worker;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     _ = dotenvy::dotenv();
 
+    // Logs are written to stderr in CLI mode as stdout carries the converted output
+    #[cfg(feature = "cli")]
+    let cli_args = std::env::args()
+        .nth(1)
+        .filter(|command| command == cli::CLI_COMMAND)
+        .map(|_| std::env::args().skip(2));
+
+    #[cfg(feature = "cli")]
+    if cli_args.is_some() {
+        tracing::init_default_subscriber_with_writer(std::io::stderr);
+    } else {
+        tracing::init_default_subscriber();
+    }
+
+    #[cfg(not(feature = "cli"))]
     tracing::init_default_subscriber();
 
+    let init = init().await;
+
+    #[cfg(feature = "cli")]
+    if let Some(args) = cli_args {
+        init.map_err(|err| Error::from(err.message))?;
+        return cli::run(args).await;
+    }
+
+    #[cfg(feature = "server")]
+    if let Ok(address) = config_var(server::SERVER_ADDRESS_ENV) {
+        init.map_err(|err| Error::from(err.message))?;
+        return server::run(&address).await;
+    }
+
+    // Configuration errors are reported to each invocation rather than failing init
+    if let Err(error) = init {
+        if error.reason != Some("CONFIGURATION_ERROR") {
+            return Err(Error::from(error.message));
+        }
+
+        tracing::error!(?error, "function is misconfigured");
+        set_init_error(error);
+    }
+
     run(service_fn(function_handler)).await
 }
+
+/// Check the configuration and download the dependencies before handling any requests
+async fn init() -> Result<(), ConvertError> {
+    // Validate the configuration before handling any requests
+    load_app_config()?;
+
+    // Apply the configuration overrides from SSM before the configuration is used
+    #[cfg(feature = "ssm")]
+    load_ssm_config().await?;
+
+    // Check the resolved region is within the configured partition, including the
+    // region and partition overrides from SSM, before any other AWS requests
+    check_aws_region().await?;
+
+    // Configured integrations that are not compiled in fail at init rather than per request
+    check_features()?;
+
+    // Download x2t when it is not installed before handling any requests
+    bootstrap_x2t().await?;
+
+    // Check the configured ICU data exists so misconfigured paths fail at init
+    check_icu_data()?;
+
+    // Download the custom fonts before handling any requests
+    sync_fonts().await?;
+
+    // Download the presentation themes bundle before handling any requests
+    sync_themes().await?;
+
+    // Load the output transforms from the WASM plugins before handling any requests
+    #[cfg(feature = "wasm-plugins")]
+    register_pipeline(load_wasm_plugins(Pipeline::default()).await?)?;
+
+    Ok(())
+}
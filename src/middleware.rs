@@ -0,0 +1,309 @@
+use std::{
+    num::NonZeroUsize,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures::future::BoxFuture;
+use lambda_runtime::{
+    Error,
+    tower::{Layer, Service},
+};
+use onlyoffice_convert_core::{
+    config::app_config, error::ConvertError, storage::with_upload_deadline,
+};
+use serde_json::Value;
+use tracing::Instrument;
+
+use crate::{
+    auth::verify_request_signature,
+    http::{EventPayload, HttpResponse},
+};
+
+/// Time reserved before the Lambda deadline to respond with the timeout error
+const DEADLINE_MARGIN: Duration = Duration::from_secs(2);
+
+/// Invocation passed through the middleware stack, the payload is parsed before
+/// the layers so each layer can inspect the request
+pub struct Invocation {
+    pub context: lambda_runtime::Context,
+    pub payload: EventPayload,
+}
+
+impl Invocation {
+    /// Kind of the invocation payload for logging
+    fn kind(&self) -> &'static str {
+        match self.payload {
+            EventPayload::Direct(_) => "direct",
+            EventPayload::Http(_) => "http",
+            EventPayload::ObjectLambda(_) => "object_lambda",
+        }
+    }
+
+    fn is_http(&self) -> bool {
+        matches!(self.payload, EventPayload::Http(_))
+    }
+}
+
+/// Respond to an invocation with the `error`, as an error response for HTTP
+/// requests otherwise as the error of the invocation
+pub fn error_response(is_http: bool, error: ConvertError) -> Result<Value, Error> {
+    if is_http {
+        return Ok(HttpResponse::json(error.status_code(), &error)?.into_event_value());
+    }
+
+    Err(Error::from(serde_json::to_string(&error)?))
+}
+
+/// Log each invocation within a span of its request ID, along with its outcome
+/// and duration once handled
+#[derive(Clone, Copy)]
+pub struct LoggingLayer;
+
+impl<S> Layer<S> for LoggingLayer {
+    type Service = LoggingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoggingService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoggingService<S> {
+    inner: S,
+}
+
+impl<S> Service<Invocation> for LoggingService<S>
+where
+    S: Service<Invocation, Response = Value, Error = Error>,
+    S::Future: Send + 'static,
+{
+    type Response = Value;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Value, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, invocation: Invocation) -> Self::Future {
+        let span = tracing::info_span!("invocation", request_id = %invocation.context.request_id);
+        let kind = invocation.kind();
+        let (method, path) = match &invocation.payload {
+            EventPayload::Http(request) => {
+                (Some(request.method.clone()), Some(request.path.clone()))
+            }
+            _ => (None, None),
+        };
+
+        let start = Instant::now();
+        let future = span.in_scope(|| self.inner.call(invocation));
+
+        Box::pin(
+            async move {
+                let result = future.await;
+                let duration_ms = start.elapsed().as_millis() as u64;
+
+                match &result {
+                    Ok(response) => {
+                        let status_code = response.get("statusCode").and_then(Value::as_u64);
+                        tracing::info!(
+                            kind,
+                            method,
+                            path,
+                            status_code,
+                            duration_ms,
+                            "handled invocation"
+                        );
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            kind,
+                            method,
+                            path,
+                            duration_ms,
+                            %err,
+                            "invocation failed"
+                        );
+                    }
+                }
+
+                result
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// Fail invocations with `REQUEST_TIMEOUT` once the configured timeout or the
+/// Lambda deadline is reached, dropping the running conversion. Dropped conversions
+/// remove their temporary files, abort their multipart uploads and fail the job
+/// of their batch chunk in the background
+#[derive(Clone, Copy)]
+pub struct TimeoutLayer;
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = TimeoutService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimeoutService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct TimeoutService<S> {
+    inner: S,
+}
+
+impl<S> Service<Invocation> for TimeoutService<S>
+where
+    S: Service<Invocation, Response = Value, Error = Error>,
+    S::Future: Send + 'static,
+{
+    type Response = Value;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Value, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, invocation: Invocation) -> Self::Future {
+        let remaining = invocation
+            .context
+            .deadline()
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .saturating_sub(DEADLINE_MARGIN);
+
+        let timeout = match app_config().request_timeout {
+            Some(timeout) => timeout.min(remaining),
+            None => remaining,
+        };
+
+        let is_http = invocation.is_http();
+        let future = self.inner.call(invocation);
+
+        // Uploads are sized to finish within the time remaining for the invocation
+        let future = with_upload_deadline(Instant::now() + timeout, future);
+
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, future).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::error!(?timeout, "invocation timed out");
+                    error_response(
+                        is_http,
+                        ConvertError {
+                            reason: Some("REQUEST_TIMEOUT"),
+                            x2t_code: None,
+                            message: "request timed out".to_string(),
+                        },
+                    )
+                }
+            }
+        })
+    }
+}
+
+/// Reject HTTP requests with bodies larger than the configured maximum size, the
+/// size is checked before oversized bodies are decoded (See [EventPayload::from_value])
+#[derive(Clone, Copy)]
+pub struct SizeLimitLayer;
+
+impl<S> Layer<S> for SizeLimitLayer {
+    type Service = SizeLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SizeLimitService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct SizeLimitService<S> {
+    inner: S,
+}
+
+impl<S> Service<Invocation> for SizeLimitService<S>
+where
+    S: Service<Invocation, Response = Value, Error = Error>,
+    S::Future: Send + 'static,
+{
+    type Response = Value;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Value, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, invocation: Invocation) -> Self::Future {
+        let max_size = app_config().max_request_body_size.map(NonZeroUsize::get);
+
+        if let (EventPayload::Http(request), Some(max_size)) = (&invocation.payload, max_size)
+            && request.body_size > max_size
+        {
+            tracing::warn!(size = request.body_size, max_size, "request body too large");
+
+            let response = error_response(
+                true,
+                ConvertError {
+                    reason: Some("REQUEST_TOO_LARGE"),
+                    x2t_code: None,
+                    message: format!("request body exceeds the maximum size of {max_size} bytes"),
+                },
+            );
+            return Box::pin(async move { response });
+        }
+
+        Box::pin(self.inner.call(invocation))
+    }
+}
+
+/// Verify the HMAC signature of HTTP requests (When enabled), JWTs are verified
+/// by the handler as their claims are merged into the request
+#[derive(Clone, Copy)]
+pub struct AuthLayer;
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthService<S> {
+    inner: S,
+}
+
+impl<S> Service<Invocation> for AuthService<S>
+where
+    S: Service<Invocation, Response = Value, Error = Error> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Value;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Value, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut invocation: Invocation) -> Self::Future {
+        // The ready inner service is used for this call, a clone is left in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            if let EventPayload::Http(request) = &mut invocation.payload
+                && let Err(error) = verify_request_signature(request).await
+            {
+                return error_response(true, error);
+            }
+
+            inner.call(invocation).await
+        })
+    }
+}
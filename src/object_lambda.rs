@@ -0,0 +1,145 @@
+use aws_sdk_s3::primitives::ByteStream;
+use onlyoffice_convert_core::{
+    aws::aws_config,
+    convert::{ConvertOutput, ConvertRequest, convert},
+    error::ConvertError,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tokio::sync::OnceCell;
+
+/// Error code reported to the caller when the conversion fails without a reason
+const DEFAULT_ERROR_CODE: &str = "CONVERT_FAILED";
+
+/// Client used to write the responses, cached across warm invocations
+static S3_CLIENT: OnceCell<aws_sdk_s3::Client> = OnceCell::const_new();
+
+/// Subset of the S3 Object Lambda event structure
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectLambdaEvent {
+    get_object_context: GetObjectContext,
+    #[serde(default)]
+    configuration: Option<ObjectLambdaConfiguration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetObjectContext {
+    /// Presigned URL for the original object through the supporting access point
+    input_s3_url: String,
+    output_route: String,
+    output_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectLambdaConfiguration {
+    /// Payload configured on the Object Lambda access point, a JSON object of
+    /// convert request options (i.e `{"output_format": "pdf"}`)
+    #[serde(default)]
+    payload: Option<String>,
+}
+
+/// Whether the payload is an S3 Object Lambda event
+pub fn is_object_lambda_event(payload: &Value) -> bool {
+    payload.get("getObjectContext").is_some()
+}
+
+/// Handle a GET made through an S3 Object Lambda access point, the original object
+/// is converted using the options configured on the access point and the output
+/// is returned to the caller using WriteGetObjectResponse
+pub async fn handle_object_lambda_event(
+    request_id: &str,
+    event: ObjectLambdaEvent,
+) -> Result<Value, lambda_runtime::Error> {
+    let context = event.get_object_context;
+
+    let result = match parse_request(event.configuration.and_then(|value| value.payload)) {
+        Ok(mut request) => {
+            request.set_presigned_source_url(context.input_s3_url);
+            convert(request_id, request).await
+        }
+        Err(error) => Err(error),
+    };
+
+    let client = S3_CLIENT
+        .get_or_init(|| async { aws_sdk_s3::Client::new(&aws_config().await) })
+        .await;
+
+    let response = client
+        .write_get_object_response()
+        .request_route(context.output_route)
+        .request_token(context.output_token);
+
+    let output = result.and_then(|result| match result.output {
+        ConvertOutput::Inline {
+            bytes,
+            content_type,
+        } => Ok((bytes, content_type)),
+        ConvertOutput::Uploaded => Err(ConvertError {
+            reason: Some("INVALID_REQUEST"),
+            x2t_code: None,
+            message: "object lambda outputs must be returned inline".to_string(),
+        }),
+    });
+
+    let (status_code, response) = match output {
+        Ok((bytes, content_type)) => (
+            200,
+            response
+                .status_code(200)
+                .content_type(content_type)
+                .body(ByteStream::from(bytes)),
+        ),
+        Err(error) => {
+            let status_code = error.status_code();
+            (
+                status_code,
+                response
+                    .status_code(i32::from(status_code))
+                    .error_code(error.reason.unwrap_or(DEFAULT_ERROR_CODE))
+                    .error_message(error.message),
+            )
+        }
+    };
+
+    response.send().await.map_err(|err| {
+        tracing::error!(?err, "failed to write object lambda response");
+        lambda_runtime::Error::from(err.to_string())
+    })?;
+
+    Ok(json!({ "statusCode": status_code }))
+}
+
+/// Parse the convert request options configured on the access point, the output
+/// is always returned to the caller so destinations are not allowed
+fn parse_request(payload: Option<String>) -> Result<ConvertRequest, ConvertError> {
+    let options = match payload.filter(|value| !value.trim().is_empty()) {
+        Some(payload) => serde_json::from_str::<Map<String, Value>>(&payload),
+        None => Ok(Map::new()),
+    };
+
+    let request: ConvertRequest = options
+        .and_then(|options| serde_json::from_value(Value::Object(options)))
+        .map_err(|err| {
+            tracing::error!(?err, "failed to parse object lambda configuration");
+
+            ConvertError {
+                reason: Some("PARSE_REQUEST"),
+                x2t_code: None,
+                message: "failed to parse object lambda configuration".to_string(),
+            }
+        })?;
+
+    if request.destination().is_some() {
+        return Err(ConvertError {
+            reason: Some("INVALID_REQUEST"),
+            x2t_code: None,
+            message: "dest_bucket and dest_key are not supported for object lambda requests"
+                .to_string(),
+        });
+    }
+
+    Ok(request)
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{OriginalUri, Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use lambda_runtime::Error;
+use uuid::Uuid;
+
+use crate::{
+    auth::verify_request_signature,
+    event_handler::{handle_diagnostics_request, handle_http_request},
+    http::{HttpRequest, HttpResponse},
+    worker::{PoolHealth, WorkerPool},
+};
+
+/// Environment variable for the address to serve HTTP on, the server is only
+/// started instead of the Lambda runtime when this is set
+pub const SERVER_ADDRESS_ENV: &str = "SERVER_ADDRESS";
+
+/// Header the request ID is read from and returned in
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Run the standalone HTTP server, the routes match those of the API Gateway
+/// / Function URL deployment of the Lambda. Conversions are run by a persistent
+/// worker pool which is also fed from SQS when built with the `sqs` feature
+pub async fn run(address: &str) -> Result<(), Error> {
+    let pool = Arc::new(WorkerPool::start().await);
+
+    #[cfg(feature = "sqs")]
+    if let Ok(queue_url) = std::env::var(crate::sqs::SQS_QUEUE_URL_ENV) {
+        tokio::spawn(crate::sqs::poll_queue(pool.clone(), queue_url));
+    }
+
+    let router = Router::new()
+        .route("/convert", post(convert))
+        .route("/convert/{format}", post(convert))
+        .route("/health", get(health))
+        .route("/diagnostics", get(diagnostics))
+        .with_state(pool);
+
+    let listener = tokio::net::TcpListener::bind(address).await?;
+
+    tracing::info!(%address, "starting http server");
+
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+async fn convert(
+    State(pool): State<Arc<WorkerPool>>,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let mut http_request = HttpRequest {
+        method: "POST".to_string(),
+        body: body.to_vec(),
+        body_size: body.len(),
+        path: uri.path().to_string(),
+        query,
+        // The format is extracted from the /convert/{format} path
+        path_parameters: HashMap::new(),
+        headers: request_headers(&headers),
+        caller: None,
+        signed: false,
+    };
+
+    let result = match verify_request_signature(&mut http_request).await {
+        Ok(()) => {
+            handle_http_request(http_request, |request| {
+                pool.submit(request_id.clone(), request)
+            })
+            .await
+        }
+        Err(error) => HttpResponse::json(error.status_code(), &error),
+    };
+
+    let mut response = match result {
+        Ok(response) => into_response(response),
+        Err(err) => {
+            tracing::error!(?err, "failed to create response");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+async fn diagnostics(
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let mut http_request = HttpRequest {
+        method: "GET".to_string(),
+        body: Vec::new(),
+        body_size: 0,
+        path: uri.path().to_string(),
+        query,
+        path_parameters: HashMap::new(),
+        headers: request_headers(&headers),
+        caller: None,
+        signed: false,
+    };
+
+    let result = match verify_request_signature(&mut http_request).await {
+        Ok(()) => handle_diagnostics_request(&http_request).await,
+        Err(error) => HttpResponse::json(error.status_code(), &error),
+    };
+
+    match result {
+        Ok(response) => into_response(response),
+        Err(err) => {
+            tracing::error!(?err, "failed to create response");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn health(State(pool): State<Arc<WorkerPool>>) -> Json<PoolHealth> {
+    Json(pool.health())
+}
+
+/// Headers of the request keyed by their lowercase name, values that are not
+/// valid strings are skipped
+fn request_headers(headers: &HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+        })
+        .collect()
+}
+
+fn into_response(response: HttpResponse) -> Response {
+    let status =
+        StatusCode::from_u16(response.status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(response.content_type),
+    );
+
+    for (name, value) in response.headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+
+    (status, headers, response.body).into_response()
+}
@@ -0,0 +1,130 @@
+use std::{sync::Arc, time::Duration};
+
+use aws_sdk_sqs::types::Message;
+use onlyoffice_convert_core::{aws::aws_config, convert::ConvertRequest};
+use uuid::Uuid;
+
+use crate::worker::WorkerPool;
+
+/// Environment variable for the URL of an SQS queue to pull convert requests
+/// from, the queue is only polled when this is set
+pub const SQS_QUEUE_URL_ENV: &str = "SQS_QUEUE_URL";
+
+/// Maximum number of messages SQS will return from a single receive
+const MAX_RECEIVE_MESSAGES: usize = 10;
+
+/// Seconds to long poll the queue for messages
+const RECEIVE_WAIT_SECONDS: i32 = 20;
+
+/// Delay before polling again when the worker queue is full
+const QUEUE_FULL_DELAY: Duration = Duration::from_secs(1);
+
+/// Delay before polling again after failing to receive messages
+const RECEIVE_ERROR_DELAY: Duration = Duration::from_secs(5);
+
+/// Poll the SQS queue for convert requests and submit them to the worker pool,
+/// messages are only received while the worker queue has room for them
+pub async fn poll_queue(pool: Arc<WorkerPool>, queue_url: String) {
+    let aws_config = aws_config().await;
+    let client = aws_sdk_sqs::Client::new(&aws_config);
+
+    tracing::info!(%queue_url, "polling sqs queue");
+
+    loop {
+        let available = pool.available_capacity().min(MAX_RECEIVE_MESSAGES);
+        if available == 0 {
+            tokio::time::sleep(QUEUE_FULL_DELAY).await;
+            continue;
+        }
+
+        let output = match client
+            .receive_message()
+            .queue_url(&queue_url)
+            .max_number_of_messages(available as i32)
+            .wait_time_seconds(RECEIVE_WAIT_SECONDS)
+            .send()
+            .await
+        {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::error!(?err, "failed to receive sqs messages");
+                tokio::time::sleep(RECEIVE_ERROR_DELAY).await;
+                continue;
+            }
+        };
+
+        for message in output.messages.unwrap_or_default() {
+            tokio::spawn(handle_message(
+                client.clone(),
+                queue_url.clone(),
+                pool.clone(),
+                message,
+            ));
+        }
+    }
+}
+
+/// Convert the request from a single message, the message is deleted once the
+/// conversion completes or fails in a way that retrying would not fix, other
+/// failures are left for SQS to redeliver after the visibility timeout
+async fn handle_message(
+    client: aws_sdk_sqs::Client,
+    queue_url: String,
+    pool: Arc<WorkerPool>,
+    message: Message,
+) {
+    let request_id = message
+        .message_id
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let Some(receipt_handle) = message.receipt_handle else {
+        tracing::error!(%request_id, "sqs message is missing a receipt handle");
+        return;
+    };
+
+    let request = message
+        .body
+        .as_deref()
+        .map(serde_json::from_str::<ConvertRequest>);
+
+    let remove = match request {
+        Some(Ok(request)) if request.destination().is_some() => {
+            match pool.submit(request_id.clone(), request).await {
+                Ok(_) => true,
+                Err(error) => {
+                    let status_code = error.status_code();
+                    tracing::error!(%request_id, ?error, "failed to convert sqs message");
+
+                    // Client errors other than being busy will fail again on retry
+                    status_code < 500 && status_code != 429
+                }
+            }
+        }
+        Some(Ok(_)) => {
+            tracing::error!(%request_id, "sqs message is missing a destination");
+            true
+        }
+        Some(Err(err)) => {
+            tracing::error!(%request_id, ?err, "failed to parse sqs message");
+            true
+        }
+        None => {
+            tracing::error!(%request_id, "sqs message is missing a body");
+            true
+        }
+    };
+
+    if !remove {
+        return;
+    }
+
+    if let Err(err) = client
+        .delete_message()
+        .queue_url(&queue_url)
+        .receipt_handle(receipt_handle)
+        .send()
+        .await
+    {
+        tracing::error!(%request_id, ?err, "failed to delete sqs message");
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    env::temp_dir,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread::available_parallelism,
+};
+
+use onlyoffice_convert_core::{
+    convert::{ConvertOptions, ConvertRequest, ConvertResult, convert_with_options},
+    error::ConvertError,
+    storage::{S3Storage, Storage},
+};
+use serde::Serialize;
+use tokio::sync::{
+    Mutex,
+    mpsc::{self, error::TrySendError},
+    oneshot,
+};
+
+/// Environment variable for the number of worker tasks in the pool, defaults
+/// to the number of available vCPUs
+const WORKER_COUNT_ENV: &str = "WORKER_COUNT";
+
+/// Environment variable for the number of jobs that can wait in the queue
+/// before new jobs are rejected, defaults to 4 jobs per worker
+const WORKER_QUEUE_SIZE_ENV: &str = "WORKER_QUEUE_SIZE";
+
+/// Environment variable for the directory the per-worker temp directories are created within
+const WORKER_TEMP_DIR_ENV: &str = "WORKER_TEMP_DIR";
+
+/// Name of the directory the worker temp directories are stored within
+const WORKER_TEMP_DIR_NAME: &str = "onlyoffice-convert-workers";
+
+/// Persistent pool of workers that run conversions pulled from a shared queue,
+/// keeps x2t and the font caches warm across jobs in long-running containers
+pub struct WorkerPool {
+    sender: mpsc::Sender<Job>,
+    queue_size: usize,
+    workers: Vec<Arc<WorkerState>>,
+}
+
+/// Conversion waiting in the queue for a worker
+struct Job {
+    request_id: String,
+    request: ConvertRequest,
+    respond: oneshot::Sender<Result<ConvertResult, ConvertError>>,
+}
+
+/// State of a single worker reported by the health check
+#[derive(Default)]
+struct WorkerState {
+    busy: AtomicBool,
+    completed: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Health report for the worker pool
+#[derive(Serialize)]
+pub struct PoolHealth {
+    /// Number of jobs waiting for a worker
+    queued: usize,
+    /// Maximum number of jobs that can wait for a worker
+    queue_size: usize,
+    workers: Vec<WorkerHealth>,
+}
+
+#[derive(Serialize)]
+struct WorkerHealth {
+    id: usize,
+    busy: bool,
+    completed: u64,
+    failed: u64,
+}
+
+impl WorkerPool {
+    /// Start the worker pool using the size from the environment
+    pub async fn start() -> WorkerPool {
+        let worker_count = std::env::var(WORKER_COUNT_ENV)
+            .ok()
+            .and_then(|value| value.parse::<NonZeroUsize>().ok())
+            .or_else(|| available_parallelism().ok())
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+
+        let queue_size = std::env::var(WORKER_QUEUE_SIZE_ENV)
+            .ok()
+            .and_then(|value| value.parse::<NonZeroUsize>().ok())
+            .map(NonZeroUsize::get)
+            .unwrap_or(worker_count * 4);
+
+        let temp_path = std::env::var(WORKER_TEMP_DIR_ENV)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| temp_dir().join(WORKER_TEMP_DIR_NAME));
+
+        // Storage client is shared by the workers rather than created for each job
+        let storage: Arc<dyn Storage> = Arc::new(S3Storage::from_env().await);
+
+        let (sender, receiver) = mpsc::channel(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..worker_count)
+            .map(|id| {
+                let state = Arc::new(WorkerState::default());
+                let temp_dir = temp_path.join(format!("worker-{id}"));
+
+                // Clear any files left behind by a previous process
+                if temp_dir.exists()
+                    && let Err(err) = std::fs::remove_dir_all(&temp_dir)
+                {
+                    tracing::warn!(?err, path = %temp_dir.display(), "failed to clear worker temp directory");
+                }
+
+                tokio::spawn(run_worker(
+                    id,
+                    temp_dir,
+                    storage.clone(),
+                    receiver.clone(),
+                    state.clone(),
+                ));
+                state
+            })
+            .collect();
+
+        tracing::info!(worker_count, queue_size, "started worker pool");
+
+        WorkerPool {
+            sender,
+            queue_size,
+            workers,
+        }
+    }
+
+    /// Queue the `request` and wait for a worker to complete it, fails with
+    /// a busy error when the queue is full
+    pub async fn submit(
+        &self,
+        request_id: String,
+        request: ConvertRequest,
+    ) -> Result<ConvertResult, ConvertError> {
+        let (respond, response) = oneshot::channel();
+
+        self.sender
+            .try_send(Job {
+                request_id,
+                request,
+                respond,
+            })
+            .map_err(|err| match err {
+                TrySendError::Full(_) => ConvertError {
+                    reason: Some("BUSY"),
+                    x2t_code: None,
+                    message: "worker queue is full, try again later".to_string(),
+                },
+                TrySendError::Closed(_) => ConvertError {
+                    reason: Some("WORKER_POOL_CLOSED"),
+                    x2t_code: None,
+                    message: "worker pool is not running".to_string(),
+                },
+            })?;
+
+        response.await.map_err(|_| ConvertError {
+            reason: Some("WORKER_FAILED"),
+            x2t_code: None,
+            message: "worker stopped before completing the conversion".to_string(),
+        })?
+    }
+
+    /// Number of jobs that can be queued before the queue is full
+    #[cfg(feature = "sqs")]
+    pub fn available_capacity(&self) -> usize {
+        self.sender.capacity()
+    }
+
+    /// Report the current state of the queue and workers
+    pub fn health(&self) -> PoolHealth {
+        PoolHealth {
+            queued: self.queue_size - self.sender.capacity(),
+            queue_size: self.queue_size,
+            workers: self
+                .workers
+                .iter()
+                .enumerate()
+                .map(|(id, state)| WorkerHealth {
+                    id,
+                    busy: state.busy.load(Ordering::Relaxed),
+                    completed: state.completed.load(Ordering::Relaxed),
+                    failed: state.failed.load(Ordering::Relaxed),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Pull jobs from the queue until the pool is dropped, each worker converts
+/// within its own temp directory
+async fn run_worker(
+    id: usize,
+    temp_dir: PathBuf,
+    storage: Arc<dyn Storage>,
+    receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+    state: Arc<WorkerState>,
+) {
+    loop {
+        let job = receiver.lock().await.recv().await;
+        let Some(Job {
+            request_id,
+            request,
+            respond,
+        }) = job
+        else {
+            break;
+        };
+
+        state.busy.store(true, Ordering::Relaxed);
+        tracing::debug!(worker = id, %request_id, "worker started job");
+
+        // Run the conversion on its own task so a panic doesn't take the worker down
+        let options = ConvertOptions {
+            temp_dir: Some(temp_dir.clone()),
+            storage: Some(storage.clone()),
+            cancel: None,
+            caller: None,
+        };
+        let result =
+            tokio::spawn(async move { convert_with_options(&request_id, request, options).await })
+                .await
+                .unwrap_or_else(|err| {
+                    tracing::error!(?err, worker = id, "worker conversion panicked");
+
+                    Err(ConvertError {
+                        reason: Some("WORKER_FAILED"),
+                        x2t_code: None,
+                        message: "conversion failed unexpectedly".to_string(),
+                    })
+                });
+
+        match result {
+            Ok(_) => state.completed.fetch_add(1, Ordering::Relaxed),
+            Err(_) => state.failed.fetch_add(1, Ordering::Relaxed),
+        };
+        state.busy.store(false, Ordering::Relaxed);
+
+        // The submitter may have gone away (i.e the HTTP client disconnected)
+        _ = respond.send(result);
+    }
+}